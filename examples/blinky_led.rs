@@ -16,9 +16,10 @@ fn main() -> ! {
     let p = stm32h7x3::Peripherals::take().unwrap();
 
     let mut flash = p.FLASH.constrain();
+    let mut pwr = p.PWR.constrain();
     let mut rcc = p.RCC.constrain();
 
-    let clocks = rcc.cfgr.freeze(&mut flash.acr);
+    let clocks = rcc.cfgr.freeze(&mut flash.acr, &mut pwr).unwrap();
 
     let gpiob = p.GPIOB.split(&mut rcc.ahb4);
     // Configure our LED as output
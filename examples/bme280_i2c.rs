@@ -23,7 +23,8 @@ fn main() -> ! {
 
     let mut rcc = p.RCC.constrain();
     let mut flash = p.FLASH.constrain();
-    let clocks = rcc.cfgr.freeze(&mut flash.acr);
+    let mut pwr = p.PWR.constrain();
+    let clocks = rcc.cfgr.freeze(&mut flash.acr, &mut pwr).unwrap();
     let gpiob = p.GPIOB.split(&mut rcc.ahb4);
 
     // Configure the SCL and the SDA pin for our I2C bus
@@ -27,11 +27,12 @@ fn main() -> ! {
     // Take ownership over the raw flash and rcc devices and convert them into the corresponding
     // HAL structs
     let mut flash = p.FLASH.constrain();
+    let mut pwr = p.PWR.constrain();
     let mut rcc = p.RCC.constrain();
 
     // Freeze the configuration of all the clocks in the system and store the frozen frequencies in
     // `clocks`
-    let clocks = rcc.cfgr.freeze(&mut flash.acr);
+    let clocks = rcc.cfgr.freeze(&mut flash.acr, &mut pwr).unwrap();
 
     // Prepare the GPIOB peripheral
     let mut gpiob = p.GPIOB.split(&mut rcc.ahb4);
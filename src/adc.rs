@@ -0,0 +1,633 @@
+//! Analog to Digital Converter
+
+use core::ptr;
+use core::slice;
+
+use cortex_m::asm;
+use hal::adc::{Channel, OneShot};
+use nb;
+use void::Void;
+use stm32h7::stm32h7x3::{ADC1, ADC2, ADC3, ADC12_COMMON, ADC3_COMMON};
+
+use crate::dma::{Config as DmaConfig, PeripheralAddress, Stream, Transfer};
+use crate::gpio::{Analog, AF0};
+use crate::gpio::gpioa::{PA0, PA1, PA2, PA3, PA4, PA5, PA6, PA7};
+use crate::gpio::gpiob::{PB0, PB1};
+use crate::gpio::gpioc::{PC0, PC1, PC2, PC3, PC4, PC5};
+use crate::gpio::gpiof::{PF3, PF4, PF5, PF6, PF7, PF8, PF9, PF10, PF11, PF12, PF13, PF14};
+use crate::gpio::gpioh::{PH2, PH3, PH4, PH5};
+use crate::rcc::{Clocks, Enable, Reset, AHB1, AHB4};
+use crate::time::Hertz;
+
+/// The highest frequency of the ADC clock, the limit of revision Y of the chip
+const MAX_ADC_CK: u32 = 36_000_000;
+
+/// Above this frequency of the ADC clock the BOOST bit has to be set
+const BOOST_THRESHOLD: u32 = 20_000_000;
+
+/// The dividers of CCR.PRESC
+const PRESC: [u32; 12] = [1, 2, 4, 6, 8, 10, 12, 16, 32, 64, 128, 256];
+
+/// Conversion of the temperature sensor at 30 °C and VDDA = 3.3 V with 16 bit
+const TS_CAL1: *const u16 = 0x1FF1_E820 as *const u16;
+/// Conversion of the temperature sensor at 110 °C and VDDA = 3.3 V with 16 bit
+const TS_CAL2: *const u16 = 0x1FF1_E840 as *const u16;
+/// Conversion of VREFINT at 30 °C and VDDA = 3.3 V with 16 bit
+const VREFINT_CAL: *const u16 = 0x1FF1_E860 as *const u16;
+
+/// VDDA during the factory calibration in millivolts
+const VDDA_CAL: u32 = 3300;
+
+/// Start-up time of the temperature sensor in microseconds
+const T_START_TS: u32 = 25;
+/// Start-up time of VREFINT and of the VBAT bridge in microseconds
+const T_START_VREFINT: u32 = 5;
+
+/// Resolution of the conversions, the values of revision Y of the chip
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Resolution {
+    /// 16 bit
+    SixteenBit = 0b000,
+    /// 14 bit
+    FourteenBit = 0b001,
+    /// 12 bit
+    TwelveBit = 0b010,
+    /// 10 bit
+    TenBit = 0b011,
+    /// 8 bit
+    EightBit = 0b100,
+}
+
+/// Sampling time of a channel in cycles of the ADC clock, longer sampling times are
+/// required for sources with a higher impedance
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum SampleTime {
+    /// 1.5 cycles
+    Cycles1_5 = 0b000,
+    /// 2.5 cycles
+    Cycles2_5 = 0b001,
+    /// 8.5 cycles
+    Cycles8_5 = 0b010,
+    /// 16.5 cycles
+    Cycles16_5 = 0b011,
+    /// 32.5 cycles
+    Cycles32_5 = 0b100,
+    /// 64.5 cycles
+    Cycles64_5 = 0b101,
+    /// 387.5 cycles
+    Cycles387_5 = 0b110,
+    /// 810.5 cycles
+    Cycles810_5 = 0b111,
+}
+
+/// External trigger of the injected sequence, the values of JEXTSEL
+#[allow(non_camel_case_types)]
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum InjectedTrigger {
+    /// TIM1 TRGO
+    TIM1_TRGO = 0,
+    /// TIM1 channel 4
+    TIM1_CH4 = 1,
+    /// TIM2 TRGO
+    TIM2_TRGO = 2,
+    /// TIM2 channel 1
+    TIM2_CH1 = 3,
+    /// TIM3 channel 4
+    TIM3_CH4 = 4,
+    /// TIM4 TRGO
+    TIM4_TRGO = 5,
+    /// EXTI line 15
+    EXTI15 = 6,
+    /// TIM8 channel 4
+    TIM8_CH4 = 7,
+    /// TIM1 TRGO2
+    TIM1_TRGO2 = 8,
+    /// TIM8 TRGO
+    TIM8_TRGO = 9,
+    /// TIM8 TRGO2
+    TIM8_TRGO2 = 10,
+    /// TIM3 channel 3
+    TIM3_CH3 = 11,
+    /// TIM3 TRGO
+    TIM3_TRGO = 12,
+    /// TIM3 channel 1
+    TIM3_CH1 = 13,
+    /// TIM6 TRGO
+    TIM6_TRGO = 14,
+    /// TIM15 TRGO
+    TIM15_TRGO = 15,
+    /// HRTIM ADC trigger 2
+    HRTIM_ADCTRG2 = 16,
+    /// HRTIM ADC trigger 4
+    HRTIM_ADCTRG4 = 17,
+    /// LPTIM1 output
+    LPTIM1_OUT = 18,
+    /// LPTIM2 output
+    LPTIM2_OUT = 19,
+    /// LPTIM3 output
+    LPTIM3_OUT = 20,
+}
+
+/// Edge of an external trigger that starts a conversion
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum TriggerEdge {
+    /// Rising edge
+    Rising = 0b01,
+    /// Falling edge
+    Falling = 0b10,
+    /// Both edges
+    Both = 0b11,
+}
+
+/// Configuration of the injected sequence
+///
+/// The default starts the sequence by software with `start_injected`
+#[derive(Clone, Copy, Default)]
+pub struct InjectedConfig {
+    trigger: Option<(InjectedTrigger, TriggerEdge)>,
+    auto_injection: bool,
+}
+
+impl InjectedConfig {
+    /// Starts the sequence on the `edge` of `trigger`, once it was armed with
+    /// `start_injected`
+    pub fn trigger(mut self, trigger: InjectedTrigger, edge: TriggerEdge) -> Self {
+        self.trigger = Some((trigger, edge));
+        self
+    }
+
+    /// Converts the injected sequence automatically after each regular sequence, this
+    /// requires the software trigger
+    pub fn auto_injection(mut self, on: bool) -> Self {
+        self.auto_injection = on;
+        self
+    }
+}
+
+/// Analog to digital converter
+///
+/// The conversions have a resolution of 16 bit and all channels are sampled for 1.5 cycles
+/// until they are changed
+pub struct Adc<ADC> {
+    adc: ADC,
+    sys_ck: Hertz,
+}
+
+/// The internal temperature sensor, channel 18 of ADC3
+pub struct Temperature;
+
+/// The internal reference voltage VREFINT, channel 19 of ADC3
+pub struct Vrefint;
+
+/// The backup battery voltage VBAT divided by 4, channel 17 of ADC3
+pub struct Vbat;
+
+impl Temperature {
+    /// Converts a 16 bit conversion of the sensor to °C, `vdda` is the analog supply in
+    /// millivolts, e.g. measured with `Vrefint::vdda`
+    pub fn celsius(raw: u16, vdda: u32) -> f32 {
+        // NOTE(unsafe) the calibration values are in read only system memory
+        let (cal1, cal2) = unsafe { (ptr::read(TS_CAL1), ptr::read(TS_CAL2)) };
+        // the calibration values were taken with VDDA = 3.3 V
+        let raw = raw as f32 * vdda as f32 / VDDA_CAL as f32;
+
+        (110.0 - 30.0) / (cal2 as f32 - cal1 as f32) * (raw - cal1 as f32) + 30.0
+    }
+}
+
+impl Vrefint {
+    /// The analog supply VDDA in millivolts from a 16 bit conversion of VREFINT
+    pub fn vdda(raw: u16) -> u32 {
+        // NOTE(unsafe) the calibration value is in read only system memory
+        let cal = u32::from(unsafe { ptr::read(VREFINT_CAL) });
+        VDDA_CAL * cal / u32::from(raw.max(1))
+    }
+}
+
+impl Vbat {
+    /// Converts a 16 bit conversion of VBAT / 4 to the battery voltage in millivolts, `vdda`
+    /// is the analog supply in millivolts
+    pub fn millivolts(raw: u16, vdda: u32) -> u32 {
+        u32::from(raw) * vdda * 4 / 0xFFFF
+    }
+}
+
+macro_rules! internal_channels {
+    ($($Source:ident: $channel:expr,)+) => {
+        $(
+            impl Channel<ADC3> for $Source {
+                type ID = u8;
+
+                fn channel() -> u8 {
+                    $channel
+                }
+            }
+        )+
+    };
+}
+
+internal_channels!(
+    Vbat: 17,
+    Temperature: 18,
+    Vrefint: 19,
+);
+
+impl Adc<ADC3> {
+    /// Enables the temperature sensor, it is sampled for at least the required 9 us
+    pub fn enable_temperature(&mut self) -> Temperature {
+        self.set_sample_time(&Temperature, SampleTime::Cycles810_5);
+        // NOTE(unsafe) the common registers of ADC3 are only used by ADC3
+        unsafe { (*ADC3_COMMON::ptr()).ccr.modify(|_, w| w.tsen().set_bit()) };
+        self.wait_us(T_START_TS);
+        Temperature
+    }
+
+    /// Disables the temperature sensor
+    pub fn disable_temperature(&mut self, _: Temperature) {
+        unsafe { (*ADC3_COMMON::ptr()).ccr.modify(|_, w| w.tsen().clear_bit()) };
+    }
+
+    /// Enables VREFINT, it is sampled for at least the required 4.3 us
+    pub fn enable_vref(&mut self) -> Vrefint {
+        self.set_sample_time(&Vrefint, SampleTime::Cycles810_5);
+        unsafe { (*ADC3_COMMON::ptr()).ccr.modify(|_, w| w.vrefen().set_bit()) };
+        self.wait_us(T_START_VREFINT);
+        Vrefint
+    }
+
+    /// Disables VREFINT
+    pub fn disable_vref(&mut self, _: Vrefint) {
+        unsafe { (*ADC3_COMMON::ptr()).ccr.modify(|_, w| w.vrefen().clear_bit()) };
+    }
+
+    /// Enables the VBAT bridge, it drains the battery and should be disabled after the
+    /// conversions
+    pub fn enable_vbat(&mut self) -> Vbat {
+        self.set_sample_time(&Vbat, SampleTime::Cycles810_5);
+        unsafe { (*ADC3_COMMON::ptr()).ccr.modify(|_, w| w.vbaten().set_bit()) };
+        self.wait_us(T_START_VREFINT);
+        Vbat
+    }
+
+    /// Disables the VBAT bridge
+    pub fn disable_vbat(&mut self, _: Vbat) {
+        unsafe { (*ADC3_COMMON::ptr()).ccr.modify(|_, w| w.vbaten().clear_bit()) };
+    }
+
+    /// Busy waits for at least `us` microseconds
+    fn wait_us(&self, us: u32) {
+        asm::delay(self.sys_ck.0 / 1_000_000 * us);
+    }
+}
+
+/// Conversions of one channel running continuously into a circular buffer
+///
+/// The DMA stream raises its half transfer interrupt once the first half of the buffer is
+/// filled and its transfer complete interrupt once the second half is filled
+pub struct CircularDma<ADC, PIN, STREAM> {
+    transfer: Transfer<STREAM, Adc<ADC>, &'static mut [u16]>,
+    pin: PIN,
+    buf: *const u16,
+    len: usize,
+}
+
+impl<ADC, PIN, STREAM: Stream> CircularDma<ADC, PIN, STREAM> {
+    /// The most recent conversion, before the first one completed this is the initial
+    /// content of the end of the buffer
+    pub fn peek(&self) -> u16 {
+        let written = self.len - self.transfer.remaining() as usize;
+        let index = if written == 0 { self.len - 1 } else { written - 1 };
+        // NOTE(unsafe) the index is in bounds, the DMA only writes whole half words
+        unsafe { ptr::read_volatile(self.buf.add(index)) }
+    }
+
+    /// The half of the buffer that was filled since the last call, `None` if none was
+    /// filled, e.g. to call from the interrupt handler of the stream
+    ///
+    /// The DMA fills the other half in the meantime, the returned half has to be processed
+    /// before that one is full
+    pub fn read_latest(&mut self) -> Option<&[u16]> {
+        let half = self.len / 2;
+        let offset = if self.transfer.is_done() {
+            self.transfer.clear_interrupts();
+            half
+        } else if self.transfer.is_half_done() {
+            self.transfer.clear_half_done();
+            0
+        } else {
+            return None;
+        };
+
+        // NOTE(unsafe) the DMA writes to the other half of the buffer
+        Some(unsafe { slice::from_raw_parts(self.buf.add(offset), half) })
+    }
+}
+
+macro_rules! adc {
+    ($($ADCX:ident: ($adcX:ident, $COMMON:ident, $AHB:ident, $enable:expr),)+) => {
+        $(
+            impl Adc<$ADCX> {
+                /// Powers up and calibrates the ADC, it is clocked by the kernel clock selected
+                /// with `CFGR::adc_src`
+                ///
+                /// ADC1 and ADC2 share their clock configuration
+                pub fn $adcX(adc: $ADCX, ahb: &mut $AHB, clocks: &Clocks) -> Self {
+                    $enable(ahb);
+
+                    let ker_ck = clocks.adc_ck().0;
+                    let presc = PRESC
+                        .iter()
+                        .position(|div| ker_ck / div <= MAX_ADC_CK)
+                        .expect("the ADC kernel clock is too fast");
+                    let adc_ck = ker_ck / PRESC[presc];
+
+                    // NOTE(unsafe) CKMODE = 00: the asynchronous kernel clock, the value is
+                    // the same for both ADCs sharing the register
+                    unsafe {
+                        (*$COMMON::ptr())
+                            .ccr
+                            .modify(|_, w| w.ckmode().bits(0b00).presc().bits(presc as u8));
+                    }
+
+                    // leave the deep power down and start the voltage regulator
+                    adc.cr.modify(|_, w| w.deeppwd().clear_bit());
+                    adc.cr.modify(|_, w| {
+                        w.advregen().set_bit().boost().bit(adc_ck > BOOST_THRESHOLD)
+                    });
+                    // T_ADCVREG_STUP of 10 us
+                    asm::delay(clocks.sys_ck().0 / 100_000);
+
+                    // offset and linearity calibration of single ended conversions
+                    adc.cr.modify(|_, w| w.adcaldif().clear_bit().adcallin().set_bit());
+                    adc.cr.modify(|_, w| w.adcal().set_bit());
+                    while adc.cr.read().adcal().bit_is_set() {}
+
+                    adc.isr.write(|w| w.adrdy().set_bit());
+                    adc.cr.modify(|_, w| w.aden().set_bit());
+                    while adc.isr.read().adrdy().bit_is_clear() {}
+
+                    Adc { adc, sys_ck: clocks.sys_ck() }
+                }
+
+                /// Converts `pin` continuously into `buffer`, which is written by `stream`
+                /// in circular mode
+                ///
+                /// # Panics
+                ///
+                /// Panics if the buffer has an odd length
+                pub fn start_circular_dma<PIN, STREAM>(
+                    self,
+                    pin: PIN,
+                    stream: STREAM,
+                    buffer: &'static mut [u16],
+                ) -> CircularDma<$ADCX, PIN, STREAM>
+                where
+                    PIN: Channel<$ADCX, ID = u8>,
+                    STREAM: Stream,
+                {
+                    assert!(buffer.len() % 2 == 0, "the buffer has to consist of two halves");
+
+                    self.select(PIN::channel());
+                    // DMNGT = 11: DMA circular mode, OVRMOD: a conversion may overwrite the
+                    // previous one
+                    self.adc.cfgr.modify(|_, w| unsafe {
+                        w.cont().set_bit().dmngt().bits(0b11).ovrmod().set_bit()
+                    });
+
+                    let buf = buffer.as_ptr();
+                    let len = buffer.len();
+                    let config = DmaConfig::default()
+                        .circular(true)
+                        .half_transfer_interrupt(true)
+                        .transfer_complete_interrupt(true);
+                    let transfer = Transfer::peripheral_to_memory(stream, self, buffer, config);
+
+                    // NOTE(unsafe) the stream runs, the conversions can start
+                    unsafe { (*$ADCX::ptr()).cr.modify(|_, w| w.adstart().set_bit()) };
+
+                    CircularDma { transfer, pin, buf, len }
+                }
+
+                /// Sets the resolution of the following conversions
+                pub fn set_resolution(&mut self, resolution: Resolution) {
+                    self.adc.cfgr.modify(|_, w| unsafe { w.res().bits(resolution as u8) });
+                }
+
+                /// Sets the sampling time of the channel of `pin`
+                pub fn set_sample_time<PIN>(&mut self, _pin: &PIN, sample_time: SampleTime)
+                where
+                    PIN: Channel<$ADCX, ID = u8>,
+                {
+                    let channel = u32::from(PIN::channel());
+                    let smp = |bits: u32, shift: u32| {
+                        bits & !(0b111 << shift) | (sample_time as u32) << shift
+                    };
+                    if channel < 10 {
+                        self.adc.smpr1.modify(|r, w| unsafe { w.bits(smp(r.bits(), 3 * channel)) });
+                    } else {
+                        self.adc.smpr2.modify(|r, w| unsafe {
+                            w.bits(smp(r.bits(), 3 * (channel - 10)))
+                        });
+                    }
+                }
+
+                /// Makes `channel` the only channel of the regular sequence
+                fn select(&self, channel: u8) {
+                    self.adc.pcsel.modify(|r, w| unsafe { w.bits(r.bits() | 1 << channel) });
+                    // L = 0: a sequence of one conversion
+                    self.adc.sqr1.write(|w| unsafe { w.l3().bits(0).sq1().bits(channel) });
+                }
+
+                /// Sets the injected sequence to `channels`, the channel numbers of e.g.
+                /// `hal::adc::Channel::channel`, converted in their order
+                ///
+                /// Injected conversions interrupt the regular sequence, which resumes
+                /// afterwards, e.g. to sample currents at a fixed point of a PWM period
+                ///
+                /// # Panics
+                ///
+                /// Panics if the sequence doesn't hold 1 to 4 channels, if a channel doesn't
+                /// exist or if auto-injection is combined with an external trigger
+                pub fn configure_injected(&mut self, channels: &[u8], config: InjectedConfig) {
+                    assert!(
+                        !channels.is_empty() && channels.len() <= 4,
+                        "the injected sequence holds 1 to 4 channels"
+                    );
+                    assert!(channels.iter().all(|&channel| channel < 20), "the ADC has 20 channels");
+                    assert!(
+                        !(config.auto_injection && config.trigger.is_some()),
+                        "auto-injection requires the software trigger"
+                    );
+
+                    // JSQR and JQDIS can only be written while no injected conversion runs
+                    self.stop_injected();
+
+                    let mut pcsel = 0;
+                    for &channel in channels {
+                        pcsel |= 1 << channel;
+                    }
+                    self.adc.pcsel.modify(|r, w| unsafe { w.bits(r.bits() | pcsel) });
+
+                    // JQDIS: JSQR is applied directly instead of through the queue
+                    self.adc.cfgr.modify(|_, w| {
+                        w.jqdis().set_bit().jauto().bit(config.auto_injection)
+                    });
+
+                    let (jextsel, jexten) = match config.trigger {
+                        Some((trigger, edge)) => (trigger as u8, edge as u8),
+                        None => (0, 0b00),
+                    };
+                    let jsq = |rank: usize| channels.get(rank).cloned().unwrap_or(0);
+                    self.adc.jsqr.write(|w| unsafe {
+                        w.jl().bits(channels.len() as u8 - 1)
+                            .jextsel().bits(jextsel)
+                            .jexten().bits(jexten)
+                            .jsq1().bits(jsq(0))
+                            .jsq2().bits(jsq(1))
+                            .jsq3().bits(jsq(2))
+                            .jsq4().bits(jsq(3))
+                    });
+                }
+
+                /// Starts the injected sequence, with an external trigger the sequence is
+                /// started by each trigger event until `stop_injected` is called
+                pub fn start_injected(&mut self) {
+                    self.adc.isr.write(|w| w.jeoc().set_bit().jeos().set_bit());
+                    self.adc.cr.modify(|_, w| w.jadstart().set_bit());
+                }
+
+                /// Waits until the injected sequence was converted and clears the end of
+                /// sequence flag
+                pub fn wait_injected(&mut self) -> nb::Result<(), Void> {
+                    if self.adc.isr.read().jeos().bit_is_clear() {
+                        Err(nb::Error::WouldBlock)
+                    } else {
+                        self.adc.isr.write(|w| w.jeoc().set_bit().jeos().set_bit());
+                        Ok(())
+                    }
+                }
+
+                /// The last conversion of the injected channel at `rank`, 1 to 4
+                ///
+                /// # Panics
+                ///
+                /// Panics if `rank` isn't 1 to 4
+                pub fn read_injected(&self, rank: u8) -> u16 {
+                    (match rank {
+                        1 => self.adc.jdr1.read().jdata1().bits(),
+                        2 => self.adc.jdr2.read().jdata2().bits(),
+                        3 => self.adc.jdr3.read().jdata3().bits(),
+                        4 => self.adc.jdr4.read().jdata4().bits(),
+                        _ => panic!("the injected sequence has the ranks 1 to 4"),
+                    }) as u16
+                }
+
+                /// Stops the injected conversions and disarms the external trigger
+                pub fn stop_injected(&mut self) {
+                    if self.adc.cr.read().jadstart().bit_is_set() {
+                        self.adc.cr.modify(|_, w| w.jadstp().set_bit());
+                        while self.adc.cr.read().jadstp().bit_is_set() {}
+                    }
+                }
+
+                /// Enables or disables the interrupt at the end of the injected sequence
+                pub fn listen_injected(&mut self, on: bool) {
+                    self.adc.ier.modify(|_, w| w.jeosie().bit(on));
+                }
+
+                /// Disables the ADC and releases it
+                pub fn free(mut self) -> $ADCX {
+                    // the ADC can only be disabled while no conversion runs
+                    self.stop_injected();
+                    self.adc.cr.modify(|_, w| w.addis().set_bit());
+                    while self.adc.cr.read().aden().bit_is_set() {}
+                    self.adc
+                }
+            }
+
+            impl<PIN> OneShot<$ADCX, u16, PIN> for Adc<$ADCX>
+            where
+                PIN: Channel<$ADCX, ID = u8>,
+            {
+                type Error = Void;
+
+                /// Converts the channel of `pin` once, blocking until the conversion is done
+                fn read(&mut self, _pin: &mut PIN) -> nb::Result<u16, Void> {
+                    self.select(PIN::channel());
+                    self.adc.cfgr.modify(|_, w| unsafe { w.cont().clear_bit().dmngt().bits(0b00) });
+
+                    self.adc.isr.write(|w| w.eoc().set_bit());
+                    self.adc.cr.modify(|_, w| w.adstart().set_bit());
+                    while self.adc.isr.read().eoc().bit_is_clear() {}
+
+                    // reading DR clears EOC
+                    Ok(self.adc.dr.read().rdata().bits() as u16)
+                }
+            }
+
+            unsafe impl PeripheralAddress for Adc<$ADCX> {
+                type Word = u16;
+
+                fn address(&self) -> u32 {
+                    &self.adc.dr as *const _ as u32
+                }
+            }
+
+            impl<PIN, STREAM: Stream> CircularDma<$ADCX, PIN, STREAM> {
+                /// Stops the conversions and the stream and releases the ADC, the pin, the
+                /// stream and the buffer
+                pub fn stop(self) -> (Adc<$ADCX>, PIN, STREAM, &'static mut [u16]) {
+                    // NOTE(unsafe) the ADC is owned by the transfer
+                    let adc = unsafe { &*$ADCX::ptr() };
+                    adc.cr.modify(|_, w| w.adstp().set_bit());
+                    while adc.cr.read().adstart().bit_is_set() {}
+                    adc.cfgr.modify(|_, w| unsafe { w.cont().clear_bit().dmngt().bits(0b00) });
+
+                    let (stream, adc, buffer) = self.transfer.free();
+                    (adc, self.pin, stream, buffer)
+                }
+            }
+        )+
+    };
+}
+
+// ADC1 and ADC2 share their reset, resetting one of them would disable the other one
+adc!(
+    ADC1: (adc1, ADC12_COMMON, AHB1, |ahb: &mut AHB1| ADC12_COMMON::enable(ahb)),
+    ADC2: (adc2, ADC12_COMMON, AHB1, |ahb: &mut AHB1| ADC12_COMMON::enable(ahb)),
+    ADC3: (adc3, ADC3_COMMON, AHB4, |ahb: &mut AHB4| {
+        ADC3::enable(ahb);
+        ADC3::reset(ahb);
+    }),
+);
+
+macro_rules! channels {
+    ($($ADCX:ident: [$($PXi:ident: $channel:expr,)+],)+) => {
+        $(
+            $(
+                impl Channel<$ADCX> for $PXi<Analog, AF0> {
+                    type ID = u8;
+
+                    fn channel() -> u8 {
+                        $channel
+                    }
+                }
+            )+
+        )+
+    };
+}
+
+channels!(
+    ADC1: [
+        PF11: 2, PA6: 3, PC4: 4, PB1: 5, PF12: 6, PA7: 7, PC5: 8, PB0: 9, PC0: 10, PC1: 11,
+        PC2: 12, PC3: 13, PA2: 14, PA3: 15, PA0: 16, PA1: 17, PA4: 18, PA5: 19,
+    ],
+    ADC2: [
+        PF13: 2, PA6: 3, PC4: 4, PB1: 5, PF14: 6, PA7: 7, PC5: 8, PB0: 9, PC0: 10, PC1: 11,
+        PC2: 12, PC3: 13, PA2: 14, PA3: 15, PA4: 18, PA5: 19,
+    ],
+    ADC3: [
+        PF9: 2, PF7: 3, PF5: 4, PF3: 5, PF10: 6, PF8: 7, PF6: 8, PF4: 9, PC0: 10, PC1: 11,
+        PC2: 12, PH2: 13, PH3: 14, PH4: 15, PH5: 16,
+    ],
+);
@@ -0,0 +1,72 @@
+//! Maintenance of the D-cache around DMA transfers
+//!
+//! Before a DMA transfer reads a buffer, the CPU's writes to it have to be cleaned from the
+//! D-cache to the memory. Before the CPU reads a buffer a DMA transfer wrote to, the stale
+//! lines of it have to be invalidated in the D-cache
+//!
+//! The maintenance works on whole cache lines of 32 bytes. Invalidating a line discards the
+//! pending writes to all of its bytes, so buffers that get invalidated have to be aligned
+//! to and padded to cache lines, e.g. by wrapping them in a `#[repr(align(32))]` struct
+
+use core::mem;
+
+use cortex_m::asm;
+use cortex_m::peripheral::CBP;
+
+/// The size of a D-cache line of the Cortex-M7
+pub const DCACHE_LINE_SIZE: usize = 32;
+
+/// Writes the cached data of `buf` to the memory, e.g. before a DMA transfer reads it
+///
+/// The lines `buf` shares with other data are cleaned as well, which has no effect but
+/// the time it takes
+pub fn clean_dcache_by_slice<T>(buf: &[T]) {
+    clean_dcache(buf.as_ptr() as usize, mem::size_of_val(buf));
+}
+
+/// Discards the cached data of `buf`, e.g. before the CPU reads the data a DMA transfer
+/// wrote to it
+///
+/// # Panics
+///
+/// Panics if `buf` doesn't start at and span whole cache lines, the other data in its
+/// lines would get lost
+pub fn invalidate_dcache_by_slice<T>(buf: &mut [T]) {
+    invalidate_dcache(buf.as_ptr() as usize, mem::size_of_val(buf));
+}
+
+pub(crate) fn clean_dcache(addr: usize, size: usize) {
+    if size == 0 {
+        return;
+    }
+
+    asm::dsb();
+    let mut line = addr & !(DCACHE_LINE_SIZE - 1);
+    while line < addr + size {
+        // NOTE(unsafe) the cache maintenance registers are stateless, cleaning a line
+        // doesn't change the data seen by the CPU
+        unsafe { (*CBP::ptr()).dccmvac.write(line as u32) };
+        line += DCACHE_LINE_SIZE;
+    }
+    asm::dsb();
+    asm::isb();
+}
+
+pub(crate) fn invalidate_dcache(addr: usize, size: usize) {
+    assert!(
+        addr.is_multiple_of(DCACHE_LINE_SIZE) && size.is_multiple_of(DCACHE_LINE_SIZE),
+        "the buffer has to be aligned to and padded to cache lines"
+    );
+    if size == 0 {
+        return;
+    }
+
+    asm::dsb();
+    for line in (addr..addr + size).step_by(DCACHE_LINE_SIZE) {
+        // NOTE(unsafe) the cache maintenance registers are stateless, the lines only hold
+        // data of the buffer
+        unsafe { (*CBP::ptr()).dcimvac.write(line as u32) };
+    }
+    asm::dsb();
+    asm::isb();
+}
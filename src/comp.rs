@@ -0,0 +1,314 @@
+//! Analog Comparators
+//!
+//! `CompExt::split` returns the two comparators COMP1 and COMP2, which are configured
+//! separately with `Comp::comp1` and `Comp::comp2`. The input pins have to be put into
+//! analog mode with `into_analog`
+//!
+//! The outputs can be driven onto PC5 or PE12 (COMP1) and PE8 or PE13 (COMP2) by putting
+//! the pins into AF13
+
+use stm32h7::stm32h7x3::{COMP1, EXTI};
+
+use crate::exti::{self, Edge};
+use crate::gpio::gpiob::{PB0, PB1, PB2};
+use crate::gpio::gpioc::PC4;
+use crate::gpio::gpioe::{PE10, PE11, PE7, PE9};
+use crate::gpio::{Analog, AF0};
+use crate::rcc::{Enable, Reset, APB4};
+
+/// Comparator 1
+pub struct C1 {
+    _0: (),
+}
+
+/// Comparator 2
+pub struct C2 {
+    _0: (),
+}
+
+/// Non-inverting input of a comparator - DO NOT IMPLEMENT THIS TRAIT
+///
+/// # Safety
+///
+/// Only the pins and signals that can be routed to the non-inverting input of `COMP`
+/// may implement this trait, `INPSEL` has to select them
+pub unsafe trait PlusInput<COMP> {
+    #[doc(hidden)]
+    const INPSEL: bool;
+}
+
+/// Inverting input of a comparator - DO NOT IMPLEMENT THIS TRAIT
+///
+/// # Safety
+///
+/// Only the pins and signals that can be routed to the inverting input of `COMP` may
+/// implement this trait, `INMSEL` has to select them
+pub unsafe trait MinusInput<COMP> {
+    #[doc(hidden)]
+    const INMSEL: u8;
+}
+
+unsafe impl PlusInput<C1> for PB0<Analog, AF0> {
+    const INPSEL: bool = false;
+}
+unsafe impl PlusInput<C1> for PB2<Analog, AF0> {
+    const INPSEL: bool = true;
+}
+unsafe impl PlusInput<C2> for PE9<Analog, AF0> {
+    const INPSEL: bool = false;
+}
+unsafe impl PlusInput<C2> for PE11<Analog, AF0> {
+    const INPSEL: bool = true;
+}
+
+unsafe impl MinusInput<C1> for PB1<Analog, AF0> {
+    const INMSEL: u8 = 0b110;
+}
+unsafe impl MinusInput<C1> for PC4<Analog, AF0> {
+    const INMSEL: u8 = 0b111;
+}
+unsafe impl MinusInput<C2> for PE10<Analog, AF0> {
+    const INMSEL: u8 = 0b110;
+}
+unsafe impl MinusInput<C2> for PE7<Analog, AF0> {
+    const INMSEL: u8 = 0b111;
+}
+
+/// A quarter of the internal reference voltage
+pub struct VrefintDiv4;
+/// Half of the internal reference voltage
+pub struct VrefintDiv2;
+/// Three quarters of the internal reference voltage
+pub struct Vrefint3Div4;
+/// The internal reference voltage
+pub struct Vrefint;
+/// Output of DAC channel 1
+pub struct DacOut1;
+/// Output of DAC channel 2
+pub struct DacOut2;
+
+macro_rules! internal_inputs {
+    ($($INPUT:ident: $inmsel:expr,)+) => {
+        $(
+            unsafe impl MinusInput<C1> for $INPUT {
+                const INMSEL: u8 = $inmsel;
+            }
+            unsafe impl MinusInput<C2> for $INPUT {
+                const INMSEL: u8 = $inmsel;
+            }
+        )+
+    };
+}
+
+internal_inputs!(
+    VrefintDiv4: 0b000,
+    VrefintDiv2: 0b001,
+    Vrefint3Div4: 0b010,
+    Vrefint: 0b011,
+    DacOut1: 0b100,
+    DacOut2: 0b101,
+);
+
+/// Hysteresis of the comparator
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Hysteresis {
+    /// No hysteresis
+    None = 0b00,
+    /// Low hysteresis
+    Low = 0b01,
+    /// Medium hysteresis
+    Medium = 0b10,
+    /// High hysteresis
+    High = 0b11,
+}
+
+/// Trade-off between the propagation delay and the power consumption
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum PowerMode {
+    /// The shortest propagation delay
+    HighSpeed = 0b00,
+    /// Medium speed and power consumption
+    MediumSpeed = 0b01,
+    /// The lowest power consumption
+    UltraLowPower = 0b11,
+}
+
+/// Timer output that blanks the comparator output, e.g. to hide the current spike after a
+/// power switch turns on
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Blanking {
+    /// The output is never blanked
+    None = 0b0000,
+    /// Output compare 5 of TIM1
+    Tim1Oc5 = 0b0001,
+    /// Output compare 3 of TIM2
+    Tim2Oc3 = 0b0010,
+    /// Output compare 3 of TIM3
+    Tim3Oc3 = 0b0011,
+    /// Output compare 4 of TIM3
+    Tim3Oc4 = 0b0100,
+    /// Output compare 5 of TIM8
+    Tim8Oc5 = 0b0101,
+    /// Output compare 1 of TIM15
+    Tim15Oc1 = 0b0110,
+}
+
+/// Configuration of a comparator
+///
+/// `default` creates the configuration of a non-inverted high speed comparator without
+/// hysteresis and blanking
+#[derive(Clone, Copy)]
+pub struct Config {
+    hysteresis: Hysteresis,
+    inverted: bool,
+    power_mode: PowerMode,
+    blanking: Blanking,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Config {
+            hysteresis: Hysteresis::None,
+            inverted: false,
+            power_mode: PowerMode::HighSpeed,
+            blanking: Blanking::None,
+        }
+    }
+}
+
+impl Config {
+    /// Sets the hysteresis
+    pub fn hysteresis(mut self, hysteresis: Hysteresis) -> Self {
+        self.hysteresis = hysteresis;
+        self
+    }
+
+    /// Inverts the output
+    pub fn inverted(mut self, inverted: bool) -> Self {
+        self.inverted = inverted;
+        self
+    }
+
+    /// Sets the power mode
+    pub fn power_mode(mut self, power_mode: PowerMode) -> Self {
+        self.power_mode = power_mode;
+        self
+    }
+
+    /// Sets the timer output that blanks the output
+    pub fn blanking(mut self, blanking: Blanking) -> Self {
+        self.blanking = blanking;
+        self
+    }
+}
+
+/// Extension trait to split the comparator block into its comparators
+pub trait CompExt {
+    /// Enables the comparator block and returns the comparators
+    fn split(self, apb4: &mut APB4) -> (C1, C2);
+}
+
+impl CompExt for COMP1 {
+    fn split(self, apb4: &mut APB4) -> (C1, C2) {
+        COMP1::enable(apb4);
+        COMP1::reset(apb4);
+
+        (C1 { _0: () }, C2 { _0: () })
+    }
+}
+
+/// A comparator along with its inputs
+pub struct Comp<COMP, PLUS, MINUS> {
+    comp: COMP,
+    plus: PLUS,
+    minus: MINUS,
+}
+
+macro_rules! hal {
+    ($($CX:ident: ($compX:ident, $cfgrx:ident, $cxval:ident, $line:expr),)+) => {
+        $(
+            impl<PLUS, MINUS> Comp<$CX, PLUS, MINUS>
+            where
+                PLUS: PlusInput<$CX>,
+                MINUS: MinusInput<$CX>,
+            {
+                /// Configures the comparator to compare `plus` with `minus`, the comparator
+                /// starts disabled
+                pub fn $compX(comp: $CX, plus: PLUS, minus: MINUS, config: Config) -> Self {
+                    // the internal reference voltage is fed through the scaler, the bridge
+                    // divides it
+                    let scalen = MINUS::INMSEL <= 0b011;
+                    let brgen = MINUS::INMSEL <= 0b010;
+
+                    // NOTE(unsafe) the configuration register belongs to this comparator
+                    unsafe {
+                        (*COMP1::ptr()).$cfgrx.write(|w| {
+                            w.inpsel().bit(PLUS::INPSEL)
+                                .inmsel().bits(MINUS::INMSEL)
+                                .scalen().bit(scalen)
+                                .brgen().bit(brgen)
+                                .hyst().bits(config.hysteresis as u8)
+                                .polarity().bit(config.inverted)
+                                .pwrmode().bits(config.power_mode as u8)
+                                .blanking().bits(config.blanking as u8)
+                        });
+                    }
+
+                    Comp { comp, plus, minus }
+                }
+
+                /// Enables the comparator
+                pub fn enable(&mut self) {
+                    // NOTE(unsafe) the configuration register belongs to this comparator
+                    unsafe { (*COMP1::ptr()).$cfgrx.modify(|_, w| w.en().set_bit()) };
+                }
+
+                /// Disables the comparator
+                pub fn disable(&mut self) {
+                    unsafe { (*COMP1::ptr()).$cfgrx.modify(|_, w| w.en().clear_bit()) };
+                }
+
+                /// The output level, high if the non-inverting input is above the inverting
+                /// one (unless the output is inverted)
+                pub fn output(&self) -> bool {
+                    // NOTE(unsafe) atomic read with no side effects
+                    unsafe { (*COMP1::ptr()).comp1_sr.read().$cxval().bit_is_set() }
+                }
+
+                /// Triggers the COMP interrupt on `edge` of the output through its EXTI line
+                pub fn listen(&mut self, edge: Edge, exti: &mut EXTI) {
+                    exti::trigger_on_edge(exti, $line, edge);
+                    exti::enable_interrupt(exti, $line);
+                }
+
+                /// Masks the EXTI line of the output
+                pub fn unlisten(&mut self, exti: &mut EXTI) {
+                    exti::disable_interrupt(exti, $line);
+                }
+
+                /// Clears the pending bit of the EXTI line, this has to be done in the
+                /// interrupt handler
+                pub fn clear_interrupt_pending_bit(&mut self) {
+                    exti::clear_interrupt_pending_bit($line);
+                }
+
+                /// Whether the interrupt of the EXTI line is pending
+                pub fn check_interrupt(&self) -> bool {
+                    exti::check_interrupt($line)
+                }
+
+                /// Disables the comparator and releases it and the inputs
+                pub fn free(mut self) -> ($CX, PLUS, MINUS) {
+                    self.disable();
+                    (self.comp, self.plus, self.minus)
+                }
+            }
+        )+
+    };
+}
+
+hal!(
+    C1: (comp1, comp1_cfgr1, c1val, 20),
+    C2: (comp2, comp1_cfgr2, c2val, 21),
+);
+
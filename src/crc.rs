@@ -0,0 +1,174 @@
+//! Cyclic Redundancy Check calculation unit
+
+use core::hash::Hasher;
+use core::ptr;
+
+use stm32h7::stm32h7x3::CRC;
+
+use crate::rcc::{Enable, Reset, AHB4};
+
+/// Size of the polynomial
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum PolySize {
+    /// 7 bit
+    Bits7 = 0b11,
+    /// 8 bit
+    Bits8 = 0b10,
+    /// 16 bit
+    Bits16 = 0b01,
+    /// 32 bit
+    Bits32 = 0b00,
+}
+
+/// Bit reversal of the input data
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum InputReversal {
+    /// No reversal
+    None = 0b00,
+    /// The bits of each byte are reversed
+    Byte = 0b01,
+    /// The bits of each half word are reversed
+    HalfWord = 0b10,
+    /// The bits of each word are reversed
+    Word = 0b11,
+}
+
+/// Parameters of a CRC
+#[derive(Clone, Copy, Debug)]
+pub struct Config {
+    poly: u32,
+    size: PolySize,
+    init: u32,
+    rev_in: InputReversal,
+    rev_out: bool,
+    xor_out: u32,
+}
+
+impl Config {
+    /// A CRC with the polynomial `poly` of `size` bits, the initial value is all ones and
+    /// the data and the result are not reversed
+    ///
+    /// The polynomial is given without its highest bit, e.g. 0x1021 for x^16 + x^12 + x^5 + 1
+    pub fn new(poly: u32, size: PolySize) -> Self {
+        Config {
+            poly,
+            size,
+            init: 0xFFFF_FFFF,
+            rev_in: InputReversal::None,
+            rev_out: false,
+            xor_out: 0,
+        }
+    }
+
+    /// CRC-32 as used by Ethernet and zlib
+    pub fn crc32() -> Self {
+        Config::new(0x04C1_1DB7, PolySize::Bits32)
+            .reverse_input(InputReversal::Byte)
+            .reverse_output(true)
+            .xor_output(0xFFFF_FFFF)
+    }
+
+    /// CRC-16/CCITT-FALSE, the CCITT polynomial with an initial value of 0xFFFF
+    pub fn crc16_ccitt() -> Self {
+        Config::new(0x1021, PolySize::Bits16).initial_value(0xFFFF)
+    }
+
+    /// Sets the value the calculation starts with
+    pub fn initial_value(mut self, init: u32) -> Self {
+        self.init = init;
+        self
+    }
+
+    /// Sets the bit reversal of the input data
+    pub fn reverse_input(mut self, rev_in: InputReversal) -> Self {
+        self.rev_in = rev_in;
+        self
+    }
+
+    /// Reverses the bits of the result
+    pub fn reverse_output(mut self, rev_out: bool) -> Self {
+        self.rev_out = rev_out;
+        self
+    }
+
+    /// Sets a value the result is XORed with, this is done in software
+    pub fn xor_output(mut self, xor_out: u32) -> Self {
+        self.xor_out = xor_out;
+        self
+    }
+}
+
+/// CRC calculation unit
+pub struct Crc {
+    crc: CRC,
+    xor_out: u32,
+    mask: u32,
+}
+
+impl Crc {
+    /// Enables the CRC unit and starts a calculation with `config`
+    pub fn new(crc: CRC, config: Config, ahb4: &mut AHB4) -> Self {
+        CRC::enable(ahb4);
+        CRC::reset(ahb4);
+
+        let mut crc = Crc { crc, xor_out: 0, mask: 0 };
+        crc.set_config(config);
+        crc
+    }
+
+    /// Changes the parameters and starts a new calculation
+    pub fn set_config(&mut self, config: Config) {
+        self.xor_out = config.xor_out;
+        self.mask = match config.size {
+            PolySize::Bits7 => 0x7F,
+            PolySize::Bits8 => 0xFF,
+            PolySize::Bits16 => 0xFFFF,
+            PolySize::Bits32 => 0xFFFF_FFFF,
+        };
+
+        self.crc.pol.write(|w| unsafe { w.pol().bits(config.poly) });
+        self.crc.init.write(|w| unsafe { w.crc_init().bits(config.init) });
+        self.crc.cr.write(|w| unsafe {
+            w.polysize()
+                .bits(config.size as u8)
+                .rev_in()
+                .bits(config.rev_in as u8)
+                .rev_out()
+                .bit(config.rev_out)
+        });
+        self.reset();
+    }
+
+    /// Starts a new calculation from the initial value
+    pub fn reset(&mut self) {
+        self.crc.cr.modify(|_, w| w.reset().set_bit());
+    }
+
+    /// Adds `data` to the calculation
+    pub fn feed(&mut self, data: &[u8]) {
+        for byte in data {
+            // NOTE(write_volatile) 8-bit write that's not possible through the svd2rust API
+            unsafe { ptr::write_volatile(ptr::addr_of!(self.crc.dr) as *mut u8, *byte) };
+        }
+    }
+
+    /// The CRC of the data fed since the last reset
+    pub fn result(&self) -> u32 {
+        (self.crc.dr.read().bits() ^ self.xor_out) & self.mask
+    }
+
+    /// Releases the CRC unit
+    pub fn free(self) -> CRC {
+        self.crc
+    }
+}
+
+impl Hasher for Crc {
+    fn finish(&self) -> u64 {
+        u64::from(self.result())
+    }
+
+    fn write(&mut self, bytes: &[u8]) {
+        self.feed(bytes);
+    }
+}
@@ -0,0 +1,111 @@
+//! Clock Recovery System
+//!
+//! The CRS trims the HSI48 against a precise synchronization signal, e.g. the start of frame
+//! packets the USB host sends every millisecond, so USB can run without a crystal. The HSI48
+//! has to be enabled with `CFGR::hsi48`
+
+use stm32h7::stm32h7x3::CRS;
+
+use crate::rcc::{Clocks, Enable, Reset, APB1H};
+
+/// The frequency of the HSI48 the CRS trims for
+const TARGET_FREQUENCY: u32 = 48_000_000;
+
+/// The trimming step of the HSI48 in 1/10000 of its frequency
+const TRIM_STEP: u32 = 14;
+
+/// Synchronization signal the HSI48 is trimmed against
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum SyncSource {
+    /// The start of frame packets of the USB, every 1 ms
+    UsbSof,
+    /// The LSE, which has to be enabled with `CFGR::lse`
+    Lse,
+}
+
+/// CRS error
+#[derive(Debug, PartialEq)]
+#[non_exhaustive]
+pub enum Error {
+    /// No synchronization event happened within the expected time
+    SyncMissed,
+    /// The frequency error was too large to be trimmed
+    SyncError,
+    /// The trimming value reached its limit
+    TrimOverflow,
+}
+
+/// Clock recovery system
+pub struct Crs {
+    crs: CRS,
+}
+
+impl Crs {
+    /// Starts trimming the HSI48 automatically against `sync`
+    ///
+    /// # Panics
+    ///
+    /// Panics if the HSI48 or, when it is the synchronization signal, the LSE is disabled
+    pub fn new(crs: CRS, sync: SyncSource, apb1h: &mut APB1H, clocks: &Clocks) -> Self {
+        assert!(clocks.hsi48_ck().is_some(), "the HSI48 is disabled");
+        let (syncsrc, sync_frequency) = match sync {
+            SyncSource::UsbSof => (0b10, 1_000),
+            SyncSource::Lse => (0b01, clocks.lse_ck().expect("the LSE is disabled").0),
+        };
+
+        CRS::enable(apb1h);
+        CRS::reset(apb1h);
+
+        // RELOAD counts the HSI48 cycles of one synchronization period, FELIM tolerates
+        // half a trimming step of frequency error
+        let cycles = (TARGET_FREQUENCY + sync_frequency / 2) / sync_frequency;
+        let felim = (cycles * TRIM_STEP).div_ceil(20_000);
+
+        // CFGR can only be written while the counter is disabled
+        crs.crs_cfgr.modify(|_, w| unsafe {
+            w.syncsrc().bits(syncsrc)
+                .syncdiv().bits(0)
+                .reload().bits(cycles as u16 - 1)
+                .felim().bits(felim as u8)
+        });
+        crs.crs_cr.modify(|_, w| w.autotrimen().set_bit().cen().set_bit());
+
+        Crs { crs }
+    }
+
+    /// Whether the last synchronization event found the HSI48 within the tolerated
+    /// frequency error
+    pub fn is_synced(&self) -> bool {
+        let isr = self.crs.crs_isr.read();
+        isr.syncokf().bit_is_set() && isr.errf().bit_is_clear()
+    }
+
+    /// Returns and clears an error of the synchronization
+    pub fn check_error(&mut self) -> Result<(), Error> {
+        let isr = self.crs.crs_isr.read();
+        if isr.errf().bit_is_clear() {
+            return Ok(());
+        }
+
+        // clears all the error flags
+        self.crs.crs_icr.write(|w| w.errc().set_bit());
+        if isr.syncmiss().bit_is_set() {
+            Err(Error::SyncMissed)
+        } else if isr.trimovf().bit_is_set() {
+            Err(Error::TrimOverflow)
+        } else {
+            Err(Error::SyncError)
+        }
+    }
+
+    /// The current trimming value of the HSI48, the center is 32
+    pub fn trim(&self) -> u8 {
+        self.crs.crs_cr.read().trim().bits()
+    }
+
+    /// Stops trimming the HSI48 and releases the CRS
+    pub fn free(self) -> CRS {
+        self.crs.crs_cr.modify(|_, w| w.cen().clear_bit().autotrimen().clear_bit());
+        self.crs
+    }
+}
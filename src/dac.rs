@@ -0,0 +1,389 @@
+//! Digital to Analog Converter
+//!
+//! The outputs of the channels are PA4 (channel 1) and PA5 (channel 2), the pins have to
+//! be put into analog mode with `into_analog` so that their digital input stage doesn't
+//! load the output
+
+use cortex_m::interrupt;
+use hal::timer::CountDown;
+use stm32h7::stm32h7x3::{DAC, TIM1, TIM2, TIM4, TIM5, TIM6, TIM7, TIM8, TIM15};
+
+use crate::dma::{Config as DmaConfig, PeripheralAddress, Stream, Transfer};
+use crate::gpio::{Analog, AF0};
+use crate::gpio::gpioa::{PA4, PA5};
+use crate::rcc::{Enable, Reset, APB1L};
+use crate::time::Hertz;
+use crate::timer::Timer;
+
+/// Channel 1, output on PA4
+pub struct C1 {
+    alignment: Alignment,
+}
+
+/// Channel 2, output on PA5
+pub struct C2 {
+    alignment: Alignment,
+}
+
+/// Format of the values written with `set_value`
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Alignment {
+    /// 12 bit in the lowest bits
+    Right12,
+    /// 12 bit in the highest bits
+    Left12,
+    /// 8 bit in the lowest bits
+    Right8,
+}
+
+/// Source of the trigger that moves a new value to the output
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Trigger {
+    /// The `trigger` method of the channel
+    Software = 0,
+    /// TRGO of TIM1
+    Tim1 = 1,
+    /// TRGO of TIM2
+    Tim2 = 2,
+    /// TRGO of TIM4
+    Tim4 = 3,
+    /// TRGO of TIM5
+    Tim5 = 4,
+    /// TRGO of TIM6
+    Tim6 = 5,
+    /// TRGO of TIM7
+    Tim7 = 6,
+    /// TRGO of TIM8
+    Tim8 = 7,
+    /// TRGO of TIM15
+    Tim15 = 8,
+    /// DAC trigger 1 of the HRTIM
+    HrtimTrg1 = 9,
+    /// DAC trigger 2 of the HRTIM
+    HrtimTrg2 = 10,
+    /// Output of LPTIM1
+    Lptim1 = 11,
+    /// Output of LPTIM2
+    Lptim2 = 12,
+    /// EXTI line 9
+    Exti9 = 13,
+}
+
+/// Waveform added to the last value written with `set_value`, the waveform advances on
+/// every trigger
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum WaveMode {
+    /// Pseudo noise of an LFSR, `mask` is the number of unmasked bits of the LFSR minus 1
+    /// (0 to 11)
+    Noise {
+        /// The number of unmasked bits minus 1
+        mask: u8,
+    },
+    /// A triangle with a peak of 2 ^ (`amplitude` + 1) - 1 (`amplitude` from 0 to 11)
+    Triangle {
+        /// The exponent of the peak
+        amplitude: u8,
+    },
+}
+
+/// A timer that can trigger the DAC - DO NOT IMPLEMENT THIS TRAIT
+///
+/// # Safety
+///
+/// `TRIGGER` has to select the TRGO of the timer that `trgo_on_update` configures
+pub unsafe trait TriggerTimer: CountDown<Time = Hertz> {
+    #[doc(hidden)]
+    const TRIGGER: Trigger;
+
+    #[doc(hidden)]
+    fn trgo_on_update(&mut self);
+}
+
+macro_rules! trigger_timers {
+    ($($TIMX:ident: $Trigger:ident,)+) => {
+        $(
+            unsafe impl TriggerTimer for Timer<$TIMX> {
+                const TRIGGER: Trigger = Trigger::$Trigger;
+
+                fn trgo_on_update(&mut self) {
+                    // NOTE(unsafe) the timer is owned by `self`
+                    // MMS = 010: the update event is output on TRGO
+                    unsafe { (*$TIMX::ptr()).cr2.modify(|_, w| w.mms().bits(0b010)) };
+                }
+            }
+        )+
+    };
+}
+
+trigger_timers!(
+    TIM1: Tim1,
+    TIM2: Tim2,
+    TIM4: Tim4,
+    TIM5: Tim5,
+    TIM6: Tim6,
+    TIM7: Tim7,
+    TIM8: Tim8,
+    TIM15: Tim15,
+);
+
+/// A pin or both pins of the DAC - DO NOT IMPLEMENT THIS TRAIT
+///
+/// # Safety
+///
+/// Only the pins of the DAC outputs in analog mode may implement this trait, `channels`
+/// hands out the channels of exactly these pins
+pub unsafe trait Pins<DAC> {
+    /// The channels of the pins
+    type Channels;
+
+    #[doc(hidden)]
+    fn channels() -> Self::Channels;
+}
+
+unsafe impl Pins<DAC> for PA4<Analog, AF0> {
+    type Channels = C1;
+
+    fn channels() -> C1 {
+        C1 { alignment: Alignment::Right12 }
+    }
+}
+
+unsafe impl Pins<DAC> for PA5<Analog, AF0> {
+    type Channels = C2;
+
+    fn channels() -> C2 {
+        C2 { alignment: Alignment::Right12 }
+    }
+}
+
+unsafe impl Pins<DAC> for (PA4<Analog, AF0>, PA5<Analog, AF0>) {
+    type Channels = (C1, C2);
+
+    fn channels() -> (C1, C2) {
+        (C1 { alignment: Alignment::Right12 }, C2 { alignment: Alignment::Right12 })
+    }
+}
+
+/// Extension trait to split the DAC into its channels
+pub trait DacExt {
+    /// Enables the DAC and returns the channels of `pins`, the channels start disabled with
+    /// the output buffer enabled and without a trigger
+    fn dac<PINS: Pins<DAC>>(self, pins: PINS, apb1: &mut APB1L) -> PINS::Channels;
+}
+
+impl DacExt for DAC {
+    fn dac<PINS: Pins<DAC>>(self, _pins: PINS, apb1: &mut APB1L) -> PINS::Channels {
+        DAC::enable(apb1);
+        DAC::reset(apb1);
+
+        PINS::channels()
+    }
+}
+
+macro_rules! channels {
+    ($($CX:ident: (
+        $enx:ident,
+        $tenx:ident,
+        $tselx:ident,
+        $modex:ident,
+        $dhr12rx:ident,
+        $dhr12lx:ident,
+        $dhr8rx:ident,
+        $dorx:ident,
+        $swtrigx:ident,
+        $wavex:ident,
+        $mampx:ident,
+        $dmaenx:ident
+    ),)+) => {
+        $(
+            impl $CX {
+                /// Enables the output
+                pub fn enable(&mut self) {
+                    // NOTE(unsafe) CR is shared with the other channel, the critical section
+                    // makes the read-modify-write atomic
+                    interrupt::free(|_| unsafe {
+                        (*DAC::ptr()).dac_cr.modify(|_, w| w.$enx().set_bit())
+                    });
+                }
+
+                /// Disables the output
+                pub fn disable(&mut self) {
+                    interrupt::free(|_| unsafe {
+                        (*DAC::ptr()).dac_cr.modify(|_, w| w.$enx().clear_bit())
+                    });
+                }
+
+                /// Whether the output is enabled
+                pub fn is_enabled(&self) -> bool {
+                    // NOTE(unsafe) atomic read with no side effects
+                    unsafe { (*DAC::ptr()).dac_cr.read().$enx().bit_is_set() }
+                }
+
+                /// Sets the format of the values written with `set_value`
+                pub fn set_alignment(&mut self, alignment: Alignment) {
+                    self.alignment = alignment;
+                }
+
+                /// Sets the next output value, it is output immediately without a trigger
+                /// and on the next trigger otherwise
+                pub fn set_value(&mut self, value: u16) {
+                    // NOTE(unsafe) the data holding registers belong to this channel
+                    let dac = unsafe { &*DAC::ptr() };
+                    match self.alignment {
+                        Alignment::Right12 => {
+                            dac.$dhr12rx.write(|w| unsafe { w.bits(u32::from(value & 0x0FFF)) })
+                        }
+                        Alignment::Left12 => {
+                            dac.$dhr12lx.write(|w| unsafe { w.bits(u32::from(value & 0xFFF0)) })
+                        }
+                        Alignment::Right8 => {
+                            dac.$dhr8rx.write(|w| unsafe { w.bits(u32::from(value & 0x00FF)) })
+                        }
+                    }
+                }
+
+                /// The current output value, 12 bit right aligned
+                pub fn get_value(&self) -> u16 {
+                    // NOTE(unsafe) atomic read with no side effects
+                    unsafe { (*DAC::ptr()).$dorx.read().bits() as u16 }
+                }
+
+                /// Enables or disables the output buffer, the buffer drives low impedance
+                /// loads but can't reach the supply rails
+                pub fn set_output_buffer(&mut self, on: bool) {
+                    // MODE = 000: buffer enabled, MODE = 010: buffer disabled, the mode can
+                    // only be written while the channel is disabled
+                    self.while_disabled(|dac| {
+                        let mode = if on { 0b000 } else { 0b010 };
+                        dac.dac_mcr.modify(|_, w| unsafe { w.$modex().bits(mode) })
+                    });
+                }
+
+                /// Updates the output only on `trigger` instead of immediately after
+                /// `set_value`, e.g. on the update event of a timer to pace the updates
+                ///
+                /// The master mode of the timer has to output its update events on TRGO
+                pub fn set_trigger(&mut self, trigger: Trigger) {
+                    self.while_disabled(|dac| {
+                        dac.dac_cr.modify(|_, w| unsafe {
+                            w.$tenx().set_bit().$tselx().bits(trigger as u8)
+                        })
+                    });
+                }
+
+                /// Updates the output immediately after `set_value` again
+                pub fn disable_trigger(&mut self) {
+                    self.while_disabled(|dac| dac.dac_cr.modify(|_, w| w.$tenx().clear_bit()));
+                }
+
+                /// Moves the last value to the output if the trigger is `Trigger::Software`
+                pub fn trigger(&mut self) {
+                    // NOTE(unsafe) the bit of the other channel is written as 0, which has no
+                    // effect
+                    unsafe { (*DAC::ptr()).dac_swtrgr.write(|w| w.$swtrigx().set_bit()) };
+                }
+
+                /// Triggers the channel with `timer` at `freq`, the timer is started
+                pub fn set_frequency<TIM, F>(&mut self, timer: &mut TIM, freq: F)
+                where
+                    TIM: TriggerTimer,
+                    F: Into<Hertz>,
+                {
+                    self.set_trigger(TIM::TRIGGER);
+                    timer.trgo_on_update();
+                    timer.start(freq);
+                }
+
+                /// Adds a waveform to the output, a trigger has to be set to advance it
+                pub fn enable_wave(&mut self, wave: WaveMode) {
+                    let (mode, mamp) = match wave {
+                        WaveMode::Noise { mask } => (0b01, mask),
+                        WaveMode::Triangle { amplitude } => (0b10, amplitude),
+                    };
+                    assert!(mamp <= 11, "the amplitude and the mask range from 0 to 11");
+
+                    self.while_disabled(|dac| {
+                        dac.dac_cr.modify(|_, w| unsafe { w.$wavex().bits(mode).$mampx().bits(mamp) })
+                    });
+                }
+
+                /// Stops adding a waveform to the output
+                pub fn disable_wave(&mut self) {
+                    self.while_disabled(|dac| {
+                        dac.dac_cr.modify(|_, w| unsafe { w.$wavex().bits(0b00) })
+                    });
+                }
+
+                /// Plays `buffer` in a loop with a DMA stream, one value per trigger, e.g. of a
+                /// timer set with `set_frequency`
+                ///
+                /// The values have the format of the alignment of the channel
+                pub fn start_dma<S: Stream>(
+                    self,
+                    stream: S,
+                    buffer: &'static [u16],
+                ) -> Transfer<S, Self, &'static [u16]> {
+                    let config = DmaConfig::default().circular(true);
+                    let transfer = Transfer::memory_to_peripheral(stream, self, buffer, config);
+
+                    // the stream has to be enabled before the first request
+                    interrupt::free(|_| unsafe {
+                        (*DAC::ptr()).dac_cr.modify(|_, w| w.$dmaenx().set_bit())
+                    });
+
+                    transfer
+                }
+
+                /// Stops requesting DMA transfers, e.g. after the transfer of `start_dma`
+                /// was released
+                pub fn disable_dma(&mut self) {
+                    interrupt::free(|_| unsafe {
+                        (*DAC::ptr()).dac_cr.modify(|_, w| w.$dmaenx().clear_bit())
+                    });
+                }
+
+                /// Runs `f` with the channel disabled and enables it again if it was enabled
+                fn while_disabled<F>(&mut self, f: F)
+                where
+                    F: FnOnce(&stm32h7::stm32h7x3::dac::RegisterBlock),
+                {
+                    interrupt::free(|_| {
+                        // NOTE(unsafe) the fields of the other channel are not changed
+                        let dac = unsafe { &*DAC::ptr() };
+                        let enabled = dac.dac_cr.read().$enx().bit_is_set();
+                        dac.dac_cr.modify(|_, w| w.$enx().clear_bit());
+                        f(dac);
+                        if enabled {
+                            dac.dac_cr.modify(|_, w| w.$enx().set_bit());
+                        }
+                    });
+                }
+            }
+
+            unsafe impl PeripheralAddress for $CX {
+                type Word = u16;
+
+                fn address(&self) -> u32 {
+                    // NOTE(unsafe) only the address of the data holding register is taken
+                    let dac = unsafe { &*DAC::ptr() };
+                    match self.alignment {
+                        Alignment::Right12 => &dac.$dhr12rx as *const _ as u32,
+                        Alignment::Left12 => &dac.$dhr12lx as *const _ as u32,
+                        Alignment::Right8 => &dac.$dhr8rx as *const _ as u32,
+                    }
+                }
+            }
+        )+
+    };
+}
+
+channels!(
+    C1: (
+        en1, ten1, tsel1, mode1, dac_dhr12r1, dac_dhr12l1, dac_dhr8r1, dac_dor1, swtrig1, wave1,
+        mamp1, dmaen1
+    ),
+    C2: (
+        en2, ten2, tsel2, mode2, dac_dhr12r2, dac_dhr12l2, dac_dhr8r2, dac_dor2, swtrig2, wave2,
+        mamp2, dmaen2
+    ),
+);
@@ -25,11 +25,31 @@ impl Delay {
     pub fn free(self) -> SYST {
         self.syst
     }
+
+    /// Waits for the given number of sys_ck cycles, delays longer than the
+    /// 24 bit reload value of the SysTick are split into multiple runs
+    fn delay_ticks(&mut self, mut ticks: u64) {
+        const MAX_RVR: u64 = (1 << 24) - 1;
+
+        while ticks != 0 {
+            let rvr = if ticks > MAX_RVR { MAX_RVR } else { ticks };
+            ticks -= rvr;
+
+            self.syst.set_reload(rvr as u32);
+            self.syst.clear_current();
+            self.syst.enable_counter();
+
+            while !self.syst.has_wrapped() {}
+
+            self.syst.disable_counter();
+        }
+    }
 }
 
 impl DelayMs<u32> for Delay {
     fn delay_ms(&mut self, ms: u32) {
-        self.delay_us(ms * 1_000);
+        let ticks = u64::from(ms) * u64::from(self.clocks.sys_ck().0) / 1_000;
+        self.delay_ticks(ticks);
     }
 }
 
@@ -47,17 +67,8 @@ impl DelayMs<u8> for Delay {
 
 impl DelayUs<u32> for Delay {
     fn delay_us(&mut self, us: u32) {
-        let rvr = us * (self.clocks.sys_ck().0 / 1_000_000);
-
-        assert!(rvr < (1 << 24));
-
-        self.syst.set_reload(rvr);
-        self.syst.clear_current();
-        self.syst.enable_counter();
-
-        while !self.syst.has_wrapped() {}
-
-        self.syst.disable_counter();
+        let ticks = u64::from(us) * u64::from(self.clocks.sys_ck().0) / 1_000_000;
+        self.delay_ticks(ticks);
     }
 }
 
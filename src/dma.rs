@@ -0,0 +1,638 @@
+//! Direct Memory Access
+//!
+//! The streams of DMA1 and DMA2 move data between memory and peripherals without the
+//! CPU, each stream is connected to a peripheral request through DMAMUX1
+//!
+//! DMA1 and DMA2 can not access the DTCM, the buffers have to be placed in the AXI SRAM
+//! or in SRAM1 to SRAM4. These SRAMs are cacheable, the buffers either have to be placed in
+//! a region made non-cacheable with `mpu::Mpu::configure_dma_region` or the D-cache has to
+//! be maintained around the transfers, e.g. with `Config::dcache_maintenance`
+//!
+//! The peripherals of the D3 domain are served by the channels of the BDMA, see the `bdma`
+//! module
+
+use core::marker::PhantomData;
+use core::mem;
+use core::ptr;
+use core::sync::atomic::{self, Ordering};
+
+use stm32h7::stm32h7x3::{DMA1, DMA2, DMAMUX1};
+
+use crate::cache;
+use crate::rcc::{Enable, Reset, AHB1};
+
+use self::dmamux::{DmaRequest, RequestId};
+
+pub mod bdma;
+pub mod dmamux;
+
+/// DMA error
+#[derive(Debug, PartialEq)]
+#[non_exhaustive]
+pub enum Error {
+    /// A bus error occurred, e.g. because a buffer is placed in memory the DMA can not access
+    Transfer,
+    /// A request occurred while the previous data was not transferred yet
+    DirectMode,
+}
+
+/// Extension trait to split a DMA controller into independent streams
+pub trait DmaExt {
+    /// The streams to split the DMA controller into
+    type Streams;
+
+    /// Enables the DMA controller and splits it into its streams
+    fn split(self, ahb1: &mut AHB1) -> Self::Streams;
+}
+
+/// Priority of a stream, used to arbitrate between the requests of the streams
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Priority {
+    /// Low
+    Low = 0,
+    /// Medium
+    Medium = 1,
+    /// High
+    High = 2,
+    /// Very high
+    VeryHigh = 3,
+}
+
+/// Configuration of a transfer
+///
+/// The default is a one-shot transfer of low priority without interrupts
+#[derive(Clone, Copy)]
+pub struct Config {
+    priority: Priority,
+    circular: bool,
+    transfer_complete_interrupt: bool,
+    half_transfer_interrupt: bool,
+    transfer_error_interrupt: bool,
+    dcache_maintenance: bool,
+}
+
+impl Config {
+    /// Sets the priority of the stream
+    pub fn priority(mut self, priority: Priority) -> Self {
+        self.priority = priority;
+        self
+    }
+
+    /// Restarts the transfer from the beginning of the buffer when it completes, this is
+    /// ignored for memory to memory transfers
+    pub fn circular(mut self, circular: bool) -> Self {
+        self.circular = circular;
+        self
+    }
+
+    /// Enables the interrupt at the end of the transfer
+    pub fn transfer_complete_interrupt(mut self, on: bool) -> Self {
+        self.transfer_complete_interrupt = on;
+        self
+    }
+
+    /// Enables the interrupt once half of the transfer is done
+    pub fn half_transfer_interrupt(mut self, on: bool) -> Self {
+        self.half_transfer_interrupt = on;
+        self
+    }
+
+    /// Enables the interrupt on a transfer error
+    pub fn transfer_error_interrupt(mut self, on: bool) -> Self {
+        self.transfer_error_interrupt = on;
+        self
+    }
+
+    /// Cleans the buffers the transfer reads from the D-cache before it starts, and
+    /// invalidates the buffers it writes to before and after it, when it's released with
+    /// `free`
+    ///
+    /// The buffers the transfer writes to have to be aligned to and padded to cache lines,
+    /// see the `cache` module
+    pub fn dcache_maintenance(mut self, on: bool) -> Self {
+        self.dcache_maintenance = on;
+        self
+    }
+}
+
+impl Default for Config {
+    fn default() -> Config {
+        Config {
+            priority: Priority::Low,
+            circular: false,
+            transfer_complete_interrupt: false,
+            half_transfer_interrupt: false,
+            transfer_error_interrupt: false,
+            dcache_maintenance: false,
+        }
+    }
+}
+
+/// Size of the data items of a transfer - DO NOT IMPLEMENT THIS TRAIT
+///
+/// # Safety
+///
+/// `SIZE` has to be the PSIZE and MSIZE encoding of the size of the type, otherwise
+/// the stream reads or writes past the items of a buffer
+pub unsafe trait Word: Copy {
+    #[doc(hidden)]
+    const SIZE: u32;
+}
+
+unsafe impl Word for u8 {
+    const SIZE: u32 = 0b00;
+}
+
+unsafe impl Word for u16 {
+    const SIZE: u32 = 0b01;
+}
+
+unsafe impl Word for u32 {
+    const SIZE: u32 = 0b10;
+}
+
+/// The data register of a peripheral a stream can transfer to or from
+///
+/// # Safety
+///
+/// Implementing this trait for a wrong address results in undefined behavior
+pub unsafe trait PeripheralAddress {
+    /// The size of the data register
+    type Word: Word;
+
+    /// The address of the data register
+    fn address(&self) -> u32;
+}
+
+/// A stream of a DMA controller - DO NOT IMPLEMENT THIS TRAIT
+///
+/// # Safety
+///
+/// `NUMBER`, `MUX_CHANNEL` and `dma` have to identify a stream that is owned by the
+/// implementor, the stream registers and the DMAMUX1 channel are accessed without
+/// synchronization
+pub unsafe trait Stream {
+    #[doc(hidden)]
+    const NUMBER: usize;
+    #[doc(hidden)]
+    const MUX_CHANNEL: usize;
+
+    #[doc(hidden)]
+    fn dma() -> usize;
+
+    /// Connects the stream to the request line of `request`
+    fn select_request<R: DmaRequest>(&mut self, _request: R) {
+        self.set_request(R::REQUEST);
+    }
+
+    /// Connects the stream to the request line `request` of DMAMUX1, e.g. to a request
+    /// generator
+    fn set_request(&mut self, request: RequestId) {
+        // NOTE(unsafe) each stream has its own DMAMUX1 channel
+        unsafe {
+            let cxcr = (DMAMUX1::ptr() as usize + 4 * Self::MUX_CHANNEL) as *mut u32;
+            let value = ptr::read_volatile(cxcr);
+            ptr::write_volatile(cxcr, (value & !0xFF) | request as u32);
+        }
+    }
+}
+
+// offsets of the registers of a stream
+const CR: usize = 0x00;
+const NDTR: usize = 0x04;
+const PAR: usize = 0x08;
+const M0AR: usize = 0x0C;
+const M1AR: usize = 0x10;
+const FCR: usize = 0x14;
+
+// flags in LISR / HISR and LIFCR / HIFCR
+const FEIF: u32 = 1 << 0;
+const DMEIF: u32 = 1 << 2;
+const TEIF: u32 = 1 << 3;
+const HTIF: u32 = 1 << 4;
+const TCIF: u32 = 1 << 5;
+const ALL_FLAGS: u32 = FEIF | DMEIF | TEIF | HTIF | TCIF;
+
+/// A register of the stream `S`
+fn register<S: Stream>(offset: usize) -> *mut u32 {
+    (S::dma() + 0x10 + 0x18 * S::NUMBER + offset) as *mut u32
+}
+
+/// The position of the flags of the stream `S` in its ISR / IFCR
+fn flag_shift<S: Stream>() -> u32 {
+    [0, 6, 16, 22][S::NUMBER % 4]
+}
+
+/// The flags of the stream `S`, shifted down to bit 0
+fn flags<S: Stream>() -> u32 {
+    let isr = (S::dma() + if S::NUMBER < 4 { 0x0 } else { 0x4 }) as *const u32;
+    // NOTE(unsafe) atomic read with no side effects
+    unsafe { ptr::read_volatile(isr) >> flag_shift::<S>() }
+}
+
+/// Clears the `flags` of the stream `S`
+fn clear_flags<S: Stream>(flags: u32) {
+    let ifcr = (S::dma() + if S::NUMBER < 4 { 0x8 } else { 0xC }) as *mut u32;
+    // NOTE(unsafe) write 1 to clear register, the flags of the other streams are not affected
+    unsafe { ptr::write_volatile(ifcr, flags << flag_shift::<S>()) }
+}
+
+/// Disables the stream `S` and waits until an ongoing transfer stopped
+fn disable<S: Stream>() {
+    // NOTE(unsafe) the registers of the stream are owned by the stream
+    unsafe {
+        let cr = register::<S>(CR);
+        ptr::write_volatile(cr, ptr::read_volatile(cr) & !1);
+        while ptr::read_volatile(cr) & 1 != 0 {}
+    }
+    clear_flags::<S>(ALL_FLAGS);
+}
+
+/// Configures and enables the stream `S`
+///
+/// `dir` is the DIR field of CR, for memory to memory transfers `par` is the source
+fn start<S: Stream, W: Word>(
+    par: u32,
+    m0ar: u32,
+    m1ar: Option<u32>,
+    ndt: usize,
+    dir: u32,
+    pinc: bool,
+    config: Config,
+) {
+    assert!(ndt > 0 && ndt <= 0xFFFF, "the buffer has to hold 1 to 65535 items");
+
+    disable::<S>();
+
+    // memory to memory transfers require the FIFO, the others use the direct mode
+    let fifo = dir == 0b10;
+    let circular = (config.circular || m1ar.is_some()) && !fifo;
+
+    let cr = (config.priority as u32) << 16
+        | W::SIZE << 13
+        | W::SIZE << 11
+        | 1 << 10
+        | (pinc as u32) << 9
+        | (circular as u32) << 8
+        | dir << 6
+        | (config.transfer_complete_interrupt as u32) << 4
+        | (config.half_transfer_interrupt as u32) << 3
+        | (config.transfer_error_interrupt as u32) << 2
+        | (m1ar.is_some() as u32) << 18;
+
+    // NOTE(unsafe) the registers of the stream are owned by the stream
+    unsafe {
+        ptr::write_volatile(register::<S>(PAR), par);
+        ptr::write_volatile(register::<S>(M0AR), m0ar);
+        ptr::write_volatile(register::<S>(M1AR), m1ar.unwrap_or(0));
+        ptr::write_volatile(register::<S>(NDTR), ndt as u32);
+        // DMDIS and a full FIFO threshold in FIFO mode
+        ptr::write_volatile(register::<S>(FCR), if fifo { 1 << 2 | 0b11 } else { 0 });
+        ptr::write_volatile(register::<S>(CR), cr);
+
+        // the buffer has to be written before the DMA reads it
+        atomic::compiler_fence(Ordering::SeqCst);
+
+        ptr::write_volatile(register::<S>(CR), cr | 1);
+    }
+}
+
+/// An ongoing transfer of a stream, owning the stream, the peripheral and the buffers
+/// until the transfer is released with `free`
+pub struct Transfer<STREAM, PERIPHERAL, BUF> {
+    stream: STREAM,
+    peripheral: PERIPHERAL,
+    buf: BUF,
+    // the address and the size of the memory to invalidate in `free`
+    invalidate: Option<(usize, usize)>,
+}
+
+/// The address and the size in bytes of `buf`
+fn extent<W>(buf: &[W]) -> (usize, usize) {
+    (buf.as_ptr() as usize, mem::size_of_val(buf))
+}
+
+impl<STREAM, PERIPHERAL> Transfer<STREAM, PERIPHERAL, &'static mut [PERIPHERAL::Word]>
+where
+    STREAM: Stream,
+    PERIPHERAL: PeripheralAddress + DmaRequest,
+{
+    /// Starts a transfer from the data register of `peripheral` into `buf`, paced by the
+    /// requests of the peripheral
+    pub fn peripheral_to_memory(
+        mut stream: STREAM,
+        peripheral: PERIPHERAL,
+        buf: &'static mut [PERIPHERAL::Word],
+        config: Config,
+    ) -> Self {
+        // no dirty line may be evicted onto the data the DMA writes
+        let invalidate = if config.dcache_maintenance {
+            let (addr, size) = extent(buf);
+            cache::invalidate_dcache(addr, size);
+            Some((addr, size))
+        } else {
+            None
+        };
+
+        stream.select_request(&peripheral);
+        start::<STREAM, PERIPHERAL::Word>(
+            peripheral.address(),
+            buf.as_ptr() as u32,
+            None,
+            buf.len(),
+            0b00,
+            false,
+            config,
+        );
+        Transfer { stream, peripheral, buf, invalidate }
+    }
+}
+
+impl<STREAM, PERIPHERAL> Transfer<STREAM, PERIPHERAL, &'static [PERIPHERAL::Word]>
+where
+    STREAM: Stream,
+    PERIPHERAL: PeripheralAddress + DmaRequest,
+{
+    /// Starts a transfer from `buf` into the data register of `peripheral`, paced by the
+    /// requests of the peripheral
+    pub fn memory_to_peripheral(
+        mut stream: STREAM,
+        peripheral: PERIPHERAL,
+        buf: &'static [PERIPHERAL::Word],
+        config: Config,
+    ) -> Self {
+        if config.dcache_maintenance {
+            let (addr, size) = extent(buf);
+            cache::clean_dcache(addr, size);
+        }
+
+        stream.select_request(&peripheral);
+        start::<STREAM, PERIPHERAL::Word>(
+            peripheral.address(),
+            buf.as_ptr() as u32,
+            None,
+            buf.len(),
+            0b01,
+            false,
+            config,
+        );
+        Transfer { stream, peripheral, buf, invalidate: None }
+    }
+}
+
+impl<STREAM, PERIPHERAL, W> Transfer<STREAM, PERIPHERAL, [&'static mut [W]; 2]>
+where
+    STREAM: Stream,
+    PERIPHERAL: PeripheralAddress<Word = W> + DmaRequest,
+    W: Word,
+{
+    /// Starts a circular transfer from the data register of `peripheral` alternating
+    /// between the two buffers, one buffer can be processed while the other one is filled
+    ///
+    /// # Panics
+    ///
+    /// Panics if the buffers differ in length
+    pub fn peripheral_to_memory_double_buffer(
+        mut stream: STREAM,
+        peripheral: PERIPHERAL,
+        bufs: [&'static mut [W]; 2],
+        config: Config,
+    ) -> Self {
+        assert_eq!(bufs[0].len(), bufs[1].len(), "the buffers have to be of the same length");
+        // the buffers are processed while the transfer runs, they have to be invalidated
+        // with `cache::invalidate_dcache_by_slice` each time before they are read
+        if config.dcache_maintenance {
+            for buf in bufs.iter() {
+                let (addr, size) = extent(buf);
+                cache::invalidate_dcache(addr, size);
+            }
+        }
+
+        stream.select_request(&peripheral);
+        start::<STREAM, W>(
+            peripheral.address(),
+            bufs[0].as_ptr() as u32,
+            Some(bufs[1].as_ptr() as u32),
+            bufs[0].len(),
+            0b00,
+            false,
+            config,
+        );
+        Transfer { stream, peripheral, buf: bufs, invalidate: None }
+    }
+
+    /// The index of the buffer the stream is currently writing to
+    pub fn current_buffer(&self) -> usize {
+        // NOTE(unsafe) atomic read with no side effects
+        unsafe { ((ptr::read_volatile(register::<STREAM>(CR)) >> 19) & 1) as usize }
+    }
+}
+
+impl<STREAM, W> Transfer<STREAM, &'static [W], &'static mut [W]>
+where
+    STREAM: Stream,
+    W: Word,
+{
+    /// Starts a copy of `src` into `dst`, the transfer runs as fast as the bus allows
+    ///
+    /// # Panics
+    ///
+    /// Panics if the buffers differ in length
+    pub fn memory_to_memory(
+        stream: STREAM,
+        src: &'static [W],
+        dst: &'static mut [W],
+        config: Config,
+    ) -> Self {
+        assert_eq!(src.len(), dst.len(), "the buffers have to be of the same length");
+        let invalidate = if config.dcache_maintenance {
+            let (addr, size) = extent(src);
+            cache::clean_dcache(addr, size);
+            let (addr, size) = extent(dst);
+            cache::invalidate_dcache(addr, size);
+            Some((addr, size))
+        } else {
+            None
+        };
+
+        start::<STREAM, W>(
+            src.as_ptr() as u32,
+            dst.as_ptr() as u32,
+            None,
+            src.len(),
+            0b10,
+            true,
+            config,
+        );
+        Transfer { stream, peripheral: src, buf: dst, invalidate }
+    }
+}
+
+/// Copies `src` into `dst` with `stream` and blocks until the copy completed, returning
+/// the number of bytes copied
+///
+/// With `Config::dcache_maintenance` `src` is cleaned from the D-cache and `dst` is
+/// invalidated before and after the copy, `dst` then has to be aligned to and padded to
+/// cache lines
+///
+/// # Panics
+///
+/// Panics if the buffers differ in length or hold more than 65535 items
+pub fn mem_to_mem<STREAM, W>(
+    _stream: &mut STREAM,
+    src: &[W],
+    dst: &mut [W],
+    config: Config,
+) -> Result<usize, Error>
+where
+    STREAM: Stream,
+    W: Word,
+{
+    assert_eq!(src.len(), dst.len(), "the buffers have to be of the same length");
+    let (src_addr, size) = extent(src);
+    let (dst_addr, _) = extent(dst);
+    if config.dcache_maintenance {
+        cache::clean_dcache(src_addr, size);
+        cache::invalidate_dcache(dst_addr, size);
+    }
+
+    // the transfer complete interrupt would fire after the flag is already cleared
+    let config = config.transfer_complete_interrupt(false).half_transfer_interrupt(false);
+    start::<STREAM, W>(src_addr as u32, dst_addr as u32, None, src.len(), 0b10, true, config);
+
+    let result = loop {
+        let flags = flags::<STREAM>();
+        if flags & TEIF != 0 {
+            break Err(Error::Transfer);
+        } else if flags & TCIF != 0 {
+            break Ok(size);
+        }
+    };
+    disable::<STREAM>();
+    // the buffer must not be read before the DMA finished writing it
+    atomic::compiler_fence(Ordering::SeqCst);
+
+    if config.dcache_maintenance {
+        cache::invalidate_dcache(dst_addr, size);
+    }
+    result
+}
+
+impl<STREAM: Stream, PERIPHERAL, BUF> Transfer<STREAM, PERIPHERAL, BUF> {
+    /// Whether the transfer completed, in circular mode this is set after every pass
+    pub fn is_done(&self) -> bool {
+        flags::<STREAM>() & TCIF != 0
+    }
+
+    /// Whether half of the transfer completed
+    pub fn is_half_done(&self) -> bool {
+        flags::<STREAM>() & HTIF != 0
+    }
+
+    /// Clears the complete and the half complete flag, e.g. in the interrupt handler of a
+    /// circular transfer
+    pub fn clear_interrupts(&mut self) {
+        clear_flags::<STREAM>(TCIF | HTIF);
+    }
+
+    /// Clears the complete flag
+    pub fn clear_done(&mut self) {
+        clear_flags::<STREAM>(TCIF);
+    }
+
+    /// Clears the half complete flag
+    pub fn clear_half_done(&mut self) {
+        clear_flags::<STREAM>(HTIF);
+    }
+
+    /// The number of items that are left to transfer in the current pass
+    pub fn remaining(&self) -> u16 {
+        // NOTE(unsafe) atomic read with no side effects
+        unsafe { ptr::read_volatile(register::<STREAM>(NDTR)) as u16 }
+    }
+
+    /// Waits until the transfer completed or failed
+    pub fn wait(&mut self) -> Result<(), Error> {
+        loop {
+            let flags = flags::<STREAM>();
+            if flags & TEIF != 0 {
+                return Err(Error::Transfer);
+            } else if flags & DMEIF != 0 {
+                return Err(Error::DirectMode);
+            } else if flags & TCIF != 0 {
+                // the buffer must not be read before the DMA finished writing it
+                atomic::compiler_fence(Ordering::SeqCst);
+                return Ok(());
+            }
+        }
+    }
+
+    /// Stops the transfer and releases the stream, the peripheral and the buffers
+    pub fn free(self) -> (STREAM, PERIPHERAL, BUF) {
+        disable::<STREAM>();
+        atomic::compiler_fence(Ordering::SeqCst);
+        // the CPU may have fetched lines of the buffer while the DMA wrote it
+        if let Some((addr, size)) = self.invalidate {
+            cache::invalidate_dcache(addr, size);
+        }
+        (self.stream, self.peripheral, self.buf)
+    }
+}
+
+macro_rules! streams {
+    ($($DMAX:ident: ($mux_offset:expr, [$($StreamX:ident: $x:expr,)+]),)+) => {
+        $(
+            impl DmaExt for $DMAX {
+                type Streams = StreamsTuple<$DMAX>;
+
+                fn split(self, ahb1: &mut AHB1) -> StreamsTuple<$DMAX> {
+                    $DMAX::enable(ahb1);
+                    $DMAX::reset(ahb1);
+
+                    StreamsTuple($($StreamX { _dma: PhantomData },)+)
+                }
+            }
+
+            $(
+                unsafe impl Stream for $StreamX<$DMAX> {
+                    const NUMBER: usize = $x;
+                    const MUX_CHANNEL: usize = $mux_offset + $x;
+
+                    fn dma() -> usize {
+                        $DMAX::ptr() as usize
+                    }
+                }
+            )+
+        )+
+    };
+}
+
+/// The streams of a DMA controller
+pub struct StreamsTuple<DMA>(
+    pub Stream0<DMA>,
+    pub Stream1<DMA>,
+    pub Stream2<DMA>,
+    pub Stream3<DMA>,
+    pub Stream4<DMA>,
+    pub Stream5<DMA>,
+    pub Stream6<DMA>,
+    pub Stream7<DMA>,
+);
+
+macro_rules! stream_types {
+    ($($StreamX:ident,)+) => {
+        $(
+            /// A stream of the DMA controller `DMA`
+            pub struct $StreamX<DMA> {
+                _dma: PhantomData<DMA>,
+            }
+        )+
+    };
+}
+
+stream_types!(Stream0, Stream1, Stream2, Stream3, Stream4, Stream5, Stream6, Stream7,);
+
+streams!(
+    DMA1: (0, [Stream0: 0, Stream1: 1, Stream2: 2, Stream3: 3, Stream4: 4, Stream5: 5, Stream6: 6, Stream7: 7,]),
+    DMA2: (8, [Stream0: 0, Stream1: 1, Stream2: 2, Stream3: 3, Stream4: 4, Stream5: 5, Stream6: 6, Stream7: 7,]),
+);
@@ -0,0 +1,419 @@
+//! Basic DMA controller of the D3 domain
+//!
+//! The channels of the BDMA serve the peripherals of the D3 domain, LPUART1, SPI6, I2C4,
+//! SAI4 and ADC3. Each channel is connected to a peripheral request through DMAMUX2
+//!
+//! The BDMA can only access the memory of the D3 domain, the buffers have to be placed in
+//! SRAM4 or in the backup SRAM, e.g. in a linker section mapped to 0x3800_0000. SRAM4 is
+//! cacheable like the other SRAMs, see the `dma` module
+
+use core::ptr;
+use core::sync::atomic::{self, Ordering};
+
+use stm32h7::stm32h7x3::{ADC3, BDMA, DMAMUX2, LPUART1};
+
+use crate::adc::Adc;
+use crate::cache;
+use crate::rcc::{Enable, Reset, AHB4};
+use crate::serial::{Rx, Tx};
+
+use super::{extent, Config, Error, PeripheralAddress, Word};
+
+/// The memory the BDMA can access, SRAM4 and the backup SRAM
+const D3_MEMORY: [(usize, usize); 2] = [(0x3800_0000, 0x1_0000), (0x3880_0000, 0x1000)];
+
+/// Request lines of DMAMUX2
+#[allow(non_camel_case_types)]
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum RequestId {
+    /// Request generator 0
+    GENERATOR0 = 1,
+    /// Request generator 1
+    GENERATOR1 = 2,
+    /// Request generator 2
+    GENERATOR2 = 3,
+    /// Request generator 3
+    GENERATOR3 = 4,
+    /// Request generator 4
+    GENERATOR4 = 5,
+    /// Request generator 5
+    GENERATOR5 = 6,
+    /// Request generator 6
+    GENERATOR6 = 7,
+    /// Request generator 7
+    GENERATOR7 = 8,
+    /// LPUART1 receiver
+    LPUART1_RX = 9,
+    /// LPUART1 transmitter
+    LPUART1_TX = 10,
+    /// SPI6 receiver
+    SPI6_RX = 11,
+    /// SPI6 transmitter
+    SPI6_TX = 12,
+    /// I2C4 receiver
+    I2C4_RX = 13,
+    /// I2C4 transmitter
+    I2C4_TX = 14,
+    /// SAI4 block A
+    SAI4_A = 15,
+    /// SAI4 block B
+    SAI4_B = 16,
+    /// ADC3
+    ADC3 = 17,
+}
+
+/// A peripheral of the D3 domain that requests BDMA transfers on a fixed request line
+///
+/// # Safety
+///
+/// Implementing this trait with a wrong request line results in transfers that never
+/// start or that are paced by another peripheral
+pub unsafe trait DmaRequest {
+    /// The request line of the peripheral
+    const REQUEST: RequestId;
+}
+
+unsafe impl<T: DmaRequest> DmaRequest for &T {
+    const REQUEST: RequestId = T::REQUEST;
+}
+
+macro_rules! requests {
+    ($($Request:ident: $ID:ident,)+) => {
+        $(
+            #[doc = "The `"]
+            #[doc = stringify!($ID)]
+            #[doc = "` request line of DMAMUX2"]
+            pub struct $Request;
+
+            unsafe impl DmaRequest for $Request {
+                const REQUEST: RequestId = RequestId::$ID;
+            }
+        )+
+    };
+}
+
+requests!(
+    Spi6Rx: SPI6_RX,
+    Spi6Tx: SPI6_TX,
+    I2c4Rx: I2C4_RX,
+    I2c4Tx: I2C4_TX,
+    Sai4A: SAI4_A,
+    Sai4B: SAI4_B,
+);
+
+unsafe impl DmaRequest for Rx<LPUART1> {
+    const REQUEST: RequestId = RequestId::LPUART1_RX;
+}
+
+unsafe impl DmaRequest for Tx<LPUART1> {
+    const REQUEST: RequestId = RequestId::LPUART1_TX;
+}
+
+unsafe impl DmaRequest for Adc<ADC3> {
+    const REQUEST: RequestId = RequestId::ADC3;
+}
+
+/// Extension trait to split the BDMA into independent channels
+pub trait BdmaExt {
+    /// Enables the BDMA and splits it into its channels
+    fn split(self, ahb4: &mut AHB4) -> Channels;
+}
+
+impl BdmaExt for BDMA {
+    fn split(self, ahb4: &mut AHB4) -> Channels {
+        BDMA::enable(ahb4);
+        BDMA::reset(ahb4);
+
+        Channels(
+            Channel0 { _0: () },
+            Channel1 { _0: () },
+            Channel2 { _0: () },
+            Channel3 { _0: () },
+            Channel4 { _0: () },
+            Channel5 { _0: () },
+            Channel6 { _0: () },
+            Channel7 { _0: () },
+        )
+    }
+}
+
+/// The channels of the BDMA
+pub struct Channels(
+    pub Channel0,
+    pub Channel1,
+    pub Channel2,
+    pub Channel3,
+    pub Channel4,
+    pub Channel5,
+    pub Channel6,
+    pub Channel7,
+);
+
+/// A channel of the BDMA - DO NOT IMPLEMENT THIS TRAIT
+///
+/// # Safety
+///
+/// `NUMBER` has to identify a channel that is owned by the implementor, the channel
+/// registers and the DMAMUX2 channel are accessed without synchronization
+pub unsafe trait Channel {
+    #[doc(hidden)]
+    const NUMBER: usize;
+
+    /// Connects the channel to the request line of `request`
+    fn select_request<R: DmaRequest>(&mut self, _request: R) {
+        self.set_request(R::REQUEST);
+    }
+
+    /// Connects the channel to the request line `request` of DMAMUX2, e.g. to a request
+    /// generator
+    fn set_request(&mut self, request: RequestId) {
+        // NOTE(unsafe) each channel has its own DMAMUX2 channel
+        unsafe {
+            let cxcr = (DMAMUX2::ptr() as usize + 4 * Self::NUMBER) as *mut u32;
+            let value = ptr::read_volatile(cxcr);
+            ptr::write_volatile(cxcr, (value & !0xFF) | request as u32);
+        }
+    }
+}
+
+macro_rules! channels {
+    ($($ChannelX:ident: $x:expr,)+) => {
+        $(
+            /// A channel of the BDMA
+            pub struct $ChannelX {
+                _0: (),
+            }
+
+            unsafe impl Channel for $ChannelX {
+                const NUMBER: usize = $x;
+            }
+        )+
+    };
+}
+
+channels!(
+    Channel0: 0,
+    Channel1: 1,
+    Channel2: 2,
+    Channel3: 3,
+    Channel4: 4,
+    Channel5: 5,
+    Channel6: 6,
+    Channel7: 7,
+);
+
+// offsets of the registers of a channel
+const CCR: usize = 0x00;
+const CNDTR: usize = 0x04;
+const CPAR: usize = 0x08;
+const CM0AR: usize = 0x0C;
+
+// flags in ISR and IFCR
+const GIF: u32 = 1 << 0;
+const TCIF: u32 = 1 << 1;
+const HTIF: u32 = 1 << 2;
+const TEIF: u32 = 1 << 3;
+const ALL_FLAGS: u32 = GIF | TCIF | HTIF | TEIF;
+
+/// A register of the channel `C`
+fn register<C: Channel>(offset: usize) -> *mut u32 {
+    (BDMA::ptr() as usize + 0x08 + 0x14 * C::NUMBER + offset) as *mut u32
+}
+
+/// The flags of the channel `C`, shifted down to bit 0
+fn flags<C: Channel>() -> u32 {
+    // NOTE(unsafe) atomic read with no side effects
+    unsafe { (*BDMA::ptr()).bdma_isr.read().bits() >> (4 * C::NUMBER) }
+}
+
+/// Clears the `flags` of the channel `C`
+fn clear_flags<C: Channel>(flags: u32) {
+    // NOTE(unsafe) write 1 to clear register, the flags of the other channels are not affected
+    unsafe { (*BDMA::ptr()).bdma_ifcr.write(|w| w.bits(flags << (4 * C::NUMBER))) }
+}
+
+/// Disables the channel `C`, an ongoing transfer stops after the current item
+fn disable<C: Channel>() {
+    // NOTE(unsafe) the registers of the channel are owned by the channel
+    unsafe {
+        let ccr = register::<C>(CCR);
+        ptr::write_volatile(ccr, ptr::read_volatile(ccr) & !1);
+    }
+    clear_flags::<C>(ALL_FLAGS);
+}
+
+/// Asserts that the BDMA can access `buf`
+fn assert_d3_memory<W>(buf: &[W]) {
+    let (addr, size) = extent(buf);
+    assert!(
+        D3_MEMORY
+            .iter()
+            .any(|&(start, len)| addr >= start && addr + size <= start + len),
+        "the BDMA can only access SRAM4 and the backup SRAM"
+    );
+}
+
+/// Configures and enables the channel `C`
+///
+/// `dir` is the DIR bit of CCR, set for transfers from memory to the peripheral
+fn start<C: Channel, W: Word>(cpar: u32, cm0ar: u32, ndt: usize, dir: bool, config: Config) {
+    assert!(ndt > 0 && ndt <= 0xFFFF, "the buffer has to hold 1 to 65535 items");
+
+    disable::<C>();
+
+    let ccr = (config.priority as u32) << 12
+        | W::SIZE << 10
+        | W::SIZE << 8
+        | 1 << 7
+        | (config.circular as u32) << 5
+        | (dir as u32) << 4
+        | (config.transfer_error_interrupt as u32) << 3
+        | (config.half_transfer_interrupt as u32) << 2
+        | (config.transfer_complete_interrupt as u32) << 1;
+
+    // NOTE(unsafe) the registers of the channel are owned by the channel
+    unsafe {
+        ptr::write_volatile(register::<C>(CPAR), cpar);
+        ptr::write_volatile(register::<C>(CM0AR), cm0ar);
+        ptr::write_volatile(register::<C>(CNDTR), ndt as u32);
+        ptr::write_volatile(register::<C>(CCR), ccr);
+
+        // the buffer has to be written before the BDMA reads it
+        atomic::compiler_fence(Ordering::SeqCst);
+
+        ptr::write_volatile(register::<C>(CCR), ccr | 1);
+    }
+}
+
+/// An ongoing transfer of a BDMA channel, owning the channel, the peripheral and the
+/// buffer until the transfer is released with `free`
+pub struct Transfer<CHANNEL, PERIPHERAL, BUF> {
+    channel: CHANNEL,
+    peripheral: PERIPHERAL,
+    buf: BUF,
+    // the address and the size of the memory to invalidate in `free`
+    invalidate: Option<(usize, usize)>,
+}
+
+impl<CHANNEL, PERIPHERAL> Transfer<CHANNEL, PERIPHERAL, &'static mut [PERIPHERAL::Word]>
+where
+    CHANNEL: Channel,
+    PERIPHERAL: PeripheralAddress + DmaRequest,
+{
+    /// Starts a transfer from the data register of `peripheral` into `buf`, paced by the
+    /// requests of the peripheral
+    ///
+    /// # Panics
+    ///
+    /// Panics if `buf` isn't placed in SRAM4 or in the backup SRAM
+    pub fn peripheral_to_memory(
+        mut channel: CHANNEL,
+        peripheral: PERIPHERAL,
+        buf: &'static mut [PERIPHERAL::Word],
+        config: Config,
+    ) -> Self {
+        assert_d3_memory(buf);
+        // no dirty line may be evicted onto the data the BDMA writes
+        let invalidate = if config.dcache_maintenance {
+            let (addr, size) = extent(buf);
+            cache::invalidate_dcache(addr, size);
+            Some((addr, size))
+        } else {
+            None
+        };
+
+        channel.select_request(&peripheral);
+        start::<CHANNEL, PERIPHERAL::Word>(
+            peripheral.address(),
+            buf.as_ptr() as u32,
+            buf.len(),
+            false,
+            config,
+        );
+        Transfer { channel, peripheral, buf, invalidate }
+    }
+}
+
+impl<CHANNEL, PERIPHERAL> Transfer<CHANNEL, PERIPHERAL, &'static [PERIPHERAL::Word]>
+where
+    CHANNEL: Channel,
+    PERIPHERAL: PeripheralAddress + DmaRequest,
+{
+    /// Starts a transfer from `buf` into the data register of `peripheral`, paced by the
+    /// requests of the peripheral
+    ///
+    /// # Panics
+    ///
+    /// Panics if `buf` isn't placed in SRAM4 or in the backup SRAM
+    pub fn memory_to_peripheral(
+        mut channel: CHANNEL,
+        peripheral: PERIPHERAL,
+        buf: &'static [PERIPHERAL::Word],
+        config: Config,
+    ) -> Self {
+        assert_d3_memory(buf);
+        if config.dcache_maintenance {
+            let (addr, size) = extent(buf);
+            cache::clean_dcache(addr, size);
+        }
+
+        channel.select_request(&peripheral);
+        start::<CHANNEL, PERIPHERAL::Word>(
+            peripheral.address(),
+            buf.as_ptr() as u32,
+            buf.len(),
+            true,
+            config,
+        );
+        Transfer { channel, peripheral, buf, invalidate: None }
+    }
+}
+
+impl<CHANNEL: Channel, PERIPHERAL, BUF> Transfer<CHANNEL, PERIPHERAL, BUF> {
+    /// Whether the transfer completed, in circular mode this is set after every pass
+    pub fn is_done(&self) -> bool {
+        flags::<CHANNEL>() & TCIF != 0
+    }
+
+    /// Whether half of the transfer completed
+    pub fn is_half_done(&self) -> bool {
+        flags::<CHANNEL>() & HTIF != 0
+    }
+
+    /// Clears the complete and the half complete flag, e.g. in the interrupt handler of a
+    /// circular transfer
+    pub fn clear_interrupts(&mut self) {
+        clear_flags::<CHANNEL>(GIF | TCIF | HTIF);
+    }
+
+    /// The number of items that are left to transfer in the current pass
+    pub fn remaining(&self) -> u16 {
+        // NOTE(unsafe) atomic read with no side effects
+        unsafe { ptr::read_volatile(register::<CHANNEL>(CNDTR)) as u16 }
+    }
+
+    /// Waits until the transfer completed or failed
+    pub fn wait(&mut self) -> Result<(), Error> {
+        loop {
+            let flags = flags::<CHANNEL>();
+            if flags & TEIF != 0 {
+                return Err(Error::Transfer);
+            } else if flags & TCIF != 0 {
+                // the buffer must not be read before the BDMA finished writing it
+                atomic::compiler_fence(Ordering::SeqCst);
+                return Ok(());
+            }
+        }
+    }
+
+    /// Stops the transfer and releases the channel, the peripheral and the buffer
+    pub fn free(self) -> (CHANNEL, PERIPHERAL, BUF) {
+        disable::<CHANNEL>();
+        atomic::compiler_fence(Ordering::SeqCst);
+        // the CPU may have fetched lines of the buffer while the BDMA wrote it
+        if let Some((addr, size)) = self.invalidate {
+            cache::invalidate_dcache(addr, size);
+        }
+        (self.channel, self.peripheral, self.buf)
+    }
+}
@@ -0,0 +1,337 @@
+//! Request routing of DMAMUX1
+//!
+//! DMAMUX1 connects each stream of DMA1 and DMA2 to one of the request lines of the
+//! peripherals, the request lines are identified by a `RequestId`
+
+use stm32h7::stm32h7x3::{ADC1, ADC2, ADC3, USART1, USART2, USART3, USART6};
+
+use crate::adc::Adc;
+use crate::dac::{C1, C2};
+use crate::serial::{Rx, Tx};
+
+/// Request lines of DMAMUX1
+#[allow(non_camel_case_types)]
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum RequestId {
+    /// Request generator 0
+    GENERATOR0 = 1,
+    /// Request generator 1
+    GENERATOR1 = 2,
+    /// Request generator 2
+    GENERATOR2 = 3,
+    /// Request generator 3
+    GENERATOR3 = 4,
+    /// Request generator 4
+    GENERATOR4 = 5,
+    /// Request generator 5
+    GENERATOR5 = 6,
+    /// Request generator 6
+    GENERATOR6 = 7,
+    /// Request generator 7
+    GENERATOR7 = 8,
+    /// ADC1
+    ADC1 = 9,
+    /// ADC2
+    ADC2 = 10,
+    /// TIM1 channel 1
+    TIM1_CH1 = 11,
+    /// TIM1 channel 2
+    TIM1_CH2 = 12,
+    /// TIM1 channel 3
+    TIM1_CH3 = 13,
+    /// TIM1 channel 4
+    TIM1_CH4 = 14,
+    /// TIM1 update
+    TIM1_UP = 15,
+    /// TIM1 trigger
+    TIM1_TRIG = 16,
+    /// TIM1 commutation
+    TIM1_COM = 17,
+    /// TIM2 channel 1
+    TIM2_CH1 = 18,
+    /// TIM2 channel 2
+    TIM2_CH2 = 19,
+    /// TIM2 channel 3
+    TIM2_CH3 = 20,
+    /// TIM2 channel 4
+    TIM2_CH4 = 21,
+    /// TIM2 update
+    TIM2_UP = 22,
+    /// TIM3 channel 1
+    TIM3_CH1 = 23,
+    /// TIM3 channel 2
+    TIM3_CH2 = 24,
+    /// TIM3 channel 3
+    TIM3_CH3 = 25,
+    /// TIM3 channel 4
+    TIM3_CH4 = 26,
+    /// TIM3 update
+    TIM3_UP = 27,
+    /// TIM3 trigger
+    TIM3_TRIG = 28,
+    /// TIM4 channel 1
+    TIM4_CH1 = 29,
+    /// TIM4 channel 2
+    TIM4_CH2 = 30,
+    /// TIM4 channel 3
+    TIM4_CH3 = 31,
+    /// TIM4 update
+    TIM4_UP = 32,
+    /// I2C1 receive
+    I2C1_RX = 33,
+    /// I2C1 transmit
+    I2C1_TX = 34,
+    /// I2C2 receive
+    I2C2_RX = 35,
+    /// I2C2 transmit
+    I2C2_TX = 36,
+    /// SPI1 receive
+    SPI1_RX = 37,
+    /// SPI1 transmit
+    SPI1_TX = 38,
+    /// SPI2 receive
+    SPI2_RX = 39,
+    /// SPI2 transmit
+    SPI2_TX = 40,
+    /// USART1 receive
+    USART1_RX = 41,
+    /// USART1 transmit
+    USART1_TX = 42,
+    /// USART2 receive
+    USART2_RX = 43,
+    /// USART2 transmit
+    USART2_TX = 44,
+    /// USART3 receive
+    USART3_RX = 45,
+    /// USART3 transmit
+    USART3_TX = 46,
+    /// TIM8 channel 1
+    TIM8_CH1 = 47,
+    /// TIM8 channel 2
+    TIM8_CH2 = 48,
+    /// TIM8 channel 3
+    TIM8_CH3 = 49,
+    /// TIM8 channel 4
+    TIM8_CH4 = 50,
+    /// TIM8 update
+    TIM8_UP = 51,
+    /// TIM8 trigger
+    TIM8_TRIG = 52,
+    /// TIM8 commutation
+    TIM8_COM = 53,
+    /// TIM5 channel 1
+    TIM5_CH1 = 55,
+    /// TIM5 channel 2
+    TIM5_CH2 = 56,
+    /// TIM5 channel 3
+    TIM5_CH3 = 57,
+    /// TIM5 channel 4
+    TIM5_CH4 = 58,
+    /// TIM5 update
+    TIM5_UP = 59,
+    /// TIM5 trigger
+    TIM5_TRIG = 60,
+    /// SPI3 receive
+    SPI3_RX = 61,
+    /// SPI3 transmit
+    SPI3_TX = 62,
+    /// UART4 receive
+    UART4_RX = 63,
+    /// UART4 transmit
+    UART4_TX = 64,
+    /// UART5 receive
+    UART5_RX = 65,
+    /// UART5 transmit
+    UART5_TX = 66,
+    /// DAC channel 1
+    DAC_CH1 = 67,
+    /// DAC channel 2
+    DAC_CH2 = 68,
+    /// TIM6 update
+    TIM6_UP = 69,
+    /// TIM7 update
+    TIM7_UP = 70,
+    /// USART6 receive
+    USART6_RX = 71,
+    /// USART6 transmit
+    USART6_TX = 72,
+    /// I2C3 receive
+    I2C3_RX = 73,
+    /// I2C3 transmit
+    I2C3_TX = 74,
+    /// DCMI
+    DCMI = 75,
+    /// CRYP input
+    #[cfg(any(feature = "h753", feature = "h750"))]
+    CRYP_IN = 76,
+    /// CRYP output
+    #[cfg(any(feature = "h753", feature = "h750"))]
+    CRYP_OUT = 77,
+    /// HASH input
+    #[cfg(any(feature = "h753", feature = "h750"))]
+    HASH_IN = 78,
+    /// UART7 receive
+    UART7_RX = 79,
+    /// UART7 transmit
+    UART7_TX = 80,
+    /// UART8 receive
+    UART8_RX = 81,
+    /// UART8 transmit
+    UART8_TX = 82,
+    /// SPI4 receive
+    SPI4_RX = 83,
+    /// SPI4 transmit
+    SPI4_TX = 84,
+    /// SPI5 receive
+    SPI5_RX = 85,
+    /// SPI5 transmit
+    SPI5_TX = 86,
+    /// SAI1 block A
+    SAI1_A = 87,
+    /// SAI1 block B
+    SAI1_B = 88,
+    /// SAI2 block A
+    SAI2_A = 89,
+    /// SAI2 block B
+    SAI2_B = 90,
+    /// SWPMI receive
+    SWPMI_RX = 91,
+    /// SWPMI transmit
+    SWPMI_TX = 92,
+    /// SPDIFRX data
+    SPDIFRX_DAT = 93,
+    /// SPDIFRX control
+    SPDIFRX_CTRL = 94,
+    /// HRTIM master timer
+    HRTIM_MASTER = 95,
+    /// HRTIM timer A
+    HRTIM_TIMA = 96,
+    /// HRTIM timer B
+    HRTIM_TIMB = 97,
+    /// HRTIM timer C
+    HRTIM_TIMC = 98,
+    /// HRTIM timer D
+    HRTIM_TIMD = 99,
+    /// HRTIM timer E
+    HRTIM_TIME = 100,
+    /// DFSDM1 filter 0
+    DFSDM1_FLT0 = 101,
+    /// DFSDM1 filter 1
+    DFSDM1_FLT1 = 102,
+    /// DFSDM1 filter 2
+    DFSDM1_FLT2 = 103,
+    /// DFSDM1 filter 3
+    DFSDM1_FLT3 = 104,
+    /// TIM15 channel 1
+    TIM15_CH1 = 105,
+    /// TIM15 update
+    TIM15_UP = 106,
+    /// TIM15 trigger
+    TIM15_TRIG = 107,
+    /// TIM15 commutation
+    TIM15_COM = 108,
+    /// TIM16 channel 1
+    TIM16_CH1 = 109,
+    /// TIM16 update
+    TIM16_UP = 110,
+    /// TIM17 channel 1
+    TIM17_CH1 = 111,
+    /// TIM17 update
+    TIM17_UP = 112,
+    /// SAI3 block A
+    SAI3_A = 113,
+    /// SAI3 block B
+    SAI3_B = 114,
+    /// ADC3
+    ADC3 = 115,
+}
+
+/// A peripheral that requests DMA transfers on a fixed request line
+///
+/// # Safety
+///
+/// Implementing this trait with a wrong request line results in transfers that never
+/// start or that are paced by another peripheral
+pub unsafe trait DmaRequest {
+    /// The request line of the peripheral
+    const REQUEST: RequestId;
+}
+
+unsafe impl<T: DmaRequest> DmaRequest for &T {
+    const REQUEST: RequestId = T::REQUEST;
+}
+
+macro_rules! requests {
+    ($($Request:ident: $ID:ident,)+) => {
+        $(
+            #[doc = "The `"]
+            #[doc = stringify!($ID)]
+            #[doc = "` request line"]
+            pub struct $Request;
+
+            unsafe impl DmaRequest for $Request {
+                const REQUEST: RequestId = RequestId::$ID;
+            }
+        )+
+    };
+}
+
+requests!(
+    I2c1Rx: I2C1_RX,
+    I2c1Tx: I2C1_TX,
+    I2c2Rx: I2C2_RX,
+    I2c2Tx: I2C2_TX,
+    I2c3Rx: I2C3_RX,
+    I2c3Tx: I2C3_TX,
+    Spi1Rx: SPI1_RX,
+    Spi1Tx: SPI1_TX,
+    Spi2Rx: SPI2_RX,
+    Spi2Tx: SPI2_TX,
+    Spi3Rx: SPI3_RX,
+    Spi3Tx: SPI3_TX,
+    Spi4Rx: SPI4_RX,
+    Spi4Tx: SPI4_TX,
+    Spi5Rx: SPI5_RX,
+    Spi5Tx: SPI5_TX,
+);
+
+macro_rules! serial_requests {
+    ($($USARTX:ident: ($RX:ident, $TX:ident),)+) => {
+        $(
+            unsafe impl DmaRequest for Rx<$USARTX> {
+                const REQUEST: RequestId = RequestId::$RX;
+            }
+
+            unsafe impl DmaRequest for Tx<$USARTX> {
+                const REQUEST: RequestId = RequestId::$TX;
+            }
+        )+
+    };
+}
+
+serial_requests!(
+    USART1: (USART1_RX, USART1_TX),
+    USART2: (USART2_RX, USART2_TX),
+    USART3: (USART3_RX, USART3_TX),
+    USART6: (USART6_RX, USART6_TX),
+);
+
+unsafe impl DmaRequest for Adc<ADC1> {
+    const REQUEST: RequestId = RequestId::ADC1;
+}
+
+unsafe impl DmaRequest for Adc<ADC2> {
+    const REQUEST: RequestId = RequestId::ADC2;
+}
+
+unsafe impl DmaRequest for Adc<ADC3> {
+    const REQUEST: RequestId = RequestId::ADC3;
+}
+
+unsafe impl DmaRequest for C1 {
+    const REQUEST: RequestId = RequestId::DAC_CH1;
+}
+
+unsafe impl DmaRequest for C2 {
+    const REQUEST: RequestId = RequestId::DAC_CH2;
+}
@@ -0,0 +1,101 @@
+//! External interrupts of the GPIO pins
+
+use stm32h7::stm32h7x3::{EXTI, GPIOA};
+use stm32h7::stm32h7x3::gpioa::RegisterBlock as GpioRegisterBlock;
+
+use crate::syscfg::SysCfg;
+
+/// The edges of the signal that trigger an interrupt
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Edge {
+    /// Rising edge
+    Rising,
+    /// Falling edge
+    Falling,
+    /// Both rising and falling edge
+    RisingFalling,
+}
+
+/// Input pins that can be used as source of an external interrupt
+///
+/// Each EXTI line is shared by the pins with the same number of all ports, only
+/// one of them can be selected as source at a time
+pub trait ExtiPin {
+    /// Routes the EXTI line of the pin number to the port of this pin
+    fn make_interrupt_source(&mut self, syscfg: &mut SysCfg);
+
+    /// Selects the edges that trigger the interrupt
+    fn trigger_on_edge(&mut self, exti: &mut EXTI, edge: Edge);
+
+    /// Unmasks the interrupt of the line for the CPU
+    fn enable_interrupt(&mut self, exti: &mut EXTI);
+
+    /// Masks the interrupt of the line for the CPU
+    fn disable_interrupt(&mut self, exti: &mut EXTI);
+
+    /// Clears the pending bit of the line, this has to be done in the interrupt handler
+    fn clear_interrupt_pending_bit(&mut self);
+
+    /// Whether the interrupt of the line is pending
+    fn check_interrupt(&self) -> bool;
+}
+
+/// The index of a GPIO port as used by EXTICR, the ports are placed 0x400 apart
+pub(crate) fn port_index(port: *const GpioRegisterBlock) -> u8 {
+    ((port as usize - GPIOA::ptr() as usize) / 0x400) as u8
+}
+
+pub(crate) fn make_interrupt_source(syscfg: &mut SysCfg, port: u8, line: u8) {
+    let offset = 4 * (line % 4);
+    let port = u32::from(port);
+    let syscfg = &syscfg.syscfg;
+    unsafe {
+        match line / 4 {
+            0 => syscfg.exticr1.modify(|r, w| w.bits((r.bits() & !(0b1111 << offset)) | (port << offset))),
+            1 => syscfg.exticr2.modify(|r, w| w.bits((r.bits() & !(0b1111 << offset)) | (port << offset))),
+            2 => syscfg.exticr3.modify(|r, w| w.bits((r.bits() & !(0b1111 << offset)) | (port << offset))),
+            _ => syscfg.exticr4.modify(|r, w| w.bits((r.bits() & !(0b1111 << offset)) | (port << offset))),
+        }
+    }
+}
+
+pub(crate) fn trigger_on_edge(exti: &mut EXTI, line: u8, edge: Edge) {
+    let rising = edge != Edge::Falling;
+    let falling = edge != Edge::Rising;
+    unsafe {
+        exti.rtsr1.modify(|r, w| {
+            w.bits(if rising { r.bits() | (1 << line) } else { r.bits() & !(1 << line) })
+        });
+        exti.ftsr1.modify(|r, w| {
+            w.bits(if falling { r.bits() | (1 << line) } else { r.bits() & !(1 << line) })
+        });
+    }
+}
+
+pub(crate) fn enable_interrupt(exti: &mut EXTI, line: u8) {
+    exti.cpuimr1.modify(|r, w| unsafe { w.bits(r.bits() | (1 << line)) });
+}
+
+pub(crate) fn disable_interrupt(exti: &mut EXTI, line: u8) {
+    exti.cpuimr1.modify(|r, w| unsafe { w.bits(r.bits() & !(1 << line)) });
+}
+
+/// Unmasks a direct line (32 to 63) of a peripheral, its wakeup event can't be configured
+/// and has no pending bit in the EXTI
+pub(crate) fn enable_direct_interrupt(exti: &mut EXTI, line: u8) {
+    exti.cpuimr2.modify(|r, w| unsafe { w.bits(r.bits() | (1 << (line - 32))) });
+}
+
+pub(crate) fn disable_direct_interrupt(exti: &mut EXTI, line: u8) {
+    exti.cpuimr2.modify(|r, w| unsafe { w.bits(r.bits() & !(1 << (line - 32))) });
+}
+
+pub(crate) fn clear_interrupt_pending_bit(line: u8) {
+    // NOTE(unsafe) the pending bits are cleared by writing 1, writing 0 has no effect so
+    // this write can not clear the pending bits of other lines
+    unsafe { (*EXTI::ptr()).cpupr1.write(|w| w.bits(1 << line)) }
+}
+
+pub(crate) fn check_interrupt(line: u8) -> bool {
+    unsafe { (*EXTI::ptr()).cpupr1.read().bits() & (1 << line) != 0 }
+}
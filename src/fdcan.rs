@@ -0,0 +1,734 @@
+//! Controller Area Network with Flexible Data-rate
+//!
+//! FDCAN1 and FDCAN2 share the 10 KB message RAM, each instance owns one half of it so
+//! their buffers can never overlap. The layout inside of the half is described by the
+//! `Config` and validated against the size of the half
+
+use core::ops::Deref;
+use core::ptr;
+
+use embedded_can::{blocking, ErrorKind, ExtendedId, Id, StandardId};
+
+use crate::gpio::gpioa::{PA11, PA12};
+use crate::gpio::gpiob::{PB5, PB6, PB8, PB9, PB12, PB13};
+use crate::gpio::gpiod::{PD0, PD1};
+use crate::gpio::gpioh::{PH13, PH14};
+use crate::gpio::{Floating, Input, Output, PushPull, AF9};
+use crate::rcc::{Clocks, Enable, APB1H};
+use crate::stm32h7x3::{fdcan1, FDCAN1, FDCAN2};
+use crate::time::Hertz;
+
+/// The address of the message RAM
+const MESSAGE_RAM: usize = 0x4000_AC00;
+/// The words of the message RAM owned by each instance
+const MESSAGE_RAM_WORDS: u32 = 1280;
+/// The words of a Rx FIFO or Tx buffer element with 64 data bytes
+const ELEMENT_WORDS: u32 = 18;
+/// The data lengths that can be encoded in the DLC field
+const DATA_LENGTHS: [usize; 16] = [0, 1, 2, 3, 4, 5, 6, 7, 8, 12, 16, 20, 24, 32, 48, 64];
+
+/// FDCAN error
+#[derive(Debug)]
+#[non_exhaustive]
+pub enum Error {
+    /// No bit timing matches the bit rate and the kernel clock
+    BitTiming,
+    /// The buffers of the configuration don't fit into the message RAM of the instance
+    MessageRam,
+    /// A Rx FIFO was full and a frame was lost
+    Overrun,
+    /// The controller is bus off
+    BusOff,
+}
+
+impl embedded_can::Error for Error {
+    fn kind(&self) -> ErrorKind {
+        match self {
+            Error::Overrun => ErrorKind::Overrun,
+            _ => ErrorKind::Other,
+        }
+    }
+}
+
+/// TX pin - DO NOT IMPLEMENT THIS TRAIT
+///
+/// # Safety
+///
+/// Only the pins whose alternate function connects them to TX of `FDCAN` may
+/// implement this trait
+pub unsafe trait TxPin<FDCAN> {}
+
+/// RX pin - DO NOT IMPLEMENT THIS TRAIT
+///
+/// # Safety
+///
+/// Only the pins whose alternate function connects them to RX of `FDCAN` may
+/// implement this trait
+pub unsafe trait RxPin<FDCAN> {}
+
+// FDCAN1
+unsafe impl TxPin<FDCAN1> for PA12<Output<PushPull>, AF9> {}
+unsafe impl TxPin<FDCAN1> for PB9<Output<PushPull>, AF9> {}
+unsafe impl TxPin<FDCAN1> for PD1<Output<PushPull>, AF9> {}
+unsafe impl TxPin<FDCAN1> for PH13<Output<PushPull>, AF9> {}
+unsafe impl RxPin<FDCAN1> for PA11<Input<Floating>, AF9> {}
+unsafe impl RxPin<FDCAN1> for PB8<Input<Floating>, AF9> {}
+unsafe impl RxPin<FDCAN1> for PD0<Input<Floating>, AF9> {}
+unsafe impl RxPin<FDCAN1> for PH14<Input<Floating>, AF9> {}
+
+// FDCAN2
+unsafe impl TxPin<FDCAN2> for PB6<Output<PushPull>, AF9> {}
+unsafe impl TxPin<FDCAN2> for PB13<Output<PushPull>, AF9> {}
+unsafe impl RxPin<FDCAN2> for PB5<Input<Floating>, AF9> {}
+unsafe impl RxPin<FDCAN2> for PB12<Input<Floating>, AF9> {}
+
+/// A Rx FIFO
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Fifo {
+    /// Rx FIFO 0
+    Fifo0,
+    /// Rx FIFO 1
+    Fifo1,
+}
+
+/// Configuration of a FDCAN
+#[derive(Clone, Copy, Debug)]
+pub struct Config {
+    bit_rate: Hertz,
+    data_bit_rate: Option<Hertz>,
+    fd: bool,
+    standard_filters: u8,
+    extended_filters: u8,
+    rx_fifo0: u8,
+    rx_fifo1: u8,
+    tx_buffers: u8,
+    reject_non_matching: bool,
+}
+
+impl Config {
+    /// A classic CAN configuration with the nominal `bit_rate`
+    ///
+    /// There are 28 standard and 8 extended filters, 16 elements in each Rx FIFO and 16 Tx
+    /// buffers, frames not matching a filter are stored in FIFO 0
+    pub fn new<F>(bit_rate: F) -> Self
+    where
+        F: Into<Hertz>,
+    {
+        Config {
+            bit_rate: bit_rate.into(),
+            data_bit_rate: None,
+            fd: false,
+            standard_filters: 28,
+            extended_filters: 8,
+            rx_fifo0: 16,
+            rx_fifo1: 16,
+            tx_buffers: 16,
+            reject_non_matching: false,
+        }
+    }
+
+    /// Enables CAN FD frames
+    pub fn fd(mut self, fd: bool) -> Self {
+        self.fd = fd;
+        self
+    }
+
+    /// Enables CAN FD frames with the data phase of frames with the bit rate switch set
+    /// transferred at `bit_rate`
+    pub fn data_bit_rate<F>(mut self, bit_rate: F) -> Self
+    where
+        F: Into<Hertz>,
+    {
+        self.data_bit_rate = Some(bit_rate.into());
+        self.fd = true;
+        self
+    }
+
+    /// Sets the number of standard (up to 128) and extended (up to 64) filters
+    pub fn filters(mut self, standard: u8, extended: u8) -> Self {
+        self.standard_filters = standard;
+        self.extended_filters = extended;
+        self
+    }
+
+    /// Sets the number of elements of the Rx FIFOs, up to 64 each
+    pub fn rx_fifos(mut self, fifo0: u8, fifo1: u8) -> Self {
+        self.rx_fifo0 = fifo0;
+        self.rx_fifo1 = fifo1;
+        self
+    }
+
+    /// Sets the number of Tx buffers, up to 32
+    pub fn tx_buffers(mut self, buffers: u8) -> Self {
+        self.tx_buffers = buffers;
+        self
+    }
+
+    /// Rejects frames not matching a filter instead of storing them in FIFO 0
+    pub fn reject_non_matching(mut self, reject: bool) -> Self {
+        self.reject_non_matching = reject;
+        self
+    }
+}
+
+/// The start addresses of the buffers in words relative to the message RAM
+#[derive(Clone, Copy)]
+struct Layout {
+    standard_filters: u32,
+    extended_filters: u32,
+    rx_fifo0: u32,
+    rx_fifo1: u32,
+    tx_buffers: u32,
+}
+
+impl Layout {
+    /// Places the buffers of `config` into the region starting at `offset`
+    fn new(config: &Config, offset: u32) -> Result<Self, Error> {
+        if config.standard_filters > 128
+            || config.extended_filters > 64
+            || config.rx_fifo0 > 64
+            || config.rx_fifo1 > 64
+            || config.tx_buffers > 32
+        {
+            return Err(Error::MessageRam);
+        }
+
+        let standard_filters = offset;
+        let extended_filters = standard_filters + u32::from(config.standard_filters);
+        let rx_fifo0 = extended_filters + 2 * u32::from(config.extended_filters);
+        let rx_fifo1 = rx_fifo0 + ELEMENT_WORDS * u32::from(config.rx_fifo0);
+        let tx_buffers = rx_fifo1 + ELEMENT_WORDS * u32::from(config.rx_fifo1);
+        let end = tx_buffers + ELEMENT_WORDS * u32::from(config.tx_buffers);
+
+        if end > offset + MESSAGE_RAM_WORDS {
+            return Err(Error::MessageRam);
+        }
+
+        Ok(Layout {
+            standard_filters,
+            extended_filters,
+            rx_fifo0,
+            rx_fifo1,
+            tx_buffers,
+        })
+    }
+}
+
+/// The number of data bytes encoded by `dlc`, classic frames carry at most 8 bytes
+fn data_length(dlc: usize, fd: bool) -> usize {
+    if fd {
+        DATA_LENGTHS[dlc]
+    } else {
+        dlc.min(8)
+    }
+}
+
+/// A word of the message RAM
+fn ram(word: u32) -> *mut u32 {
+    (MESSAGE_RAM + 4 * word as usize) as *mut u32
+}
+
+/// Bit timing of a phase
+struct BitTiming {
+    prescaler: u32,
+    tseg1: u32,
+    tseg2: u32,
+}
+
+impl BitTiming {
+    /// Finds the timing with the most time quanta for `bit_rate` with a sample point at
+    /// 87.5 % of the bit
+    fn new(ker_ck: u32, bit_rate: u32, max_prescaler: u32, max_tseg1: u32, max_tseg2: u32) -> Option<Self> {
+        (1..=max_prescaler)
+            .filter(|prescaler| ker_ck.is_multiple_of(prescaler * bit_rate))
+            .map(|prescaler| (prescaler, ker_ck / (prescaler * bit_rate)))
+            .filter(|&(_, quanta)| quanta >= 8)
+            .map(|(prescaler, quanta)| {
+                let tseg2 = (quanta / 8).max(1);
+                BitTiming {
+                    prescaler,
+                    tseg1: quanta - 1 - tseg2,
+                    tseg2,
+                }
+            })
+            .find(|timing| timing.tseg1 <= max_tseg1 && timing.tseg2 <= max_tseg2)
+    }
+}
+
+/// A classic CAN or CAN FD frame
+#[derive(Clone, Copy, Debug)]
+pub struct CanFrame {
+    id: Id,
+    remote: bool,
+    fd: bool,
+    bit_rate_switch: bool,
+    len: u8,
+    data: [u8; 64],
+}
+
+impl CanFrame {
+    /// A CAN FD data frame, the length of `data` has to be one of 0 to 8, 12, 16, 20, 24,
+    /// 32, 48 and 64
+    pub fn new_fd(id: impl Into<Id>, data: &[u8]) -> Option<Self> {
+        if !DATA_LENGTHS.contains(&data.len()) {
+            return None;
+        }
+
+        let mut frame = CanFrame {
+            id: id.into(),
+            remote: false,
+            fd: true,
+            bit_rate_switch: false,
+            len: data.len() as u8,
+            data: [0; 64],
+        };
+        frame.data[..data.len()].copy_from_slice(data);
+        Some(frame)
+    }
+
+    /// Transfers the data phase of a CAN FD frame with the data bit rate
+    pub fn bit_rate_switch(mut self, on: bool) -> Self {
+        self.bit_rate_switch = on && self.fd;
+        self
+    }
+
+    /// Whether the frame is a CAN FD frame
+    pub fn is_fd(&self) -> bool {
+        self.fd
+    }
+
+    /// Whether the data phase is transferred with the data bit rate
+    pub fn is_bit_rate_switched(&self) -> bool {
+        self.bit_rate_switch
+    }
+}
+
+impl embedded_can::Frame for CanFrame {
+    fn new(id: impl Into<Id>, data: &[u8]) -> Option<Self> {
+        if data.len() > 8 {
+            return None;
+        }
+        CanFrame::new_fd(id, data).map(|mut frame| {
+            frame.fd = false;
+            frame
+        })
+    }
+
+    fn new_remote(id: impl Into<Id>, dlc: usize) -> Option<Self> {
+        if dlc > 8 {
+            return None;
+        }
+        Some(CanFrame {
+            id: id.into(),
+            remote: true,
+            fd: false,
+            bit_rate_switch: false,
+            len: dlc as u8,
+            data: [0; 64],
+        })
+    }
+
+    fn is_extended(&self) -> bool {
+        match self.id {
+            Id::Extended(_) => true,
+            Id::Standard(_) => false,
+        }
+    }
+
+    fn is_remote_frame(&self) -> bool {
+        self.remote
+    }
+
+    fn id(&self) -> Id {
+        self.id
+    }
+
+    fn dlc(&self) -> usize {
+        usize::from(self.len)
+    }
+
+    fn data(&self) -> &[u8] {
+        if self.remote {
+            &[]
+        } else {
+            &self.data[..usize::from(self.len)]
+        }
+    }
+}
+
+/// FDCAN peripheral
+pub struct FdCan<FDCAN, PINS> {
+    fdcan: FDCAN,
+    pins: PINS,
+    layout: Layout,
+    config: Config,
+}
+
+macro_rules! hal {
+    ($($FDCANX:ident: ($fdcanX:ident, $ram_offset:expr),)+) => {
+        $(
+            impl<TX, RX> FdCan<$FDCANX, (TX, RX)>
+            where
+                TX: TxPin<$FDCANX>,
+                RX: RxPin<$FDCANX>,
+            {
+                /// Configures a FDCAN and joins the bus, it is clocked by the kernel clock
+                /// selected with `CFGR::fdcan_src`
+                ///
+                /// FDCAN1 and FDCAN2 share their reset, so the peripheral is configured
+                /// without resetting it
+                ///
+                /// # Panics
+                ///
+                /// Panics if the kernel clock is disabled
+                pub fn $fdcanX(
+                    fdcan: $FDCANX,
+                    pins: (TX, RX),
+                    config: Config,
+                    apb1h: &mut APB1H,
+                    clocks: &Clocks,
+                ) -> Result<Self, Error> {
+                    let ker_ck = clocks.fdcan_ck().expect("the kernel clock of the FDCAN is disabled");
+                    let layout = Layout::new(&config, $ram_offset)?;
+
+                    $FDCANX::enable(apb1h);
+
+                    let mut fdcan = FdCan { fdcan, pins, layout, config };
+                    fdcan.configure(ker_ck.0)?;
+                    Ok(fdcan)
+                }
+            }
+        )+
+    };
+}
+
+hal!(
+    FDCAN1: (fdcan1, 0),
+    FDCAN2: (fdcan2, MESSAGE_RAM_WORDS),
+);
+
+impl<FDCAN, PINS> FdCan<FDCAN, PINS>
+where
+    FDCAN: Deref<Target = fdcan1::RegisterBlock>,
+{
+    /// Queues a frame in a free Tx buffer
+    pub fn transmit(&mut self, frame: &CanFrame) -> nb::Result<(), Error> {
+        if self.fdcan.fdcan_psr.read().bo().bit_is_set() {
+            return Err(nb::Error::Other(Error::BusOff));
+        }
+        let txfqs = self.fdcan.fdcan_txfqs.read();
+        if txfqs.tfqf().bit_is_set() {
+            return Err(nb::Error::WouldBlock);
+        }
+        let index = u32::from(txfqs.tfqpi().bits());
+        let element = self.layout.tx_buffers + ELEMENT_WORDS * index;
+
+        let (xtd, id) = match frame.id {
+            Id::Standard(id) => (0, u32::from(id.as_raw()) << 18),
+            Id::Extended(id) => (1, id.as_raw()),
+        };
+        let dlc = DATA_LENGTHS.iter().position(|&len| len >= usize::from(frame.len)).unwrap_or(15);
+        let t0 = xtd << 30 | (frame.remote as u32) << 29 | id;
+        let t1 = (frame.fd as u32) << 21 | (frame.bit_rate_switch as u32) << 20 | (dlc as u32) << 16;
+
+        // NOTE(unsafe) the element is inside of the message RAM of this instance
+        unsafe {
+            ptr::write_volatile(ram(element), t0);
+            ptr::write_volatile(ram(element + 1), t1);
+            for (i, chunk) in frame.data[..DATA_LENGTHS[dlc]].chunks(4).enumerate() {
+                let mut word = [0; 4];
+                word[..chunk.len()].copy_from_slice(chunk);
+                ptr::write_volatile(ram(element + 2 + i as u32), u32::from_le_bytes(word));
+            }
+        }
+
+        self.fdcan.fdcan_txbar.write(|w| unsafe { w.ar().bits(1 << index) });
+        Ok(())
+    }
+
+    /// Takes the oldest frame from Rx FIFO 0, or from Rx FIFO 1 if FIFO 0 is empty
+    pub fn receive(&mut self) -> nb::Result<CanFrame, Error> {
+        match self.receive_fifo(Fifo::Fifo0) {
+            Err(nb::Error::WouldBlock) => self.receive_fifo(Fifo::Fifo1),
+            result => result,
+        }
+    }
+
+    /// Takes the oldest frame from a Rx FIFO
+    ///
+    /// `Error::Overrun` is returned once after a frame was lost, the frames in the FIFO
+    /// are kept
+    pub fn receive_fifo(&mut self, fifo: Fifo) -> nb::Result<CanFrame, Error> {
+        let (fill_level, get_index, lost, start) = match fifo {
+            Fifo::Fifo0 => {
+                let rxf0s = self.fdcan.fdcan_rxf0s.read();
+                (rxf0s.f0fl().bits(), rxf0s.f0g().bits(), rxf0s.rf0l().bit_is_set(), self.layout.rx_fifo0)
+            }
+            Fifo::Fifo1 => {
+                let rxf1s = self.fdcan.fdcan_rxf1s.read();
+                (rxf1s.f1fl().bits(), rxf1s.f1gi().bits(), rxf1s.rf1l().bit_is_set(), self.layout.rx_fifo1)
+            }
+        };
+
+        if lost {
+            // RF0L and RF1L are cleared through the matching interrupt flags
+            let rfl = match fifo {
+                Fifo::Fifo0 => 1 << 3,
+                Fifo::Fifo1 => 1 << 7,
+            };
+            write_read_only(&self.fdcan.fdcan_ir, rfl);
+            return Err(nb::Error::Other(Error::Overrun));
+        }
+        if fill_level == 0 {
+            return Err(nb::Error::WouldBlock);
+        }
+
+        let element = start + ELEMENT_WORDS * u32::from(get_index);
+        // NOTE(unsafe) the element is inside of the message RAM of this instance
+        let (r0, r1) = unsafe { (ptr::read_volatile(ram(element)), ptr::read_volatile(ram(element + 1))) };
+
+        let id = if r0 & (1 << 30) != 0 {
+            Id::Extended(ExtendedId::new(r0 & 0x1FFF_FFFF).unwrap())
+        } else {
+            Id::Standard(StandardId::new(((r0 >> 18) & 0x7FF) as u16).unwrap())
+        };
+        let fd = r1 & (1 << 21) != 0;
+        let len = data_length(((r1 >> 16) & 0xF) as usize, fd);
+        let mut frame = CanFrame {
+            id,
+            remote: !fd && r0 & (1 << 29) != 0,
+            fd,
+            bit_rate_switch: r1 & (1 << 20) != 0,
+            len: len as u8,
+            data: [0; 64],
+        };
+        for (i, chunk) in frame.data[..len].chunks_mut(4).enumerate() {
+            // NOTE(unsafe) the element is inside of the message RAM of this instance
+            let word = unsafe { ptr::read_volatile(ram(element + 2 + i as u32)) };
+            chunk.copy_from_slice(&word.to_le_bytes()[..chunk.len()]);
+        }
+
+        match fifo {
+            Fifo::Fifo0 => self.fdcan.fdcan_rxf0a.write(|w| unsafe { w.fa01().bits(get_index) }),
+            Fifo::Fifo1 => self.fdcan.fdcan_rxf1a.write(|w| unsafe { w.f1ai().bits(get_index) }),
+        }
+        Ok(frame)
+    }
+
+    /// Sets the standard filter `index`, frames whose masked identifier matches the masked
+    /// `id` are stored in `fifo`
+    ///
+    /// # Panics
+    ///
+    /// Panics if the index is not below the number of standard filters of the `Config`
+    pub fn set_standard_filter(&mut self, index: u8, id: StandardId, mask: u16, fifo: Fifo) {
+        assert!(index < self.config.standard_filters, "the filter index is out of range");
+        // SFT = 0b10: classic filter, SFEC = 0b001 / 0b010: store in FIFO 0 / 1
+        let element = 0b10 << 30
+            | sfec(fifo) << 27
+            | u32::from(id.as_raw()) << 16
+            | u32::from(mask & 0x7FF);
+        // NOTE(unsafe) the filter is inside of the message RAM of this instance
+        unsafe {
+            ptr::write_volatile(ram(self.layout.standard_filters + u32::from(index)), element)
+        };
+    }
+
+    /// Sets the extended filter `index`, frames whose masked identifier matches the masked
+    /// `id` are stored in `fifo`
+    ///
+    /// # Panics
+    ///
+    /// Panics if the index is not below the number of extended filters of the `Config`
+    pub fn set_extended_filter(&mut self, index: u8, id: ExtendedId, mask: u32, fifo: Fifo) {
+        assert!(index < self.config.extended_filters, "the filter index is out of range");
+        let element = self.layout.extended_filters + 2 * u32::from(index);
+        // EFT = 0b10: classic filter
+        // NOTE(unsafe) the filter is inside of the message RAM of this instance
+        unsafe {
+            ptr::write_volatile(ram(element), sfec(fifo) << 29 | id.as_raw());
+            ptr::write_volatile(ram(element + 1), 0b10 << 30 | (mask & 0x1FFF_FFFF));
+        }
+    }
+
+    /// Disables the standard filter `index`
+    pub fn disable_standard_filter(&mut self, index: u8) {
+        assert!(index < self.config.standard_filters, "the filter index is out of range");
+        // NOTE(unsafe) the filter is inside of the message RAM of this instance
+        unsafe { ptr::write_volatile(ram(self.layout.standard_filters + u32::from(index)), 0) };
+    }
+
+    /// Disables the extended filter `index`
+    pub fn disable_extended_filter(&mut self, index: u8) {
+        assert!(index < self.config.extended_filters, "the filter index is out of range");
+        let element = self.layout.extended_filters + 2 * u32::from(index);
+        // NOTE(unsafe) the filter is inside of the message RAM of this instance
+        unsafe { ptr::write_volatile(ram(element), 0) };
+    }
+
+    /// Leaves the bus and releases the FDCAN and the pins
+    pub fn free(self) -> (FDCAN, PINS) {
+        self.fdcan.fdcan_cccr.modify(|_, w| w.init().set_bit());
+        while self.fdcan.fdcan_cccr.read().init().bit_is_clear() {}
+        (self.fdcan, self.pins)
+    }
+
+    /// Writes the configuration in the initialization mode and joins the bus
+    fn configure(&mut self, ker_ck: u32) -> Result<(), Error> {
+        let config = self.config;
+        let layout = self.layout;
+
+        // NBRP up to 512, NTSEG1 up to 256 and NTSEG2 up to 128
+        let nominal = BitTiming::new(ker_ck, config.bit_rate.0, 512, 256, 128).ok_or(Error::BitTiming)?;
+        // DBRP up to 32, DTSEG1 up to 32 and DTSEG2 up to 16
+        let data = match config.data_bit_rate {
+            Some(rate) => Some(BitTiming::new(ker_ck, rate.0, 32, 32, 16).ok_or(Error::BitTiming)?),
+            None => None,
+        };
+
+        self.fdcan.fdcan_cccr.modify(|_, w| w.init().set_bit());
+        while self.fdcan.fdcan_cccr.read().init().bit_is_clear() {}
+        self.fdcan.fdcan_cccr.modify(|_, w| w.cce().set_bit());
+
+        self.fdcan.fdcan_cccr.modify(|_, w| {
+            w.fdoe().bit(config.fd).bse().bit(data.is_some())
+        });
+        self.fdcan.fdcan_nbtp.write(|w| unsafe {
+            w.nsjw()
+                .bits((nominal.tseg2 - 1) as u8)
+                .nbrp()
+                .bits((nominal.prescaler - 1) as u16)
+                .ntseg1()
+                .bits((nominal.tseg1 - 1) as u8)
+                .tseg2()
+                .bits((nominal.tseg2 - 1) as u8)
+        });
+        if let Some(data) = data {
+            // the transceiver loop delay is compensated with an offset at the sample point
+            let tdc = data.prescaler <= 2;
+            write_read_only(
+                &self.fdcan.fdcan_dbtp,
+                (data.tseg2 - 1)
+                    | (data.tseg2 - 1) << 4
+                    | (data.tseg1 - 1) << 8
+                    | (data.prescaler - 1) << 16
+                    | (tdc as u32) << 23,
+            );
+            if tdc {
+                let offset = (data.prescaler * (1 + data.tseg1)).min(127);
+                write_read_only(&self.fdcan.fdcan_tdcr, offset << 8);
+            }
+        }
+
+        // the filters are disabled until they get set
+        // NOTE(unsafe) the filters are inside of the message RAM of this instance
+        for word in layout.standard_filters..layout.rx_fifo0 {
+            unsafe { ptr::write_volatile(ram(word), 0) };
+        }
+
+        // ANFS / ANFE = 0b00: accept in FIFO 0, 0b10: reject
+        let anf = if config.reject_non_matching { 0b10 } else { 0b00 };
+        self.fdcan.fdcan_gfc.write(|w| unsafe { w.anfs().bits(anf).anfe().bits(anf) });
+        self.fdcan.fdcan_xidam.write(|w| unsafe { w.eidm().bits(0x1FFF_FFFF) });
+
+        self.fdcan.fdcan_sidfc.write(|w| unsafe {
+            w.flssa().bits(layout.standard_filters as u16).lss().bits(config.standard_filters)
+        });
+        self.fdcan.fdcan_xidfc.write(|w| unsafe {
+            w.flesa().bits(layout.extended_filters as u16).lse().bits(config.extended_filters)
+        });
+        self.fdcan.fdcan_rxf0c.write(|w| unsafe {
+            w.f0sa().bits(layout.rx_fifo0 as u16).f0s().bits(config.rx_fifo0)
+        });
+        self.fdcan.fdcan_rxf1c.write(|w| unsafe {
+            w.f1sa().bits(layout.rx_fifo1 as u16).f1s().bits(config.rx_fifo1)
+        });
+        // 0b111: 64 data bytes in every element
+        self.fdcan.fdcan_rxesc.write(|w| unsafe {
+            w.f0ds().bits(0b111).f1ds().bits(0b111).rbds().bits(0b111)
+        });
+        // TFQM = 0: the Tx buffers are a FIFO
+        self.fdcan.fdcan_txbc.write(|w| unsafe {
+            w.tbsa().bits(layout.tx_buffers as u16).tfqs().bits(config.tx_buffers).tfqm().clear_bit()
+        });
+        self.fdcan.fdcan_txesc.write(|w| unsafe { w.tbds().bits(0b111) });
+        self.fdcan.fdcan_txefc.write(|w| unsafe { w.efs().bits(0) });
+
+        self.fdcan.fdcan_cccr.modify(|_, w| w.init().clear_bit());
+        while self.fdcan.fdcan_cccr.read().init().bit_is_set() {}
+        Ok(())
+    }
+}
+
+impl<FDCAN, PINS> blocking::Can for FdCan<FDCAN, PINS>
+where
+    FDCAN: Deref<Target = fdcan1::RegisterBlock>,
+{
+    type Frame = CanFrame;
+    type Error = Error;
+
+    fn transmit(&mut self, frame: &CanFrame) -> Result<(), Error> {
+        nb::block!(FdCan::transmit(self, frame))
+    }
+
+    fn receive(&mut self) -> Result<CanFrame, Error> {
+        nb::block!(FdCan::receive(self))
+    }
+}
+
+/// The SFEC / EFEC encoding of storing a frame in `fifo`
+fn sfec(fifo: Fifo) -> u32 {
+    match fifo {
+        Fifo::Fifo0 => 0b001,
+        Fifo::Fifo1 => 0b010,
+    }
+}
+
+/// Writes a register the svd wrongly marks as read only
+fn write_read_only<REG>(register: &REG, value: u32) {
+    // NOTE(unsafe) IR, DBTP and TDCR are writable 32 bit registers
+    unsafe { ptr::write_volatile(ptr::addr_of!(*register) as *mut u32, value) };
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn default_config_fits_into_the_message_ram() {
+        let config = Config::new(Hertz(500_000));
+        let layout = Layout::new(&config, 0).ok().unwrap();
+        let end = layout.tx_buffers + ELEMENT_WORDS * u32::from(config.tx_buffers);
+        assert!(end <= MESSAGE_RAM_WORDS);
+    }
+
+    #[test]
+    fn maximal_config_overflows_the_message_ram() {
+        let config = Config::new(Hertz(500_000)).filters(128, 64).rx_fifos(64, 64).tx_buffers(32);
+        assert!(matches!(Layout::new(&config, 0), Err(Error::MessageRam)));
+    }
+
+    #[test]
+    fn fdcan2_region_starts_at_word_1280() {
+        let config = Config::new(Hertz(500_000));
+        let layout = Layout::new(&config, MESSAGE_RAM_WORDS).ok().unwrap();
+        assert_eq!(layout.standard_filters, 1280);
+        assert_eq!(layout.extended_filters, 1280 + 28);
+    }
+
+    #[test]
+    fn bit_timing_samples_at_87_5_percent() {
+        let timing = BitTiming::new(80_000_000, 500_000, 512, 256, 128).unwrap();
+        let quanta = 1 + timing.tseg1 + timing.tseg2;
+        assert_eq!(80_000_000 / (timing.prescaler * quanta), 500_000);
+        assert_eq!((1 + timing.tseg1) * 1000 / quanta, 875);
+    }
+
+    #[test]
+    fn classic_frames_carry_at_most_8_bytes() {
+        assert_eq!(data_length(8, false), 8);
+        assert_eq!(data_length(15, false), 8);
+        assert_eq!(data_length(9, true), 12);
+        assert_eq!(data_length(15, true), 64);
+    }
+}
@@ -33,4 +33,11 @@ impl ACR {
         // NOTE(unsafe) this proxy grants exclusive access to this register
         unsafe { &(*FLASH::ptr()).acr }
     }
+
+    /// Sets the read latency and the programming delay and waits until the
+    /// new latency took effect
+    pub(crate) fn set_latency(&mut self, latency: u8, wrhighfreq: u8) {
+        self.acr().modify(|_, w| unsafe { w.latency().bits(latency).wrhighfreq().bits(wrhighfreq) });
+        while self.acr().read().latency().bits() != latency {}
+    }
 }
\ No newline at end of file
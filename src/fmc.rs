@@ -0,0 +1,263 @@
+//! Flexible Memory Controller for external SDRAM
+//!
+//! The SDRAM is connected to the SDRAM bank 1 (SDCKE0 and SDNE0) and mapped to 0xC000_0000,
+//! the address, data and control pins have to be put into AF12
+//!
+//! The default memory map treats 0xC000_0000 as device memory, which faults on unaligned
+//! accesses. `mpu::Mpu::configure_dma_region` makes the SDRAM normal memory
+
+use core::slice;
+
+use cortex_m::asm;
+
+use crate::rcc::{Clocks, Enable, Reset, AHB3};
+use crate::stm32h7x3::FMC;
+use crate::time::Hertz;
+
+/// The address the SDRAM bank 1 is mapped to
+const SDRAM_BANK1: usize = 0xC000_0000;
+
+/// The number of auto-refresh commands sent during the initialization
+const INIT_AUTO_REFRESH: u8 = 8;
+
+/// Width of the data bus of the SDRAM
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum MemoryWidth {
+    /// D0 to D7
+    Bits8 = 0b00,
+    /// D0 to D15
+    Bits16 = 0b01,
+    /// D0 to D31
+    Bits32 = 0b10,
+}
+
+/// Timings of an SDRAM from its datasheet, in cycles of the SDRAM clock from 1 to 16
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Timing {
+    /// Load mode register to active delay (tMRD)
+    pub load_mode_to_active: u8,
+    /// Exit self-refresh delay (tXSR)
+    pub exit_self_refresh: u8,
+    /// Minimum self-refresh time (tRAS)
+    pub self_refresh: u8,
+    /// Row cycle delay (tRC)
+    pub row_cycle: u8,
+    /// Write recovery time (tWR)
+    pub write_recovery: u8,
+    /// Row precharge delay (tRP)
+    pub row_precharge: u8,
+    /// Row to column delay (tRCD)
+    pub row_to_column: u8,
+}
+
+/// Configuration of the SDRAM
+///
+/// `new` creates the configuration of an SDRAM with 4 internal banks, a CAS latency of 3
+/// and 8192 refresh cycles every 64 ms
+#[derive(Clone, Copy)]
+pub struct Config {
+    frequency: u32,
+    column_bits: u8,
+    row_bits: u8,
+    width: MemoryWidth,
+    internal_banks: u8,
+    cas_latency: u8,
+    timing: Timing,
+    refresh_period_ms: u32,
+    refresh_cycles: u32,
+}
+
+impl Config {
+    /// Creates the configuration of an SDRAM that runs at most at `frequency` with
+    /// `column_bits` (8 to 11) column and `row_bits` (11 to 13) row address bits
+    pub fn new<F>(
+        frequency: F,
+        column_bits: u8,
+        row_bits: u8,
+        width: MemoryWidth,
+        timing: Timing,
+    ) -> Self
+    where
+        F: Into<Hertz>,
+    {
+        Config {
+            frequency: frequency.into().0,
+            column_bits,
+            row_bits,
+            width,
+            internal_banks: 4,
+            cas_latency: 3,
+            timing,
+            refresh_period_ms: 64,
+            refresh_cycles: 8192,
+        }
+    }
+
+    /// Sets the number of internal banks, 2 or 4
+    pub fn internal_banks(mut self, banks: u8) -> Self {
+        self.internal_banks = banks;
+        self
+    }
+
+    /// Sets the CAS latency, 1 to 3 cycles of the SDRAM clock
+    pub fn cas_latency(mut self, cycles: u8) -> Self {
+        self.cas_latency = cycles;
+        self
+    }
+
+    /// Sets the refresh requirement of the SDRAM, `cycles` auto-refresh commands within
+    /// `period_ms` milliseconds
+    pub fn refresh(mut self, cycles: u32, period_ms: u32) -> Self {
+        self.refresh_cycles = cycles;
+        self.refresh_period_ms = period_ms;
+        self
+    }
+
+    /// The size of the SDRAM in bytes
+    fn size(&self) -> usize {
+        let width = 1 << self.width as usize;
+        (width * usize::from(self.internal_banks)) << (self.column_bits + self.row_bits)
+    }
+}
+
+/// SDRAM command modes of SDCMR
+#[derive(Clone, Copy)]
+enum Command {
+    ClockEnable = 0b001,
+    PrechargeAll = 0b010,
+    AutoRefresh = 0b011,
+    LoadModeRegister = 0b100,
+}
+
+/// An SDRAM on the SDRAM bank 1
+pub struct Sdram {
+    fmc: FMC,
+    size: usize,
+    sdclk: Hertz,
+}
+
+impl Sdram {
+    /// Initializes the SDRAM, the SDRAM clock is the FMC kernel clock selected with
+    /// `CFGR::fmc_src` divided by 2 or 3
+    ///
+    /// # Panics
+    ///
+    /// Panics if the configuration isn't supported or if the SDRAM clock can't run at
+    /// most at the frequency of the configuration
+    pub fn new(fmc: FMC, config: Config, ahb3: &mut AHB3, clocks: &Clocks) -> Self {
+        assert!(
+            config.column_bits >= 8 && config.column_bits <= 11,
+            "an SDRAM has 8 to 11 column address bits"
+        );
+        assert!(
+            config.row_bits >= 11 && config.row_bits <= 13,
+            "an SDRAM has 11 to 13 row address bits"
+        );
+        assert!(
+            config.internal_banks == 2 || config.internal_banks == 4,
+            "an SDRAM has 2 or 4 internal banks"
+        );
+        assert!(
+            config.cas_latency >= 1 && config.cas_latency <= 3,
+            "the CAS latency is 1 to 3 cycles"
+        );
+        let t = config.timing;
+        for &cycles in &[
+            t.load_mode_to_active,
+            t.exit_self_refresh,
+            t.self_refresh,
+            t.row_cycle,
+            t.write_recovery,
+            t.row_precharge,
+            t.row_to_column,
+        ] {
+            assert!((1..=16).contains(&cycles), "the timings are 1 to 16 cycles");
+        }
+
+        let fmc_ck = clocks.fmc_ck().0;
+        let sdclk_div = (2..=3)
+            .find(|div| fmc_ck / div <= config.frequency)
+            .expect("the SDRAM can't run at the FMC kernel clock divided by 3");
+        let sdclk = fmc_ck / sdclk_div;
+
+        FMC::enable(ahb3);
+        FMC::reset(ahb3);
+
+        fmc.fmc_sdcr1.write(|w| unsafe {
+            w.nc().bits(config.column_bits - 8)
+                .nr().bits(config.row_bits - 11)
+                .mwid().bits(config.width as u8)
+                .nb().bit(config.internal_banks == 4)
+                .cas().bits(config.cas_latency)
+                .sdclk().bits(sdclk_div as u8)
+                // read bursts in the pipeline, with the delay of the CAS latency
+                .rburst().set_bit()
+                .rpipe().bits(0)
+        });
+        fmc.fmc_sdtr1.write(|w| unsafe {
+            w.tmrd().bits(t.load_mode_to_active - 1)
+                .txsr().bits(t.exit_self_refresh - 1)
+                .tras().bits(t.self_refresh - 1)
+                .trc().bits(t.row_cycle - 1)
+                .twr().bits(t.write_recovery - 1)
+                .trp().bits(t.row_precharge - 1)
+                .trcd().bits(t.row_to_column - 1)
+        });
+        fmc.fmc_bcr1.modify(|_, w| w.fmcen().set_bit());
+
+        let mut sdram = Sdram {
+            fmc,
+            size: config.size(),
+            sdclk: Hertz(sdclk),
+        };
+
+        // the SDRAM needs a stable clock for at least 100 us before the first command
+        sdram.command(Command::ClockEnable, 0, 0);
+        asm::delay(clocks.sys_ck().0 / 10_000);
+
+        sdram.command(Command::PrechargeAll, 0, 0);
+        sdram.command(Command::AutoRefresh, INIT_AUTO_REFRESH, 0);
+
+        // a burst length of 1, sequential bursts, the CAS latency and single writes
+        let mode = u16::from(config.cas_latency) << 4 | 1 << 9;
+        sdram.command(Command::LoadModeRegister, 0, mode);
+
+        // the refresh timer issues an auto-refresh every COUNT + 1 cycles, 20 cycles are
+        // subtracted as a safety margin for the refresh to be delayed by a read
+        let refresh_interval = u64::from(sdclk) * u64::from(config.refresh_period_ms)
+            / 1000
+            / u64::from(config.refresh_cycles);
+        let count = refresh_interval.saturating_sub(20).min(0x1FFF) as u16;
+        // COUNT has to be at least 41
+        assert!(count >= 41, "the SDRAM can't be refreshed often enough");
+        sdram.fmc.fmc_sdrtr.write(|w| unsafe { w.count().bits(count) });
+
+        sdram
+    }
+
+    /// Sends `command` to the SDRAM
+    fn command(&mut self, command: Command, auto_refresh: u8, mode_register: u16) {
+        self.fmc.fmc_sdcmr.write(|w| unsafe {
+            w.mode().bits(command as u8)
+                .ctb1().set_bit()
+                .nrfs().bits(auto_refresh.max(1) - 1)
+                .mrd().bits(mode_register)
+        });
+    }
+
+    /// The frequency of the SDRAM clock
+    pub fn sdclk(&self) -> Hertz {
+        self.sdclk
+    }
+
+    /// The SDRAM as a byte slice
+    pub fn as_mut_slice(&mut self) -> &mut [u8] {
+        // NOTE(unsafe) the SDRAM is only accessed through `self`
+        unsafe { slice::from_raw_parts_mut(SDRAM_BANK1 as *mut u8, self.size) }
+    }
+
+    /// Releases the FMC, the SDRAM keeps being refreshed
+    pub fn free(self) -> FMC {
+        self.fmc
+    }
+}
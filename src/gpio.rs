@@ -1,10 +1,19 @@
 //! General Purpose In and Output implementation
+//!
+//! The output pins are driven through BSRR, which sets and resets single pins in one write.
+//! Pins of the same port can be used from different contexts, e.g. the main loop and an
+//! interrupt handler, without a read-modify-write of ODR racing with the other context
 // Taken over from https://github.com/saschagrunert/stm32h7-rs/blob/master/src/gpio.rs and slightly modified to ensure more
 // safety at compile time when using alternate functions
 // if you are reading this, your code helped me a lot understanding how to implement stuff for the stm32h7x3
 
+use crate::exti::{self, Edge, ExtiPin};
 use crate::rcc::AHB4;
+use crate::syscfg::SysCfg;
 use core::marker::PhantomData;
+use hal::digital::{InputPin, OutputPin, StatefulOutputPin, ToggleableOutputPin};
+use stm32h7::stm32h7x3::gpioa::RegisterBlock as GpioRegisterBlock;
+use stm32h7::stm32h7x3::EXTI;
 
 /// Extension trait to split a GPIO peripheral in independent pins and registers
 pub trait GpioExt {
@@ -32,6 +41,33 @@ pub struct AF13;
 pub struct AF14;
 pub struct AF15;
 
+/// An alternate function a pin can be muxed to (type state)
+pub trait AlternateFunction {
+    /// The value of the alternate function in the AFRL / AFRH registers
+    const NUMBER: u32;
+}
+
+macro_rules! alternate_functions {
+    ($($AFx:ident: $x:expr,)+) => {
+        $(
+            impl AlternateFunction for $AFx {
+                const NUMBER: u32 = $x;
+            }
+        )+
+    };
+}
+
+alternate_functions!(
+    AF0: 0, AF1: 1, AF2: 2, AF3: 3, AF4: 4, AF5: 5, AF6: 6, AF7: 7,
+    AF8: 8, AF9: 9, AF10: 10, AF11: 11, AF12: 12, AF13: 13, AF14: 14, AF15: 15,
+);
+
+/// The AFRL or AFRH value `afr` with the alternate function of the pin `index` set to `af`
+fn with_alternate_function(afr: u32, index: usize, af: u32) -> u32 {
+    let offset = 4 * (index % 8);
+    (afr & !(0b1111 << offset)) | (af << offset)
+}
+
 /// Input mode (type state)
 pub struct Input<MODE> {
     _mode: PhantomData<MODE>,
@@ -57,6 +93,117 @@ pub struct OpenDrain;
 /// Push pull output (type state)
 pub struct PushPull;
 
+/// Analog mode (type state)
+pub struct Analog;
+
+/// Output speed of a pin, the values of the OSPEEDR register
+///
+/// The maximum frequency of each speed depends on VDD and the load of the pin, the high
+/// speeds usually require the I/O compensation cell to be enabled as well
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Speed {
+    /// Low speed
+    Low = 0b00,
+    /// Medium speed
+    Medium = 0b01,
+    /// High speed
+    High = 0b10,
+    /// Very high speed, this may require a VDD above 2.7 V to reach its full rate
+    VeryHigh = 0b11,
+}
+
+/// Location of a pin, allows drivers to operate a pin of their alternate function as
+/// GPIO for a short time, e.g. to recover a hung I2C bus
+pub trait GpioPin {
+    /// The number of the pin within its port
+    const NUMBER: u8;
+
+    /// The registers of the port of the pin
+    fn port() -> *const GpioRegisterBlock;
+}
+
+/// Fully erased pin, the port is stored at runtime
+///
+/// This is useful when you want to collect pins of different ports into an array
+pub struct ErasedPin<MODE> {
+    i: u8,
+    port: *const GpioRegisterBlock,
+    _mode: PhantomData<MODE>,
+}
+
+// NOTE(unsafe) the pointer refers to the memory mapped registers of the port, they are only
+// accessed atomically through BSRR and IDR
+unsafe impl<MODE> Send for ErasedPin<MODE> {}
+
+impl<MODE> ErasedPin<MODE> {
+    /// Returns the pin number within its port
+    pub fn get_id(&self) -> u8 {
+        self.i
+    }
+}
+
+impl<MODE> OutputPin for ErasedPin<Output<MODE>> {
+    fn set_high(&mut self) {
+        unsafe { (*self.port).bsrr.write(|w| w.bits(1 << self.i)) }
+    }
+
+    fn set_low(&mut self) {
+        unsafe { (*self.port).bsrr.write(|w| w.bits(1 << (self.i + 16))) }
+    }
+}
+
+impl<MODE> StatefulOutputPin for ErasedPin<Output<MODE>> {
+    fn is_set_high(&self) -> bool {
+        !self.is_set_low()
+    }
+
+    fn is_set_low(&self) -> bool {
+        unsafe { (*self.port).odr.read().bits() & (1 << self.i) == 0 }
+    }
+}
+
+impl<MODE> ToggleableOutputPin for ErasedPin<Output<MODE>> {
+    fn toggle(&mut self) {
+        if self.is_set_low() { self.set_high() } else { self.set_low() }
+    }
+}
+
+impl<MODE> InputPin for ErasedPin<Input<MODE>> {
+    fn is_high(&self) -> bool {
+        !self.is_low()
+    }
+
+    fn is_low(&self) -> bool {
+        unsafe { (*self.port).idr.read().bits() & (1 << self.i) == 0 }
+    }
+}
+
+impl<MODE> ExtiPin for ErasedPin<Input<MODE>> {
+    fn make_interrupt_source(&mut self, syscfg: &mut SysCfg) {
+        exti::make_interrupt_source(syscfg, exti::port_index(self.port), self.i);
+    }
+
+    fn trigger_on_edge(&mut self, exti: &mut EXTI, edge: Edge) {
+        exti::trigger_on_edge(exti, self.i, edge);
+    }
+
+    fn enable_interrupt(&mut self, exti: &mut EXTI) {
+        exti::enable_interrupt(exti, self.i);
+    }
+
+    fn disable_interrupt(&mut self, exti: &mut EXTI) {
+        exti::disable_interrupt(exti, self.i);
+    }
+
+    fn clear_interrupt_pending_bit(&mut self) {
+        exti::clear_interrupt_pending_bit(self.i);
+    }
+
+    fn check_interrupt(&self) -> bool {
+        exti::check_interrupt(self.i)
+    }
+}
+
 macro_rules! gpio {
     ($GPIOX:ident, $gpiox:ident, $iopxenr:ident, $PXx:ident, [
         $($PXi:ident: ($pxi:ident, $i:expr, $MODE:ty),)+
@@ -64,7 +211,7 @@ macro_rules! gpio {
         /// GPIO Block
         pub mod $gpiox {
             use core::marker::PhantomData;
-            use hal::digital::{InputPin, OutputPin};
+            use hal::digital::{InputPin, OutputPin, StatefulOutputPin, ToggleableOutputPin};
             use stm32h7::stm32h7x3::{$GPIOX};
             use super::*;
 
@@ -101,6 +248,48 @@ macro_rules! gpio {
                 }
             }
 
+            impl<MODE> StatefulOutputPin for $PXx<Output<MODE>> {
+                fn is_set_high(&self) -> bool {
+                    !self.is_set_low()
+                }
+
+                fn is_set_low(&self) -> bool {
+                    unsafe { (*$GPIOX::ptr()).odr.read().bits() & (1 << self.i) == 0 }
+                }
+            }
+
+            impl<MODE> ToggleableOutputPin for $PXx<Output<MODE>> {
+                fn toggle(&mut self) {
+                    if self.is_set_low() { self.set_high() } else { self.set_low() }
+                }
+            }
+
+            impl<MODE> ExtiPin for $PXx<Input<MODE>> {
+                fn make_interrupt_source(&mut self, syscfg: &mut SysCfg) {
+                    exti::make_interrupt_source(syscfg, exti::port_index($GPIOX::ptr()), self.i);
+                }
+
+                fn trigger_on_edge(&mut self, exti: &mut EXTI, edge: Edge) {
+                    exti::trigger_on_edge(exti, self.i, edge);
+                }
+
+                fn enable_interrupt(&mut self, exti: &mut EXTI) {
+                    exti::enable_interrupt(exti, self.i);
+                }
+
+                fn disable_interrupt(&mut self, exti: &mut EXTI) {
+                    exti::disable_interrupt(exti, self.i);
+                }
+
+                fn clear_interrupt_pending_bit(&mut self) {
+                    exti::clear_interrupt_pending_bit(self.i);
+                }
+
+                fn check_interrupt(&self) -> bool {
+                    exti::check_interrupt(self.i)
+                }
+            }
+
             impl<MODE> InputPin for $PXx<Input<MODE>> {
                 fn is_high(&self) -> bool {
                     !self.is_low()
@@ -121,12 +310,11 @@ macro_rules! gpio {
                     });
                     if offset2 < 32 {
                         &(*$GPIOX::ptr()).afrl.modify(|r, w| {
-                            w.bits((r.bits() & !(0b1111 << offset2)) | (mode << offset2))
+                            w.bits(with_alternate_function(r.bits(), index, mode))
                         });
                     } else {
-                        let offset2 = offset2 - 32;
                         &(*$GPIOX::ptr()).afrh.modify(|r, w| {
-                            w.bits((r.bits() & !(0b1111 << offset2)) | (mode << offset2))
+                            w.bits(with_alternate_function(r.bits(), index, mode))
                         });
                     }
                 }
@@ -140,6 +328,32 @@ macro_rules! gpio {
                 }
 
                 impl<IoMode, AlternateMode> $PXi<IoMode, AlternateMode> {
+                    /// Configures the pin to operate in the alternate function `AF`
+                    pub fn into_alternate<AF: AlternateFunction>(
+                        self,
+                    ) -> $PXi<IoMode, AF> {
+                        _set_alternate_mode($i, AF::NUMBER);
+                        $PXi {_io_mode: PhantomData, _af_mode: PhantomData,}
+                    }
+
+                    /// Configures the pin to operate in the alternate function `AF` with an
+                    /// open drain output and without pull resistor, as it is used by I2C
+                    pub fn into_alternate_open_drain<AF: AlternateFunction>(
+                        self,
+                    ) -> $PXi<Output<OpenDrain>, AF> {
+                        _set_alternate_mode($i, AF::NUMBER);
+                        let offset = 2 * $i;
+                        unsafe {
+                            (*$GPIOX::ptr()).pupdr.modify(|r, w| {
+                                w.bits((r.bits() & !(0b11 << offset)) | (0b00 << offset))
+                            });
+                            (*$GPIOX::ptr()).otyper.modify(|r, w| {
+                                w.bits(r.bits() | (0b1 << $i))
+                            });
+                        }
+                        $PXi {_io_mode: PhantomData, _af_mode: PhantomData,}
+                    }
+
                     /// Configures the pin to operate in AF0 mode
                     pub fn into_alternate_af0(
                         self,
@@ -314,6 +528,22 @@ macro_rules! gpio {
                         $PXi {_io_mode: PhantomData, _af_mode: PhantomData,}
                     }
 
+                    /// Configures the pin to operate as an analog pin, e.g. for the ADC or the DAC
+                    pub fn into_analog(
+                        self,
+                    ) -> $PXi<Analog, AF0> {
+                        let offset = 2 * $i;
+                        unsafe {
+                            (*$GPIOX::ptr()).moder.modify(|r, w| {
+                                w.bits((r.bits() & !(0b11 << offset)) | (0b11 << offset))
+                            });
+                            (*$GPIOX::ptr()).pupdr.modify(|r, w| {
+                                w.bits((r.bits() & !(0b11 << offset)) | (0b00 << offset))
+                            });
+                        }
+                        $PXi {_io_mode: PhantomData, _af_mode: PhantomData,}
+                    }
+
                     /// Configures the pin to operate as an open drain output pin
                     pub fn into_open_drain_output(
                         self,
@@ -371,18 +601,36 @@ macro_rules! gpio {
                 }
 
                 impl<IoMode, AlternateMode> $PXi<IoMode, AlternateMode> {
+                    /// Sets the output speed of the pin
+                    pub fn set_speed(&mut self, speed: Speed) {
+                        let offset = 2 * $i;
+                        unsafe {
+                            (*$GPIOX::ptr()).ospeedr.modify(|r, w| {
+                                w.bits((r.bits() & !(0b11 << offset)) | ((speed as u32) << offset))
+                            });
+                        }
+                    }
+
                     /// Turns pin alternate configuration pin into open drain
                     pub fn set_open_drain(self) -> $PXi<Output<OpenDrain>, AlternateMode> {
                         let offset = $i;
                         unsafe {
                             &(*$GPIOX::ptr()).otyper.modify(|r, w| {
-                                w.bits(r.bits() & (1 << offset))
+                                w.bits(r.bits() | (1 << offset))
                          })};
 
                         $PXi {_io_mode: PhantomData, _af_mode: PhantomData,}
                     }
                 }
 
+                impl<IoMode, AlternateMode> GpioPin for $PXi<IoMode, AlternateMode> {
+                    const NUMBER: u8 = $i;
+
+                    fn port() -> *const GpioRegisterBlock {
+                        $GPIOX::ptr()
+                    }
+                }
+
                 impl<OutputMode, AlternateMode> $PXi<Output<OutputMode>, AlternateMode> {
                     /// Erases the pin number from the type
                     ///
@@ -394,6 +642,11 @@ macro_rules! gpio {
                             _mode: self._io_mode,
                         }
                     }
+
+                    /// Erases the pin number and the port from the type
+                    pub fn erase(self) -> ErasedPin<Output<OutputMode>> {
+                        self.downgrade().downgrade()
+                    }
                 }
 
                 impl<OutputMode> OutputPin for $PXi<Output<OutputMode>, AF0> {
@@ -406,6 +659,24 @@ macro_rules! gpio {
                     }
                 }
 
+                impl<OutputMode> StatefulOutputPin for $PXi<Output<OutputMode>, AF0> {
+                    fn is_set_high(&self) -> bool {
+                        !self.is_set_low()
+                    }
+
+                    fn is_set_low(&self) -> bool {
+                        unsafe { (*$GPIOX::ptr()).odr.read().bits() & (1 << $i) == 0 }
+                    }
+                }
+
+                impl<OutputMode> ToggleableOutputPin for $PXi<Output<OutputMode>, AF0> {
+                    /// Reads ODR once and sets or resets the pin through BSRR, the other
+                    /// pins of the port are not touched
+                    fn toggle(&mut self) {
+                        if self.is_set_low() { self.set_high() } else { self.set_low() }
+                    }
+                }
+
                 impl<InputMode, AlternateMode> $PXi<Input<InputMode>, AlternateMode> {
                     /// Erases the pin number from the type
                     ///
@@ -417,6 +688,37 @@ macro_rules! gpio {
                             _mode: self._io_mode,
                         }
                     }
+
+                    /// Erases the pin number and the port from the type
+                    pub fn erase(self) -> ErasedPin<Input<InputMode>> {
+                        self.downgrade().downgrade()
+                    }
+                }
+
+                impl<InputMode, AlternateMode> ExtiPin for $PXi<Input<InputMode>, AlternateMode> {
+                    fn make_interrupt_source(&mut self, syscfg: &mut SysCfg) {
+                        exti::make_interrupt_source(syscfg, exti::port_index($GPIOX::ptr()), $i);
+                    }
+
+                    fn trigger_on_edge(&mut self, exti: &mut EXTI, edge: Edge) {
+                        exti::trigger_on_edge(exti, $i, edge);
+                    }
+
+                    fn enable_interrupt(&mut self, exti: &mut EXTI) {
+                        exti::enable_interrupt(exti, $i);
+                    }
+
+                    fn disable_interrupt(&mut self, exti: &mut EXTI) {
+                        exti::disable_interrupt(exti, $i);
+                    }
+
+                    fn clear_interrupt_pending_bit(&mut self) {
+                        exti::clear_interrupt_pending_bit($i);
+                    }
+
+                    fn check_interrupt(&self) -> bool {
+                        exti::check_interrupt($i)
+                    }
                 }
 
                 impl<InputMode> InputPin for $PXi<Input<InputMode>, AF0> {
@@ -435,6 +737,15 @@ macro_rules! gpio {
                     {
                         self.i
                     }
+
+                    /// Erases the port from the type
+                    pub fn downgrade(self) -> ErasedPin<TYPE> {
+                        ErasedPin {
+                            i: self.i,
+                            port: $GPIOX::ptr(),
+                            _mode: self._mode,
+                        }
+                    }
                 }
         }
     }
@@ -639,4 +950,21 @@ gpio!(GPIOK, gpiok, gpioken, PK, [
     PK5: (pk5, 5, Input<Floating>),
     PK6: (pk6, 6, Input<Floating>),
     PK7: (pk7, 7, Input<Floating>),
-]);
\ No newline at end of file
+]);
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn af7_programs_afrl() {
+        assert_eq!(with_alternate_function(0, 3, AF7::NUMBER), 0x0000_7000);
+        // the other pins keep their alternate function
+        assert_eq!(with_alternate_function(0xFFFF_FFFF, 3, AF7::NUMBER), 0xFFFF_7FFF);
+    }
+
+    #[test]
+    fn af7_programs_afrh() {
+        // pin 9 is the second pin of AFRH
+        assert_eq!(with_alternate_function(0, 9, AF7::NUMBER), 0x0000_0070);
+    }
+}
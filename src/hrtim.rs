@@ -0,0 +1,270 @@
+//! High Resolution Timer
+//!
+//! Each of the timing units A to E generates a PWM signal on its first output: the output
+//! is set at the end of the period and reset on compare 1. The periods and duty cycles are
+//! given in ticks of the counter clock, whose frequency is returned by `tick_frequency`
+//!
+//! The HRTIM of the STM32H7 has no delay locked loop, the counter runs at most at the HRTIM
+//! clock instead of 32 times of it. Selecting the CPU clock with `CFGR::hrtim_src` gives
+//! the finest resolution
+
+use cortex_m::interrupt;
+use hal::PwmPin;
+
+use crate::gpio::gpioa::{PA9, PA11};
+use crate::gpio::gpioc::{PC6, PC8};
+use crate::gpio::gpiog::PG6;
+use crate::gpio::{Output, PushPull, AF1, AF2};
+use crate::rcc::{Clocks, Enable, Reset, APB2};
+use crate::stm32h7x3::{
+    HRTIM_COMMON, HRTIM_MASTER, HRTIM_TIMA, HRTIM_TIMB, HRTIM_TIMC, HRTIM_TIMD, HRTIM_TIME,
+};
+use crate::time::Hertz;
+
+/// The smallest period and compare value the timing units support without the DLL
+const MIN_TICKS: u16 = 0x0003;
+/// The largest period and compare value the timing units support without the DLL
+const MAX_TICKS: u16 = 0xFFFD;
+
+/// Output 1 pin of a timing unit - DO NOT IMPLEMENT THIS TRAIT
+///
+/// # Safety
+///
+/// Only the pins whose alternate function connects them to output 1 of `TIM` may
+/// implement this trait
+pub unsafe trait Out1Pin<TIM> {}
+
+unsafe impl Out1Pin<HRTIM_TIMA> for PC6<Output<PushPull>, AF1> {}
+unsafe impl Out1Pin<HRTIM_TIMB> for PC8<Output<PushPull>, AF1> {}
+unsafe impl Out1Pin<HRTIM_TIMC> for PA9<Output<PushPull>, AF2> {}
+unsafe impl Out1Pin<HRTIM_TIMD> for PA11<Output<PushPull>, AF2> {}
+unsafe impl Out1Pin<HRTIM_TIME> for PG6<Output<PushPull>, AF2> {}
+
+/// The master timer and the common registers of the HRTIM
+///
+/// The timing units are started through the master timer, so it has to be owned by the
+/// application while they are used
+pub struct Hrtim {
+    common: HRTIM_COMMON,
+    master: HRTIM_MASTER,
+    hrtim_ck: Hertz,
+}
+
+impl Hrtim {
+    /// Enables and resets the HRTIM, the clock is selected with `CFGR::hrtim_src`
+    pub fn new(
+        common: HRTIM_COMMON,
+        master: HRTIM_MASTER,
+        apb2: &mut APB2,
+        clocks: &Clocks,
+    ) -> Self {
+        HRTIM_COMMON::enable(apb2);
+        HRTIM_COMMON::reset(apb2);
+
+        Hrtim {
+            common,
+            master,
+            hrtim_ck: clocks.hrtim_ck(),
+        }
+    }
+
+    /// Releases the master timer and the common registers
+    pub fn free(self) -> (HRTIM_COMMON, HRTIM_MASTER) {
+        (self.common, self.master)
+    }
+}
+
+/// Extension trait to use a timing unit of the HRTIM as PWM generator
+pub trait HrPwmExt: Sized {
+    /// Configures the timing unit to generate a PWM signal of `freq` on `pin`, the output
+    /// starts disabled with a duty cycle of 0
+    ///
+    /// # Panics
+    ///
+    /// Panics if `freq` can't be derived from the HRTIM clock
+    fn hr_pwm<PIN, F>(self, pin: PIN, freq: F, hrtim: &mut Hrtim) -> HrPwm<Self, PIN>
+    where
+        PIN: Out1Pin<Self>,
+        F: Into<Hertz>;
+}
+
+/// A PWM output of a timing unit of the HRTIM
+pub struct HrPwm<TIM, PIN> {
+    tim: TIM,
+    pin: PIN,
+    tick_frequency: Hertz,
+    duty: u16,
+}
+
+/// Selects CKPSC for `ticks` HRTIM clock cycles per period
+///
+/// Without the DLL only CKPSC 5 to 7 are usable, they divide the HRTIM clock by 1, 2 and 4
+fn prescaler(ticks: u32) -> (u8, u16) {
+    for ck_psc in 5..=7 {
+        let period = ticks >> (ck_psc - 5);
+        if period <= u32::from(MAX_TICKS) {
+            assert!(period >= u32::from(MIN_TICKS), "impossible frequency");
+            return (ck_psc, period as u16);
+        }
+    }
+    panic!("impossible frequency");
+}
+
+macro_rules! hrtim {
+    ($($TIMX:ident: (
+        $timxcr:ident, $perxr:ident, $cmp1xr:ident, $repxr:ident, $setx1r:ident, $rstx1r:ident,
+        $txcen:ident, $txswu:ident, $tx1oen:ident, $tx1odis:ident
+    ),)+) => {
+        $(
+            impl HrPwmExt for $TIMX {
+                fn hr_pwm<PIN, F>(self, pin: PIN, freq: F, hrtim: &mut Hrtim) -> HrPwm<$TIMX, PIN>
+                where
+                    PIN: Out1Pin<$TIMX>,
+                    F: Into<Hertz>,
+                {
+                    let tim = self;
+
+                    let (ck_psc, period) = prescaler(hrtim.hrtim_ck.0 / freq.into().0.max(1));
+
+                    // continuous mode, the preloaded registers are updated at the end of
+                    // each period (a repetition counter of 0)
+                    tim.$timxcr.write(|w| unsafe {
+                        w.ck_pscx().bits(ck_psc)
+                            .cont().set_bit()
+                            .preen().set_bit()
+                            .tx_repu().set_bit()
+                    });
+                    tim.$repxr.write(|w| unsafe { w.repx().bits(0) });
+                    tim.$perxr.write(|w| unsafe { w.perx().bits(period) });
+                    tim.$cmp1xr.write(|w| unsafe { w.cmp1x().bits(MIN_TICKS) });
+                    // a duty cycle of 0: the output is never set
+                    tim.$setx1r.reset();
+                    tim.$rstx1r.write(|w| w.cmp1().set_bit());
+
+                    // load the preloaded registers, then start counting
+                    hrtim.common.cr2.write(|w| w.$txswu().set_bit());
+                    hrtim.master.mcr.modify(|_, w| w.$txcen().set_bit());
+
+                    HrPwm {
+                        tim,
+                        pin,
+                        tick_frequency: Hertz(hrtim.hrtim_ck.0 >> (ck_psc - 5)),
+                        duty: 0,
+                    }
+                }
+            }
+
+            impl<PIN> HrPwm<$TIMX, PIN> {
+                /// The frequency of the ticks the period and the duty cycle are given in
+                pub fn tick_frequency(&self) -> Hertz {
+                    self.tick_frequency
+                }
+
+                /// Returns the period in ticks
+                pub fn get_period(&self) -> u16 {
+                    self.tim.$perxr.read().perx().bits()
+                }
+
+                /// Sets the period in ticks, it takes effect at the end of the current period
+                ///
+                /// The duty cycle is kept in ticks, so its ratio to the period changes
+                ///
+                /// # Panics
+                ///
+                /// Panics if the period is outside of 3 to 65533 ticks
+                pub fn set_period(&mut self, ticks: u16) {
+                    assert!(
+                        (MIN_TICKS..=MAX_TICKS).contains(&ticks),
+                        "the period has to be 3 to 65533 ticks"
+                    );
+                    self.tim.$perxr.write(|w| unsafe { w.perx().bits(ticks) });
+                    let duty = self.duty;
+                    self.set_duty(duty);
+                }
+
+                /// Returns the duty cycle in ticks
+                pub fn get_duty(&self) -> u16 {
+                    self.duty
+                }
+
+                /// Sets the duty cycle in ticks, it takes effect at the end of the current
+                /// period
+                ///
+                /// 0 keeps the output low and a duty cycle of at least the period keeps it
+                /// high, other duty cycles are at least 3 ticks long
+                pub fn set_duty(&mut self, ticks: u16) {
+                    self.duty = ticks;
+                    let period = self.get_period();
+
+                    if ticks == 0 {
+                        self.tim.$setx1r.reset();
+                        self.tim.$rstx1r.write(|w| w.cmp1().set_bit());
+                    } else if ticks >= period {
+                        self.tim.$setx1r.write(|w| w.per().set_bit());
+                        self.tim.$rstx1r.reset();
+                    } else {
+                        let ticks = ticks.max(MIN_TICKS);
+                        self.tim.$cmp1xr.write(|w| unsafe { w.cmp1x().bits(ticks) });
+                        self.tim.$setx1r.write(|w| w.per().set_bit());
+                        self.tim.$rstx1r.write(|w| w.cmp1().set_bit());
+                    }
+                }
+
+                /// Drives the output pin with the PWM signal
+                pub fn enable_output(&mut self) {
+                    // NOTE(unsafe) write 1 to set register, the other outputs are not affected
+                    unsafe { (*HRTIM_COMMON::ptr()).oenr.write(|w| w.$tx1oen().set_bit()) };
+                }
+
+                /// Puts the output pin into its inactive state
+                pub fn disable_output(&mut self) {
+                    // NOTE(unsafe) write 1 to set register, the other outputs are not affected
+                    unsafe { (*HRTIM_COMMON::ptr()).disr.write(|w| w.$tx1odis().set_bit()) };
+                }
+
+                /// Stops the timing unit and releases it and the pin
+                pub fn free(mut self) -> ($TIMX, PIN) {
+                    self.disable_output();
+                    // NOTE(unsafe) MCR is shared with the other timing units, the critical
+                    // section makes the read-modify-write atomic
+                    interrupt::free(|_| unsafe {
+                        (*HRTIM_MASTER::ptr()).mcr.modify(|_, w| w.$txcen().clear_bit())
+                    });
+                    (self.tim, self.pin)
+                }
+            }
+
+            impl<PIN> PwmPin for HrPwm<$TIMX, PIN> {
+                type Duty = u16;
+
+                fn disable(&mut self) {
+                    self.disable_output()
+                }
+
+                fn enable(&mut self) {
+                    self.enable_output()
+                }
+
+                fn get_duty(&self) -> u16 {
+                    HrPwm::<$TIMX, PIN>::get_duty(self)
+                }
+
+                fn get_max_duty(&self) -> u16 {
+                    self.get_period()
+                }
+
+                fn set_duty(&mut self, duty: u16) {
+                    HrPwm::<$TIMX, PIN>::set_duty(self, duty)
+                }
+            }
+        )+
+    };
+}
+
+hrtim!(
+    HRTIM_TIMA: (timacr, perar, cmp1ar, repar, seta1r, rsta1r, tacen, taswu, ta1oen, ta1odis),
+    HRTIM_TIMB: (timbcr, perbr, cmp1br, repbr, setb1r, rstb1r, tbcen, tbswu, tb1oen, tb1odis),
+    HRTIM_TIMC: (timccr, percr, cmp1cr, repcr, setc1r, rstc1r, tccen, tcswu, tc1oen, tc1odis),
+    HRTIM_TIMD: (timdcr, perdr, cmp1dr, repdr, setd1r, rstd1r, tdcen, tdswu, td1oen, td1odis),
+    HRTIM_TIME: (timecr, perer, cmp1er, reper, sete1r, rste1r, tecen, teswu, te1oen, te1odis),
+);
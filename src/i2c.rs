@@ -7,12 +7,15 @@ use crate::gpio::gpioc::PC9;
 use crate::gpio::gpiod::{PD12, PD13};
 use crate::gpio::gpiof::{PF0, PF1, PF14, PF15};
 use crate::gpio::gpioh::{PH11, PH12, PH4, PH5, PH7, PH8};
-use crate::gpio::{AF4, Output, OpenDrain};
-use crate::rcc::{Clocks, APB1L, APB4};
+use crate::gpio::{AF4, GpioPin, Output, OpenDrain};
+use crate::rcc::{Clocks, Enable, Reset, APB1L, APB4};
 use crate::time::Hertz;
+use crate::timer::{self, MonoTimer};
+use cortex_m::asm;
 use hal::blocking::i2c::{Write, WriteRead, Read};
-use stm32h7::stm32h7x3::{I2C1, I2C2, I2C3, I2C4};
-use cast::{u8, u16};
+use stm32h7::stm32h7x3::{I2C1, I2C2, I2C3, I2C4, TIM2, TIM5};
+use stm32h7::stm32h7x3::gpioa::RegisterBlock as GpioRegisterBlock;
+use cast::u16;
 
 
 /// I2C error
@@ -22,21 +25,25 @@ pub enum Error {
     Bus,
     /// Arbitration loss
     Arbitration,
+    /// The slave did not acknowledge its address or a data byte
+    Nack,
+    /// The bus did not respond in time, see `set_bus_timeout` and `with_timeout`
+    Timeout,
     // Overrun, // slave mode only
     // Pec, // SMBUS mode only
-    // Timeout, // SMBUS mode only
     // Alert, // SMBUS mode only
     #[doc(hidden)]
     _Extensible,
 }
 
 /// A trait to represent the SCL Pin of an I2C Port
-pub unsafe trait SclPin<I2C> {}
+pub unsafe trait SclPin<I2C>: GpioPin {}
 
 /// A trait to represent the SDL Pin of an I2C Port
-pub unsafe trait SdaPin<I2C> {}
+pub unsafe trait SdaPin<I2C>: GpioPin {}
 
 // I2C1_SCL
+unsafe impl SclPin<I2C1> for PB6<Output<OpenDrain>, AF4> {}
 unsafe impl SclPin<I2C1> for PB8<Output<OpenDrain>, AF4> {}
 
 // I2C1_SDA
@@ -75,20 +82,204 @@ unsafe impl SdaPin<I2C4> for PD13<Output<OpenDrain>, AF4> {}
 unsafe impl SdaPin<I2C4> for PF15<Output<OpenDrain>, AF4> {}
 unsafe impl SdaPin<I2C4> for PH12<Output<OpenDrain>, AF4> {}
 
-pub struct I2c<I2C, PINS> {
+/// I2C peripheral operating in master mode
+pub struct I2c<I2C, PINS, TIMEOUT = NoTimeout> {
     i2c: I2C,
     pins: PINS,
+    clocks: Clocks,
+    timeout: TIMEOUT,
+}
+
+/// Bounds the time the blocking transfers wait for the bus, see `I2c::with_timeout`
+pub trait Timeout {
+    /// A point in time
+    type Instant: Copy;
+
+    /// The current point in time
+    fn now(&self) -> Self::Instant;
+
+    /// Whether the timeout passed since `start`
+    fn expired(&self, start: Self::Instant) -> bool;
+}
+
+/// The blocking transfers wait for the bus without a time limit
+pub struct NoTimeout;
+
+impl Timeout for NoTimeout {
+    type Instant = ();
+
+    fn now(&self) {}
+
+    fn expired(&self, _start: ()) -> bool {
+        false
+    }
+}
+
+/// Limits the time the blocking transfers wait for the bus with a `MonoTimer`
+pub struct TimerTimeout<TIM> {
+    timer: MonoTimer<TIM>,
+    timeout_us: u32,
+}
+
+macro_rules! timer_timeouts {
+    ($($TIMX:ident,)+) => {
+        $(
+            impl Timeout for TimerTimeout<$TIMX> {
+                type Instant = timer::Instant;
+
+                fn now(&self) -> timer::Instant {
+                    self.timer.now()
+                }
+
+                fn expired(&self, start: timer::Instant) -> bool {
+                    self.timer.elapsed(start) >= self.timeout_us
+                }
+            }
+        )+
+    };
+}
+
+timer_timeouts!(
+    TIM2,
+    TIM5,
+);
+
+/// The bus timings of a speed mode as specified by the I2C-bus specification, in ns
+struct Mode {
+    /// Minimum data setup time, tSU;DAT
+    su_dat: u64,
+    /// Maximum data valid time, tVD;DAT
+    vd_dat: u64,
+    /// Minimum low period of SCL, tLOW
+    low: u64,
+    /// Minimum high period of SCL, tHIGH
+    high: u64,
+    /// Maximum rise time, tr
+    rise: u64,
+    /// Maximum fall time, tf
+    fall: u64,
+}
+
+const STANDARD_MODE: Mode = Mode { su_dat: 250, vd_dat: 3450, low: 4700, high: 4000, rise: 1000, fall: 300 };
+const FAST_MODE: Mode = Mode { su_dat: 100, vd_dat: 900, low: 1300, high: 600, rise: 300, fall: 300 };
+const FAST_MODE_PLUS: Mode = Mode { su_dat: 50, vd_dat: 450, low: 500, high: 260, rise: 120, fall: 120 };
+
+/// Minimum and maximum delay of the analog noise filter, in ns
+const T_AF_MIN: u64 = 50;
+const T_AF_MAX: u64 = 260;
+
+/// Computes the fields PRESC, SCLL, SCLH, SDADEL and SCLDEL of TIMINGR for a SCL
+/// frequency of at most `freq`, with the analog filter enabled and the digital filter
+/// disabled, see section "I2C timings" of RM0433
+///
+/// Returns `None` if the kernel clock is too slow or too fast for the requested frequency
+fn timing(i2c_ck: u32, freq: u32) -> Option<(u8, u8, u8, u8, u8)> {
+    let mode = match freq {
+        0 => return None,
+        1..=100_000 => STANDARD_MODE,
+        100_001..=400_000 => FAST_MODE,
+        400_001..=1_000_000 => FAST_MODE_PLUS,
+        _ => return None,
+    };
+
+    // all times in ps to keep enough precision at high kernel clocks
+    let ps = |ns: u64| ns * 1_000;
+    let t_i2c_ck = 1_000_000_000_000 / u64::from(i2c_ck);
+    let t_scl = 1_000_000_000_000 / u64::from(freq);
+
+    // the kernel clock has to be fast enough to sample SCL
+    if 4 * t_i2c_ck >= ps(mode.low - T_AF_MAX) || t_i2c_ck >= ps(mode.high) {
+        return None;
+    }
+
+    // both edges are delayed by the rise and fall times, the analog filter and the
+    // synchronization to the kernel clock, these delays are not counted by SCLL and SCLH
+    let t_sync = ps(mode.rise + mode.fall + 2 * T_AF_MIN) + 4 * t_i2c_ck;
+
+    for presc in 0..16u64 {
+        let t_presc = (presc + 1) * t_i2c_ck;
+
+        // tSDADEL >= tf - tAF(min) - 3 * tI2CCLK
+        let sdadel_min = ps(mode.fall).saturating_sub(ps(T_AF_MIN) + 3 * t_i2c_ck);
+        let sdadel = sdadel_min.div_ceil(t_presc);
+        // tSDADEL <= tVD;DAT(max) - tr - tAF(max) - 4 * tI2CCLK
+        let sdadel_max = ps(mode.vd_dat - mode.rise - T_AF_MAX).saturating_sub(4 * t_i2c_ck);
+        if sdadel > 15 || sdadel * t_presc > sdadel_max {
+            continue;
+        }
+
+        // tSCLDEL = (SCLDEL + 1) * tPRESC >= tr + tSU;DAT(min)
+        let scldel = ps(mode.rise + mode.su_dat).div_ceil(t_presc);
+        let scldel = if scldel > 0 { scldel - 1 } else { 0 };
+        if scldel > 15 {
+            continue;
+        }
+
+        let low_min = ps(mode.low).div_ceil(t_presc);
+        let high_min = ps(mode.high).div_ceil(t_presc);
+        let total = t_scl.saturating_sub(t_sync).div_ceil(t_presc);
+        let spare = total.saturating_sub(low_min + high_min);
+
+        // the low period gets the odd cycle
+        let low = low_min + spare - spare / 2;
+        let high = high_min + spare / 2;
+        if low > 256 || high > 256 {
+            continue;
+        }
+
+        return Some((presc as u8, (low - 1) as u8, (high - 1) as u8, sdadel as u8, scldel as u8));
+    }
+
+    None
+}
+
+/// Sets the MODER field of pin `i`
+unsafe fn set_mode(port: *const GpioRegisterBlock, i: u8, mode: u32) {
+    let offset = 2 * i;
+    (*port).moder.modify(|r, w| w.bits((r.bits() & !(0b11 << offset)) | (mode << offset)));
+}
+
+macro_rules! timeout_reset {
+    ($i2c:expr) => {
+        $i2c.icr.write(|w| w.timoutcf().set_bit());
+        // a software reset releases SCL and SDA and resets the state machine
+        $i2c.cr1.modify(|_, w| w.pe().clear_bit());
+        while $i2c.cr1.read().pe().bit_is_set() {}
+        $i2c.cr1.modify(|_, w| w.pe().set_bit());
+        return Err(Error::Timeout);
+    };
 }
 
 macro_rules! busy_wait {
-    ($i2c:expr, $flag:ident) => {
+    ($i2c:expr, $timeout:expr, $flag:ident) => {
+        let start = $timeout.now();
         loop {
             let isr = $i2c.isr.read();
 
             if isr.berr().bit_is_set() {
+                $i2c.icr.write(|w| w.berrcf().set_bit());
                 return Err(Error::Bus);
             } else if isr.arlo().bit_is_set() {
+                $i2c.icr.write(|w| w.arlocf().set_bit());
                 return Err(Error::Arbitration);
+            } else if isr.nackf().bit_is_set() {
+                // the hardware generates a STOP after a NACK, wait for it before the
+                // next transfer may start
+                loop {
+                    let isr = $i2c.isr.read();
+
+                    if isr.stopf().bit_is_set() {
+                        $i2c.icr.write(|w| w.nackcf().set_bit().stopcf().set_bit());
+                        // flush a byte that is left in TXDR
+                        $i2c.isr.write(|w| w.txe().set_bit());
+                        return Err(Error::Nack);
+                    } else if isr.timeout().bit_is_set() || $timeout.expired(start) {
+                        $i2c.icr.write(|w| w.nackcf().set_bit());
+                        timeout_reset!($i2c);
+                    }
+                }
+            } else if isr.timeout().bit_is_set() || $timeout.expired(start) {
+                timeout_reset!($i2c);
             } else if isr.$flag().bit_is_set() {
                 break;
             } else {
@@ -99,10 +290,18 @@ macro_rules! busy_wait {
 }
 
 macro_rules! i2c {
-    ($($I2CX:ident: ($i2cX:ident, $i2cXen:ident, $i2cXrst:ident, $APBX:ident, $PCLKX:ident),)+) => {
+    ($($I2CX:ident: ($i2cX:ident, $APBX:ident, $i2cX_ck:ident),)+) => {
         $(
             impl<SCL, SDA> I2c<$I2CX, (SCL, SDA)> {
-                /// Basically a new function for the I2C peripheral
+                /// Configures an I2C peripheral as master, the SCL frequency is the
+                /// highest one possible that does not exceed `freq`
+                ///
+                /// Standard mode is used up to 100 kHz, fast mode up to 400 kHz and fast
+                /// mode plus up to 1 MHz
+                ///
+                /// # Panics
+                ///
+                /// Panics if the kernel clock of the peripheral can not generate `freq`
                 pub fn $i2cX<F> (
                     i2c: $I2CX,
                     pins: (SCL, SDA),
@@ -114,81 +313,16 @@ macro_rules! i2c {
                     SCL: SclPin<$I2CX>,
                     SDA: SdaPin<$I2CX>,
                 {
-                    apb.enr().modify(|_, w| w.$i2cXen().set_bit());
-                    apb.rstr().modify(|_, w| w.$i2cXrst().set_bit());
-                    apb.rstr().modify(|_, w| w.$i2cXrst().clear_bit());
+                    $I2CX::enable(apb);
+                    $I2CX::reset(apb);
 
-                    let freq = freq.into().0;
-
-                    assert!(freq <= 1_000_000);
-
-                    let i2cclk = clocks.$PCLKX().0;    
+                    let (presc, scll, sclh, sdadel, scldel) = timing(clocks.$i2cX_ck().0, freq.into().0)
+                        .expect("the I2C kernel clock can not generate the requested frequency");
 
                     // Refer to figure 539 for this:
-                    // Clear PE bit in I2C_CR1
-                    unsafe { &(*I2C1::ptr()).cr1.modify(|_, w| w.pe().clear_bit())};
-
-                    // Enable the Analog Noise Filter by setting ANFOFF (Analog Noise Filter OFF) to 0
-                    // This is usually enabled by default but you never know
-                    unsafe { &(*I2C1::ptr()).cr1.modify(|_, w| w.anfoff().clear_bit())};
-
-                    // experimental, not sure if this works
-                                    // TODO review compliance with the timing requirements of I2C
-                    // t_I2CCLK = 1 / PCLK1
-                    // t_PRESC  = (PRESC + 1) * t_I2CCLK
-                    // t_SCLL   = (SCLL + 1) * t_PRESC
-                    // t_SCLH   = (SCLH + 1) * t_PRESC
-                    //
-                    // t_SYNC1 + t_SYNC2 > 4 * t_I2CCLK
-                    // t_SCL ~= t_SYNC1 + t_SYNC2 + t_SCLL + t_SCLH
-                    let ratio = i2cclk / freq - 4;
-                    let (presc, scll, sclh, sdadel, scldel) = if freq > 100_000 {
-                        // fast-mode or fast-mode plus
-                        // here we pick SCLL + 1 = 2 * (SCLH + 1)
-                        let presc = ratio / 387;
-
-                        let sclh = ((ratio / (presc + 1)) - 3) / 3;
-                        let scll = 2 * (sclh + 1) - 1;
-
-                        let (sdadel, scldel) = if freq > 400_000 {
-                            // fast-mode plus
-                            let sdadel = 0;
-                            let scldel = i2cclk / 4_000_000 / (presc + 1) - 1;
-
-                            (sdadel, scldel)
-                        } else {
-                            // fast-mode
-                            let sdadel = i2cclk / 8_000_000 / (presc + 1);
-                            let scldel = i2cclk / 2_000_000 / (presc + 1) - 1;
-
-                            (sdadel, scldel)
-                        };
-
-                        (presc, scll, sclh, sdadel, scldel)
-                    } else {
-                        // standard-mode
-                        // here we pick SCLL = SCLH
-                        let presc = ratio / 514;
-                        let sclh = ((ratio / (presc + 1)) - 2) / 2;
-                        let scll = sclh;
-
-                        let sdadel = i2cclk / 2_000_000 / (presc + 1);
-                        let scldel = i2cclk / 800_000 / (presc + 1) - 1;
-
-                        (presc, scll, sclh, sdadel, scldel)
-                    };
-
-                    let presc = u8(presc).unwrap();
-                    //assert!(presc < 16);
-                    let scldel = u8(scldel).unwrap();
-                    //assert!(scldel < 16);
-                    let sdadel = u8(sdadel).unwrap();
-                    //assert!(sdadel < 16);
-                    let sclh = u8(sclh).unwrap();
-                    let scll = u8(scll).unwrap();
-
-                    // Configure for "fast mode" (400 KHz)
-                    i2c.timingr.write(|w| 
+                    // The peripheral is disabled after the reset, the analog noise filter
+                    // is enabled and the digital noise filter disabled
+                    i2c.timingr.write(|w|
                         w.presc()
                             .bits(presc)
                             .scll()
@@ -204,16 +338,131 @@ macro_rules! i2c {
                     // Enable the peripheral
                     i2c.cr1.write(|w| w.pe().set_bit());
 
-                    I2c { i2c, pins}
+                    I2c { i2c, pins, clocks, timeout: NoTimeout }
+                }
 
+                /// Limits the time the blocking transfers wait for the bus to `timeout_us`
+                /// microseconds, measured with `timer`
+                ///
+                /// A transfer that runs out of time returns `Error::Timeout` and resets the
+                /// peripheral, this bounds the transfers even if a slave stretches SCL forever
+                pub fn with_timeout<TIM>(
+                    self,
+                    timer: MonoTimer<TIM>,
+                    timeout_us: u32,
+                ) -> I2c<$I2CX, (SCL, SDA), TimerTimeout<TIM>>
+                where
+                    TimerTimeout<TIM>: Timeout,
+                {
+                    I2c {
+                        i2c: self.i2c,
+                        pins: self.pins,
+                        clocks: self.clocks,
+                        timeout: TimerTimeout { timer, timeout_us },
+                    }
                 }
-                
+
                 /// Releases the I2C peripheral and associated pins
                 pub fn free(self) -> ($I2CX, (SCL, SDA)) {
                     (self.i2c, self.pins)
                 }
             }
-            impl<PINS> Write for I2c<$I2CX, PINS> {
+
+            impl<SCL, SDA, TIM> I2c<$I2CX, (SCL, SDA), TimerTimeout<TIM>> {
+                /// Removes the timeout of the blocking transfers and releases the timer
+                pub fn without_timeout(self) -> (I2c<$I2CX, (SCL, SDA)>, MonoTimer<TIM>) {
+                    let i2c = I2c {
+                        i2c: self.i2c,
+                        pins: self.pins,
+                        clocks: self.clocks,
+                        timeout: NoTimeout,
+                    };
+                    (i2c, self.timeout.timer)
+                }
+            }
+
+            impl<SCL, SDA, TIMEOUT> I2c<$I2CX, (SCL, SDA), TIMEOUT>
+            where
+                SCL: GpioPin,
+                SDA: GpioPin,
+            {
+                /// Enables the hardware timeout of the peripheral, which aborts a transfer
+                /// with `Error::Timeout` if SCL is held low for at least `timeout_us`
+                /// microseconds, `None` disables it
+                ///
+                /// The timeout is limited to 4096 * 2048 periods of the kernel clock
+                pub fn set_bus_timeout(&mut self, timeout_us: Option<u32>) {
+                    // TIMEOUTA can only be written while the timeout is disabled
+                    self.i2c.timeoutr.write(|w| w.timouten().clear_bit());
+
+                    if let Some(timeout_us) = timeout_us {
+                        // tTIMEOUT = (TIMEOUTA + 1) * 2048 * tI2CCLK
+                        let ticks = u64::from(timeout_us) * u64::from(self.clocks.$i2cX_ck().0)
+                            / 1_000_000;
+                        let timeouta = ticks.div_ceil(2048).clamp(1, 4096) - 1;
+
+                        // TIDLE = 0 detects SCL being held low
+                        self.i2c.timeoutr.write(|w| {
+                            w.timeouta().bits(timeouta as u16).tidle().clear_bit()
+                        });
+                        self.i2c.timeoutr.modify(|_, w| w.timouten().set_bit());
+                    }
+                }
+
+                /// Recovers the bus from a slave that holds SDA low, e.g. because it was reset
+                /// in the middle of a transfer
+                ///
+                /// SCL is toggled as GPIO up to 9 times at about 100 kHz until the slave
+                /// releases SDA, followed by a STOP condition
+                pub fn bus_reset(&mut self) {
+                    let scl = SCL::port();
+                    let sda = SDA::port();
+                    let scl_i = SCL::NUMBER;
+                    let sda_i = SDA::NUMBER;
+
+                    // half of a SCL period at 100 kHz
+                    let half_period = self.clocks.sys_ck().0 / 200_000;
+
+                    self.i2c.cr1.modify(|_, w| w.pe().clear_bit());
+
+                    // NOTE(unsafe) the pins are owned by this driver, both are open drain
+                    // outputs already, only their mode is switched from alternate function
+                    // to GPIO output and back
+                    unsafe {
+                        (*scl).bsrr.write(|w| w.bits(1 << scl_i));
+                        (*sda).bsrr.write(|w| w.bits(1 << sda_i));
+                        set_mode(scl, scl_i, 0b01);
+                        set_mode(sda, sda_i, 0b01);
+                        asm::delay(half_period);
+
+                        for _ in 0..9 {
+                            if (*sda).idr.read().bits() & (1 << sda_i) != 0 {
+                                break;
+                            }
+
+                            (*scl).bsrr.write(|w| w.bits(1 << (scl_i + 16)));
+                            asm::delay(half_period);
+                            (*scl).bsrr.write(|w| w.bits(1 << scl_i));
+                            asm::delay(half_period);
+                        }
+
+                        // STOP: SDA rises while SCL is high
+                        (*scl).bsrr.write(|w| w.bits(1 << (scl_i + 16)));
+                        (*sda).bsrr.write(|w| w.bits(1 << (sda_i + 16)));
+                        asm::delay(half_period);
+                        (*scl).bsrr.write(|w| w.bits(1 << scl_i));
+                        asm::delay(half_period);
+                        (*sda).bsrr.write(|w| w.bits(1 << sda_i));
+                        asm::delay(half_period);
+
+                        set_mode(scl, scl_i, 0b10);
+                        set_mode(sda, sda_i, 0b10);
+                    }
+
+                    self.i2c.cr1.modify(|_, w| w.pe().set_bit());
+                }
+            }
+            impl<PINS, TIMEOUT: Timeout> Write for I2c<$I2CX, PINS, TIMEOUT> {
                 type Error = Error;
 
                 fn write(&mut self, addr: u8, bytes: &[u8]) -> Result<(), Error> {
@@ -238,14 +487,14 @@ macro_rules! i2c {
                     for byte in bytes {
                         // Wait until we are allowed to send data (START has been ACKed or last byte
                         // when through)
-                        busy_wait!(self.i2c, txis);
+                        busy_wait!(self.i2c, self.timeout, txis);
 
                         // put byte on the wire
                         self.i2c.txdr.write(|w| w.txdata().bits(*byte));
                     }
 
                     // Wait until the last transmission is finished ???
-                    // busy_wait!(self.i2c, busy);
+                    // busy_wait!(self.i2c, self.timeout, busy);
 
                     // automatic STOP
 
@@ -253,7 +502,7 @@ macro_rules! i2c {
                 }
             }
 
-            impl<PINS> WriteRead for I2c<$I2CX, PINS> {
+            impl<PINS, TIMEOUT: Timeout> WriteRead for I2c<$I2CX, PINS, TIMEOUT> {
                 type Error = Error;
 
                 fn write_read(
@@ -284,18 +533,18 @@ macro_rules! i2c {
                             .clear_bit()
 
                     });
-                    //busy_wait!(self.i2c, addr);
+                    //busy_wait!(self.i2c, self.timeout, addr);
                     for byte in bytes {
                         // Wait until we are allowed to send data (START has been ACKed or last byte
                         // when through)
                         // put byte on the wire
-                        busy_wait!(self.i2c, txis);
+                        busy_wait!(self.i2c, self.timeout, txis);
                         self.i2c.txdr.write(|w| w.txdata().bits(*byte));
                         
                     }
 
                     // Wait until the last transmission is finished
-                    busy_wait!(self.i2c, tc);
+                    busy_wait!(self.i2c, self.timeout, tc);
 
                     // reSTART and prepare to receive bytes into `buffer`
                     self.i2c.cr2.write(|w| {
@@ -314,7 +563,7 @@ macro_rules! i2c {
 
                     for byte in buffer {
                         // Wait until we have received something
-                        busy_wait!(self.i2c, rxne);
+                        busy_wait!(self.i2c, self.timeout, rxne);
 
                         *byte = self.i2c.rxdr.read().rxdata().bits();
                     }
@@ -325,7 +574,7 @@ macro_rules! i2c {
                 }
             }
 
-            impl<PINS> Read for I2c<$I2CX, PINS> {
+            impl<PINS, TIMEOUT: Timeout> Read for I2c<$I2CX, PINS, TIMEOUT> {
             type Error = Error;
 
             fn read(
@@ -355,7 +604,7 @@ macro_rules! i2c {
 
                 for byte in buffer {
                     // Wait until we have received something
-                    busy_wait!(self.i2c, rxne);
+                    busy_wait!(self.i2c, self.timeout, rxne);
 
                     *byte = self.i2c.rxdr.read().rxdata().bits();
                 }
@@ -370,8 +619,69 @@ macro_rules! i2c {
 }
 
 i2c!(
-    I2C1: (i2c1, i2c1en, i2c1rst, APB1L, pclk1),
-    I2C2: (i2c2, i2c2en, i2c2rst, APB1L, pclk4),
-    I2C3: (i2c3, i2c3en, i2c3rst, APB1L, pclk4),
-    I2C4: (i2c4, i2c4en, i2c4rst, APB4, pclk4),
+    I2C1: (i2c1, APB1L, i2c123_ck),
+    I2C2: (i2c2, APB1L, i2c123_ck),
+    I2C3: (i2c3, APB1L, i2c123_ck),
+    I2C4: (i2c4, APB4, i2c4_ck),
 );
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Checks TIMINGR against the constraints of section "I2C timings" of RM0433, which
+    /// the example settings of its table are derived from
+    fn check(i2c_ck: u32, freq: u32, mode: &Mode) {
+        let (presc, scll, sclh, sdadel, scldel) = timing(i2c_ck, freq).unwrap();
+        let ps = |ns: u64| ns * 1_000;
+        let t_i2c_ck = 1_000_000_000_000 / u64::from(i2c_ck);
+        let t_presc = (u64::from(presc) + 1) * t_i2c_ck;
+
+        assert!((u64::from(scll) + 1) * t_presc >= ps(mode.low));
+        assert!((u64::from(sclh) + 1) * t_presc >= ps(mode.high));
+        let t_sdadel = u64::from(sdadel) * t_presc;
+        assert!(t_sdadel + ps(T_AF_MIN) + 3 * t_i2c_ck >= ps(mode.fall));
+        // with the maximum rise time slow kernel clocks can't meet tVD;DAT, SDADEL is 0 then
+        let sdadel_max = ps(mode.vd_dat - mode.rise - T_AF_MAX).saturating_sub(4 * t_i2c_ck);
+        assert!(t_sdadel <= sdadel_max);
+        assert!((u64::from(scldel) + 1) * t_presc >= ps(mode.rise + mode.su_dat));
+
+        // with the slowest edges SCL doesn't exceed `freq`, but stays within 25 % of it
+        let t_sync = ps(mode.rise + mode.fall + 2 * T_AF_MIN) + 4 * t_i2c_ck;
+        let t_scl = (u64::from(scll) + u64::from(sclh) + 2) * t_presc + t_sync;
+        let f_scl = 1_000_000_000_000 / t_scl;
+        assert!(f_scl <= u64::from(freq));
+        assert!(f_scl * 4 >= u64::from(freq) * 3);
+    }
+
+    #[test]
+    fn standard_mode_timing() {
+        // the kernel clocks of the example table and a fast one
+        for &i2c_ck in &[8_000_000, 16_000_000, 48_000_000, 100_000_000] {
+            check(i2c_ck, 100_000, &STANDARD_MODE);
+        }
+    }
+
+    #[test]
+    fn fast_mode_timing() {
+        for &i2c_ck in &[8_000_000, 16_000_000, 48_000_000, 100_000_000] {
+            check(i2c_ck, 400_000, &FAST_MODE);
+        }
+    }
+
+    #[test]
+    fn timing_at_8_mhz() {
+        // the example table uses PRESC = 1 for 100 kHz and PRESC = 0 for 400 kHz, the
+        // solver picks the smallest prescaler that fits
+        assert_eq!(timing(8_000_000, 100_000), Some((0, 37, 31, 0, 9)));
+        assert_eq!(timing(8_000_000, 400_000), Some((0, 10, 4, 0, 3)));
+    }
+
+    #[test]
+    fn timing_rejects_unreachable_frequencies() {
+        assert_eq!(timing(8_000_000, 0), None);
+        assert_eq!(timing(8_000_000, 1_000_001), None);
+        // a 1 MHz kernel clock can't sample the fast mode SCL
+        assert_eq!(timing(1_000_000, 400_000), None);
+    }
+}
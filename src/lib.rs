@@ -2,19 +2,55 @@
 
 extern crate cast;
 extern crate cortex_m;
+extern crate embedded_can;
 extern crate embedded_hal as hal;
+extern crate embedded_sdmmc;
 extern crate nb;
+extern crate rand_core;
+extern crate synopsys_usb_otg;
 extern crate void;
 
+#[cfg(any(
+    all(feature = "h743", feature = "h753"),
+    all(feature = "h743", feature = "h750"),
+    all(feature = "h753", feature = "h750"),
+))]
+compile_error!("only one of the features h743, h753 and h750 can be selected");
+
+pub mod adc;
+pub mod cache;
+pub mod comp;
+pub mod crc;
+pub mod crs;
+pub mod dac;
 pub mod gpio;
 pub mod flash;
+pub mod fmc;
 pub mod i2c;
+pub mod lptim;
 pub mod rcc;
 pub mod time;
+pub mod usb;
+pub mod timer;
 pub mod delay;
+pub mod dma;
+pub mod exti;
+pub mod fdcan;
+pub mod hrtim;
 pub mod watchdog;
+pub mod mpu;
 pub mod prelude;
+pub mod pwm;
+pub mod qei;
+pub mod qspi;
+pub mod rng;
+pub mod rtc;
+pub mod sai;
+pub mod sdmmc;
+pub mod pwr;
 pub mod serial;
+pub mod spi;
+pub mod syscfg;
 pub use stm32h7::stm32h7x3;
 pub use stm32h7::stm32h7x3 as pac;
 pub use compile_time_calculations::*;
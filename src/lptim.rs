@@ -0,0 +1,145 @@
+//! Low Power Timer
+//!
+//! LPTIM1 keeps counting in Stop mode if its kernel clock, selected with `CFGR::lptim1_src`,
+//! is the LSE or the LSI. Its interrupt then wakes up the core through EXTI line 47
+//!
+//! ARR can only be written while the timer is enabled, the write is synchronized to the
+//! kernel clock and has to complete before the counter is started
+
+use hal::timer::{Cancel, CountDown, Periodic};
+use nb;
+use stm32h7::stm32h7x3::{EXTI, LPTIM1};
+use void::Void;
+
+use crate::exti;
+use crate::rcc::{Clocks, Enable, Reset, APB1L};
+use crate::time::Hertz;
+use crate::timer::{Error, Event};
+
+/// The EXTI line of the LPTIM1 wakeup event
+const EXTI_LINE: u8 = 47;
+
+/// A low power timer counting down periods
+pub struct LpTimer {
+    tim: LPTIM1,
+    ker_ck: Hertz,
+}
+
+impl LpTimer {
+    /// Enables LPTIM1, which stays stopped until `start` is called
+    pub fn lptim1(tim: LPTIM1, clocks: &Clocks, apb1l: &mut APB1L) -> Self {
+        LPTIM1::enable(apb1l);
+        LPTIM1::reset(apb1l);
+
+        LpTimer {
+            tim,
+            ker_ck: clocks.lptim1_ck(),
+        }
+    }
+
+    /// Starts listening for an `event`
+    ///
+    /// IER can only be written while the timer is disabled, so the timer is stopped and has
+    /// to be started again
+    pub fn listen(&mut self, event: Event) {
+        self.tim.cr.modify(|_, w| w.enable().clear_bit());
+        match event {
+            Event::Update => self.tim.ier.modify(|_, w| w.arrmie().set_bit()),
+        }
+    }
+
+    /// Stops listening for an `event`
+    ///
+    /// IER can only be written while the timer is disabled, so the timer is stopped and has
+    /// to be started again
+    pub fn unlisten(&mut self, event: Event) {
+        self.tim.cr.modify(|_, w| w.enable().clear_bit());
+        match event {
+            Event::Update => self.tim.ier.modify(|_, w| w.arrmie().clear_bit()),
+        }
+    }
+
+    /// Clears the interrupt flag of an `event`, this has to be done in the interrupt handler
+    pub fn clear_interrupt(&mut self, event: Event) {
+        match event {
+            Event::Update => self.tim.icr.write(|w| w.arrmcf().set_bit()),
+        }
+    }
+
+    /// Lets the interrupts the timer listens for wake up the core from Stop mode
+    pub fn enable_wakeup(&mut self, exti: &mut EXTI) {
+        exti::enable_direct_interrupt(exti, EXTI_LINE);
+    }
+
+    /// Keeps the interrupts of the timer from waking up the core from Stop mode
+    pub fn disable_wakeup(&mut self, exti: &mut EXTI) {
+        exti::disable_direct_interrupt(exti, EXTI_LINE);
+    }
+
+    /// Stops the timer and releases it
+    pub fn free(self) -> LPTIM1 {
+        self.tim.cr.modify(|_, w| w.enable().clear_bit());
+        self.tim
+    }
+}
+
+/// Selects the prescaler for `ticks` kernel clock cycles per period, the prescaler divides
+/// by 2 ^ PRESC
+fn presc_arr(ticks: u32) -> (u8, u16) {
+    let presc = (0..=7)
+        .find(|presc| ticks >> presc <= 0x1_0000)
+        .unwrap_or(7);
+    // ARR has to be above CMP, which is 0
+    let arr = ((ticks >> presc).max(2) - 1).min(0xFFFF);
+    (presc, arr as u16)
+}
+
+impl CountDown for LpTimer {
+    type Time = Hertz;
+
+    /// Starts a period of `timeout`, periods longer than 65536 * 128 kernel clock cycles
+    /// are cut to that length
+    fn start<T>(&mut self, timeout: T)
+    where
+        T: Into<Hertz>,
+    {
+        let (presc, arr) = presc_arr(self.ker_ck.0 / timeout.into().0.max(1));
+
+        // CFGR can only be written while the timer is disabled
+        self.tim.cr.modify(|_, w| w.enable().clear_bit());
+        self.tim.cfgr.modify(|_, w| unsafe { w.presc().bits(presc) });
+        self.tim.cr.modify(|_, w| w.enable().set_bit());
+
+        // wait until the write of ARR is synchronized, another write before would be lost
+        self.tim.icr.write(|w| w.arrokcf().set_bit());
+        self.tim.arr.write(|w| unsafe { w.arr().bits(arr) });
+        while self.tim.isr.read().arrok().bit_is_clear() {}
+        self.tim.icr.write(|w| w.arrokcf().set_bit().arrmcf().set_bit());
+
+        self.tim.cr.modify(|_, w| w.cntstrt().set_bit());
+    }
+
+    fn wait(&mut self) -> nb::Result<(), Void> {
+        if self.tim.isr.read().arrm().bit_is_clear() {
+            Err(nb::Error::WouldBlock)
+        } else {
+            self.tim.icr.write(|w| w.arrmcf().set_bit());
+            Ok(())
+        }
+    }
+}
+
+impl Periodic for LpTimer {}
+
+impl Cancel for LpTimer {
+    type Error = Error;
+
+    fn cancel(&mut self) -> Result<(), Error> {
+        if self.tim.cr.read().enable().bit_is_clear() {
+            return Err(Error::Disabled);
+        }
+
+        self.tim.cr.modify(|_, w| w.enable().clear_bit());
+        Ok(())
+    }
+}
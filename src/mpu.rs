@@ -0,0 +1,116 @@
+//! Memory Protection Unit
+//!
+//! The SRAMs are cacheable by default, so the CPU can read stale data from the D-cache
+//! after a DMA transfer wrote to a buffer, and a DMA transfer can read stale data from the
+//! SRAM while the CPU's writes are still in the D-cache. `Mpu::configure_dma_region` makes
+//! a region non-cacheable, the DMA buffers placed in it need no cache maintenance
+
+use cortex_m::asm;
+use cortex_m::peripheral::MPU;
+
+/// MPU_CTRL: the MPU is enabled
+const CTRL_ENABLE: u32 = 1 << 0;
+/// MPU_CTRL: the default memory map is used for privileged accesses outside of the regions
+const CTRL_PRIVDEFENA: u32 = 1 << 2;
+
+/// MPU_RASR: instruction fetches are not allowed
+const RASR_XN: u32 = 1 << 28;
+/// MPU_RASR: full access for privileged and unprivileged code
+const RASR_AP_FULL: u32 = 0b011 << 24;
+/// MPU_RASR: normal memory, non-cacheable (TEX = 001, C = 0, B = 0)
+const RASR_NON_CACHEABLE: u32 = 0b001 << 19;
+/// MPU_RASR: the memory is shared between several bus masters
+const RASR_S: u32 = 1 << 18;
+/// MPU_RASR: the region is enabled
+const RASR_ENABLE: u32 = 1 << 0;
+
+/// Extension trait to constrain the MPU peripheral
+pub trait MpuExt {
+    /// Constrains the MPU peripheral to play nicely with the other abstractions
+    fn constrain(self) -> Mpu;
+}
+
+impl MpuExt for MPU {
+    fn constrain(self) -> Mpu {
+        Mpu {
+            mpu: self,
+            next_region: 0,
+        }
+    }
+}
+
+/// Constrained MPU peripheral
+pub struct Mpu {
+    mpu: MPU,
+    next_region: u8,
+}
+
+impl Mpu {
+    /// Makes the `size` bytes at `addr` non-cacheable and shareable memory in a new region,
+    /// and enables the MPU
+    ///
+    /// The default memory map stays in place for all other addresses. Regions configured
+    /// later take precedence where they overlap
+    ///
+    /// # Panics
+    ///
+    /// Panics if `size` isn't a power of 2 of at least 32 bytes, if `addr` isn't a multiple
+    /// of `size` or if all regions are used
+    pub fn configure_dma_region(&mut self, addr: u32, size: u32) {
+        assert!(
+            size >= 32 && size.is_power_of_two(),
+            "the size of a region is a power of 2 of at least 32 bytes"
+        );
+        assert!(addr.is_multiple_of(size), "a region has to be aligned to its size");
+
+        // DREGION: the number of supported regions
+        let regions = (self.mpu._type.read() >> 8) as u8;
+        assert!(self.next_region < regions, "all regions of the MPU are used");
+        let region = self.next_region;
+        self.next_region += 1;
+
+        // SIZE: the region spans 2 ^ (SIZE + 1) bytes
+        let size_field = size.trailing_zeros() - 1;
+
+        // complete the outstanding memory accesses before the memory attributes change
+        asm::dmb();
+        // NOTE(unsafe) the regions are only configured through `self`
+        unsafe {
+            self.mpu.rnr.write(u32::from(region));
+            self.mpu.rbar.write(addr);
+            self.mpu.rasr.write(
+                RASR_XN
+                    | RASR_AP_FULL
+                    | RASR_NON_CACHEABLE
+                    | RASR_S
+                    | size_field << 1
+                    | RASR_ENABLE,
+            );
+            self.mpu.ctrl.write(CTRL_PRIVDEFENA | CTRL_ENABLE);
+        }
+        // the new attributes apply to the following accesses and instruction fetches
+        asm::dsb();
+        asm::isb();
+    }
+
+    /// Disables the MPU and all regions
+    pub fn disable(&mut self) {
+        asm::dmb();
+        // NOTE(unsafe) the regions are only configured through `self`
+        unsafe {
+            self.mpu.ctrl.write(0);
+            for region in 0..self.next_region {
+                self.mpu.rnr.write(u32::from(region));
+                self.mpu.rasr.write(0);
+            }
+        }
+        self.next_region = 0;
+        asm::dsb();
+        asm::isb();
+    }
+
+    /// Releases the MPU peripheral, the regions stay configured
+    pub fn free(self) -> MPU {
+        self.mpu
+    }
+}
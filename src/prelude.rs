@@ -1,6 +1,24 @@
+//! Prelude
+
 pub use crate::rcc::RccExt;
 pub use crate::gpio::GpioExt;
+pub use crate::exti::ExtiPin as _stm32h7x3_hal_exti_ExtiPin;
 pub use crate::flash::FlashExt;
+pub use crate::comp::CompExt;
+pub use crate::dac::DacExt;
+pub use crate::dma::DmaExt;
+pub use crate::dma::Stream as _stm32h7x3_hal_dma_Stream;
+pub use crate::dma::bdma::BdmaExt;
+pub use crate::dma::bdma::Channel as _stm32h7x3_hal_dma_bdma_Channel;
+pub use crate::mpu::MpuExt;
+pub use crate::pwr::PwrExt;
+pub use crate::hrtim::HrPwmExt;
+pub use crate::pwm::PwmExt;
+pub use crate::timer::InputCaptureExt;
+pub use crate::syscfg::SysCfgExt;
+pub use crate::rcc::Enable as _stm32h7x3_hal_rcc_Enable;
+pub use crate::rcc::Reset as _stm32h7x3_hal_rcc_Reset;
+pub use crate::rcc::SleepEnable as _stm32h7x3_hal_rcc_SleepEnable;
 pub use crate::time::U32Ext;
 pub use crate::hal::digital::InputPin as _embedded_hal_digital_InputPin;
 pub use crate::hal::digital::OutputPin as _embedded_hal_digital_OutputPin;
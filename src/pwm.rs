@@ -0,0 +1,524 @@
+//! Pulse Width Modulation
+
+use core::marker::PhantomData;
+use core::mem;
+
+use cortex_m::interrupt;
+use hal::PwmPin;
+use stm32h7::stm32h7x3::{TIM1, TIM2, TIM3, TIM4, TIM5, TIM8};
+
+use crate::gpio::{Floating, Input, Output, PushPull, AF1, AF2, AF3};
+use crate::gpio::gpioa::{PA0, PA1, PA2, PA3, PA5, PA6, PA7, PA8, PA9, PA10, PA11, PA15};
+use crate::gpio::gpiob::{
+    PB0, PB1, PB3, PB4, PB5, PB6, PB7, PB8, PB9, PB10, PB11, PB12, PB13, PB14, PB15,
+};
+use crate::gpio::gpioc::{PC6, PC7, PC8, PC9};
+use crate::gpio::gpiod::{PD12, PD13, PD14, PD15};
+use crate::gpio::gpioe::{PE8, PE9, PE10, PE11, PE12, PE13, PE14, PE15};
+use crate::gpio::gpiog::PG2;
+use crate::gpio::gpioh::{PH10, PH11, PH12, PH13, PH14, PH15};
+use crate::gpio::gpioi::{PI0, PI2, PI4, PI5, PI6, PI7};
+use crate::rcc::{Clocks, Enable, RccBus, Reset};
+use crate::time::Hertz;
+use crate::timer::psc_arr;
+
+/// Channel 1 (type state)
+pub struct C1;
+/// Channel 2 (type state)
+pub struct C2;
+/// Channel 3 (type state)
+pub struct C3;
+/// Channel 4 (type state)
+pub struct C4;
+
+/// Pins of channel 1 - DO NOT IMPLEMENT THIS TRAIT
+///
+/// # Safety
+///
+/// Only the pins whose alternate function connects them to channel 1 of `TIM` may
+/// implement this trait
+pub unsafe trait PinC1<TIM> {}
+/// Pins of channel 2 - DO NOT IMPLEMENT THIS TRAIT
+///
+/// # Safety
+///
+/// Only the pins whose alternate function connects them to channel 2 of `TIM` may
+/// implement this trait
+pub unsafe trait PinC2<TIM> {}
+/// Pins of channel 3 - DO NOT IMPLEMENT THIS TRAIT
+///
+/// # Safety
+///
+/// Only the pins whose alternate function connects them to channel 3 of `TIM` may
+/// implement this trait
+pub unsafe trait PinC3<TIM> {}
+/// Pins of channel 4 - DO NOT IMPLEMENT THIS TRAIT
+///
+/// # Safety
+///
+/// Only the pins whose alternate function connects them to channel 4 of `TIM` may
+/// implement this trait
+pub unsafe trait PinC4<TIM> {}
+
+/// A pin or a tuple of pins of different channels of a timer - DO NOT IMPLEMENT THIS TRAIT
+///
+/// `CHANNELS` is inferred, it tells the implementations for the tuples of different
+/// channels apart
+///
+/// # Safety
+///
+/// The flags `C1` to `C4` have to be set for exactly the channels of the pins, they
+/// select the channels that get configured for PWM
+pub unsafe trait Pins<TIM, CHANNELS> {
+    #[doc(hidden)]
+    const C1: bool = false;
+    #[doc(hidden)]
+    const C2: bool = false;
+    #[doc(hidden)]
+    const C3: bool = false;
+    #[doc(hidden)]
+    const C4: bool = false;
+
+    /// The PWM channels of the pins
+    type Channels;
+}
+
+macro_rules! pins_impl {
+    ($(($($PINX:ident),+), ($($TRAIT:ident),+), ($($ENCHX:ident),+);)+) => {
+        $(
+            #[allow(unused_parens)]
+            unsafe impl<TIM, $($PINX,)+> Pins<TIM, ($($ENCHX),+)> for ($($PINX),+)
+            where
+                $($PINX: $TRAIT<TIM>,)+
+            {
+                $(const $ENCHX: bool = true;)+
+                type Channels = ($(Pwm<TIM, $ENCHX>),+);
+            }
+        )+
+    };
+}
+
+pins_impl!(
+    (P1, P2, P3, P4), (PinC1, PinC2, PinC3, PinC4), (C1, C2, C3, C4);
+    (P2, P3, P4), (PinC2, PinC3, PinC4), (C2, C3, C4);
+    (P1, P3, P4), (PinC1, PinC3, PinC4), (C1, C3, C4);
+    (P1, P2, P4), (PinC1, PinC2, PinC4), (C1, C2, C4);
+    (P1, P2, P3), (PinC1, PinC2, PinC3), (C1, C2, C3);
+    (P3, P4), (PinC3, PinC4), (C3, C4);
+    (P2, P4), (PinC2, PinC4), (C2, C4);
+    (P2, P3), (PinC2, PinC3), (C2, C3);
+    (P1, P4), (PinC1, PinC4), (C1, C4);
+    (P1, P3), (PinC1, PinC3), (C1, C3);
+    (P1, P2), (PinC1, PinC2), (C1, C2);
+    (P1), (PinC1), (C1);
+    (P2), (PinC2), (C2);
+    (P3), (PinC3), (C3);
+    (P4), (PinC4), (C4);
+);
+
+// TIM1
+unsafe impl PinC1<TIM1> for PA8<Output<PushPull>, AF1> {}
+unsafe impl PinC1<TIM1> for PE9<Output<PushPull>, AF1> {}
+unsafe impl PinC2<TIM1> for PA9<Output<PushPull>, AF1> {}
+unsafe impl PinC2<TIM1> for PE11<Output<PushPull>, AF1> {}
+unsafe impl PinC3<TIM1> for PA10<Output<PushPull>, AF1> {}
+unsafe impl PinC3<TIM1> for PE13<Output<PushPull>, AF1> {}
+unsafe impl PinC4<TIM1> for PA11<Output<PushPull>, AF1> {}
+unsafe impl PinC4<TIM1> for PE14<Output<PushPull>, AF1> {}
+
+// TIM2
+unsafe impl PinC1<TIM2> for PA0<Output<PushPull>, AF1> {}
+unsafe impl PinC1<TIM2> for PA5<Output<PushPull>, AF1> {}
+unsafe impl PinC1<TIM2> for PA15<Output<PushPull>, AF1> {}
+unsafe impl PinC2<TIM2> for PA1<Output<PushPull>, AF1> {}
+unsafe impl PinC2<TIM2> for PB3<Output<PushPull>, AF1> {}
+unsafe impl PinC3<TIM2> for PA2<Output<PushPull>, AF1> {}
+unsafe impl PinC3<TIM2> for PB10<Output<PushPull>, AF1> {}
+unsafe impl PinC4<TIM2> for PA3<Output<PushPull>, AF1> {}
+unsafe impl PinC4<TIM2> for PB11<Output<PushPull>, AF1> {}
+
+// TIM3
+unsafe impl PinC1<TIM3> for PA6<Output<PushPull>, AF2> {}
+unsafe impl PinC1<TIM3> for PB4<Output<PushPull>, AF2> {}
+unsafe impl PinC1<TIM3> for PC6<Output<PushPull>, AF2> {}
+unsafe impl PinC2<TIM3> for PA7<Output<PushPull>, AF2> {}
+unsafe impl PinC2<TIM3> for PB5<Output<PushPull>, AF2> {}
+unsafe impl PinC2<TIM3> for PC7<Output<PushPull>, AF2> {}
+unsafe impl PinC3<TIM3> for PB0<Output<PushPull>, AF2> {}
+unsafe impl PinC3<TIM3> for PC8<Output<PushPull>, AF2> {}
+unsafe impl PinC4<TIM3> for PB1<Output<PushPull>, AF2> {}
+unsafe impl PinC4<TIM3> for PC9<Output<PushPull>, AF2> {}
+
+// TIM4
+unsafe impl PinC1<TIM4> for PB6<Output<PushPull>, AF2> {}
+unsafe impl PinC1<TIM4> for PD12<Output<PushPull>, AF2> {}
+unsafe impl PinC2<TIM4> for PB7<Output<PushPull>, AF2> {}
+unsafe impl PinC2<TIM4> for PD13<Output<PushPull>, AF2> {}
+unsafe impl PinC3<TIM4> for PB8<Output<PushPull>, AF2> {}
+unsafe impl PinC3<TIM4> for PD14<Output<PushPull>, AF2> {}
+unsafe impl PinC4<TIM4> for PB9<Output<PushPull>, AF2> {}
+unsafe impl PinC4<TIM4> for PD15<Output<PushPull>, AF2> {}
+
+// TIM5
+unsafe impl PinC1<TIM5> for PA0<Output<PushPull>, AF2> {}
+unsafe impl PinC1<TIM5> for PH10<Output<PushPull>, AF2> {}
+unsafe impl PinC2<TIM5> for PA1<Output<PushPull>, AF2> {}
+unsafe impl PinC2<TIM5> for PH11<Output<PushPull>, AF2> {}
+unsafe impl PinC3<TIM5> for PA2<Output<PushPull>, AF2> {}
+unsafe impl PinC3<TIM5> for PH12<Output<PushPull>, AF2> {}
+unsafe impl PinC4<TIM5> for PA3<Output<PushPull>, AF2> {}
+unsafe impl PinC4<TIM5> for PI0<Output<PushPull>, AF2> {}
+
+// TIM8
+unsafe impl PinC1<TIM8> for PC6<Output<PushPull>, AF3> {}
+unsafe impl PinC1<TIM8> for PI5<Output<PushPull>, AF3> {}
+unsafe impl PinC2<TIM8> for PC7<Output<PushPull>, AF3> {}
+unsafe impl PinC2<TIM8> for PI6<Output<PushPull>, AF3> {}
+unsafe impl PinC3<TIM8> for PC8<Output<PushPull>, AF3> {}
+unsafe impl PinC3<TIM8> for PI7<Output<PushPull>, AF3> {}
+unsafe impl PinC4<TIM8> for PC9<Output<PushPull>, AF3> {}
+unsafe impl PinC4<TIM8> for PI2<Output<PushPull>, AF3> {}
+
+/// Complementary pins of channel 1 - DO NOT IMPLEMENT THIS TRAIT
+///
+/// # Safety
+///
+/// Only the pins whose alternate function connects them to channel 1N of `TIM` may
+/// implement this trait
+pub unsafe trait PinC1N<TIM> {}
+/// Complementary pins of channel 2 - DO NOT IMPLEMENT THIS TRAIT
+///
+/// # Safety
+///
+/// Only the pins whose alternate function connects them to channel 2N of `TIM` may
+/// implement this trait
+pub unsafe trait PinC2N<TIM> {}
+/// Complementary pins of channel 3 - DO NOT IMPLEMENT THIS TRAIT
+///
+/// # Safety
+///
+/// Only the pins whose alternate function connects them to channel 3N of `TIM` may
+/// implement this trait
+pub unsafe trait PinC3N<TIM> {}
+/// Break input pins - DO NOT IMPLEMENT THIS TRAIT
+///
+/// # Safety
+///
+/// Only the pins whose alternate function connects them to BKIN of `TIM` may
+/// implement this trait
+pub unsafe trait BkinPin<TIM> {}
+
+// TIM1
+unsafe impl PinC1N<TIM1> for PA7<Output<PushPull>, AF1> {}
+unsafe impl PinC1N<TIM1> for PB13<Output<PushPull>, AF1> {}
+unsafe impl PinC1N<TIM1> for PE8<Output<PushPull>, AF1> {}
+unsafe impl PinC2N<TIM1> for PB0<Output<PushPull>, AF1> {}
+unsafe impl PinC2N<TIM1> for PB14<Output<PushPull>, AF1> {}
+unsafe impl PinC2N<TIM1> for PE10<Output<PushPull>, AF1> {}
+unsafe impl PinC3N<TIM1> for PB1<Output<PushPull>, AF1> {}
+unsafe impl PinC3N<TIM1> for PB15<Output<PushPull>, AF1> {}
+unsafe impl PinC3N<TIM1> for PE12<Output<PushPull>, AF1> {}
+unsafe impl BkinPin<TIM1> for PA6<Input<Floating>, AF1> {}
+unsafe impl BkinPin<TIM1> for PB12<Input<Floating>, AF1> {}
+unsafe impl BkinPin<TIM1> for PE15<Input<Floating>, AF1> {}
+
+// TIM8
+unsafe impl PinC1N<TIM8> for PA5<Output<PushPull>, AF3> {}
+unsafe impl PinC1N<TIM8> for PA7<Output<PushPull>, AF3> {}
+unsafe impl PinC1N<TIM8> for PH13<Output<PushPull>, AF3> {}
+unsafe impl PinC2N<TIM8> for PB0<Output<PushPull>, AF3> {}
+unsafe impl PinC2N<TIM8> for PB14<Output<PushPull>, AF3> {}
+unsafe impl PinC2N<TIM8> for PH14<Output<PushPull>, AF3> {}
+unsafe impl PinC3N<TIM8> for PB1<Output<PushPull>, AF3> {}
+unsafe impl PinC3N<TIM8> for PB15<Output<PushPull>, AF3> {}
+unsafe impl PinC3N<TIM8> for PH15<Output<PushPull>, AF3> {}
+unsafe impl BkinPin<TIM8> for PA6<Input<Floating>, AF3> {}
+unsafe impl BkinPin<TIM8> for PG2<Input<Floating>, AF3> {}
+unsafe impl BkinPin<TIM8> for PI4<Input<Floating>, AF3> {}
+
+/// A PWM output channel of a timer
+pub struct Pwm<TIM, CHANNEL> {
+    _tim: PhantomData<TIM>,
+    _channel: PhantomData<CHANNEL>,
+}
+
+/// A PWM output channel of an advanced timer that drives a complementary output as well,
+/// e.g. for the high and low side switches of a half bridge
+///
+/// Both outputs switch with a dead time in between, during which both are inactive
+pub struct ComplementaryPwm<TIM, CHANNEL> {
+    _tim: PhantomData<TIM>,
+    _channel: PhantomData<CHANNEL>,
+    ker_ck: Hertz,
+}
+
+/// Active level of the break input
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum BreakPolarity {
+    /// The break is triggered by a low level
+    ActiveLow,
+    /// The break is triggered by a high level
+    ActiveHigh,
+}
+
+/// Encodes a dead time of `ticks` kernel clock cycles as DTG of BDTR, the dead time is
+/// rounded down to the steps of the encoding and limited to 1008 cycles
+fn dtg(ticks: u32) -> u8 {
+    match ticks {
+        // DT = DTG[7:0] * tDTS
+        0..=127 => ticks as u8,
+        // DT = (64 + DTG[5:0]) * 2 * tDTS
+        128..=255 => 0b1000_0000 | ((ticks / 2 - 64) as u8),
+        // DT = (32 + DTG[4:0]) * 8 * tDTS
+        256..=511 => 0b1100_0000 | ((ticks / 8 - 32) as u8),
+        // DT = (32 + DTG[4:0]) * 16 * tDTS
+        _ => 0b1110_0000 | ((ticks.min(1008) / 16 - 32) as u8),
+    }
+}
+
+/// Extension trait to use a timer as PWM generator
+pub trait PwmExt: Sized + RccBus {
+    /// Configures the timer to generate PWM signals of `freq` on the channels of `pins`,
+    /// the channels start disabled with a duty cycle of 0
+    fn pwm<PINS, CHANNELS, T>(
+        self,
+        pins: PINS,
+        freq: T,
+        clocks: Clocks,
+        bus: &mut Self::Bus,
+    ) -> PINS::Channels
+    where
+        PINS: Pins<Self, CHANNELS>,
+        T: Into<Hertz>;
+}
+
+macro_rules! pwm {
+    ($($TIMX:ident: ($ker_ck:ident, $main_output:expr),)+) => {
+        $(
+            impl PwmExt for $TIMX {
+                fn pwm<PINS, CHANNELS, T>(
+                    self,
+                    _pins: PINS,
+                    freq: T,
+                    clocks: Clocks,
+                    bus: &mut Self::Bus,
+                ) -> PINS::Channels
+                where
+                    PINS: Pins<$TIMX, CHANNELS>,
+                    T: Into<Hertz>,
+                {
+                    $TIMX::enable(bus);
+                    $TIMX::reset(bus);
+
+                    let tim = self;
+
+                    // PWM mode 1 (OCxM = 110) with preloaded compare registers (OCxPE)
+                    let pwm_mode_1 = 0b0110_1000;
+                    if PINS::C1 {
+                        tim.ccmr1_output.modify(|r, w| unsafe { w.bits(r.bits() | pwm_mode_1) });
+                    }
+                    if PINS::C2 {
+                        tim.ccmr1_output.modify(|r, w| unsafe { w.bits(r.bits() | (pwm_mode_1 << 8)) });
+                    }
+                    if PINS::C3 {
+                        tim.ccmr2_output.modify(|r, w| unsafe { w.bits(r.bits() | pwm_mode_1) });
+                    }
+                    if PINS::C4 {
+                        tim.ccmr2_output.modify(|r, w| unsafe { w.bits(r.bits() | (pwm_mode_1 << 8)) });
+                    }
+
+                    // the duty cycle is a u16, so the auto reload value is limited to 16 bits
+                    // for the 32 bit timers as well
+                    let ticks = u64::from(clocks.$ker_ck().0) / u64::from(freq.into().0.max(1));
+                    let (psc, arr) = psc_arr(ticks, 0xFFFF);
+                    tim.psc.write(|w| unsafe { w.psc().bits(psc) });
+                    tim.arr.write(|w| unsafe { w.bits(arr) });
+
+                    // the outputs of the advanced timers are only driven with MOE set
+                    ($main_output)(&tim);
+
+                    // load the prescaler and the auto reload value, then start counting
+                    tim.cr1.modify(|_, w| w.arpe().set_bit());
+                    tim.egr.write(|w| w.ug().set_bit());
+                    tim.cr1.modify(|_, w| w.cen().set_bit());
+
+                    // NOTE(unsafe) the channels are zero sized types
+                    unsafe { mem::MaybeUninit::uninit().assume_init() }
+                }
+            }
+
+            pwm_channel!($TIMX, C1, ccr1, 0);
+            pwm_channel!($TIMX, C2, ccr2, 4);
+            pwm_channel!($TIMX, C3, ccr3, 8);
+            pwm_channel!($TIMX, C4, ccr4, 12);
+        )+
+    };
+}
+
+macro_rules! pwm_channel {
+    ($TIMX:ident, $CX:ident, $ccrx:ident, $ccxe:expr) => {
+        impl PwmPin for Pwm<$TIMX, $CX> {
+            type Duty = u16;
+
+            fn disable(&mut self) {
+                // NOTE(unsafe) CCER is shared with the other channels, the critical section
+                // makes the read-modify-write atomic
+                interrupt::free(|_| unsafe {
+                    (*$TIMX::ptr()).ccer.modify(|r, w| w.bits(r.bits() & !(1 << $ccxe)))
+                });
+            }
+
+            fn enable(&mut self) {
+                interrupt::free(|_| unsafe {
+                    (*$TIMX::ptr()).ccer.modify(|r, w| w.bits(r.bits() | (1 << $ccxe)))
+                });
+            }
+
+            fn get_duty(&self) -> u16 {
+                // NOTE(unsafe) atomic read with no side effects
+                unsafe { (*$TIMX::ptr()).$ccrx.read().bits() as u16 }
+            }
+
+            fn get_max_duty(&self) -> u16 {
+                // NOTE(unsafe) atomic read with no side effects
+                unsafe { (*$TIMX::ptr()).arr.read().bits() as u16 }
+            }
+
+            fn set_duty(&mut self, duty: u16) {
+                // NOTE(unsafe) this channel owns its compare register
+                unsafe { (*$TIMX::ptr()).$ccrx.write(|w| w.bits(u32::from(duty))) }
+            }
+        }
+    };
+}
+
+macro_rules! complementary_channel {
+    ($TIMX:ident, $CX:ident, $PinCXN:ident, $ccrx:ident, $ccxe:expr) => {
+        impl Pwm<$TIMX, $CX> {
+            /// Adds the complementary output `pin` to the channel, the dead time starts
+            /// out as 0
+            pub fn into_complementary<PIN>(self, _pin: PIN, clocks: &Clocks) -> ComplementaryPwm<$TIMX, $CX>
+            where
+                PIN: $PinCXN<$TIMX>,
+            {
+                ComplementaryPwm {
+                    _tim: PhantomData,
+                    _channel: PhantomData,
+                    ker_ck: clocks.timy_ker_ck(),
+                }
+            }
+        }
+
+        impl ComplementaryPwm<$TIMX, $CX> {
+            /// Sets the dead time between the edges of the output and the complementary
+            /// output, the dead time is shared by all channels of the timer
+            ///
+            /// The dead time is rounded down to the resolution of the timer and is limited
+            /// to 1008 periods of the timer kernel clock
+            pub fn set_dead_time_ns(&mut self, dead_time_ns: u16) {
+                let ticks = u64::from(dead_time_ns) * u64::from(self.ker_ck.0) / 1_000_000_000;
+                let dtg = dtg(ticks as u32);
+                // NOTE(unsafe) BDTR is shared with the other channels, the critical section
+                // makes the read-modify-write atomic
+                interrupt::free(|_| unsafe {
+                    (*$TIMX::ptr()).bdtr.modify(|_, w| w.dtg().bits(dtg))
+                });
+            }
+
+            /// Enables the break input `pin`, which disables all outputs of the timer as
+            /// soon as it becomes active, until `resume` is called
+            pub fn enable_break<PIN>(&mut self, _pin: PIN, polarity: BreakPolarity)
+            where
+                PIN: BkinPin<$TIMX>,
+            {
+                interrupt::free(|_| unsafe {
+                    (*$TIMX::ptr()).bdtr.modify(|_, w| {
+                        w.bke().set_bit()
+                            .bkp().bit(polarity == BreakPolarity::ActiveHigh)
+                            .aoe().clear_bit()
+                    })
+                });
+            }
+
+            /// Enables the outputs of the timer again after a break
+            pub fn resume(&mut self) {
+                interrupt::free(|_| unsafe {
+                    (*$TIMX::ptr()).sr.modify(|_, w| w.bif().clear_bit());
+                    (*$TIMX::ptr()).bdtr.modify(|_, w| w.moe().set_bit())
+                });
+            }
+        }
+
+        impl PwmPin for ComplementaryPwm<$TIMX, $CX> {
+            type Duty = u16;
+
+            fn disable(&mut self) {
+                // NOTE(unsafe) CCER is shared with the other channels, the critical section
+                // makes the read-modify-write atomic
+                interrupt::free(|_| unsafe {
+                    (*$TIMX::ptr()).ccer.modify(|r, w| w.bits(r.bits() & !(0b101 << $ccxe)))
+                });
+            }
+
+            fn enable(&mut self) {
+                // CCxE and CCxNE
+                interrupt::free(|_| unsafe {
+                    (*$TIMX::ptr()).ccer.modify(|r, w| w.bits(r.bits() | (0b101 << $ccxe)))
+                });
+            }
+
+            fn get_duty(&self) -> u16 {
+                // NOTE(unsafe) atomic read with no side effects
+                unsafe { (*$TIMX::ptr()).$ccrx.read().bits() as u16 }
+            }
+
+            fn get_max_duty(&self) -> u16 {
+                // NOTE(unsafe) atomic read with no side effects
+                unsafe { (*$TIMX::ptr()).arr.read().bits() as u16 }
+            }
+
+            fn set_duty(&mut self, duty: u16) {
+                // NOTE(unsafe) this channel owns its compare register
+                unsafe { (*$TIMX::ptr()).$ccrx.write(|w| w.bits(u32::from(duty))) }
+            }
+        }
+    };
+}
+
+complementary_channel!(TIM1, C1, PinC1N, ccr1, 0);
+complementary_channel!(TIM1, C2, PinC2N, ccr2, 4);
+complementary_channel!(TIM1, C3, PinC3N, ccr3, 8);
+complementary_channel!(TIM8, C1, PinC1N, ccr1, 0);
+complementary_channel!(TIM8, C2, PinC2N, ccr2, 4);
+complementary_channel!(TIM8, C3, PinC3N, ccr3, 8);
+
+pwm!(
+    TIM1: (timy_ker_ck, |tim: &TIM1| tim.bdtr.modify(|_, w| w.moe().set_bit())),
+    TIM2: (timx_ker_ck, |_: &TIM2| ()),
+    TIM3: (timx_ker_ck, |_: &TIM3| ()),
+    TIM4: (timx_ker_ck, |_: &TIM4| ()),
+    TIM5: (timx_ker_ck, |_: &TIM5| ()),
+    TIM8: (timy_ker_ck, |tim: &TIM8| tim.bdtr.modify(|_, w| w.moe().set_bit())),
+);
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn dtg_encodes_the_range_boundaries() {
+        assert_eq!(dtg(127), 0b0111_1111);
+        assert_eq!(dtg(128), 0b1000_0000);
+        assert_eq!(dtg(255), 0b1011_1111);
+        assert_eq!(dtg(256), 0b1100_0000);
+        assert_eq!(dtg(511), 0b1101_1111);
+        assert_eq!(dtg(512), 0b1110_0000);
+        assert_eq!(dtg(1008), 0b1111_1111);
+    }
+
+    #[test]
+    fn dtg_limits_the_dead_time_to_1008_ticks() {
+        assert_eq!(dtg(1009), 0b1111_1111);
+        assert_eq!(dtg(u32::MAX), 0b1111_1111);
+    }
+}
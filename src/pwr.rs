@@ -0,0 +1,315 @@
+//! Power control
+
+use core::ptr;
+
+use cortex_m::asm;
+use cortex_m::peripheral::SCB;
+use stm32h7::stm32h7x3::{pwr, PWR, SYSCFG};
+
+use crate::rcc::AHB4;
+
+/// The address of the backup SRAM
+const BACKUP_SRAM: usize = 0x3880_0000;
+/// The size of the backup SRAM in bytes
+const BACKUP_SRAM_SIZE: usize = 4096;
+
+/// Extension trait to constrain the PWR peripheral
+pub trait PwrExt {
+    /// Constrains the PWR peripheral to play nicely with the other abstractions
+    fn constrain(self) -> Pwr;
+}
+
+impl PwrExt for PWR {
+    fn constrain(self) -> Pwr {
+        Pwr {
+            cr1: CR1 { _0: () },
+            d3cr: D3CR { _0: () },
+        }
+    }
+}
+
+/// Constrained PWR peripheral
+pub struct Pwr {
+    /// Opaque CR1 register
+    pub cr1: CR1,
+    /// Opaque D3CR register
+    pub d3cr: D3CR,
+}
+
+impl Pwr {
+    fn cpucr(&mut self) -> &pwr::PWR_CPUCR {
+        // NOTE(unsafe) the constrained PWR is the only owner of this register
+        unsafe { &(*PWR::ptr()).pwr_cpucr }
+    }
+
+    /// Enters the Stop mode and returns after a wakeup by an EXTI line
+    ///
+    /// All domains stop, the plls and the HSE are disabled and sys_ck runs from the HSI
+    /// after the wakeup, a copy of the `CFGR` has to be frozen again to restore the clocks.
+    /// SysTick halts as well, so delays and timeouts spanning the Stop mode take longer
+    pub fn enter_stop(&mut self, scb: &mut SCB, mode: StopMode) {
+        self.cr1
+            .cr1()
+            .modify(|_, w| w.lpds().bit(mode == StopMode::LowPowerRegulator));
+        self.cpucr().modify(|_, w| {
+            w.pdds_d1()
+                .clear_bit()
+                .pdds_d2()
+                .clear_bit()
+                .pdds_d3()
+                .clear_bit()
+                .run_d3()
+                .clear_bit()
+        });
+
+        scb.set_sleepdeep();
+        asm::dsb();
+        asm::wfi();
+        scb.clear_sleepdeep();
+    }
+
+    /// Enters the Standby mode, the chip resets on a wakeup
+    ///
+    /// Only the backup domain keeps its content, `clear_standby_flags` tells after the
+    /// reset whether the chip was in Standby
+    pub fn enter_standby(&mut self, scb: &mut SCB) -> ! {
+        // a pending wakeup flag prevents entering Standby
+        self.clear_wakeup_pins();
+        self.cpucr().modify(|_, w| {
+            w.pdds_d1()
+                .set_bit()
+                .pdds_d2()
+                .set_bit()
+                .pdds_d3()
+                .set_bit()
+                .cssf()
+                .set_bit()
+        });
+
+        scb.set_sleepdeep();
+        asm::dsb();
+        loop {
+            asm::wfi();
+        }
+    }
+
+    /// Enables the backup SRAM, its content is retained in Standby and on VBAT by the
+    /// backup regulator
+    pub fn backup_sram(&mut self, ahb4: &mut AHB4) -> BackupSram {
+        self.cr1.enable_backup_domain_access();
+
+        // NOTE(unsafe) the constrained PWR is the only owner of this register
+        let cr2 = unsafe { &(*PWR::ptr()).pwr_cr2 };
+        cr2.modify(|_, w| w.bren().set_bit());
+        while cr2.read().brrdy().bit_is_clear() {}
+
+        ahb4.enr().modify(|_, w| w.bkpramen().set_bit());
+
+        BackupSram { _0: () }
+    }
+
+    /// Enables the 3.3 V regulator of the USB transceivers and waits until it is ready
+    pub(crate) fn enable_usb_regulator(&mut self) {
+        // NOTE(unsafe) the constrained PWR is the only owner of this register
+        let cr3 = unsafe { &(*PWR::ptr()).pwr_cr3 };
+        cr3.modify(|_, w| w.usbregen().set_bit().usb33den().set_bit());
+        while cr3.read().usb33rdy().bit_is_clear() {}
+    }
+
+    /// Reads and clears the flags telling which low power mode the chip returned from
+    pub fn clear_standby_flags(&mut self) -> WakeupFlags {
+        let cpucr = self.cpucr().read();
+        // NOTE(unsafe) read only register
+        let wkupfr = unsafe { (*PWR::ptr()).pwr_wkupfr.read().bits() };
+        let flags = WakeupFlags {
+            standby: cpucr.sbf().bit_is_set(),
+            stop: cpucr.stopf().bit_is_set(),
+            wakeup_pins: (wkupfr & 0x3F) as u8,
+        };
+
+        self.cpucr().modify(|_, w| w.cssf().set_bit());
+        self.clear_wakeup_pins();
+        flags
+    }
+
+    fn clear_wakeup_pins(&mut self) {
+        // NOTE(unsafe) write only register that is only used by the constrained PWR
+        unsafe { (*PWR::ptr()).pwr_wkupcr.write(|w| w.wkupc().bits(0x3F)) };
+    }
+}
+
+/// Regulator configuration in the Stop mode
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum StopMode {
+    /// The main regulator stays on, the wakeup is faster
+    MainRegulator,
+    /// The regulator runs in low-power mode, the consumption is lower
+    LowPowerRegulator,
+}
+
+/// The wakeup flags of the low power modes
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct WakeupFlags {
+    /// The chip was in Standby
+    pub standby: bool,
+    /// The chip was in Stop
+    pub stop: bool,
+    /// The wakeup pins that caused a wakeup, WKUP1 is bit 0
+    pub wakeup_pins: u8,
+}
+
+/// Opaque CR1 register
+pub struct CR1 {
+    _0: (),
+}
+
+impl CR1 {
+    pub(crate) fn cr1(&mut self) -> &pwr::PWR_CR1 {
+        // NOTE(unsafe) this proxy grants exclusive access to this register
+        unsafe { &(*PWR::ptr()).pwr_cr1 }
+    }
+
+    /// Disables the write protection of the backup domain, which holds the LSE
+    /// and RTC configuration, the protection stays disabled afterwards
+    pub(crate) fn enable_backup_domain_access(&mut self) {
+        self.cr1().modify(|_, w| w.dbp().set_bit());
+        while self.cr1().read().dbp().bit_is_clear() {}
+    }
+}
+
+/// Opaque D3CR register
+pub struct D3CR {
+    _0: (),
+}
+
+impl D3CR {
+    pub(crate) fn d3cr(&mut self) -> &pwr::PWR_D3CR {
+        // NOTE(unsafe) this proxy grants exclusive access to this register
+        unsafe { &(*PWR::ptr()).pwr_d3cr }
+    }
+
+    /// Applies the voltage scale and waits until VCORE reached the new level
+    ///
+    /// The SYSCFG clock has to be enabled for `VoltageScale::Scale0`
+    pub(crate) fn set_voltage_scale(&mut self, scale: VoltageScale) {
+        // VOS0 is VOS1 with the overdrive of the SYSCFG_PWRCR register enabled
+        // the overdrive can only be disabled while in VOS1
+        if scale != VoltageScale::Scale0 {
+            set_overdrive(false);
+        }
+
+        self.d3cr().modify(|_, w| unsafe { w.vos().bits(scale.vos_bits()) });
+        while self.d3cr().read().vosrdy().bit_is_clear() {}
+
+        if scale == VoltageScale::Scale0 {
+            set_overdrive(true);
+            while self.d3cr().read().vosrdy().bit_is_clear() {}
+        }
+    }
+}
+
+/// Sets the ODEN bit of SYSCFG_PWRCR, the svd lacks this register
+fn set_overdrive(enable: bool) {
+    // NOTE(unsafe) SYSCFG_PWRCR is located at offset 0x2C and is only used by this function
+    unsafe {
+        let pwrcr = (SYSCFG::ptr() as *mut u8).offset(0x2C) as *mut u32;
+        let value = ptr::read_volatile(pwrcr);
+        ptr::write_volatile(pwrcr, if enable { value | 1 } else { value & !1 });
+    }
+}
+
+/// Voltage scaling of the core domain, higher voltages allow for higher clocks
+///
+/// After reset the core runs in `Scale3`
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum VoltageScale {
+    /// VOS0, sys_ck up to 480 MHz
+    Scale0,
+    /// VOS1, sys_ck up to 400 MHz
+    Scale1,
+    /// VOS2, sys_ck up to 300 MHz
+    Scale2,
+    /// VOS3, sys_ck up to 200 MHz
+    Scale3,
+}
+
+impl VoltageScale {
+    /// The encoding of the VOS field in PWR_D3CR
+    fn vos_bits(self) -> u8 {
+        match self {
+            VoltageScale::Scale0 | VoltageScale::Scale1 => 0b11,
+            VoltageScale::Scale2 => 0b10,
+            VoltageScale::Scale3 => 0b01,
+        }
+    }
+
+    /// The highest sys_ck frequency allowed in this voltage scale
+    pub(crate) fn max_sys_ck(self) -> u32 {
+        match self {
+            VoltageScale::Scale0 => 480_000_000,
+            VoltageScale::Scale1 => 400_000_000,
+            VoltageScale::Scale2 => 300_000_000,
+            VoltageScale::Scale3 => 200_000_000,
+        }
+    }
+}
+
+/// The 4 KB backup SRAM
+///
+/// The memory is accessed with volatile reads and writes
+pub struct BackupSram {
+    _0: (),
+}
+
+impl BackupSram {
+    /// The size in bytes
+    pub fn size(&self) -> usize {
+        BACKUP_SRAM_SIZE
+    }
+
+    /// Reads the byte at `index`
+    ///
+    /// # Panics
+    ///
+    /// Panics if `index` is out of bounds
+    pub fn read(&self, index: usize) -> u8 {
+        assert!(index < BACKUP_SRAM_SIZE, "the index is out of bounds");
+        // NOTE(unsafe) the index is inside of the backup SRAM
+        unsafe { ptr::read_volatile((BACKUP_SRAM + index) as *const u8) }
+    }
+
+    /// Writes `value` to the byte at `index`
+    ///
+    /// # Panics
+    ///
+    /// Panics if `index` is out of bounds
+    pub fn write(&mut self, index: usize, value: u8) {
+        assert!(index < BACKUP_SRAM_SIZE, "the index is out of bounds");
+        // NOTE(unsafe) the index is inside of the backup SRAM
+        unsafe { ptr::write_volatile((BACKUP_SRAM + index) as *mut u8, value) };
+    }
+
+    /// Copies the bytes starting at `offset` into `buffer`
+    ///
+    /// # Panics
+    ///
+    /// Panics if the range is out of bounds
+    pub fn read_slice(&self, offset: usize, buffer: &mut [u8]) {
+        assert!(offset + buffer.len() <= BACKUP_SRAM_SIZE, "the range is out of bounds");
+        for (i, byte) in buffer.iter_mut().enumerate() {
+            *byte = self.read(offset + i);
+        }
+    }
+
+    /// Copies `data` to the bytes starting at `offset`
+    ///
+    /// # Panics
+    ///
+    /// Panics if the range is out of bounds
+    pub fn write_slice(&mut self, offset: usize, data: &[u8]) {
+        assert!(offset + data.len() <= BACKUP_SRAM_SIZE, "the range is out of bounds");
+        for (i, byte) in data.iter().enumerate() {
+            self.write(offset + i, *byte);
+        }
+    }
+}
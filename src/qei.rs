@@ -0,0 +1,151 @@
+//! Quadrature Encoder Interface
+
+use hal::{Direction, Qei as QeiTrait};
+use stm32h7::stm32h7x3::{TIM1, TIM2, TIM3, TIM4, TIM5, TIM8};
+
+use crate::gpio::{Floating, Input, AF1, AF2, AF3};
+use crate::gpio::gpioa::{PA0, PA1, PA5, PA6, PA7, PA8, PA9, PA15};
+use crate::gpio::gpiob::{PB3, PB4, PB5, PB6, PB7};
+use crate::gpio::gpioc::{PC6, PC7};
+use crate::gpio::gpiod::{PD12, PD13};
+use crate::gpio::gpioe::{PE9, PE11};
+use crate::gpio::gpioh::{PH10, PH11};
+use crate::gpio::gpioi::{PI5, PI6};
+use crate::rcc::{Enable, Reset, APB1L, APB2};
+
+/// Pin of the A signal of the encoder, channel 1 of the timer - DO NOT IMPLEMENT THIS TRAIT
+///
+/// # Safety
+///
+/// Only the pins whose alternate function connects them to channel 1 of `TIM` may
+/// implement this trait
+pub unsafe trait PinA<TIM> {}
+
+/// Pin of the B signal of the encoder, channel 2 of the timer - DO NOT IMPLEMENT THIS TRAIT
+///
+/// # Safety
+///
+/// Only the pins whose alternate function connects them to channel 2 of `TIM` may
+/// implement this trait
+pub unsafe trait PinB<TIM> {}
+
+// TIM1
+unsafe impl PinA<TIM1> for PA8<Input<Floating>, AF1> {}
+unsafe impl PinA<TIM1> for PE9<Input<Floating>, AF1> {}
+unsafe impl PinB<TIM1> for PA9<Input<Floating>, AF1> {}
+unsafe impl PinB<TIM1> for PE11<Input<Floating>, AF1> {}
+
+// TIM2
+unsafe impl PinA<TIM2> for PA0<Input<Floating>, AF1> {}
+unsafe impl PinA<TIM2> for PA5<Input<Floating>, AF1> {}
+unsafe impl PinA<TIM2> for PA15<Input<Floating>, AF1> {}
+unsafe impl PinB<TIM2> for PA1<Input<Floating>, AF1> {}
+unsafe impl PinB<TIM2> for PB3<Input<Floating>, AF1> {}
+
+// TIM3
+unsafe impl PinA<TIM3> for PA6<Input<Floating>, AF2> {}
+unsafe impl PinA<TIM3> for PB4<Input<Floating>, AF2> {}
+unsafe impl PinA<TIM3> for PC6<Input<Floating>, AF2> {}
+unsafe impl PinB<TIM3> for PA7<Input<Floating>, AF2> {}
+unsafe impl PinB<TIM3> for PB5<Input<Floating>, AF2> {}
+unsafe impl PinB<TIM3> for PC7<Input<Floating>, AF2> {}
+
+// TIM4
+unsafe impl PinA<TIM4> for PB6<Input<Floating>, AF2> {}
+unsafe impl PinA<TIM4> for PD12<Input<Floating>, AF2> {}
+unsafe impl PinB<TIM4> for PB7<Input<Floating>, AF2> {}
+unsafe impl PinB<TIM4> for PD13<Input<Floating>, AF2> {}
+
+// TIM5
+unsafe impl PinA<TIM5> for PA0<Input<Floating>, AF2> {}
+unsafe impl PinA<TIM5> for PH10<Input<Floating>, AF2> {}
+unsafe impl PinB<TIM5> for PA1<Input<Floating>, AF2> {}
+unsafe impl PinB<TIM5> for PH11<Input<Floating>, AF2> {}
+
+// TIM8
+unsafe impl PinA<TIM8> for PC6<Input<Floating>, AF3> {}
+unsafe impl PinA<TIM8> for PI5<Input<Floating>, AF3> {}
+unsafe impl PinB<TIM8> for PC7<Input<Floating>, AF3> {}
+unsafe impl PinB<TIM8> for PI6<Input<Floating>, AF3> {}
+
+/// A timer counting the edges of both signals of a quadrature encoder
+///
+/// The counter counts up or down depending on the direction, it wraps around from the
+/// auto reload value to 0 and back
+pub struct Qei<TIM, PINS> {
+    tim: TIM,
+    pins: PINS,
+}
+
+macro_rules! qei {
+    ($($TIMX:ident: ($timx:ident, $APB:ident, $Count:ty),)+) => {
+        $(
+            impl<PA, PB> Qei<$TIMX, (PA, PB)> {
+                /// Configures the timer as quadrature encoder interface, counting on both
+                /// edges of both signals over the full range of the counter
+                pub fn $timx(tim: $TIMX, pins: (PA, PB), apb: &mut $APB) -> Self
+                where
+                    PA: PinA<$TIMX>,
+                    PB: PinB<$TIMX>,
+                {
+                    $TIMX::enable(apb);
+                    $TIMX::reset(apb);
+
+                    // CC1S = 01, CC2S = 01: TI1 and TI2 are the inputs of IC1 and IC2
+                    tim.ccmr1_output.write(|w| unsafe { w.bits(0b01 | (0b01 << 8)) });
+                    // CC1E, CC2E with non inverted polarity
+                    tim.ccer.write(|w| unsafe { w.bits(1 | (1 << 4)) });
+                    // SMS = 011: encoder mode 3, counting on TI1 and TI2
+                    tim.smcr.write(|w| unsafe { w.bits(0b011) });
+
+                    tim.arr.write(|w| unsafe { w.bits(<$Count>::max_value().into()) });
+                    tim.cr1.modify(|_, w| w.cen().set_bit());
+
+                    Qei { tim, pins }
+                }
+
+                /// Sets the highest count, the counter wraps around to 0 after it
+                pub fn set_max_count(&mut self, max_count: $Count) {
+                    self.tim.arr.write(|w| unsafe { w.bits(max_count.into()) });
+                }
+
+                /// Sets the counter, e.g. to zero a position
+                pub fn set_count(&mut self, count: $Count) {
+                    self.tim.cnt.write(|w| unsafe { w.bits(count.into()) });
+                }
+
+                /// Releases the timer and associated pins
+                pub fn free(self) -> ($TIMX, (PA, PB)) {
+                    self.tim.cr1.modify(|_, w| w.cen().clear_bit());
+                    (self.tim, self.pins)
+                }
+            }
+
+            impl<PINS> QeiTrait for Qei<$TIMX, PINS> {
+                type Count = $Count;
+
+                fn count(&self) -> $Count {
+                    self.tim.cnt.read().bits() as $Count
+                }
+
+                fn direction(&self) -> Direction {
+                    if self.tim.cr1.read().dir().bit_is_clear() {
+                        Direction::Upcounting
+                    } else {
+                        Direction::Downcounting
+                    }
+                }
+            }
+        )+
+    };
+}
+
+// TIM2 and TIM5 have 32 bit counters, the others 16 bit counters
+qei!(
+    TIM1: (tim1, APB2, u16),
+    TIM2: (tim2, APB1L, u32),
+    TIM3: (tim3, APB1L, u16),
+    TIM4: (tim4, APB1L, u16),
+    TIM5: (tim5, APB1L, u32),
+    TIM8: (tim8, APB2, u16),
+);
@@ -0,0 +1,479 @@
+//! Quad Serial Peripheral Interface for external flash memories
+//!
+//! The QUADSPI runs commands in the indirect mode or maps the flash memory to 0x9000_0000 in
+//! the memory mapped mode, only the bank 1 pins are supported
+
+use core::ptr;
+use core::slice;
+
+use crate::gpio::gpioa::PA1;
+use crate::gpio::gpiob::{PB2, PB6, PB10};
+use crate::gpio::gpioc::{PC9, PC10};
+use crate::gpio::gpiod::{PD11, PD12, PD13};
+use crate::gpio::gpioe::PE2;
+use crate::gpio::gpiof::{PF6, PF7, PF8, PF9, PF10};
+use crate::gpio::gpiog::PG6;
+use crate::gpio::{Output, PushPull, AF10, AF9};
+use crate::rcc::{Clocks, Enable, Reset, AHB3};
+use crate::stm32h7x3::QUADSPI;
+use crate::time::Hertz;
+
+/// The address the flash gets mapped to in the memory mapped mode
+const MEMORY_MAPPED: usize = 0x9000_0000;
+
+/// QUADSPI error
+#[derive(Debug)]
+#[non_exhaustive]
+pub enum Error {
+    /// An access was outside of the flash size
+    Transfer,
+}
+
+/// CLK pin - DO NOT IMPLEMENT THIS TRAIT
+///
+/// # Safety
+///
+/// Only the pins whose alternate function connects them to CLK of `QSPI` may
+/// implement this trait
+pub unsafe trait ClkPin<QSPI> {}
+
+/// Bank 1 chip select pin - DO NOT IMPLEMENT THIS TRAIT
+///
+/// # Safety
+///
+/// Only the pins whose alternate function connects them to BK1_NCS of `QSPI` may
+/// implement this trait
+pub unsafe trait NcsPin<QSPI> {}
+
+/// Bank 1 IO0 pin - DO NOT IMPLEMENT THIS TRAIT
+///
+/// # Safety
+///
+/// Only the pins whose alternate function connects them to BK1_IO0 of `QSPI` may
+/// implement this trait
+pub unsafe trait Io0Pin<QSPI> {}
+
+/// Bank 1 IO1 pin - DO NOT IMPLEMENT THIS TRAIT
+///
+/// # Safety
+///
+/// Only the pins whose alternate function connects them to BK1_IO1 of `QSPI` may
+/// implement this trait
+pub unsafe trait Io1Pin<QSPI> {}
+
+/// Bank 1 IO2 pin - DO NOT IMPLEMENT THIS TRAIT
+///
+/// # Safety
+///
+/// Only the pins whose alternate function connects them to BK1_IO2 of `QSPI` may
+/// implement this trait
+pub unsafe trait Io2Pin<QSPI> {}
+
+/// Bank 1 IO3 pin - DO NOT IMPLEMENT THIS TRAIT
+///
+/// # Safety
+///
+/// Only the pins whose alternate function connects them to BK1_IO3 of `QSPI` may
+/// implement this trait
+pub unsafe trait Io3Pin<QSPI> {}
+
+unsafe impl ClkPin<QUADSPI> for PB2<Output<PushPull>, AF9> {}
+unsafe impl ClkPin<QUADSPI> for PF10<Output<PushPull>, AF9> {}
+unsafe impl NcsPin<QUADSPI> for PB6<Output<PushPull>, AF10> {}
+unsafe impl NcsPin<QUADSPI> for PB10<Output<PushPull>, AF9> {}
+unsafe impl NcsPin<QUADSPI> for PG6<Output<PushPull>, AF10> {}
+unsafe impl Io0Pin<QUADSPI> for PC9<Output<PushPull>, AF9> {}
+unsafe impl Io0Pin<QUADSPI> for PD11<Output<PushPull>, AF9> {}
+unsafe impl Io0Pin<QUADSPI> for PF8<Output<PushPull>, AF10> {}
+unsafe impl Io1Pin<QUADSPI> for PC10<Output<PushPull>, AF9> {}
+unsafe impl Io1Pin<QUADSPI> for PD12<Output<PushPull>, AF9> {}
+unsafe impl Io1Pin<QUADSPI> for PF9<Output<PushPull>, AF10> {}
+unsafe impl Io2Pin<QUADSPI> for PE2<Output<PushPull>, AF9> {}
+unsafe impl Io2Pin<QUADSPI> for PF7<Output<PushPull>, AF9> {}
+unsafe impl Io3Pin<QUADSPI> for PA1<Output<PushPull>, AF9> {}
+unsafe impl Io3Pin<QUADSPI> for PD13<Output<PushPull>, AF9> {}
+unsafe impl Io3Pin<QUADSPI> for PF6<Output<PushPull>, AF9> {}
+
+/// The pins of the QUADSPI - DO NOT IMPLEMENT THIS TRAIT
+///
+/// Implemented for (CLK, NCS, IO0, IO1) for single and dual line flashes and for
+/// (CLK, NCS, IO0, IO1, IO2, IO3) for quad line flashes
+///
+/// # Safety
+///
+/// Only tuples of the pins of `QSPI` may implement this trait, `QUAD` has to tell
+/// whether IO2 and IO3 are among them
+pub unsafe trait Pins<QSPI> {
+    /// Whether IO2 and IO3 are connected
+    const QUAD: bool;
+}
+
+unsafe impl<CLK, NCS, IO0, IO1> Pins<QUADSPI> for (CLK, NCS, IO0, IO1)
+where
+    CLK: ClkPin<QUADSPI>,
+    NCS: NcsPin<QUADSPI>,
+    IO0: Io0Pin<QUADSPI>,
+    IO1: Io1Pin<QUADSPI>,
+{
+    const QUAD: bool = false;
+}
+
+unsafe impl<CLK, NCS, IO0, IO1, IO2, IO3> Pins<QUADSPI> for (CLK, NCS, IO0, IO1, IO2, IO3)
+where
+    CLK: ClkPin<QUADSPI>,
+    NCS: NcsPin<QUADSPI>,
+    IO0: Io0Pin<QUADSPI>,
+    IO1: Io1Pin<QUADSPI>,
+    IO2: Io2Pin<QUADSPI>,
+    IO3: Io3Pin<QUADSPI>,
+{
+    const QUAD: bool = true;
+}
+
+/// The lines a phase of a command is transferred on
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Lines {
+    /// IO0 for output and IO1 for input
+    Single = 0b01,
+    /// IO0 and IO1
+    Dual = 0b10,
+    /// IO0 to IO3
+    Quad = 0b11,
+}
+
+/// The size of the address of a command
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum AddressSize {
+    /// 8 bit
+    Bits8 = 0b00,
+    /// 16 bit
+    Bits16 = 0b01,
+    /// 24 bit
+    Bits24 = 0b10,
+    /// 32 bit
+    Bits32 = 0b11,
+}
+
+/// Configuration of the QUADSPI
+#[derive(Clone, Copy, Debug)]
+pub struct Config {
+    frequency: Hertz,
+    flash_size: u32,
+    cs_high_time: u8,
+    mode3: bool,
+    sample_shift: bool,
+}
+
+impl Config {
+    /// A configuration for a flash of `flash_size` bytes clocked with at most `frequency`
+    ///
+    /// The flash is sampled in mode 0 with the chip select high for at least a cycle
+    /// between commands
+    ///
+    /// # Panics
+    ///
+    /// Panics if the flash size is not a power of two of at least 2 bytes
+    pub fn new<F>(frequency: F, flash_size: u32) -> Self
+    where
+        F: Into<Hertz>,
+    {
+        assert!(
+            flash_size >= 2 && flash_size.is_power_of_two(),
+            "the flash size has to be a power of two"
+        );
+
+        Config {
+            frequency: frequency.into(),
+            flash_size,
+            cs_high_time: 1,
+            mode3: false,
+            sample_shift: false,
+        }
+    }
+
+    /// Sets the minimal number of cycles the chip select is high between commands, 1 to 8
+    pub fn cs_high_time(mut self, cycles: u8) -> Self {
+        assert!((1..=8).contains(&cycles), "the chip select high time is 1 to 8 cycles");
+        self.cs_high_time = cycles;
+        self
+    }
+
+    /// Keeps CLK high while the chip select is high (mode 3) instead of low (mode 0)
+    pub fn mode3(mut self, mode3: bool) -> Self {
+        self.mode3 = mode3;
+        self
+    }
+
+    /// Samples the input half a cycle later, this is required for higher frequencies
+    pub fn sample_shift(mut self, shift: bool) -> Self {
+        self.sample_shift = shift;
+        self
+    }
+}
+
+/// A command to the flash
+///
+/// A command consists of the instruction, the address, dummy cycles and the data, each
+/// phase except the instruction is optional
+#[derive(Clone, Copy, Debug)]
+pub struct Command {
+    instruction: u8,
+    instruction_lines: Lines,
+    address: Option<(u32, AddressSize, Lines)>,
+    dummy_cycles: u8,
+    data_lines: Lines,
+}
+
+impl Command {
+    /// A command with only the `instruction` sent on `lines`, the data is transferred on
+    /// the same lines
+    pub fn new(instruction: u8, lines: Lines) -> Self {
+        Command {
+            instruction,
+            instruction_lines: lines,
+            address: None,
+            dummy_cycles: 0,
+            data_lines: lines,
+        }
+    }
+
+    /// Adds an address phase, in the memory mapped mode `address` is replaced by the
+    /// accessed address
+    pub fn address(mut self, address: u32, size: AddressSize, lines: Lines) -> Self {
+        self.address = Some((address, size, lines));
+        self
+    }
+
+    /// Sets the number of dummy cycles between the address and the data, up to 31
+    pub fn dummy_cycles(mut self, cycles: u8) -> Self {
+        assert!(cycles < 32, "at most 31 dummy cycles are supported");
+        self.dummy_cycles = cycles;
+        self
+    }
+
+    /// Sets the lines of the data phase
+    pub fn data_lines(mut self, lines: Lines) -> Self {
+        self.data_lines = lines;
+        self
+    }
+
+    /// The lines of the command
+    fn lines(&self) -> impl Iterator<Item = Lines> {
+        let address = self.address.map(|(_, _, lines)| lines);
+        Some(self.instruction_lines)
+            .into_iter()
+            .chain(address)
+            .chain(Some(self.data_lines))
+    }
+}
+
+/// The functional mode of the QUADSPI
+#[derive(Clone, Copy, PartialEq)]
+enum Mode {
+    IndirectWrite = 0b00,
+    IndirectRead = 0b01,
+    MemoryMapped = 0b11,
+}
+
+/// QUADSPI in the indirect mode
+pub struct Qspi<PINS> {
+    qspi: QUADSPI,
+    pins: PINS,
+    flash_size: u32,
+}
+
+impl<PINS> Qspi<PINS>
+where
+    PINS: Pins<QUADSPI>,
+{
+    /// Enables the QUADSPI, it is clocked by the kernel clock selected with `CFGR::qspi_src`
+    ///
+    /// # Panics
+    ///
+    /// Panics if the frequency can't be reached with a prescaler of at most 256
+    pub fn new(qspi: QUADSPI, pins: PINS, config: Config, ahb3: &mut AHB3, clocks: &Clocks) -> Self {
+        QUADSPI::enable(ahb3);
+        QUADSPI::reset(ahb3);
+
+        let qspi_ck = clocks.qspi_ck().0;
+        let prescaler = qspi_ck.div_ceil(config.frequency.0);
+        assert!((1..=256).contains(&prescaler), "the frequency is out of range");
+
+        // FSIZE + 1 is the number of address bits
+        let fsize = config.flash_size.trailing_zeros() - 1;
+        qspi.quadspi_dcr.write(|w| unsafe {
+            w.fsize()
+                .bits(fsize as u8)
+                .csht()
+                .bits(config.cs_high_time - 1)
+                .ckmode()
+                .bit(config.mode3)
+        });
+
+        // FTHRES = 0: FTF is set as soon as a byte can be transferred
+        qspi.quadspi_cr.write(|w| unsafe {
+            w.prescaler()
+                .bits((prescaler - 1) as u8)
+                .sshift()
+                .bit(config.sample_shift)
+                .fthres()
+                .bits(0)
+                .en()
+                .set_bit()
+        });
+
+        Qspi {
+            qspi,
+            pins,
+            flash_size: config.flash_size,
+        }
+    }
+
+    /// Sends a command without a data phase
+    pub fn command(&mut self, command: &Command) -> Result<(), Error> {
+        self.start(command, Mode::IndirectWrite, 0);
+        self.finish()
+    }
+
+    /// Sends a command and reads its data into `buffer`
+    pub fn read(&mut self, command: &Command, buffer: &mut [u8]) -> Result<(), Error> {
+        self.start(command, Mode::IndirectRead, buffer.len());
+
+        for byte in buffer.iter_mut() {
+            while self.qspi.quadspi_sr.read().flevel().bits() == 0 {
+                if self.qspi.quadspi_sr.read().tef().bit_is_set() {
+                    return self.finish();
+                }
+            }
+            // NOTE(read_volatile) 8-bit read that's not possible through the svd2rust API
+            *byte = unsafe { ptr::read_volatile(&self.qspi.quadspi_dr as *const _ as *const u8) };
+        }
+
+        self.finish()
+    }
+
+    /// Sends a command followed by `data`
+    pub fn write(&mut self, command: &Command, data: &[u8]) -> Result<(), Error> {
+        self.start(command, Mode::IndirectWrite, data.len());
+
+        for byte in data {
+            while self.qspi.quadspi_sr.read().ftf().bit_is_clear() {
+                if self.qspi.quadspi_sr.read().tef().bit_is_set() {
+                    return self.finish();
+                }
+            }
+            // NOTE(write_volatile) 8-bit write that's not possible through the svd2rust API
+            unsafe { ptr::write_volatile(ptr::addr_of!(self.qspi.quadspi_dr) as *mut u8, *byte) };
+        }
+
+        self.finish()
+    }
+
+    /// Maps the flash to 0x9000_0000, the flash is read with `command` whose address phase
+    /// is replaced by the accessed address
+    ///
+    /// # Panics
+    ///
+    /// Panics if the command has no address phase
+    pub fn memory_mapped(mut self, command: Command) -> MemoryMapped<PINS> {
+        assert!(command.address.is_some(), "the memory mapped mode needs an address phase");
+        self.start(&command, Mode::MemoryMapped, 0);
+        MemoryMapped { qspi: self }
+    }
+
+    /// Releases the QUADSPI and the pins
+    pub fn free(self) -> (QUADSPI, PINS) {
+        self.qspi.quadspi_cr.modify(|_, w| w.en().clear_bit());
+        (self.qspi, self.pins)
+    }
+
+    /// Writes the registers that start a command with `len` bytes of data
+    fn start(&mut self, command: &Command, mode: Mode, len: usize) {
+        assert!(
+            PINS::QUAD || command.lines().all(|lines| lines != Lines::Quad),
+            "the quad lines need IO2 and IO3"
+        );
+
+        while self.qspi.quadspi_sr.read().busy().bit_is_set() {}
+        self.qspi.quadspi_fcr.write(|w| {
+            w.ctcf().set_bit().ctef().set_bit().csmf().set_bit().ctof().set_bit()
+        });
+
+        if len > 0 {
+            self.qspi.quadspi_dlr.write(|w| unsafe { w.dl().bits(len as u32 - 1) });
+        }
+
+        let dmode = if len > 0 || mode == Mode::MemoryMapped {
+            command.data_lines as u8
+        } else {
+            0
+        };
+        let (address, adsize, admode) = match command.address {
+            Some((address, size, lines)) => (Some(address), size as u8, lines as u8),
+            None => (None, 0, 0),
+        };
+        self.qspi.quadspi_ccr.write(|w| unsafe {
+            w.fmode()
+                .bits(mode as u8)
+                .dmode()
+                .bits(dmode)
+                .dcyc()
+                .bits(command.dummy_cycles)
+                .adsize()
+                .bits(adsize)
+                .admode()
+                .bits(admode)
+                .imode()
+                .bits(command.instruction_lines as u8)
+                .instruction()
+                .bits(command.instruction)
+        });
+
+        // the command starts with the write of the address if it has one
+        match (address, mode) {
+            (_, Mode::MemoryMapped) | (None, _) => {}
+            (Some(address), _) => {
+                self.qspi.quadspi_ar.write(|w| unsafe { w.address().bits(address) })
+            }
+        }
+    }
+
+    /// Waits for the end of a command
+    fn finish(&mut self) -> Result<(), Error> {
+        loop {
+            let sr = self.qspi.quadspi_sr.read();
+            if sr.tef().bit_is_set() {
+                self.qspi.quadspi_fcr.write(|w| w.ctef().set_bit());
+                return Err(Error::Transfer);
+            }
+            if sr.tcf().bit_is_set() {
+                break;
+            }
+        }
+
+        self.qspi.quadspi_fcr.write(|w| w.ctcf().set_bit());
+        while self.qspi.quadspi_sr.read().busy().bit_is_set() {}
+        Ok(())
+    }
+}
+
+/// QUADSPI in the memory mapped mode
+pub struct MemoryMapped<PINS> {
+    qspi: Qspi<PINS>,
+}
+
+impl<PINS> MemoryMapped<PINS> {
+    /// The mapped flash
+    pub fn as_slice(&self) -> &[u8] {
+        // NOTE(unsafe) the region is mapped for the lifetime of this handle and is read only
+        unsafe { slice::from_raw_parts(MEMORY_MAPPED as *const u8, self.qspi.flash_size as usize) }
+    }
+
+    /// Leaves the memory mapped mode
+    pub fn release(self) -> Qspi<PINS> {
+        let qspi = &self.qspi.qspi;
+        qspi.quadspi_cr.modify(|_, w| w.abort().set_bit());
+        while qspi.quadspi_cr.read().abort().bit_is_set() {}
+        self.qspi
+    }
+}
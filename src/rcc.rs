@@ -1,9 +1,13 @@
 //! Reset and clock control implementation
 
-use stm32h7::stm32h7x3::{rcc, RCC};
-use cast::{u8, u16};
+use stm32h7::stm32h7x3::{self, rcc, RCC};
+use cast::{u8, u16, u32};
 use crate::time::Hertz;
 use crate::flash::ACR;
+use crate::gpio::gpioa::PA8;
+use crate::gpio::gpioc::PC9;
+use crate::gpio::{Output, PushPull, AF0};
+use crate::pwr::{Pwr, VoltageScale};
 
 /// Extension trait that constrains the `RCC` peripheral
 pub trait RccExt {
@@ -23,20 +27,7 @@ impl RccExt for RCC {
             apb2: APB2 { _0: ()},
             apb3: APB3 { _0: ()},
             apb4: APB4 { _0: ()},
-            cfgr: CFGR {
-                hclk1: None,
-                hclk2: None,
-                hclk3: None,
-                hclk4: None,
-                pclk1: None,
-                pclk2: None,
-                pclk3: None,
-                pclk4: None,
-                sys_ck: None,
-                divp: None,
-                divn: None,
-                divm: None,
-            }    
+            cfgr: CFGR::new(),
         }
     }
 }
@@ -66,7 +57,10 @@ pub struct Rcc {
 }
 
 macro_rules! ahb {
-    ($($AHBx:ident: ($ahbx:ident, $AHBXENR:ident, $ahbxenr:ident, $AHBXRSTR:ident, $ahbxrstr:ident),)+) => {
+    ($($AHBx:ident: (
+        $ahbx:ident, $AHBXENR:ident, $ahbxenr:ident, $AHBXRSTR:ident, $ahbxrstr:ident,
+        $AHBXLPENR:ident, $ahbxlpenr:ident
+    ),)+) => {
         $(
 
             /// A simple struct for providing safe APIs to the registers of the AHB busses
@@ -84,13 +78,21 @@ macro_rules! ahb {
                 pub(crate) fn rstr(&mut self) -> &rcc::$AHBXRSTR {
                     unsafe {&(*RCC::ptr()).$ahbxrstr}
                 }
+
+                /// Provides access to the lpenr register of the AHB
+                pub(crate) fn lpenr(&mut self) -> &rcc::$AHBXLPENR {
+                    unsafe {&(*RCC::ptr()).$ahbxlpenr}
+                }
             }
         )+
     };
 }
 
 macro_rules! apb {
-    ($($APBx:ident: ($apbx:ident, $APBXENR:ident, $apbxenr:ident, $APBXRSTR:ident, $apbxrstr:ident),)+) => {
+    ($($APBx:ident: (
+        $apbx:ident, $APBXENR:ident, $apbxenr:ident, $APBXRSTR:ident, $apbxrstr:ident,
+        $APBXLPENR:ident, $apbxlpenr:ident
+    ),)+) => {
         $(
 
             /// A simple struct providing safe APIs to the regsiters of the APB busses
@@ -108,30 +110,980 @@ macro_rules! apb {
                 pub(crate) fn rstr(&mut self) -> &rcc::$APBXRSTR {
                     unsafe {&(*RCC::ptr()).$apbxrstr}
                 }
+
+                /// Provides access to the lpenr register of the APB
+                pub(crate) fn lpenr(&mut self) -> &rcc::$APBXLPENR {
+                    unsafe {&(*RCC::ptr()).$apbxlpenr}
+                }
             }
         )+
     };
 }
 
 ahb!(
-    AHB1: (abh1, AHB1ENR, ahb1enr, AHB1RSTR, ahb1rstr),
-    AHB2: (ahb2, AHB2ENR, ahb2enr, AHB2RSTR, ahb2rstr),
-    AHB3: (ahb3, AHB3ENR, ahb3enr, AHB3RSTR, ahb3rstr),
-    AHB4: (ahb4, AHB4ENR, ahb4enr, AHB4RSTR, ahb4rstr),
+    AHB1: (abh1, AHB1ENR, ahb1enr, AHB1RSTR, ahb1rstr, AHB1LPENR, ahb1lpenr),
+    AHB2: (ahb2, AHB2ENR, ahb2enr, AHB2RSTR, ahb2rstr, AHB2LPENR, ahb2lpenr),
+    AHB3: (ahb3, AHB3ENR, ahb3enr, AHB3RSTR, ahb3rstr, AHB3LPENR, ahb3lpenr),
+    AHB4: (ahb4, AHB4ENR, ahb4enr, AHB4RSTR, ahb4rstr, AHB4LPENR, ahb4lpenr),
 );
 
 apb!(
-    APB1H: (apb1h, APB1HENR, apb1henr, APB1HRSTR, apb1hrstr),
-    APB1L: (apb1l, APB1LENR, apb1lenr, APB1LRSTR, apb1lrstr),
-    APB2: (apb2, APB2ENR, apb2enr, APB2RSTR, apb2rstr),
-    APB3: (apb3, APB3ENR, apb3enr, APB3RSTR, apb3rstr),
-    APB4: (apb4, APB4ENR, apb4enr, APB4RSTR, apb4rstr),
+    APB1H: (apb1h, APB1HENR, apb1henr, APB1HRSTR, apb1hrstr, APB1HLPENR, apb1hlpenr),
+    APB1L: (apb1l, APB1LENR, apb1lenr, APB1LRSTR, apb1lrstr, APB1LLPENR, apb1llpenr),
+    APB2: (apb2, APB2ENR, apb2enr, APB2RSTR, apb2rstr, APB2LPENR, apb2lpenr),
+    APB3: (apb3, APB3ENR, apb3enr, APB3RSTR, apb3rstr, APB3LPENR, apb3lpenr),
+    APB4: (apb4, APB4ENR, apb4enr, APB4RSTR, apb4rstr, APB4LPENR, apb4lpenr),
+);
+
+/// Peripherals connected to one of the AHB or APB busses
+pub trait RccBus {
+    /// The bus the peripheral is connected to
+    type Bus;
+}
+
+/// Peripherals whose clock can be enabled and disabled through their bus
+pub trait Enable: RccBus {
+    /// Enables the clock of the peripheral
+    fn enable(bus: &mut Self::Bus);
+
+    /// Disables the clock of the peripheral
+    fn disable(bus: &mut Self::Bus);
+}
+
+/// Peripherals which can be reset through their bus
+pub trait Reset: RccBus {
+    /// Resets all the registers of the peripheral
+    fn reset(bus: &mut Self::Bus);
+}
+
+/// Peripherals whose clock can keep running while the core sleeps
+///
+/// After a reset the clocks of all enabled peripherals keep running in Sleep mode
+pub trait SleepEnable: RccBus {
+    /// Keeps the clock of the peripheral running in Sleep mode, e.g. for a DMA transfer or
+    /// a USART to wake up the core
+    fn enable_in_sleep(bus: &mut Self::Bus);
+
+    /// Stops the clock of the peripheral in Sleep mode to save power
+    fn disable_in_sleep(bus: &mut Self::Bus);
+}
+
+macro_rules! bus {
+    ($($PER:ident: ($BUSx:ident, $perxen:ident $(, $perxrst:ident)?),)+) => {
+        $(
+            impl RccBus for stm32h7x3::$PER {
+                type Bus = $BUSx;
+            }
+
+            impl Enable for stm32h7x3::$PER {
+                fn enable(bus: &mut Self::Bus) {
+                    bus.enr().modify(|_, w| w.$perxen().set_bit());
+                }
+
+                fn disable(bus: &mut Self::Bus) {
+                    bus.enr().modify(|_, w| w.$perxen().clear_bit());
+                }
+            }
+
+            $(
+                impl Reset for stm32h7x3::$PER {
+                    fn reset(bus: &mut Self::Bus) {
+                        bus.rstr().modify(|_, w| w.$perxrst().set_bit());
+                        bus.rstr().modify(|_, w| w.$perxrst().clear_bit());
+                    }
+                }
+            )?
+        )+
+    };
+}
+
+// FDCAN1 and FDCAN2 share their enable and reset bits, the WWDG and the RTC
+// can not be reset through their bus
+bus!(
+    DMA1: (AHB1, dma1en, dma1rst),
+    DMA2: (AHB1, dma2en, dma2rst),
+    ADC12_COMMON: (AHB1, adc12en, adc12rst),
+    ETHERNET_MAC: (AHB1, eth1macen, eth1macrst),
+    OTG1_HS_GLOBAL: (AHB1, usb1otgen, usb1otgrst),
+    OTG2_HS_GLOBAL: (AHB1, usb2otgen, usb2otgrst),
+    DCMI: (AHB2, camitfen, camitfrst),
+    RNG: (AHB2, rngen, rngrst),
+    SDMMC2: (AHB2, sdmmc2en, sdmmc2rst),
+    MDMA: (AHB3, mdmaen, mdmarst),
+    DMA2D: (AHB3, dma2den, dma2drst),
+    JPEG: (AHB3, jpgdecen, jpgdecrst),
+    FMC: (AHB3, fmcen, fmcrst),
+    QUADSPI: (AHB3, qspien, qspirst),
+    SDMMC1: (AHB3, sdmmc1en, sdmmc1rst),
+    GPIOA: (AHB4, gpioaen, gpioarst),
+    GPIOB: (AHB4, gpioben, gpiobrst),
+    GPIOC: (AHB4, gpiocen, gpiocrst),
+    GPIOD: (AHB4, gpioden, gpiodrst),
+    GPIOE: (AHB4, gpioeen, gpioerst),
+    GPIOF: (AHB4, gpiofen, gpiofrst),
+    GPIOG: (AHB4, gpiogen, gpiogrst),
+    GPIOH: (AHB4, gpiohen, gpiohrst),
+    GPIOI: (AHB4, gpioien, gpioirst),
+    GPIOJ: (AHB4, gpiojen, gpiojrst),
+    GPIOK: (AHB4, gpioken, gpiokrst),
+    CRC: (AHB4, crcen, crcrst),
+    BDMA: (AHB4, bdmaen, bdmarst),
+    ADC3: (AHB4, adc3en, adc3rst),
+    HSEM: (AHB4, hsemen, hsemrst),
+    TIM2: (APB1L, tim2en, tim2rst),
+    TIM3: (APB1L, tim3en, tim3rst),
+    TIM4: (APB1L, tim4en, tim4rst),
+    TIM5: (APB1L, tim5en, tim5rst),
+    TIM6: (APB1L, tim6en, tim6rst),
+    TIM7: (APB1L, tim7en, tim7rst),
+    TIM12: (APB1L, tim12en, tim12rst),
+    TIM13: (APB1L, tim13en, tim13rst),
+    TIM14: (APB1L, tim14en, tim14rst),
+    LPTIM1: (APB1L, lptim1en, lptim1rst),
+    SPI2: (APB1L, spi2en, spi2rst),
+    SPI3: (APB1L, spi3en, spi3rst),
+    SPDIFRX: (APB1L, spdifrxen, spdifrxrst),
+    USART2: (APB1L, usart2en, usart2rst),
+    USART3: (APB1L, usart3en, usart3rst),
+    UART4: (APB1L, uart4en, uart4rst),
+    UART5: (APB1L, uart5en, uart5rst),
+    I2C1: (APB1L, i2c1en, i2c1rst),
+    I2C2: (APB1L, i2c2en, i2c2rst),
+    I2C3: (APB1L, i2c3en, i2c3rst),
+    CEC: (APB1L, hdmicecen, hdmicecrst),
+    DAC: (APB1L, dac12en, dac12rst),
+    UART7: (APB1L, usart7en, usart7rst),
+    UART8: (APB1L, usart8en, usart8rst),
+    CRS: (APB1H, crsen, crsrst),
+    SWPMI: (APB1H, swpen, swprst),
+    OPAMP: (APB1H, opampen, opamprst),
+    MDIOS: (APB1H, mdiosen, mdiosrst),
+    FDCAN1: (APB1H, fdcanen, fdcanrst),
+    FDCAN2: (APB1H, fdcanen, fdcanrst),
+    TIM1: (APB2, tim1en, tim1rst),
+    TIM8: (APB2, tim8en, tim8rst),
+    USART1: (APB2, usart1en, usart1rst),
+    USART6: (APB2, usart6en, usart6rst),
+    SPI1: (APB2, spi1en, spi1rst),
+    SPI4: (APB2, spi4en, spi4rst),
+    TIM15: (APB2, tim15en, tim15rst),
+    TIM16: (APB2, tim16en, tim16rst),
+    TIM17: (APB2, tim17en, tim17rst),
+    SPI5: (APB2, spi5en, spi5rst),
+    SAI1: (APB2, sai1en, sai1rst),
+    SAI2: (APB2, sai2en, sai2rst),
+    SAI3: (APB2, sai3en, sai3rst),
+    DFSDM: (APB2, dfsdm1en, dfsdm1rst),
+    HRTIM_COMMON: (APB2, hrtimen, hrtimrst),
+    LTDC: (APB3, ltdcen, ltdcrst),
+    WWDG: (APB3, wwdg1en),
+    SYSCFG: (APB4, syscfgen, syscfgrst),
+    LPUART1: (APB4, lpuart1en, lpuart1rst),
+    SPI6: (APB4, spi6en, spi6rst),
+    I2C4: (APB4, i2c4en, i2c4rst),
+    LPTIM2: (APB4, lptim2en, lptim2rst),
+    LPTIM3: (APB4, lptim3en, lptim3rst),
+    LPTIM4: (APB4, lptim4en, lptim4rst),
+    LPTIM5: (APB4, lptim5en, lptim5rst),
+    COMP1: (APB4, comp12en, comp12rst),
+    VREFBUF: (APB4, vrefen, vrefrst),
+    RTC: (APB4, rtcapben),
+    SAI4: (APB4, sai4en, sai4rst),
+);
+
+macro_rules! sleep {
+    ($($PER:ident: $perxlpen:ident,)+) => {
+        $(
+            impl SleepEnable for stm32h7x3::$PER {
+                fn enable_in_sleep(bus: &mut Self::Bus) {
+                    bus.lpenr().modify(|_, w| w.$perxlpen().set_bit());
+                }
+
+                fn disable_in_sleep(bus: &mut Self::Bus) {
+                    bus.lpenr().modify(|_, w| w.$perxlpen().clear_bit());
+                }
+            }
+        )+
+    };
+}
+
+// the CRYP and the HASH only exist on the parts with crypto acceleration
+#[cfg(any(feature = "h753", feature = "h750"))]
+bus!(
+    CRYP: (AHB2, crypten, cryptrst),
+    HASH: (AHB2, hashen, hashrst),
+);
+
+#[cfg(any(feature = "h753", feature = "h750"))]
+sleep!(
+    CRYP: cryptlpen,
+    HASH: hashlpen,
+);
+
+// the HSEM has no low power enable bit
+sleep!(
+    DMA1: dma1lpen,
+    DMA2: dma2lpen,
+    ADC12_COMMON: adc12lpen,
+    ETHERNET_MAC: eth1maclpen,
+    OTG1_HS_GLOBAL: usb1otglpen,
+    OTG2_HS_GLOBAL: usb2otglpen,
+    DCMI: camitflpen,
+    RNG: rnglpen,
+    SDMMC2: sdmmc2lpen,
+    MDMA: mdmalpen,
+    DMA2D: dma2dlpen,
+    JPEG: jpgdeclpen,
+    FMC: fmclpen,
+    QUADSPI: qspilpen,
+    SDMMC1: sdmmc1lpen,
+    GPIOA: gpioalpen,
+    GPIOB: gpioblpen,
+    GPIOC: gpioclpen,
+    GPIOD: gpiodlpen,
+    GPIOE: gpioelpen,
+    GPIOF: gpioflpen,
+    GPIOG: gpioglpen,
+    GPIOH: gpiohlpen,
+    GPIOI: gpioilpen,
+    GPIOJ: gpiojlpen,
+    GPIOK: gpioklpen,
+    CRC: crclpen,
+    BDMA: bdmalpen,
+    ADC3: adc3lpen,
+    TIM2: tim2lpen,
+    TIM3: tim3lpen,
+    TIM4: tim4lpen,
+    TIM5: tim5lpen,
+    TIM6: tim6lpen,
+    TIM7: tim7lpen,
+    TIM12: tim12lpen,
+    TIM13: tim13lpen,
+    TIM14: tim14lpen,
+    LPTIM1: lptim1lpen,
+    SPI2: spi2lpen,
+    SPI3: spi3lpen,
+    SPDIFRX: spdifrxlpen,
+    USART2: usart2lpen,
+    USART3: usart3lpen,
+    UART4: uart4lpen,
+    UART5: uart5lpen,
+    I2C1: i2c1lpen,
+    I2C2: i2c2lpen,
+    I2C3: i2c3lpen,
+    CEC: hdmiceclpen,
+    DAC: dac12lpen,
+    UART7: usart7lpen,
+    UART8: usart8lpen,
+    CRS: crslpen,
+    SWPMI: swplpen,
+    OPAMP: opamplpen,
+    MDIOS: mdioslpen,
+    FDCAN1: fdcanlpen,
+    FDCAN2: fdcanlpen,
+    TIM1: tim1lpen,
+    TIM8: tim8lpen,
+    USART1: usart1lpen,
+    USART6: usart6lpen,
+    SPI1: spi1lpen,
+    SPI4: spi4lpen,
+    TIM15: tim15lpen,
+    TIM16: tim16lpen,
+    TIM17: tim17lpen,
+    SPI5: spi5lpen,
+    SAI1: sai1lpen,
+    SAI2: sai2lpen,
+    SAI3: sai3lpen,
+    DFSDM: dfsdm1lpen,
+    HRTIM_COMMON: hrtimlpen,
+    LTDC: ltdclpen,
+    WWDG: wwdg1lpen,
+    SYSCFG: syscfglpen,
+    LPUART1: lpuart1lpen,
+    SPI6: spi6lpen,
+    I2C4: i2c4lpen,
+    LPTIM2: lptim2lpen,
+    LPTIM3: lptim3lpen,
+    LPTIM4: lptim4lpen,
+    LPTIM5: lptim5lpen,
+    COMP1: comp12lpen,
+    VREFBUF: vreflpen,
+    RTC: rtcapblpen,
+    SAI4: sai4lpen,
 );
 
 const HSI: u32 = 64_000_000; // Hz
+const CSI: u32 = 4_000_000; // Hz
+const HSI48: u32 = 48_000_000; // Hz
+const LSI: u32 = 32_000; // Hz
+
+/// The number of polls of a ready flag before the wait is given up, far more than the HSE,
+/// the plls or a clock switch take at the slowest sys_ck
+const READY_TIMEOUT: u32 = 1_000_000;
+
+/// The number of polls of LSERDY before the wait is given up, an LSE crystal takes up to
+/// 2 s to start, which are roughly 15 million polls at the HSI
+const LSE_READY_TIMEOUT: u32 = 50_000_000;
+
+/// Polls `ready` until it returns true, or fails with `error` after `READY_TIMEOUT` polls
+fn wait_until<F: FnMut() -> bool>(ready: F, error: RccError) -> Result<(), RccError> {
+    wait_polls(ready, READY_TIMEOUT, error)
+}
+
+/// Polls `ready` until it returns true, or fails with `error` after `polls` polls
+fn wait_polls<F: FnMut() -> bool>(mut ready: F, polls: u32, error: RccError) -> Result<(), RccError> {
+    for _ in 0..polls {
+        if ready() {
+            return Ok(());
+        }
+    }
+    Err(error)
+}
+
+/// The dividers selectable through the HPRE and D1CPRE fields, there is no divider of 32
+const HPRE_DIVIDERS: [u32; 9] = [1, 2, 4, 8, 16, 64, 128, 256, 512];
+
+/// The dividers selectable through the D1PPRE, D2PPRE1, D2PPRE2 and D3PPRE fields
+const PPRE_DIVIDERS: [u32; 5] = [1, 2, 4, 8, 16];
+
+/// The HPRE or D1CPRE encoding of a divider out of `HPRE_DIVIDERS`, 0b1000 is 2 and
+/// 0b1100 is 64
+fn encode_hpre(divider: u32) -> u8 {
+    match divider {
+        1 => 0b0000,
+        64..=512 => u8(0b1100 + divider.trailing_zeros() - 6).unwrap(),
+        _ => u8(0b0111 + divider.trailing_zeros()).unwrap(),
+    }
+}
+
+/// The divider encoded in the HPRE or D1CPRE field
+fn decode_hpre(bits: u8) -> u32 {
+    if bits & 0b1000 == 0 { 1 } else { HPRE_DIVIDERS[usize::from(bits & 0b111) + 1] }
+}
+
+/// The D1PPRE, D2PPRE1, D2PPRE2 or D3PPRE encoding of a divider out of `PPRE_DIVIDERS`,
+/// 0b100 is 2 and 0b111 is 16
+fn encode_ppre(divider: u32) -> u8 {
+    match divider {
+        1 => 0b000,
+        _ => u8(0b011 + divider.trailing_zeros()).unwrap(),
+    }
+}
+
+/// The divider encoded in a D1PPRE, D2PPRE1, D2PPRE2 or D3PPRE field
+fn decode_ppre(bits: u8) -> u32 {
+    if bits & 0b100 == 0 { 1 } else { 2 << (bits & 0b11) }
+}
+
+/// The hclk out of the frequencies set for the AHB buses, which share one divider and so
+/// have to agree
+fn requested_hclk(requests: &[Option<u32>]) -> Result<Option<u32>, RccError> {
+    let mut requested = None;
+    for &freq in requests.iter().flatten() {
+        match requested {
+            Some(hclk) if hclk != freq => return Err(RccError::ConflictingHclk),
+            _ => requested = Some(freq),
+        }
+    }
+    Ok(requested)
+}
+
+/// The APB divider that brings `hclk` closest to the frequency requested for the APB `bus`
+fn ppre_divider(pclk: Option<u32>, hclk: u32, bus: u8) -> Result<u32, RccError> {
+    match pclk {
+        Some(pclk) if pclk > hclk => Err(RccError::PclkTooHigh { bus }),
+        Some(pclk) => Ok(best_divider(pclk, hclk, &PPRE_DIVIDERS)),
+        None => Ok(1),
+    }
+}
+
+/// Picks the divider out of `allowed` which brings `source` closest to `target`
+/// without exceeding it, if none of them fit the largest divider is returned
+fn best_divider(target: u32, source: u32, allowed: &[u32]) -> u32 {
+    allowed.iter()
+        .cloned()
+        .filter(|divider| source / divider <= target)
+        .max_by_key(|divider| source / divider)
+        .unwrap_or_else(|| allowed.iter().cloned().max().unwrap_or(1))
+}
+
+/// Looks up the flash LATENCY and WRHIGHFREQ values required to run the AXI bus
+/// at `hclk` in the given voltage scale, see table 17 of the reference manual
+fn flash_latency(vos: VoltageScale, hclk: u32) -> Result<(u8, u8), RccError> {
+    let config = match vos {
+        VoltageScale::Scale0 | VoltageScale::Scale1 => match hclk {
+            0..=70_000_000 => (0, 0),
+            70_000_001..=140_000_000 => (1, 1),
+            140_000_001..=185_000_000 => (2, 1),
+            185_000_001..=210_000_000 => (2, 2),
+            210_000_001..=225_000_000 => (3, 2),
+            // only VOS0 allows for an AXI bus above 225 MHz
+            225_000_001..=240_000_000 if vos == VoltageScale::Scale0 => (4, 2),
+            _ => return Err(RccError::HclkTooHigh),
+        },
+        VoltageScale::Scale2 => match hclk {
+            0..=55_000_000 => (0, 0),
+            55_000_001..=110_000_000 => (1, 1),
+            110_000_001..=165_000_000 => (2, 1),
+            165_000_001..=225_000_000 => (3, 2),
+            _ => return Err(RccError::HclkTooHigh),
+        },
+        VoltageScale::Scale3 => match hclk {
+            0..=45_000_000 => (0, 0),
+            45_000_001..=90_000_000 => (1, 1),
+            90_000_001..=135_000_000 => (2, 1),
+            135_000_001..=180_000_000 => (3, 2),
+            180_000_001..=225_000_000 => (4, 2),
+            _ => return Err(RccError::HclkTooHigh),
+        },
+    };
+    Ok(config)
+}
+
+/// Clock source of the RTC
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum RtcClockSource {
+    /// The LSE
+    Lse,
+    /// The LSI
+    Lsi,
+    /// The HSE divided by RTCPRE down to at most 1 MHz
+    Hse,
+}
+
+/// Kernel clock source of SPI1, SPI2 and SPI3
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Spi123ClkSource {
+    /// The q output of the pll1
+    Pll1Q = 0b000,
+    /// The p output of the pll2
+    Pll2P = 0b001,
+    /// The p output of the pll3
+    Pll3P = 0b010,
+    /// per_ck, which is driven by the HSI
+    PerCk = 0b100,
+}
+
+/// Kernel clock source of the USARTs and LPUART1
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum UsartClkSource {
+    /// The clock of the APB the USART is connected to, pclk2 for USART1 and USART6, pclk4
+    /// for LPUART1, pclk1 otherwise
+    Pclk = 0b000,
+    /// The q output of the pll2
+    Pll2Q = 0b001,
+    /// The q output of the pll3
+    Pll3Q = 0b010,
+    /// The HSI
+    Hsi = 0b011,
+    /// The CSI
+    Csi = 0b100,
+    /// The LSE
+    Lse = 0b101,
+}
+
+/// Kernel clock source of LPTIM1
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum LptimClkSource {
+    /// pclk1
+    Pclk = 0b000,
+    /// The p output of the pll2
+    Pll2P = 0b001,
+    /// The r output of the pll3
+    Pll3R = 0b010,
+    /// The LSE
+    Lse = 0b011,
+    /// The LSI
+    Lsi = 0b100,
+    /// per_ck, which is driven by the HSI
+    PerCk = 0b101,
+}
+
+/// Kernel clock source of the I2Cs
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum I2cClkSource {
+    /// The clock of the APB the I2C is connected to, pclk4 for I2C4, pclk1 otherwise
+    Pclk = 0b00,
+    /// The r output of the pll3
+    Pll3R = 0b01,
+    /// The HSI
+    Hsi = 0b10,
+    /// The CSI
+    Csi = 0b11,
+}
+
+/// Kernel clock source of the ADCs
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum AdcClkSource {
+    /// The p output of the pll2
+    Pll2P = 0b00,
+    /// The r output of the pll3
+    Pll3R = 0b01,
+    /// per_ck, which is driven by the HSI
+    PerCk = 0b10,
+}
+
+/// Kernel clock source of the RNG
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum RngClkSource {
+    /// The HSI48
+    Hsi48 = 0b00,
+    /// The q output of the pll1
+    Pll1Q = 0b01,
+    /// The LSE
+    Lse = 0b10,
+    /// The LSI
+    Lsi = 0b11,
+}
+
+/// Kernel clock source of the QUADSPI
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum QspiClkSource {
+    /// hclk3
+    Hclk3 = 0b00,
+    /// The q output of the pll1
+    Pll1Q = 0b01,
+    /// The r output of the pll2
+    Pll2R = 0b10,
+    /// per_ck, which is driven by the HSI
+    PerCk = 0b11,
+}
+
+/// Kernel clock source of the FMC
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum FmcClkSource {
+    /// hclk3
+    Hclk3 = 0b00,
+    /// The q output of the pll1
+    Pll1Q = 0b01,
+    /// The r output of the pll2
+    Pll2R = 0b10,
+    /// per_ck, which is driven by the HSI
+    PerCk = 0b11,
+}
+
+/// Kernel clock source of the SAIs
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum SaiClkSource {
+    /// The q output of the pll1
+    Pll1Q = 0b000,
+    /// The p output of the pll2
+    Pll2P = 0b001,
+    /// The p output of the pll3
+    Pll3P = 0b010,
+    /// per_ck, which is driven by the HSI
+    PerCk = 0b100,
+}
+
+/// Kernel clock source of SDMMC1 and SDMMC2
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum SdmmcClkSource {
+    /// The q output of the pll1
+    Pll1Q,
+    /// The r output of the pll2
+    Pll2R,
+}
+
+/// Kernel clock source of FDCAN1 and FDCAN2
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum FdcanClkSource {
+    /// The HSE
+    Hse = 0b00,
+    /// The q output of the pll1
+    Pll1Q = 0b01,
+    /// The q output of the pll2
+    Pll2Q = 0b10,
+}
+
+/// Kernel clock source of the USB OTG cores
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum UsbClkSource {
+    /// The q output of the pll1
+    Pll1Q = 0b01,
+    /// The q output of the pll3
+    Pll3Q = 0b10,
+    /// The HSI48
+    Hsi48 = 0b11,
+}
+
+/// Clock source of the HRTIM
+#[derive(Clone, Copy, Debug, PartialEq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum HrtimClkSource {
+    /// The kernel clock of the timers on APB2
+    TimyKerCk,
+    /// The CPU clock
+    CpuCk,
+}
+
+/// Clock routed to the MCO1 pin
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Mco1Source {
+    /// The HSI
+    Hsi = 0b000,
+    /// The LSE
+    Lse = 0b001,
+    /// The HSE
+    Hse = 0b010,
+    /// The q output of the pll1
+    Pll1Q = 0b011,
+    /// The HSI48
+    Hsi48 = 0b100,
+}
+
+/// Clock routed to the MCO2 pin
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Mco2Source {
+    /// sys_ck
+    SysCk = 0b000,
+    /// The p output of the pll2
+    Pll2P = 0b001,
+    /// The HSE
+    Hse = 0b010,
+    /// The p output of the pll1
+    Pll1P = 0b011,
+    /// The CSI
+    Csi = 0b100,
+    /// The LSI
+    Lsi = 0b101,
+}
+
+/// MCO1 pin - DO NOT IMPLEMENT THIS TRAIT
+///
+/// # Safety
+///
+/// Only the pins whose alternate function connects them to MCO1 may implement this
+/// trait
+pub unsafe trait Mco1Pin {}
+
+/// MCO2 pin - DO NOT IMPLEMENT THIS TRAIT
+///
+/// # Safety
+///
+/// Only the pins whose alternate function connects them to MCO2 may implement this
+/// trait
+pub unsafe trait Mco2Pin {}
+
+unsafe impl Mco1Pin for PA8<Output<PushPull>, AF0> {}
+unsafe impl Mco2Pin for PC9<Output<PushPull>, AF0> {}
+
+/// Clock configuration error
+#[derive(Debug, PartialEq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+#[non_exhaustive]
+pub enum RccError {
+    /// divm was outside of 1 to 63
+    DivmOutOfRange,
+    /// divn was outside of 4 to 512
+    DivnOutOfRange,
+    /// divp was outside of 1 to 128, for the pll1 it also has to be even
+    DivpOutOfRange,
+    /// divq was outside of 1 to 128
+    DivqOutOfRange,
+    /// divr was outside of 1 to 128
+    DivrOutOfRange,
+    /// fracn was above 8191
+    FracnOutOfRange,
+    /// The reference clock (pll source / divm) of a pll was outside of 1 MHz to 16 MHz
+    RefClkOutOfRange {
+        /// The number of the pll
+        pll: u8,
+        /// The offending reference clock frequency in Hz
+        ref_ck: u32,
+    },
+    /// The VCO of a pll was outside of the range allowed for its reference clock
+    VcoOutOfRange {
+        /// The number of the pll
+        pll: u8,
+        /// The offending VCO frequency in Hz
+        vco_ck: u32,
+    },
+    /// pll1_p_ck, which drives sys_ck, was above the limit of the voltage scale
+    SysClkTooHigh,
+    /// No pll1 dividers exist that generate a sys_ck close to the target frequency
+    SysClkUnreachable,
+    /// hclk was higher than the flash wait states allow for
+    HclkTooHigh,
+    /// The frequency requested for an APB was higher than hclk
+    PclkTooHigh {
+        /// The number of the APB
+        bus: u8,
+    },
+    /// The oscillator selected as RTC clock is not enabled
+    RtcSourceDisabled,
+    /// The HSE can not be divided down to 1 MHz for the RTC
+    RtcClockTooHigh,
+    /// The clock selected as kernel clock of a peripheral is not enabled
+    KernelClockDisabled,
+    /// Both the HSE and the CSI were selected as clock source
+    ConflictingSources,
+    /// The q or r output of the pll1 was enabled without configuring the pll1 through sys_ck
+    Pll1NotConfigured,
+    /// The HSE didn't get ready, e.g. because no crystal is connected
+    HseTimeout,
+    /// The CSI didn't get ready
+    CsiTimeout,
+    /// The HSI48 didn't get ready
+    Hsi48Timeout,
+    /// The LSI didn't get ready
+    LsiTimeout,
+    /// The LSE didn't get ready, e.g. because no crystal is connected
+    LseTimeout,
+    /// A pll didn't lock or stop
+    PllTimeout {
+        /// The number of the pll
+        pll: u8,
+    },
+    /// The switch of sys_ck to the selected source didn't complete
+    SwitchTimeout,
+    /// Different frequencies were set for the AHB buses, which share one divider
+    ConflictingHclk,
+    /// The HSI was stopped with `keep_hsi` but drives sys_ck, the plls or a kernel clock
+    HsiRequired,
+    /// The prescaler of an MCO was outside of 1 to 15
+    McoPrescalerOutOfRange,
+    /// The clock routed to an MCO is not enabled
+    McoSourceDisabled,
+}
+
+/// The range of the VCO selected for `ref_ck`, the medium VCO below 2 MHz and the wide one above
+fn pll_vco_range(ref_ck: u32) -> (u32, u32) {
+    if ref_ck < 2_000_000 {
+        (150_000_000, 420_000_000)
+    }
+    else {
+        (192_000_000, 836_000_000)
+    }
+}
+
+/// Calculates the VCO frequency of a pll fed by `ref_ck` and checks it against the
+/// range of the VCO that gets selected for that reference clock
+fn pll_vco_ck(pll: u8, ref_ck: u32, divn: u32, fracn: Option<u16>) -> Result<u32, RccError> {
+    if !(1_000_000..=16_000_000).contains(&ref_ck) {
+        return Err(RccError::RefClkOutOfRange { pll, ref_ck });
+    }
+
+    let fracn = u64::from(fracn.unwrap_or(0));
+    let vco_ck = u64::from(ref_ck) * (u64::from(divn) * 8192 + fracn) / 8192;
+    let vco_ck = u32(vco_ck).unwrap();
+
+    let (min, max) = pll_vco_range(ref_ck);
+    if vco_ck < min || vco_ck > max {
+        return Err(RccError::VcoOutOfRange { pll, vco_ck });
+    }
+    Ok(vco_ck)
+}
+
+/// Searches the pll1 dividers (divm, divn, divp) that bring pll1_p_ck closest to
+/// `target` without exceeding `max`, the first exact match wins
+fn pll1_dividers(src_ck: u32, target: u32, max: u32) -> Option<(u32, u32, u32)> {
+    // above `max` the closest frequency is the one closest to `max`
+    let target = target.min(max);
+    let mut best: Option<((u32, u32, u32), u32)> = None;
+
+    for divm in 1..64 {
+        let ref_ck = src_ck / divm;
+        for divp in (2..=128).step_by(2) {
+            // only the two divn values around the ideal one can be the closest, if they
+            // are out of the VCO range the closest value is at the edge of the range
+            let (vco_min, vco_max) = pll_vco_range(ref_ck);
+            let min_divn = (vco_min + ref_ck - 1) / ref_ck.max(1);
+            let max_divn = vco_max / ref_ck.max(1);
+            let ideal_divn = u32(u64::from(target) * u64::from(divp) / u64::from(ref_ck.max(1))).unwrap_or(max_divn);
+            let clamp = |divn: u32| divn.max(min_divn).min(max_divn);
+            for &divn in &[clamp(ideal_divn), clamp(ideal_divn + 1)] {
+                if !(4..=512).contains(&divn) {
+                    continue;
+                }
+                let vco_ck = match pll_vco_ck(1, ref_ck, divn, None) {
+                    Ok(vco_ck) => vco_ck,
+                    Err(_) => continue,
+                };
+
+                let pll_p_ck = vco_ck / divp;
+                if pll_p_ck > max {
+                    continue;
+                }
+                let error = pll_p_ck.abs_diff(target);
+                if best.is_none_or(|(_, best_error)| error < best_error) {
+                    best = Some(((divm, divn, divp), error));
+                }
+            }
+        }
+    }
+    best.map(|(dividers, _)| dividers)
+}
+
+/// Calculates the RGE bits and the VCOSEL bit of a pll fed by `ref_ck`
+///
+/// `ref_ck` is expected to be checked by `pll_vco_ck` already
+fn pll_range_bits(ref_ck: u32) -> (u8, bool) {
+    let rge_bits = match ref_ck  {
+        0..=2_000_000 => 0b00,
+        2_000_001..=4_000_000 => 0b01,
+        4_000_001..=8_000_000 => 0b10,
+        _ => 0b11,
+    };
+    // if the frequency of ref_ck is < 2 Mhz and > 1 Mhz use the medium VCO otherwise the wide one
+    let vcosel_bit = ref_ck < 2_000_000;
+    (rge_bits, vcosel_bit)
+}
+
+/// The frequencies of the p, q and r outputs of a pll, `None` for a disabled output
+type PllOutputs = (Option<u32>, Option<u32>, Option<u32>);
+
+/// Configuration of one of the auxiliary plls (pll2 and pll3)
+///
+/// The auxiliary plls are fed by the same oscillator as pll1, the values are
+/// checked when the configuration gets frozen
+#[derive(Clone, Copy)]
+pub struct PllConfig {
+    divm: u32,
+    divn: u32,
+    fracn: Option<u16>,
+    divp: Option<u32>,
+    divq: Option<u32>,
+    divr: Option<u32>,
+}
+
+impl PllConfig {
+    /// Creates a pll configuration with the given divm and divn dividers and all outputs disabled
+    pub fn new(divm: u32, divn: u32) -> Self {
+        PllConfig {
+            divm,
+            divn,
+            fracn: None,
+            divp: None,
+            divq: None,
+            divr: None,
+        }
+    }
+
+    /// Enables the fractional mode, the VCO then runs at ref_ck * (divn + fracn / 8192)
+    pub fn fractional(mut self, fracn: u16) -> Self {
+        self.fracn = Some(fracn);
+        self
+    }
+
+    /// Enables the p output, running at the VCO frequency divided by `div`
+    pub fn p(mut self, div: u32) -> Self {
+        self.divp = Some(div);
+        self
+    }
+
+    /// Enables the q output, running at the VCO frequency divided by `div`
+    pub fn q(mut self, div: u32) -> Self {
+        self.divq = Some(div);
+        self
+    }
+
+    /// Enables the r output, running at the VCO frequency divided by `div`
+    pub fn r(mut self, div: u32) -> Self {
+        self.divr = Some(div);
+        self
+    }
+
+    /// Checks the configuration against the limits of the pll and calculates
+    /// the frequencies of its p, q and r outputs
+    fn outputs(&self, pll: u8, src_ck: u32) -> Result<PllOutputs, RccError> {
+        let out_of_range = |div: Option<u32>| div.is_some_and(|div| div == 0 || div > 128);
+
+        if self.divm == 0 || self.divm > 63 {
+            return Err(RccError::DivmOutOfRange);
+        }
+        if self.divn < 4 || self.divn > 512 {
+            return Err(RccError::DivnOutOfRange);
+        }
+        if self.fracn.is_some_and(|fracn| fracn > 8191) {
+            return Err(RccError::FracnOutOfRange);
+        }
+        if out_of_range(self.divp) {
+            return Err(RccError::DivpOutOfRange);
+        }
+        if out_of_range(self.divq) {
+            return Err(RccError::DivqOutOfRange);
+        }
+        if out_of_range(self.divr) {
+            return Err(RccError::DivrOutOfRange);
+        }
+
+        let vco_ck = pll_vco_ck(pll, src_ck / self.divm, self.divn, self.fracn)?;
+        Ok((
+            self.divp.map(|divp| vco_ck / divp),
+            self.divq.map(|divq| vco_ck / divq),
+            self.divr.map(|divr| vco_ck / divr),
+        ))
+    }
+}
+
+macro_rules! pll_setup {
+    ($($setup_pllx:ident: ($pll:expr, $divmx:ident, $pllxdivr:ident, $divnx:ident, $divpx:ident, $divqx:ident, $divrx:ident,
+        $pllxfracr:ident, $fracnx:ident, $pllxfracen:ident, $pllxrge:ident, $pllxvcosel:ident,
+        $divpxen:ident, $divqxen:ident, $divrxen:ident, $pllxon:ident, $pllxrdy:ident),)+) => {
+        $(
+            /// Configures and enables the pll, the configuration has to be checked by `PllConfig::outputs` already
+            fn $setup_pllx(rcc: &rcc::RegisterBlock, config: &PllConfig, src_ck: u32) -> Result<(), RccError> {
+                let (rge_bits, vcosel_bit) = pll_range_bits(src_ck / config.divm);
+
+                // the pll can only be configured while it is disabled
+                rcc.cr.modify(|_, w| w.$pllxon().clear_bit());
+                wait_until(|| rcc.cr.read().$pllxrdy().bit_is_clear(), RccError::PllTimeout { pll: $pll })?;
+
+                rcc.pllckselr.modify(|_, w| unsafe { w.$divmx().bits(u8(config.divm).unwrap()) });
+
+                // like divn all the output dividers hold the factor minus one
+                rcc.$pllxdivr.modify(|_, w| unsafe { w.$divnx().bits(u16(config.divn - 1).unwrap()) });
+                if let Some(divp) = config.divp {
+                    rcc.$pllxdivr.modify(|_, w| unsafe { w.$divpx().bits(u8(divp - 1).unwrap()) });
+                }
+                if let Some(divq) = config.divq {
+                    rcc.$pllxdivr.modify(|_, w| unsafe { w.$divqx().bits(u8(divq - 1).unwrap()) });
+                }
+                if let Some(divr) = config.divr {
+                    rcc.$pllxdivr.modify(|_, w| unsafe { w.$divrx().bits(u8(divr - 1).unwrap()) });
+                }
+
+                // FRACN may only be changed while the frac mode is disabled
+                rcc.pllcfgr.modify(|_, w| w.$pllxfracen().clear_bit());
+                if let Some(fracn) = config.fracn {
+                    rcc.$pllxfracr.modify(|_, w| unsafe { w.$fracnx().bits(fracn) });
+                    rcc.pllcfgr.modify(|_, w| w.$pllxfracen().set_bit());
+                }
+
+                rcc.pllcfgr.modify(|_, w| unsafe {
+                    w.$pllxrge().bits(rge_bits)
+                        .$pllxvcosel().bit(vcosel_bit)
+                        .$divpxen().bit(config.divp.is_some())
+                        .$divqxen().bit(config.divq.is_some())
+                        .$divrxen().bit(config.divr.is_some())
+                });
+
+                // enable the pll and wait until its ready
+                rcc.cr.modify(|_, w| w.$pllxon().set_bit());
+                wait_until(|| rcc.cr.read().$pllxrdy().bit_is_set(), RccError::PllTimeout { pll: $pll })
+            }
+        )+
+    };
+}
+
+pll_setup!(
+    setup_pll1: (1, divm1, pll1divr, divn1, divp1, divq1, divr1, pll1fracr, fracn1, pll1fracen, pll1rge, pll1vcosel,
+        divp1en, divq1en, divr1en, pll1on, pll1rdy),
+    // the svd names the pll2 dividers like the ones of pll1
+    setup_pll2: (2, divm2, pll2divr, divn1, divp1, divq1, divr1, pll2fracr, fracn2, pll2fracen, pll2rge, pll2vcosel,
+        divp2en, divq2en, divr2en, pll2on, pll2rdy),
+    setup_pll3: (3, divm3, pll3divr, divn3, divp3, divq3, divr3, pll3fracr, fracn3, pll3fracen, pll3rge, pll3vcosel,
+        divp3en, divq3en, divr3en, pll3on, pll3rdy),
+);
 
 /// Clock configuration
+///
+/// The configuration can be copied to `freeze` it again after the clocks were reset by the
+/// Stop mode
+#[derive(Clone, Copy)]
 pub struct CFGR {
+    /// The clock of all AHB buses
+    hclk: Option<u32>,
     /// The clock of AHB1
     hclk1: Option<u32>,
     /// The clock of AHB2
@@ -148,18 +1100,152 @@ pub struct CFGR {
     pclk3: Option<u32>,
     /// The clock of APB4
     pclk4: Option<u32>,
-    /// The system clock
-    sys_ck: Option<u32>,
-    /// The divm divider of the pll1
-    divm: Option<u32>,
-    /// The divn divider of the pll1
-    divn: Option<u32>,
-    /// The dip divider of the pll1
-    divp: Option<u32>,
+    /// The frequency of the HSE oscillator, if it is used
+    hse: Option<u32>,
+    /// Whether the HSE is driven by an external clock instead of a crystal
+    hse_bypass: bool,
+    /// Whether the CSI is used instead of the HSI
+    csi: bool,
+    /// Whether the HSI48 is enabled
+    hsi48: bool,
+    /// Whether the HSI keeps running, `None` if it was left to the default
+    keep_hsi: Option<bool>,
+    /// The frequency of the LSE, if it is used
+    lse: Option<u32>,
+    /// Whether the LSE is driven by an external clock instead of a crystal
+    lse_bypass: bool,
+    /// Whether the LSI is enabled
+    lsi: bool,
+    /// The clock source of the RTC, if it is clocked
+    rtc_src: Option<RtcClockSource>,
+    /// The kernel clock source of SPI1, SPI2 and SPI3
+    spi123_src: Option<Spi123ClkSource>,
+    /// The kernel clock source of USART1 and USART6
+    usart16_src: Option<UsartClkSource>,
+    /// The kernel clock source of LPTIM1
+    lptim1_src: Option<LptimClkSource>,
+    /// The kernel clock source of USART2, USART3, UART4, UART5, UART7 and UART8
+    usart234578_src: Option<UsartClkSource>,
+    /// The kernel clock source of LPUART1
+    lpuart1_src: Option<UsartClkSource>,
+    /// The kernel clock source of SAI1
+    sai1_src: Option<SaiClkSource>,
+    /// The kernel clock source of SAI2 and SAI3
+    sai23_src: Option<SaiClkSource>,
+    /// The kernel clock source of I2C1, I2C2 and I2C3
+    i2c123_src: Option<I2cClkSource>,
+    /// The kernel clock source of I2C4
+    i2c4_src: Option<I2cClkSource>,
+    /// The kernel clock source of the ADCs
+    adc_src: Option<AdcClkSource>,
+    /// The kernel clock source of the RNG
+    rng_src: Option<RngClkSource>,
+    /// The kernel clock source of the QUADSPI
+    qspi_src: Option<QspiClkSource>,
+    /// The kernel clock source of the FMC
+    fmc_src: Option<FmcClkSource>,
+    /// The kernel clock source of SDMMC1 and SDMMC2
+    sdmmc_src: Option<SdmmcClkSource>,
+    /// The kernel clock source of FDCAN1 and FDCAN2
+    fdcan_src: Option<FdcanClkSource>,
+    /// The kernel clock source of the USB OTG cores
+    usb_src: Option<UsbClkSource>,
+    /// Whether the timers run at up to four times pclk
+    timpre: bool,
+    /// The clock source of the HRTIM
+    hrtim_src: HrtimClkSource,
+    /// The clock routed to MCO1 along with its prescaler
+    mco1: Option<(Mco1Source, u8)>,
+    /// The clock routed to MCO2 along with its prescaler
+    mco2: Option<(Mco2Source, u8)>,
+    /// The divm, divn and divp dividers of the pll1, they are only set together so the pll1
+    /// can't be enabled with a partial configuration
+    sys_ck_dividers: Option<(u32, u32, u32)>,
+    /// The fractional part of the multiplication factor of the pll1
+    pll1_fracn: Option<u16>,
+    /// The divq divider of the pll1
+    pll1_divq: Option<u32>,
+    /// The divr divider of the pll1
+    pll1_divr: Option<u32>,
+    /// The configuration of the pll2
+    pll2: Option<PllConfig>,
+    /// The configuration of the pll3
+    pll3: Option<PllConfig>,
+    /// The voltage scale of the core domain
+    vos: VoltageScale,
+    /// The sys_ck frequency the pll1 dividers get searched for
+    sys_ck_target: Option<u32>,
 }
 
 impl CFGR {
+    /// The configuration that leaves the clocks at their reset values
+    fn new() -> Self {
+        CFGR {
+        hclk: None,
+        hclk1: None,
+        hclk2: None,
+        hclk3: None,
+        hclk4: None,
+        pclk1: None,
+        pclk2: None,
+        pclk3: None,
+        pclk4: None,
+        hse: None,
+        hse_bypass: false,
+        csi: false,
+        hsi48: false,
+        keep_hsi: None,
+        lse: None,
+        lse_bypass: false,
+        lsi: false,
+        rtc_src: None,
+        spi123_src: None,
+        sai1_src: None,
+        sai23_src: None,
+        usart16_src: None,
+        lptim1_src: None,
+        usart234578_src: None,
+        lpuart1_src: None,
+        i2c123_src: None,
+        i2c4_src: None,
+        adc_src: None,
+        rng_src: None,
+        qspi_src: None,
+        fmc_src: None,
+        sdmmc_src: None,
+        fdcan_src: None,
+        usb_src: None,
+        timpre: false,
+        hrtim_src: HrtimClkSource::TimyKerCk,
+        mco1: None,
+        mco2: None,
+        sys_ck_dividers: None,
+        pll1_fracn: None,
+        pll1_divq: None,
+        pll1_divr: None,
+        pll2: None,
+        pll3: None,
+        vos: VoltageScale::Scale3,
+        sys_ck_target: None,
+        }
+    }
+
+    /// Sets a frequency for the AHB buses
+    ///
+    /// The AHB1 to AHB4 buses and the AXI share the HPRE divider, so they always run at the
+    /// same frequency
+    pub fn hclk<F>(mut self, freq: F) -> Self
+    where
+        F: Into<Hertz>,
+    {
+        self.hclk = Some(freq.into().0);
+        self
+    }
+
     /// Sets a frequency for AHB1 bus
+    ///
+    /// `freeze` fails if it differs from the frequency set for another AHB bus
+    #[deprecated(note = "the AHB buses share one divider, use `hclk` instead")]
     pub fn hclk1<F>(mut self, freq: F) -> Self
     where 
         F: Into<Hertz>,
@@ -169,6 +1255,9 @@ impl CFGR {
     }
 
     /// Sets a frequency for AHB2 bus
+    ///
+    /// `freeze` fails if it differs from the frequency set for another AHB bus
+    #[deprecated(note = "the AHB buses share one divider, use `hclk` instead")]
     pub fn hclk2<F>(mut self, freq: F) -> Self
     where 
         F: Into<Hertz>,
@@ -178,6 +1267,9 @@ impl CFGR {
     }
 
     /// Sets a frequency for AHB3 bus
+    ///
+    /// `freeze` fails if it differs from the frequency set for another AHB bus
+    #[deprecated(note = "the AHB buses share one divider, use `hclk` instead")]
     pub fn hclk3<F>(mut self, freq: F) -> Self
     where 
         F: Into<Hertz>,
@@ -187,6 +1279,9 @@ impl CFGR {
     }
 
     /// Sets a frequency for AHB4 bus
+    ///
+    /// `freeze` fails if it differs from the frequency set for another AHB bus
+    #[deprecated(note = "the AHB buses share one divider, use `hclk` instead")]
     pub fn hclk4<F>(mut self, freq: F) -> Self
     where 
         F: Into<Hertz>,
@@ -233,203 +1328,867 @@ impl CFGR {
 
     /// Sets the value for the registers used for sys_ck generation
     /// This function is expected to be used with values from the
-    /// calc_config macro for now
-    /// TODO: implement the calc_config macro using a const fn onc
-    /// const fns support iteration
+    /// calc_config macro or if the dividers should be calculated at
+    /// runtime `target_sys_ck` can be used instead, the values are checked by `freeze`
     pub fn sys_ck(mut self, divm: u32, divn: u32, divp:u32) -> Self
     {
-        assert!(divm > 0 && divm < 64, "divm value was out of bounds");
-        assert!(divn > 2 && divn < 513, "divn value was out of bounds");
-        assert!(divp > 1 && divp < 129 && divp % 2 == 0, "divp value was out of bounds");
-        self.divm = Some(divm);
-        self.divp = Some(divp);
-        self.divn = Some(divn);
-        let ref_ck = HSI/divm;
-        assert!(ref_ck > 1_000_000 && ref_ck < 16_000_000, "illegal config values for ref_ck");
-        let pll_p_ck = (ref_ck * divn) / divp;
-        assert!(pll_p_ck < 400_000_000, "illegal config values for pll_p_ck");
-        self.sys_ck = Some(pll_p_ck);
+        self.sys_ck_dividers = Some((divm, divn, divp));
         self
     }
 
-    /// Freezes the clock configuration, making it effective
-    pub fn freeze(self, acr: &mut ACR) -> Clocks {
-        let mut sys_ck = self.sys_ck.unwrap_or(HSI);
-        let rcc = unsafe { &*RCC::ptr()};
-        
-        // set the system clock
-        let pll_frequency = if sys_ck == HSI {
-            None
-        }
-        else {
-            // this calculates the sys_ck frequency generated from the pll with the given config values
-            // for closer details check the clock tree in the reference manual at page 323
-            let frequency = ((HSI / self.divm.unwrap_or(0b100000)) * self.divn.unwrap_or(0x080)) / self.divp.unwrap_or(0b0000001);
-            sys_ck = frequency;
-            Some(frequency)
-        };
-        
-        // Calculate the hpre divider value
-        // As hclk 1,2,3 and 4 are generated from the same source we just need one value
-        let hclk = self.hclk1.unwrap_or(self.hclk2.unwrap_or(self.hclk3.unwrap_or(self.hclk4.unwrap_or(if sys_ck > 240_000_000 {sys_ck/2} else {sys_ck}))));
-        
-        let hpre_bits: u8 = match sys_ck / hclk {
-            0 => unreachable!(),
-            1 => 0b0111,
-            2 => 0b1000,
-            3...5 => 0b1001,
-            6...9 => 0b1010,
-            10...16 => 0b1011,
-            17...64 => 0b1100,
-            65...128 => 0b1101,
-            129...256 => 0b1110,
-            257...512 => 0b1111,
-            _ => 0b1111 
-        };
-        let hpre = 1 << (hpre_bits - 0b0111);
-        let hclk = sys_ck / hpre;
+    /// Sets a frequency for sys_ck, `freeze` searches the pll1 dividers which
+    /// get closest to it, dividers set through `sys_ck` take precedence
+    pub fn target_sys_ck<F>(mut self, freq: F) -> Self
+    where
+        F: Into<Hertz>,
+    {
+        self.sys_ck_target = Some(freq.into().0);
+        self
+    }
 
-        // adjust flash wait states
-        // as VOS3 is the default VOS used only the values for VOS3 are implemented here
-        let acr_config: (u8, u8) = match hclk {
-            0...45_000_000 => (0, 0),
-            45_000_001...90_000_000 => (1, 1),
-            90_000_001...135_000_000 => (2, 1),
-            135_000_001...180_000_000 => (3, 2),
-            180_000_001...225_000_000 => (4, 2),
-             _ => unreachable!(),
-        };
-        
-        // calculate d1ppre
-        let d1ppre_bits: u8 = match hclk / self.pclk3.unwrap_or(hclk) {
-            0 => unreachable!(),
-            1 => 0b011,
-            2 => 0b100,
-            3...4 => 0b101,
-            5...8 => 0b110,
-            9...16 => 0b111,
-            _ => 0b111,
-        };
-        let d1ppre = 1 << (d1ppre_bits - 0b011);
-        let pclk3 = hclk / d1ppre;
+    /// Enables the fractional mode of the pll1, the VCO then runs at
+    /// ref_ck * (divn + fracn / 8192) instead of ref_ck * divn
+    pub fn pll1_fractional(mut self, fracn: u16) -> Self {
+        self.pll1_fracn = Some(fracn);
+        self
+    }
 
-        // calculate d2ppre1
-        let d2ppre1_bits: u8 = match hclk / self.pclk1.unwrap_or(hclk) {
-            0 => unreachable!(),
-            1 => 0b011,
-            2 => 0b100,
-            3...4 => 0b101,
-            5...8 => 0b110,
-            9...16 => 0b111,
-            _ => 0b111,
-        };
-        let d2ppre1 = 1 << (d2ppre1_bits - 0b011);
-        let pclk1 = hclk / d2ppre1;
+    /// Enables the q output of the pll1, running at the VCO frequency divided by `div`
+    pub fn pll1_q(mut self, div: u32) -> Self {
+        self.pll1_divq = Some(div);
+        self
+    }
 
-        // calculate d2ppre2
-        let d2ppre2_bits: u8 = match hclk / self.pclk2.unwrap_or(hclk) {
-            0 => unreachable!(),
-            1 => 0b011,
-            2 => 0b100,
-            3...4 => 0b101,
-            5...8 => 0b110,
-            9...16 => 0b111,
-            _ => 0b111,
-        };
-        let d2ppre2 = 1 << (d2ppre2_bits - 0b011);
-        let pclk2 = hclk / d2ppre2;
+    /// Enables the r output of the pll1, running at the VCO frequency divided by `div`
+    pub fn pll1_r(mut self, div: u32) -> Self {
+        self.pll1_divr = Some(div);
+        self
+    }
 
-        //calculate d3ppre
-        let d3ppre_bits: u8 = match hclk / self.pclk4.unwrap_or(hclk) {
-            0 => unreachable!(),
-            1 => 0b011,
-            2 => 0b100,
-            3...4 => 0b101,
-            5...8 => 0b110,
-            9...16 => 0b111,
-            _ => 0b111,
-        };
-        let d3ppre = 1 << (d3ppre_bits - 0b011);
-        let pclk4 = hclk / d3ppre;
+    /// Configures and enables the pll2
+    pub fn pll2(mut self, config: PllConfig) -> Self {
+        self.pll2 = Some(config);
+        self
+    }
 
-        // write the flash wait states
-        acr.acr().modify(|_, w| unsafe {w.latency().bits(acr_config.0).wrhighfreq().bits(acr_config.1)});
+    /// Configures and enables the pll3
+    pub fn pll3(mut self, config: PllConfig) -> Self {
+        self.pll3 = Some(config);
+        self
+    }
 
-        // set the hpre value
-        rcc.d1cfgr.modify(|_, w| unsafe {w.hpre().bits(hpre_bits)});
-        
-        // set all the AHB prescaler values
-        rcc.d1cfgr.modify(|_, w| unsafe {
-            w.d1ppre().bits(d1ppre_bits)
-            
-        });
+    /// Uses the HSE oscillator running at `freq` instead of the HSI as the pll source
+    /// or, if the pll is not used, directly as sys_ck
+    pub fn use_hse<F>(mut self, freq: F) -> Self
+    where
+        F: Into<Hertz>,
+    {
+        self.hse = Some(freq.into().0);
+        self
+    }
 
-        rcc.d2cfgr.modify(|_, w| unsafe {
-            w
-            .d2ppre1().bits(d2ppre1_bits)
-            .d2ppre2().bits(d2ppre2_bits)
-        });
+    /// Uses the 4 MHz low power CSI oscillator instead of the HSI as the pll source
+    /// or, if the pll is not used, directly as sys_ck
+    pub fn use_csi(mut self) -> Self {
+        self.csi = true;
+        self
+    }
 
-        rcc.d3cfgr.modify(|_, w| unsafe {
-            w.d3ppre().bits(d3ppre_bits)
+    /// Enables or disables the 48 MHz HSI48 oscillator, which is used as kernel clock
+    /// by the USB and RNG peripherals, independent of the sys_ck source
+    pub fn hsi48(mut self, on: bool) -> Self {
+        self.hsi48 = on;
+        self
+    }
+
+    /// Keeps the HSI running or stops it once sys_ck is switched to its source, the HSI
+    /// keeps running if this is not called
+    ///
+    /// `freeze` fails if the HSI is stopped while it drives sys_ck, the plls or a kernel
+    /// clock, either directly or through per_ck
+    pub fn keep_hsi(mut self, on: bool) -> Self {
+        self.keep_hsi = Some(on);
+        self
+    }
+
+    /// Enables the LSE with the given frequency, usually a 32.768 kHz crystal, or if `bypass`
+    /// is set an external clock signal on OSC32_IN
+    pub fn lse<F>(mut self, freq: F, bypass: bool) -> Self
+    where
+        F: Into<Hertz>,
+    {
+        self.lse = Some(freq.into().0);
+        self.lse_bypass = bypass;
+        self
+    }
+
+    /// Enables or disables the 32 kHz LSI oscillator
+    pub fn lsi(mut self, on: bool) -> Self {
+        self.lsi = on;
+        self
+    }
+
+    /// Selects the clock of the RTC and enables it, the selected oscillator has to be enabled as well
+    ///
+    /// The selection is kept in the backup domain, changing it resets the backup domain
+    pub fn rtc_src(mut self, src: RtcClockSource) -> Self {
+        self.rtc_src = Some(src);
+        self
+    }
+
+    /// Selects the kernel clock of SPI1, SPI2 and SPI3, pll1_q_ck is used if none is selected
+    pub fn spi123_src(mut self, src: Spi123ClkSource) -> Self {
+        self.spi123_src = Some(src);
+        self
+    }
+
+    /// Selects the kernel clock of USART1 and USART6, pclk2 is used if none is selected
+    pub fn usart16_src(mut self, src: UsartClkSource) -> Self {
+        self.usart16_src = Some(src);
+        self
+    }
+
+    /// Selects the kernel clock of USART2, USART3, UART4, UART5, UART7 and UART8,
+    /// pclk1 is used if none is selected
+    pub fn usart234578_src(mut self, src: UsartClkSource) -> Self {
+        self.usart234578_src = Some(src);
+        self
+    }
+
+    /// Selects the kernel clock of LPUART1, pclk4 is used if none is selected
+    ///
+    /// Only the LSE, the HSI and the CSI keep running in Stop mode, one of them has to be
+    /// selected to wake up from Stop mode with LPUART1
+    pub fn lpuart1_src(mut self, src: UsartClkSource) -> Self {
+        self.lpuart1_src = Some(src);
+        self
+    }
+
+    /// Selects the kernel clock of LPTIM1, pclk1 is used if none is selected
+    ///
+    /// Only the LSE and the LSI keep running in Stop mode, one of them has to be selected
+    /// to wake up from Stop mode with LPTIM1
+    pub fn lptim1_src(mut self, src: LptimClkSource) -> Self {
+        self.lptim1_src = Some(src);
+        self
+    }
+
+    /// Selects the kernel clock of I2C1, I2C2 and I2C3, pclk1 is used if none is selected
+    pub fn i2c123_src(mut self, src: I2cClkSource) -> Self {
+        self.i2c123_src = Some(src);
+        self
+    }
+
+    /// Selects the kernel clock of I2C4, pclk4 is used if none is selected
+    pub fn i2c4_src(mut self, src: I2cClkSource) -> Self {
+        self.i2c4_src = Some(src);
+        self
+    }
+
+    /// Selects the kernel clock of the ADCs, per_ck is used if none is selected
+    pub fn adc_src(mut self, src: AdcClkSource) -> Self {
+        self.adc_src = Some(src);
+        self
+    }
+
+    /// Selects the kernel clock of the RNG, the HSI48 is used if none is selected
+    pub fn rng_src(mut self, src: RngClkSource) -> Self {
+        self.rng_src = Some(src);
+        self
+    }
+
+    /// Selects the kernel clock of the QUADSPI, hclk3 is used if none is selected
+    pub fn qspi_src(mut self, src: QspiClkSource) -> Self {
+        self.qspi_src = Some(src);
+        self
+    }
+
+    /// Selects the kernel clock of the FMC, hclk3 is used if none is selected
+    pub fn fmc_src(mut self, src: FmcClkSource) -> Self {
+        self.fmc_src = Some(src);
+        self
+    }
+
+    /// Selects the kernel clock of SAI1, the q output of the pll1 is used if none is selected
+    pub fn sai1_src(mut self, src: SaiClkSource) -> Self {
+        self.sai1_src = Some(src);
+        self
+    }
+
+    /// Selects the kernel clock of SAI2 and SAI3, the q output of the pll1 is used if none
+    /// is selected
+    pub fn sai23_src(mut self, src: SaiClkSource) -> Self {
+        self.sai23_src = Some(src);
+        self
+    }
+
+    /// Selects the kernel clock of SDMMC1 and SDMMC2, the q output of the pll1 is used if
+    /// none is selected
+    pub fn sdmmc_src(mut self, src: SdmmcClkSource) -> Self {
+        self.sdmmc_src = Some(src);
+        self
+    }
+
+    /// Selects the kernel clock of FDCAN1 and FDCAN2, the HSE is used if none is selected
+    pub fn fdcan_src(mut self, src: FdcanClkSource) -> Self {
+        self.fdcan_src = Some(src);
+        self
+    }
+
+    /// Selects the kernel clock of the USB OTG cores, which has to run at 48 MHz, the HSI48
+    /// is used if none is selected
+    pub fn usb_src(mut self, src: UsbClkSource) -> Self {
+        self.usb_src = Some(src);
+        self
+    }
+
+    /// Sets the TIMPRE bit, the timers then run at four times pclk, limited to hclk,
+    /// instead of twice pclk if the APB prescaler is not 1
+    pub fn timpre(mut self, on: bool) -> Self {
+        self.timpre = on;
+        self
+    }
+
+    /// Selects the clock of the HRTIM, the kernel clock of the timers on APB2 is used if
+    /// none is selected
+    pub fn hrtim_src(mut self, src: HrtimClkSource) -> Self {
+        self.hrtim_src = src;
+        self
+    }
+
+    /// Routes `src` divided by `prescaler` (1 to 15) to the MCO1 pin, which has to be put
+    /// into AF0
+    pub fn mco1<PIN>(mut self, src: Mco1Source, prescaler: u8, _pin: &PIN) -> Self
+    where
+        PIN: Mco1Pin,
+    {
+        self.mco1 = Some((src, prescaler));
+        self
+    }
+
+    /// Routes `src` divided by `prescaler` (1 to 15) to the MCO2 pin, which has to be put
+    /// into AF0
+    pub fn mco2<PIN>(mut self, src: Mco2Source, prescaler: u8, _pin: &PIN) -> Self
+    where
+        PIN: Mco2Pin,
+    {
+        self.mco2 = Some((src, prescaler));
+        self
+    }
+
+    /// Puts the HSE into bypass mode so it can be driven by an external clock
+    /// signal on OSC_IN instead of a crystal
+    pub fn bypass_hse(mut self) -> Self {
+        self.hse_bypass = true;
+        self
+    }
+
+    /// Sets the voltage scale of the core domain, a higher scale is required for higher clocks
+    pub fn vos(mut self, scale: VoltageScale) -> Self {
+        self.vos = scale;
+        self
+    }
+
+    /// The clocks of a NUCLEO-H743ZI or NUCLEO-H753ZI in its default solder bridge
+    /// configuration, where the 8 MHz MCO of the ST-LINK drives the HSE in bypass mode
+    ///
+    /// sys_ck runs at 400 MHz in VOS1 from a 2 MHz pll1 reference, hclk at 200 MHz and
+    /// all pclks at 100 MHz. The other settings of `self` are kept
+    ///
+    /// There is no 480 MHz profile: 480 MHz needs VOS0 and a VCO of 960 MHz, which only
+    /// revision V of the chip supports, while the pll checks follow the 836 MHz VCO limit
+    /// of revision Y
+    pub fn nucleo_h743zi_400mhz(self) -> Self {
+        self.use_hse(Hertz(8_000_000))
+            .bypass_hse()
+            .vos(VoltageScale::Scale1)
+            .sys_ck(4, 400, 2)
+            .ahb_apb_400mhz()
+    }
+
+    /// The clocks of an STM32H743I-EVAL or STM32H753I-EVAL, whose HSE is a 25 MHz crystal
+    ///
+    /// sys_ck runs at 400 MHz in VOS1 from a 5 MHz pll1 reference, hclk at 200 MHz and
+    /// all pclks at 100 MHz. The other settings of `self` are kept
+    pub fn eval_h743i_400mhz(self) -> Self {
+        self.use_hse(Hertz(25_000_000))
+            .vos(VoltageScale::Scale1)
+            .sys_ck(5, 160, 2)
+            .ahb_apb_400mhz()
+    }
+
+    /// The fastest bus clocks for a sys_ck of 400 MHz
+    fn ahb_apb_400mhz(self) -> Self {
+        self.hclk(Hertz(200_000_000))
+            .pclk1(Hertz(100_000_000))
+            .pclk2(Hertz(100_000_000))
+            .pclk3(Hertz(100_000_000))
+            .pclk4(Hertz(100_000_000))
+    }
+
+    /// The configuration of the pll1 fed by `src_ck`, `None` if sys_ck is not driven by it
+    ///
+    /// The pll1 is described by the sys_ck dividers along with the pll1 options, it is only
+    /// enabled with complete dividers, which are searched if only a sys_ck frequency was given
+    fn pll1(&self, src_ck: u32) -> Result<Option<PllConfig>, RccError> {
+        let dividers = match (self.sys_ck_dividers, self.sys_ck_target) {
+            (Some(dividers), _) => dividers,
+            (None, Some(target)) => pll1_dividers(src_ck, target, self.vos.max_sys_ck())
+                .ok_or(RccError::SysClkUnreachable)?,
+            (None, None) => {
+                // the q and r outputs can only be used alongside the p output which drives sys_ck
+                if self.pll1_divq.is_some() || self.pll1_divr.is_some() {
+                    return Err(RccError::Pll1NotConfigured);
+                }
+                return Ok(None);
+            }
+        };
+
+        let (divm, divn, divp) = dividers;
+        // the pll1 p output only supports even dividers
+        if divp % 2 != 0 {
+            return Err(RccError::DivpOutOfRange);
+        }
+        Ok(Some(PllConfig {
+            divm,
+            divn,
+            fracn: self.pll1_fracn,
+            divp: Some(divp),
+            divq: self.pll1_divq,
+            divr: self.pll1_divr,
+        }))
+    }
+
+    /// Freezes the clock configuration, making it effective
+    ///
+    /// The whole configuration is checked before the first register gets written,
+    /// so an error leaves the clocks untouched. Only the timeout errors of the oscillators,
+    /// the plls and the clock switch occur while the registers are written, the clocks are
+    /// then left partially configured
+    ///
+    /// The sys_ck source, the bus prescalers and the plls are reset before they are
+    /// configured, so a configuration left by a bootloader doesn't leak into the new one
+    pub fn freeze(self, acr: &mut ACR, pwr: &mut Pwr) -> Result<Clocks, RccError> {
+        let rcc = unsafe { &*RCC::ptr()};
+
+        if self.csi && self.hse.is_some() {
+            return Err(RccError::ConflictingSources);
+        }
+
+        // the oscillator feeding the plls or, if the pll1 is not used, sys_ck directly
+        // along with its pllsrc and sw encodings
+        let (src_ck, pllsrc_bits, sw_bits) = match (self.hse, self.csi) {
+            (Some(hse), _) => (hse, 0b10, 0b010),
+            (None, true) => (CSI, 0b01, 0b001),
+            (None, false) => (HSI, 0b00, 0b000),
+        };
+
+        let pll1 = self.pll1(src_ck)?;
+        let dividers = pll1.map(|config| (config.divm, config.divn, config.divp.unwrap_or(0)));
+
+        // this calculates the sys_ck frequency generated from the pll with the given config values
+        // for closer details check the clock tree in the reference manual at page 323
+        let (pll_frequency, pll1_q_ck, pll1_r_ck) = match pll1 {
+            Some(ref config) => config.outputs(1, src_ck)?,
+            None => (None, None, None),
+        };
+        if pll_frequency.is_some_and(|frequency| frequency > self.vos.max_sys_ck()) {
+            return Err(RccError::SysClkTooHigh);
+        }
+        let sys_ck = pll_frequency.unwrap_or(src_ck);
+
+        let (pll2_p_ck, pll2_q_ck, pll2_r_ck) = match self.pll2 {
+            Some(ref config) => config.outputs(2, src_ck)?,
+            None => (None, None, None),
+        };
+        let (pll3_p_ck, pll3_q_ck, pll3_r_ck) = match self.pll3 {
+            Some(ref config) => config.outputs(3, src_ck)?,
+            None => (None, None, None),
+        };
+
+        // Calculate the hpre divider value
+        // As hclk 1,2,3 and 4 are generated from the same source we just need one value
+        let requested_hclk =
+            requested_hclk(&[self.hclk, self.hclk1, self.hclk2, self.hclk3, self.hclk4])?;
+        let hclk = requested_hclk.unwrap_or(if sys_ck > 240_000_000 {sys_ck/2} else {sys_ck});
+
+        let hpre = best_divider(hclk, sys_ck, &HPRE_DIVIDERS);
+        let hpre_bits = encode_hpre(hpre);
+        let hclk = sys_ck / decode_hpre(hpre_bits);
+
+        // adjust flash wait states
+        let acr_config = flash_latency(self.vos, hclk)?;
+
+        // the APB prescalers only divide hclk
+        let d2ppre1 = ppre_divider(self.pclk1, hclk, 1)?;
+        let d2ppre2 = ppre_divider(self.pclk2, hclk, 2)?;
+        let d1ppre = ppre_divider(self.pclk3, hclk, 3)?;
+        let d3ppre = ppre_divider(self.pclk4, hclk, 4)?;
+
+        // calculate d1ppre
+        let d1ppre_bits = encode_ppre(d1ppre);
+        let pclk3 = hclk / decode_ppre(d1ppre_bits);
+
+        // calculate d2ppre1
+        let d2ppre1_bits = encode_ppre(d2ppre1);
+        let pclk1 = hclk / decode_ppre(d2ppre1_bits);
+
+        // calculate d2ppre2
+        let d2ppre2_bits = encode_ppre(d2ppre2);
+        let pclk2 = hclk / decode_ppre(d2ppre2_bits);
+
+        //calculate d3ppre
+        let d3ppre_bits = encode_ppre(d3ppre);
+        let pclk4 = hclk / decode_ppre(d3ppre_bits);
+
+        // the RTCSRC bits and the resulting rtc_ck along with RTCPRE if the HSE is used
+        let rtc = match self.rtc_src {
+            Some(RtcClockSource::Lse) => {
+                let lse = self.lse.ok_or(RccError::RtcSourceDisabled)?;
+                Some((0b01, lse, None))
+            },
+            Some(RtcClockSource::Lsi) => {
+                if !self.lsi {
+                    return Err(RccError::RtcSourceDisabled);
+                }
+                Some((0b10, LSI, None))
+            },
+            Some(RtcClockSource::Hse) => {
+                let hse = self.hse.ok_or(RccError::RtcSourceDisabled)?;
+                // RTCPRE values below 2 disable the clock
+                let rtcpre = hse.div_ceil(1_000_000).max(2);
+                if rtcpre > 63 {
+                    return Err(RccError::RtcClockTooHigh);
+                }
+                Some((0b11, hse / rtcpre, Some(u8(rtcpre).unwrap())))
+            },
+            None => None,
+        };
+
+        // kernel clock of SPI1, SPI2 and SPI3
+        let spi123_src = self.spi123_src.unwrap_or(Spi123ClkSource::Pll1Q);
+        let spi123_ck = match spi123_src {
+            Spi123ClkSource::Pll1Q => pll1_q_ck,
+            Spi123ClkSource::Pll2P => pll2_p_ck,
+            Spi123ClkSource::Pll3P => pll3_p_ck,
+            Spi123ClkSource::PerCk => Some(HSI),
+        };
+        if self.spi123_src.is_some() && spi123_ck.is_none() {
+            return Err(RccError::KernelClockDisabled);
+        }
+
+        // kernel clocks of the SAIs
+        let sai_ck = |src: SaiClkSource| match src {
+            SaiClkSource::Pll1Q => pll1_q_ck,
+            SaiClkSource::Pll2P => pll2_p_ck,
+            SaiClkSource::Pll3P => pll3_p_ck,
+            SaiClkSource::PerCk => Some(HSI),
+        };
+        let sai1_src = self.sai1_src.unwrap_or(SaiClkSource::Pll1Q);
+        let sai1_ck = sai_ck(sai1_src);
+        let sai23_src = self.sai23_src.unwrap_or(SaiClkSource::Pll1Q);
+        let sai23_ck = sai_ck(sai23_src);
+        if (self.sai1_src.is_some() && sai1_ck.is_none())
+            || (self.sai23_src.is_some() && sai23_ck.is_none())
+        {
+            return Err(RccError::KernelClockDisabled);
+        }
+
+        // kernel clocks of the USARTs
+        let usart_ck = |src: UsartClkSource, pclk: u32| match src {
+            UsartClkSource::Pclk => Some(pclk),
+            UsartClkSource::Pll2Q => pll2_q_ck,
+            UsartClkSource::Pll3Q => pll3_q_ck,
+            UsartClkSource::Hsi => Some(HSI),
+            UsartClkSource::Csi => if self.csi { Some(CSI) } else { None },
+            UsartClkSource::Lse => self.lse,
+        };
+        let usart16_src = self.usart16_src.unwrap_or(UsartClkSource::Pclk);
+        let usart16_ck = usart_ck(usart16_src, pclk2).ok_or(RccError::KernelClockDisabled)?;
+        let usart234578_src = self.usart234578_src.unwrap_or(UsartClkSource::Pclk);
+        let usart234578_ck = usart_ck(usart234578_src, pclk1).ok_or(RccError::KernelClockDisabled)?;
+        let lpuart1_src = self.lpuart1_src.unwrap_or(UsartClkSource::Pclk);
+        let lpuart1_ck = usart_ck(lpuart1_src, pclk4).ok_or(RccError::KernelClockDisabled)?;
+
+        // kernel clock of LPTIM1
+        let lptim1_src = self.lptim1_src.unwrap_or(LptimClkSource::Pclk);
+        let lptim1_ck = match lptim1_src {
+            LptimClkSource::Pclk => Some(pclk1),
+            LptimClkSource::Pll2P => pll2_p_ck,
+            LptimClkSource::Pll3R => pll3_r_ck,
+            LptimClkSource::Lse => self.lse,
+            LptimClkSource::Lsi => if self.lsi { Some(LSI) } else { None },
+            LptimClkSource::PerCk => Some(HSI),
+        }.ok_or(RccError::KernelClockDisabled)?;
+
+        // kernel clocks of the I2Cs
+        let i2c_ck = |src: I2cClkSource, pclk: u32| match src {
+            I2cClkSource::Pclk => Some(pclk),
+            I2cClkSource::Pll3R => pll3_r_ck,
+            I2cClkSource::Hsi => Some(HSI),
+            I2cClkSource::Csi => if self.csi { Some(CSI) } else { None },
+        };
+        let i2c123_src = self.i2c123_src.unwrap_or(I2cClkSource::Pclk);
+        let i2c123_ck = i2c_ck(i2c123_src, pclk1).ok_or(RccError::KernelClockDisabled)?;
+        let i2c4_src = self.i2c4_src.unwrap_or(I2cClkSource::Pclk);
+        let i2c4_ck = i2c_ck(i2c4_src, pclk4).ok_or(RccError::KernelClockDisabled)?;
+
+        // kernel clock of the ADCs
+        let adc_src = self.adc_src.unwrap_or(AdcClkSource::PerCk);
+        let adc_ck = match adc_src {
+            AdcClkSource::Pll2P => pll2_p_ck,
+            AdcClkSource::Pll3R => pll3_r_ck,
+            AdcClkSource::PerCk => Some(HSI),
+        }.ok_or(RccError::KernelClockDisabled)?;
+
+        // kernel clock of the RNG
+        let rng_src = self.rng_src.unwrap_or(RngClkSource::Hsi48);
+        let rng_ck = match rng_src {
+            RngClkSource::Hsi48 => if self.hsi48 { Some(HSI48) } else { None },
+            RngClkSource::Pll1Q => pll1_q_ck,
+            RngClkSource::Lse => self.lse,
+            RngClkSource::Lsi => if self.lsi { Some(LSI) } else { None },
+        };
+        if self.rng_src.is_some() && rng_ck.is_none() {
+            return Err(RccError::KernelClockDisabled);
+        }
+
+        // kernel clock of the QUADSPI
+        let qspi_src = self.qspi_src.unwrap_or(QspiClkSource::Hclk3);
+        let qspi_ck = match qspi_src {
+            QspiClkSource::Hclk3 => Some(hclk),
+            QspiClkSource::Pll1Q => pll1_q_ck,
+            QspiClkSource::Pll2R => pll2_r_ck,
+            QspiClkSource::PerCk => Some(HSI),
+        }.ok_or(RccError::KernelClockDisabled)?;
+
+        // kernel clock of the FMC
+        let fmc_src = self.fmc_src.unwrap_or(FmcClkSource::Hclk3);
+        let fmc_ck = match fmc_src {
+            FmcClkSource::Hclk3 => Some(hclk),
+            FmcClkSource::Pll1Q => pll1_q_ck,
+            FmcClkSource::Pll2R => pll2_r_ck,
+            FmcClkSource::PerCk => Some(HSI),
+        }.ok_or(RccError::KernelClockDisabled)?;
+
+        // kernel clock of SDMMC1 and SDMMC2
+        let sdmmc_src = self.sdmmc_src.unwrap_or(SdmmcClkSource::Pll1Q);
+        let sdmmc_ck = match sdmmc_src {
+            SdmmcClkSource::Pll1Q => pll1_q_ck,
+            SdmmcClkSource::Pll2R => pll2_r_ck,
+        };
+        if self.sdmmc_src.is_some() && sdmmc_ck.is_none() {
+            return Err(RccError::KernelClockDisabled);
+        }
+
+        // kernel clock of FDCAN1 and FDCAN2
+        let fdcan_src = self.fdcan_src.unwrap_or(FdcanClkSource::Hse);
+        let fdcan_ck = match fdcan_src {
+            FdcanClkSource::Hse => self.hse,
+            FdcanClkSource::Pll1Q => pll1_q_ck,
+            FdcanClkSource::Pll2Q => pll2_q_ck,
+        };
+        if self.fdcan_src.is_some() && fdcan_ck.is_none() {
+            return Err(RccError::KernelClockDisabled);
+        }
+
+        // kernel clock of the USB OTG cores
+        let usb_src = self.usb_src.unwrap_or(UsbClkSource::Hsi48);
+        let usb_ck = match usb_src {
+            UsbClkSource::Pll1Q => pll1_q_ck,
+            UsbClkSource::Pll3Q => pll3_q_ck,
+            UsbClkSource::Hsi48 => if self.hsi48 { Some(HSI48) } else { None },
+        };
+        if self.usb_src.is_some() && usb_ck.is_none() {
+            return Err(RccError::KernelClockDisabled);
+        }
+
+        // the clock outputs
+        let mco1_ck = match self.mco1 {
+            Some((src, prescaler)) => {
+                if !(1..=15).contains(&prescaler) {
+                    return Err(RccError::McoPrescalerOutOfRange);
+                }
+                let src_ck = match src {
+                    Mco1Source::Hsi => Some(HSI),
+                    Mco1Source::Lse => self.lse,
+                    Mco1Source::Hse => self.hse,
+                    Mco1Source::Pll1Q => pll1_q_ck,
+                    Mco1Source::Hsi48 => if self.hsi48 { Some(HSI48) } else { None },
+                };
+                Some(src_ck.ok_or(RccError::McoSourceDisabled)? / u32(prescaler))
+            },
+            None => None,
+        };
+        let mco2_ck = match self.mco2 {
+            Some((src, prescaler)) => {
+                if !(1..=15).contains(&prescaler) {
+                    return Err(RccError::McoPrescalerOutOfRange);
+                }
+                let src_ck = match src {
+                    Mco2Source::SysCk => Some(sys_ck),
+                    Mco2Source::Pll2P => pll2_p_ck,
+                    Mco2Source::Hse => self.hse,
+                    Mco2Source::Pll1P => pll_frequency,
+                    Mco2Source::Csi => if self.csi { Some(CSI) } else { None },
+                    Mco2Source::Lsi => if self.lsi { Some(LSI) } else { None },
+                };
+                Some(src_ck.ok_or(RccError::McoSourceDisabled)? / u32(prescaler))
+            },
+            None => None,
+        };
+
+        // per_ck is driven by the HSI
+        let per_ck_used = spi123_src == Spi123ClkSource::PerCk
+            || adc_src == AdcClkSource::PerCk
+            || qspi_src == QspiClkSource::PerCk
+            || fmc_src == FmcClkSource::PerCk
+            || sai1_src == SaiClkSource::PerCk
+            || sai23_src == SaiClkSource::PerCk
+            || lptim1_src == LptimClkSource::PerCk;
+        let hsi_used = (self.hse.is_none() && !self.csi)
+            || per_ck_used
+            || [usart16_src, usart234578_src, lpuart1_src].contains(&UsartClkSource::Hsi)
+            || [i2c123_src, i2c4_src].contains(&I2cClkSource::Hsi)
+            || self.mco1.is_some_and(|(src, _)| src == Mco1Source::Hsi);
+        let hsi_on = match self.keep_hsi {
+            Some(false) if hsi_used => return Err(RccError::HsiRequired),
+            Some(on) => on,
+            None => true,
+        };
+
+        // the configuration is valid, from here on the registers get written
+
+        // start from the reset state of the clock tree, which a bootloader may have changed:
+        // sys_ck is switched back to the undivided HSI before anything else changes, so the
+        // bus prescalers and the plls can be rewritten safely. RTCPRE keeps clocking the RTC
+        rcc.cr.modify(|_, w| unsafe { w.hsion().set_bit().hsidiv().bits(0b00) });
+        wait_until(|| rcc.cr.read().hsirdy().bit_is_set(), RccError::SwitchTimeout)?;
+        rcc.cfgr.modify(|_, w| unsafe {
+            w.sw().bits(0b000)
+                .stopwuck().clear_bit()
+                .stopkerwuck().clear_bit()
+                .hrtimsel().clear_bit()
+                .timpre().clear_bit()
+                .mco1pre().bits(0)
+                .mco1sel().bits(0)
+                .mco2pre().bits(0)
+                .mco2sel().bits(0)
         });
+        wait_until(|| rcc.cfgr.read().sws().bits() == 0b000, RccError::SwitchTimeout)?;
+        rcc.d1cfgr.reset();
+        rcc.d2cfgr.reset();
+        rcc.d3cfgr.reset();
 
-        // adjust sys_ck source
-        if pll_frequency .is_some() {
-            // use pll as sys_ck
-            
-            // set HSI as pll source
-            rcc.pllckselr.modify(|_, w| unsafe {w.pllsrc().bits(00)});
+        // stop the plls, their configuration registers can only be written while they are
+        // disabled
+        rcc.cr.modify(|_, w| w.pll1on().clear_bit().pll2on().clear_bit().pll3on().clear_bit());
+        wait_until(|| rcc.cr.read().pll1rdy().bit_is_clear(), RccError::PllTimeout { pll: 1 })?;
+        wait_until(|| rcc.cr.read().pll2rdy().bit_is_clear(), RccError::PllTimeout { pll: 2 })?;
+        wait_until(|| rcc.cr.read().pll3rdy().bit_is_clear(), RccError::PllTimeout { pll: 3 })?;
+        rcc.pllckselr.reset();
+        rcc.pllcfgr.reset();
+        rcc.pll1divr.reset();
+        rcc.pll1fracr.reset();
+        rcc.pll2divr.reset();
+        rcc.pll2fracr.reset();
+        rcc.pll3divr.reset();
+        rcc.pll3fracr.reset();
+
+        // raise the core voltage before any clock gets faster, the SYSCFG clock is
+        // needed for the overdrive of VOS0
+        if self.vos == VoltageScale::Scale0 {
+            rcc.apb4enr.modify(|_, w| w.syscfgen().set_bit());
+        }
+        pwr.d3cr.set_voltage_scale(self.vos);
+
+        // start the CSI and wait until it is stable
+        if self.csi {
+            rcc.cr.modify(|_, w| w.csion().set_bit());
+            wait_until(|| rcc.cr.read().csirdy().bit_is_set(), RccError::CsiTimeout)?;
+        }
 
-            // set DIVN1
-            rcc.pll1divr.modify(|_, w| unsafe { w.divn1().bits(u16(self.divn.unwrap_or(0x080)).unwrap())});
+        // start the HSI48 and wait until it is stable, it is not used by the sys_ck
+        // path so it can be turned on or off regardless of the source chosen above
+        if self.hsi48 {
+            rcc.cr.modify(|_, w| w.rc48on().set_bit());
+            wait_until(|| rcc.cr.read().rc48rdy().bit_is_set(), RccError::Hsi48Timeout)?;
+        }
+        else {
+            rcc.cr.modify(|_, w| w.rc48on().clear_bit());
+        }
 
-            // set divm1 value, set to default if not set by software
-            rcc.pllckselr.modify(|_, w| unsafe{ w.divm1().bits(u8(self.divm.unwrap_or(0b100000)).unwrap())});
+        // start the HSE and wait until it is stable before anything gets switched over to it
+        if self.hse.is_some() {
+            // HSEBYP can only be written while the HSE is disabled
+            rcc.cr.modify(|_, w| w.hsebyp().bit(self.hse_bypass));
+            rcc.cr.modify(|_, w| w.hseon().set_bit());
+            wait_until(|| rcc.cr.read().hserdy().bit_is_set(), RccError::HseTimeout)?;
+        }
 
-            // enable and set DIVP1
-            rcc.pllcfgr.modify(|_, w| w.divp1en().set_bit());
+        // start the LSI and wait until it is stable
+        if self.lsi {
+            rcc.csr.modify(|_, w| w.lsion().set_bit());
+            wait_until(|| rcc.csr.read().lsirdy().bit_is_set(), RccError::LsiTimeout)?;
+        }
+        else {
+            rcc.csr.modify(|_, w| w.lsion().clear_bit());
+        }
 
-            //disable frac mode of pll1
-            rcc.pllcfgr.modify(|_, w| w.pll1fracen().clear_bit());
+        // the LSE and the RTC clock live in the write protected backup domain
+        if self.lse.is_some() || rtc.is_some() {
+            pwr.cr1.enable_backup_domain_access();
+        }
+
+        // once selected RTCSRC can only be changed through a reset of the backup domain
+        if let Some((rtcsrc_bits, _, _)) = rtc {
+            let current_bits = rcc.bdcr.read().rtcsrc().bits();
+            if current_bits != 0b00 && current_bits != rtcsrc_bits {
+                rcc.bdcr.modify(|_, w| w.vswrst().set_bit());
+                rcc.bdcr.modify(|_, w| w.vswrst().clear_bit());
+            }
+        }
 
-            let ref_ck = HSI / self.divm.unwrap_or(0b100000);
+        // start the LSE and wait until it is stable
+        if self.lse.is_some() {
+            // LSEBYP can only be written while the LSE is disabled
+            rcc.bdcr.modify(|_, w| w.lsebyp().bit(self.lse_bypass));
+            rcc.bdcr.modify(|_, w| w.lseon().set_bit());
+            wait_polls(
+                || rcc.bdcr.read().lserdy().bit_is_set(),
+                LSE_READY_TIMEOUT,
+                RccError::LseTimeout,
+            )?;
+        }
 
-            // calculate and set the bits for the RGE register
-            let rge_bits = match ref_ck  {
-                1_000_000...2_000_000 => 0b00,
-                2_000_001...4_000_000 => 0b01,
-                4_000_001...8_000_000 => 0b10,
-                8_000_001...16_000_000 => 0b11,
-                _ => unreachable!(),
-            };
-            rcc.pllcfgr.modify(|_, w| unsafe{ w.pll1rge().bits(rge_bits)});
+        // clock the RTC
+        if let Some((rtcsrc_bits, _, rtcpre)) = rtc {
+            if let Some(rtcpre) = rtcpre {
+                rcc.cfgr.modify(|_, w| unsafe { w.rtcpre().bits(rtcpre) });
+            }
+            rcc.bdcr.modify(|_, w| unsafe { w.rtcsrc().bits(rtcsrc_bits) });
+            rcc.bdcr.modify(|_, w| w.rtcen().set_bit());
+        }
 
-            // calculate and set the bits for the VCOSEL register
-            // if the frequency of ref_ck is < 2 Mhz and > 1 Mhz set to 1 otherwise to 0
-            let vcosel_bit = if ref_ck < 2_000_000 { true } else { false };
-            rcc.pllcfgr.modify(|_, w| w.pll1vcosel().bit(vcosel_bit));
+        // write the flash wait states
+        acr.set_latency(acr_config.0, acr_config.1);
 
-            rcc.pll1divr.modify(|_, w| unsafe {w.divp1().bits(u8(self.divp.unwrap_or(0b0000001)).unwrap())});
+        rcc.cfgr.modify(|_, w| {
+            w.timpre().bit(self.timpre)
+                .hrtimsel().bit(self.hrtim_src == HrtimClkSource::CpuCk)
+        });
 
-            // enable pll1 and wait until its ready
-            rcc.cr.modify(|_, w| w.pll1on().set_bit());
-            while rcc.cr.read().pll1rdy().bit_is_set() {}
+        // set the hpre value
+        rcc.d1cfgr.modify(|_, w| unsafe {w.hpre().bits(hpre_bits)});
+        
+        // set all the AHB prescaler values
+        rcc.d1cfgr.modify(|_, w| unsafe {
+            w.d1ppre().bits(d1ppre_bits)
+            
+        });
+
+        rcc.d2cfgr.modify(|_, w| unsafe {
+            w
+            .d2ppre1().bits(d2ppre1_bits)
+            .d2ppre2().bits(d2ppre2_bits)
+        });
+
+        rcc.d3cfgr.modify(|_, w| unsafe {
+            w.d3ppre().bits(d3ppre_bits)
+        });
+
+        // set HSI, CSI or HSE as source of all the plls
+        if pll1.is_some() || self.pll2.is_some() || self.pll3.is_some() {
+            rcc.pllckselr.modify(|_, w| unsafe {w.pllsrc().bits(pllsrc_bits)});
+        }
+
+        // adjust sys_ck source
+        if let Some(ref config) = pll1 {
+            // use pll as sys_ck
+            setup_pll1(rcc, config, src_ck)?;
 
             // set pll1_p_ck as sys_ck
             rcc.cfgr.modify(|_, w| unsafe {w.sw().bits(0b011)});
 
             // wait until the clock switch is done
-            while rcc.cfgr.read().sws().bits() != 0b011 {}
+            wait_until(|| rcc.cfgr.read().sws().bits() == 0b011, RccError::SwitchTimeout)?;
         }
         else {
-            // use HSI AS CLOCK SOURCE
-            // usually this value is set to what we write to it by default but you never know
-            rcc.cfgr.modify(|_, w| unsafe {w.sw().bits(0b000)});
-            while rcc.cfgr.read().sws().bits() != 0b000 {}
+            // use HSI, CSI or HSE as clock source
+            // for the HSI this value is usually set by default but you never know
+            rcc.cfgr.modify(|_, w| unsafe {w.sw().bits(sw_bits)});
+            wait_until(|| rcc.cfgr.read().sws().bits() == sw_bits, RccError::SwitchTimeout)?;
         }
 
-        Clocks {
+        // bring up the auxiliary plls
+        if let Some(ref config) = self.pll2 {
+            setup_pll2(rcc, config, src_ck)?;
+        }
+        if let Some(ref config) = self.pll3 {
+            setup_pll3(rcc, config, src_ck)?;
+        }
+
+        // select the kernel clocks of the peripherals
+        if per_ck_used {
+            rcc.d1ccipr.modify(|_, w| unsafe { w.ckpersrc().bits(0b00) });
+        }
+        rcc.d1ccipr.modify(|_, w| unsafe {
+            w.qspisrc().bits(qspi_src as u8)
+                .fmcsrc().bits(fmc_src as u8)
+                .sdmmcsrc().bit(sdmmc_src == SdmmcClkSource::Pll2R)
+        });
+        rcc.d2ccip1r.modify(|_, w| unsafe {
+            w.spi123src().bits(spi123_src as u8)
+                .sai1src().bits(sai1_src as u8)
+                .sai23src().bits(sai23_src as u8)
+                .fdcansrc().bits(fdcan_src as u8)
+        });
+        rcc.d2ccip2r.modify(|_, w| unsafe {
+            w.usart16src().bits(usart16_src as u8)
+                .usart234578src().bits(usart234578_src as u8)
+                .i2c123src().bits(i2c123_src as u8)
+                .rngsrc().bits(rng_src as u8)
+                .usbsrc().bits(if usb_ck.is_some() { usb_src as u8 } else { 0b00 })
+                .lptim1src().bits(lptim1_src as u8)
+        });
+        rcc.d3ccipr.modify(|_, w| unsafe {
+            w.lpuart1src().bits(lpuart1_src as u8)
+                .i2c4src().bits(i2c4_src as u8)
+                .adcsrc().bits(adc_src as u8)
+        });
+
+        // route the clock outputs, MCOxPRE of 0 disables the prescaler just like 1
+        if let Some((src, prescaler)) = self.mco1 {
+            rcc.cfgr.modify(|_, w| unsafe { w.mco1sel().bits(src as u8).mco1pre().bits(prescaler) });
+        }
+        if let Some((src, prescaler)) = self.mco2 {
+            rcc.cfgr.modify(|_, w| unsafe { w.mco2sel().bits(src as u8).mco2pre().bits(prescaler) });
+        }
+
+        // the HSI is only stopped once nothing is switched to it anymore
+        if !hsi_on {
+            rcc.cr.modify(|_, w| w.hsion().clear_bit());
+        }
+
+        #[cfg(feature = "defmt")]
+        {
+            let vos = match self.vos {
+                VoltageScale::Scale0 => "VOS0",
+                VoltageScale::Scale1 => "VOS1",
+                VoltageScale::Scale2 => "VOS2",
+                VoltageScale::Scale3 => "VOS3",
+            };
+            defmt::info!(
+                "sys_ck: {=u32} Hz, pll1 divm, divn, divp: {}, {=str}, flash latency: {=u8}",
+                sys_ck, dividers, vos, acr_config.0
+            );
+            defmt::info!(
+                "hclk: {=u32} Hz, pclk1: {=u32} Hz, pclk2: {=u32} Hz, pclk3: {=u32} Hz, pclk4: {=u32} Hz",
+                hclk, pclk1, pclk2, pclk3, pclk4
+            );
+        }
+
+        Ok(Clocks {
             sys_ck: Hertz(sys_ck),
             hclk1: Hertz(hclk),
             hclk2: Hertz(hclk),
@@ -439,19 +2198,67 @@ impl CFGR {
             pclk2: Hertz(pclk2),
             pclk3: Hertz(pclk3),
             pclk4: Hertz(pclk4),
-            hpre: u8(hpre).unwrap(),
+            hpre: u16(hpre).unwrap(),
             d1ppre: u8(d1ppre).unwrap(),
             d2ppre1: u8(d2ppre1).unwrap(),
             d2ppre2: u8(d2ppre2).unwrap(),
             d3ppre: u8(d3ppre).unwrap(),
-        }
+            timpre: self.timpre,
+            hrtim_src: self.hrtim_src,
+            hclk1_requested: requested_hclk.map(Hertz),
+            hclk2_requested: requested_hclk.map(Hertz),
+            hclk3_requested: requested_hclk.map(Hertz),
+            hclk4_requested: requested_hclk.map(Hertz),
+            pclk1_requested: self.pclk1.map(Hertz),
+            pclk2_requested: self.pclk2.map(Hertz),
+            pclk3_requested: self.pclk3.map(Hertz),
+            pclk4_requested: self.pclk4.map(Hertz),
+            hsi_ck: if hsi_on { Some(Hertz(HSI)) } else { None },
+            hsi48_ck: if self.hsi48 { Some(Hertz(HSI48)) } else { None },
+            lse_ck: self.lse.map(Hertz),
+            lsi_ck: if self.lsi { Some(Hertz(LSI)) } else { None },
+            rtc_ck: rtc.map(|(_, rtc_ck, _)| Hertz(rtc_ck)),
+            pll1_q_ck: pll1_q_ck.map(Hertz),
+            pll1_r_ck: pll1_r_ck.map(Hertz),
+            pll2_p_ck: pll2_p_ck.map(Hertz),
+            pll2_q_ck: pll2_q_ck.map(Hertz),
+            pll2_r_ck: pll2_r_ck.map(Hertz),
+            pll3_p_ck: pll3_p_ck.map(Hertz),
+            pll3_q_ck: pll3_q_ck.map(Hertz),
+            pll3_r_ck: pll3_r_ck.map(Hertz),
+            pll1_dividers: dividers,
+            spi123_ck: spi123_ck.map(Hertz),
+            sai1_ck: sai1_ck.map(Hertz),
+            sai23_ck: sai23_ck.map(Hertz),
+            usart16_ck: Hertz(usart16_ck),
+            usart234578_ck: Hertz(usart234578_ck),
+            lpuart1_ck: Hertz(lpuart1_ck),
+            lptim1_ck: Hertz(lptim1_ck),
+            i2c123_ck: Hertz(i2c123_ck),
+            i2c4_ck: Hertz(i2c4_ck),
+            adc_ck: Hertz(adc_ck),
+            rng_ck: rng_ck.map(Hertz),
+            qspi_ck: Hertz(qspi_ck),
+            fmc_ck: Hertz(fmc_ck),
+            sdmmc_ck: sdmmc_ck.map(Hertz),
+            fdcan_ck: fdcan_ck.map(Hertz),
+            usb_ck: usb_ck.map(Hertz),
+            mco1_ck: mco1_ck.map(Hertz),
+            mco2_ck: mco2_ck.map(Hertz),
+        })
+    }
+
+    /// Freezes the clock configuration like `freeze` but panics if it is invalid
+    pub fn freeze_unwrap(self, acr: &mut ACR, pwr: &mut Pwr) -> Clocks {
+        self.freeze(acr, pwr).unwrap()
     }
 }
 
 /// Frozen clock frequencies
 ///
 /// The existence of this value indicates that the clock configuration can no longer be changed
-#[derive(Clone, Copy)]
+#[derive(Clone, Copy, Debug)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
 pub struct Clocks {
     /// The system clock frequency
     sys_ck: Hertz,
@@ -472,7 +2279,7 @@ pub struct Clocks {
     /// The AHB4 bus frequency
     hclk4: Hertz,
     /// The divider for all the AHB busses
-    hpre: u8,
+    hpre: u16,
     /// The APB3 divider
     d1ppre: u8,
     /// The APB1 divider
@@ -481,10 +2288,340 @@ pub struct Clocks {
     d2ppre2: u8,
     /// The APB4 divider
     d3ppre: u8,
+    /// Whether the TIMPRE bit is set
+    timpre: bool,
+    /// The clock source of the HRTIM
+    hrtim_src: HrtimClkSource,
+    /// The AHB1 bus frequency that was requested from `CFGR`
+    hclk1_requested: Option<Hertz>,
+    /// The AHB2 bus frequency that was requested from `CFGR`
+    hclk2_requested: Option<Hertz>,
+    /// The AHB3 bus frequency that was requested from `CFGR`
+    hclk3_requested: Option<Hertz>,
+    /// The AHB4 bus frequency that was requested from `CFGR`
+    hclk4_requested: Option<Hertz>,
+    /// The APB1 bus frequency that was requested from `CFGR`
+    pclk1_requested: Option<Hertz>,
+    /// The APB2 bus frequency that was requested from `CFGR`
+    pclk2_requested: Option<Hertz>,
+    /// The APB3 bus frequency that was requested from `CFGR`
+    pclk3_requested: Option<Hertz>,
+    /// The APB4 bus frequency that was requested from `CFGR`
+    pclk4_requested: Option<Hertz>,
+    /// The HSI frequency, if it is running
+    hsi_ck: Option<Hertz>,
+    /// The HSI48 frequency, if it is enabled
+    hsi48_ck: Option<Hertz>,
+    /// The LSE frequency, if it is enabled
+    lse_ck: Option<Hertz>,
+    /// The LSI frequency, if it is enabled
+    lsi_ck: Option<Hertz>,
+    /// The RTC kernel clock frequency, if the RTC is clocked
+    rtc_ck: Option<Hertz>,
+    /// The pll1 q output frequency, if it is enabled
+    pll1_q_ck: Option<Hertz>,
+    /// The pll1 r output frequency, if it is enabled
+    pll1_r_ck: Option<Hertz>,
+    /// The pll2 p output frequency, if it is enabled
+    pll2_p_ck: Option<Hertz>,
+    /// The pll2 q output frequency, if it is enabled
+    pll2_q_ck: Option<Hertz>,
+    /// The pll2 r output frequency, if it is enabled
+    pll2_r_ck: Option<Hertz>,
+    /// The pll3 p output frequency, if it is enabled
+    pll3_p_ck: Option<Hertz>,
+    /// The pll3 q output frequency, if it is enabled
+    pll3_q_ck: Option<Hertz>,
+    /// The pll3 r output frequency, if it is enabled
+    pll3_r_ck: Option<Hertz>,
+    /// The divm, divn and divp dividers of the pll1
+    pll1_dividers: Option<(u32, u32, u32)>,
+    /// The kernel clock frequency of SPI1, SPI2 and SPI3
+    spi123_ck: Option<Hertz>,
+    /// The kernel clock frequency of SAI1
+    sai1_ck: Option<Hertz>,
+    /// The kernel clock frequency of SAI2 and SAI3
+    sai23_ck: Option<Hertz>,
+    /// The kernel clock frequency of USART1 and USART6
+    usart16_ck: Hertz,
+    /// The kernel clock frequency of USART2, USART3, UART4, UART5, UART7 and UART8
+    usart234578_ck: Hertz,
+    /// The kernel clock frequency of LPUART1
+    lpuart1_ck: Hertz,
+    /// The kernel clock frequency of LPTIM1
+    lptim1_ck: Hertz,
+    /// The kernel clock frequency of I2C1, I2C2 and I2C3
+    i2c123_ck: Hertz,
+    /// The kernel clock frequency of I2C4
+    i2c4_ck: Hertz,
+    /// The kernel clock frequency of the ADCs
+    adc_ck: Hertz,
+    /// The kernel clock frequency of the RNG
+    rng_ck: Option<Hertz>,
+    /// The kernel clock frequency of the QUADSPI
+    qspi_ck: Hertz,
+    /// The kernel clock frequency of the FMC
+    fmc_ck: Hertz,
+    /// The kernel clock frequency of SDMMC1 and SDMMC2
+    sdmmc_ck: Option<Hertz>,
+    /// The kernel clock frequency of FDCAN1 and FDCAN2
+    fdcan_ck: Option<Hertz>,
+    /// The kernel clock frequency of the USB OTG cores
+    usb_ck: Option<Hertz>,
+    /// The frequency on the MCO1 pin
+    mco1_ck: Option<Hertz>,
+    /// The frequency on the MCO2 pin
+    mco2_ck: Option<Hertz>,
 }
 
 
 impl Clocks {
+    /// Reconstructs the clock frequencies from the RCC registers, e.g. when a bootloader
+    /// configured the clocks. The frequencies of the HSE and the LSE can't be read back,
+    /// they have to be given if the oscillators are used
+    ///
+    /// hclk includes the CPU prescaler D1CPRE, which `freeze` leaves at 1. Kernel clocks
+    /// without an `Option` are 0 Hz if their source is disabled
+    ///
+    /// # Panics
+    ///
+    /// Panics if sys_ck is driven by the HSE, directly or through the pll1, and `hse` is
+    /// `None`
+    pub fn from_rcc_registers(hse: Option<Hertz>, lse: Option<Hertz>) -> Clocks {
+        // NOTE(unsafe) only reads without side effects
+        let rcc = unsafe { &*RCC::ptr() };
+
+        let cr = rcc.cr.read();
+        let cfgr = rcc.cfgr.read();
+        let bdcr = rcc.bdcr.read();
+        let hse = hse.map(|hse| hse.0).filter(|_| cr.hserdy().bit_is_set());
+        let lse = lse.map(|lse| lse.0).filter(|_| bdcr.lserdy().bit_is_set());
+        let hsi = HSI >> cr.hsidiv().bits();
+        let csi = if cr.csirdy().bit_is_set() { Some(CSI) } else { None };
+        let lsi = if rcc.csr.read().lsirdy().bit_is_set() { Some(LSI) } else { None };
+        let hsi48 = if cr.rc48rdy().bit_is_set() { Some(HSI48) } else { None };
+
+        // the p, q and r outputs of the plls
+        let pllckselr = rcc.pllckselr.read().bits();
+        let pllcfgr = rcc.pllcfgr.read().bits();
+        let pll_src = match pllckselr & 0b11 {
+            0b00 => Some(hsi),
+            0b01 => csi,
+            0b10 => hse,
+            _ => None,
+        };
+        let divrs = [rcc.pll1divr.read().bits(), rcc.pll2divr.read().bits(), rcc.pll3divr.read().bits()];
+        let fracrs = [rcc.pll1fracr.read().bits(), rcc.pll2fracr.read().bits(), rcc.pll3fracr.read().bits()];
+        let mut plls = [(None, None, None); 3];
+        for (pll, outputs) in plls.iter_mut().enumerate() {
+            let ready = cr.bits() & (1 << (25 + 2 * pll)) != 0;
+            let divm = (pllckselr >> (4 + 8 * pll)) & 0x3F;
+            let src_ck = match pll_src {
+                Some(src_ck) if ready && divm != 0 => src_ck,
+                _ => continue,
+            };
+            let divr = divrs[pll];
+            // the fractional part is in 1 / 8192 of the multiplication factor
+            let fracn = if pllcfgr & (1 << (4 * pll)) != 0 { (fracrs[pll] >> 3) & 0x1FFF } else { 0 };
+            let divn = (divr & 0x1FF) + 1;
+            let vco_ck = u64::from(src_ck / divm) * (u64::from(divn) * 8192 + u64::from(fracn)) / 8192;
+            let output = |shift: u32, enable: u32| {
+                if pllcfgr & (1 << (16 + 3 * pll as u32 + enable)) != 0 {
+                    Some((vco_ck / u64::from(((divr >> shift) & 0x7F) + 1)) as u32)
+                } else {
+                    None
+                }
+            };
+            *outputs = (output(9, 0), output(16, 1), output(24, 2));
+        }
+        let (pll1_p_ck, pll1_q_ck, pll1_r_ck) = plls[0];
+        let (pll2_p_ck, pll2_q_ck, pll2_r_ck) = plls[1];
+        let (pll3_p_ck, pll3_q_ck, pll3_r_ck) = plls[2];
+
+        let sys_ck = match cfgr.sws().bits() {
+            0b000 => Some(hsi),
+            0b001 => csi,
+            0b010 => hse,
+            _ => pll1_p_ck,
+        }.expect("the frequency of the HSE is needed");
+
+        // the bus prescalers
+        let d1cfgr = rcc.d1cfgr.read();
+        let d2cfgr = rcc.d2cfgr.read();
+        let d3cfgr = rcc.d3cfgr.read();
+        let hpre = decode_hpre(d1cfgr.hpre().bits());
+        let hclk = sys_ck / decode_hpre(d1cfgr.d1cpre().bits()) / hpre;
+        let d1ppre = decode_ppre(d1cfgr.d1ppre().bits());
+        let d2ppre1 = decode_ppre(d2cfgr.d2ppre1().bits());
+        let d2ppre2 = decode_ppre(d2cfgr.d2ppre2().bits());
+        let d3ppre = decode_ppre(d3cfgr.d3ppre().bits());
+        let (pclk1, pclk2, pclk3, pclk4) = (hclk / d2ppre1, hclk / d2ppre2, hclk / d1ppre, hclk / d3ppre);
+
+        let rtc_ck = if bdcr.rtcen().bit_is_set() {
+            match bdcr.rtcsrc().bits() {
+                0b01 => lse,
+                0b10 => lsi,
+                0b11 => hse.and_then(|hse| match cfgr.rtcpre().bits() {
+                    0 | 1 => None,
+                    rtcpre => Some(hse / u32::from(rtcpre)),
+                }),
+                _ => None,
+            }
+        } else {
+            None
+        };
+
+        // the kernel clocks
+        let d1ccipr = rcc.d1ccipr.read();
+        let d2ccip1r = rcc.d2ccip1r.read();
+        let d2ccip2r = rcc.d2ccip2r.read();
+        let d3ccipr = rcc.d3ccipr.read();
+        let per_ck = match d1ccipr.ckpersrc().bits() {
+            0b00 => Some(hsi),
+            0b01 => csi,
+            0b10 => hse,
+            _ => None,
+        };
+        let sai_ck = |bits: u8| match bits {
+            0b000 => pll1_q_ck,
+            0b001 => pll2_p_ck,
+            0b010 => pll3_p_ck,
+            0b100 => per_ck,
+            _ => None,
+        };
+        let usart_ck = |bits: u8, pclk: u32| match bits {
+            0b000 => Some(pclk),
+            0b001 => pll2_q_ck,
+            0b010 => pll3_q_ck,
+            0b011 => Some(hsi),
+            0b100 => csi,
+            0b101 => lse,
+            _ => None,
+        };
+        let i2c_ck = |bits: u8, pclk: u32| match bits {
+            0b00 => Some(pclk),
+            0b01 => pll3_r_ck,
+            0b10 => Some(hsi),
+            _ => csi,
+        };
+        let hclk3_ck = |bits: u8| match bits {
+            0b00 => Some(hclk),
+            0b01 => pll1_q_ck,
+            0b10 => pll2_r_ck,
+            _ => per_ck,
+        };
+
+        Clocks {
+            sys_ck: Hertz(sys_ck),
+            hclk1: Hertz(hclk),
+            hclk2: Hertz(hclk),
+            hclk3: Hertz(hclk),
+            hclk4: Hertz(hclk),
+            pclk1: Hertz(pclk1),
+            pclk2: Hertz(pclk2),
+            pclk3: Hertz(pclk3),
+            pclk4: Hertz(pclk4),
+            hpre: u16(hpre).unwrap(),
+            d1ppre: u8(d1ppre).unwrap(),
+            d2ppre1: u8(d2ppre1).unwrap(),
+            d2ppre2: u8(d2ppre2).unwrap(),
+            d3ppre: u8(d3ppre).unwrap(),
+            timpre: cfgr.timpre().bit_is_set(),
+            hrtim_src: if cfgr.hrtimsel().bit_is_set() { HrtimClkSource::CpuCk } else { HrtimClkSource::TimyKerCk },
+            hclk1_requested: None,
+            hclk2_requested: None,
+            hclk3_requested: None,
+            hclk4_requested: None,
+            pclk1_requested: None,
+            pclk2_requested: None,
+            pclk3_requested: None,
+            pclk4_requested: None,
+            hsi_ck: if cr.hsirdy().bit_is_set() { Some(Hertz(hsi)) } else { None },
+            hsi48_ck: hsi48.map(Hertz),
+            lse_ck: lse.map(Hertz),
+            lsi_ck: lsi.map(Hertz),
+            rtc_ck: rtc_ck.map(Hertz),
+            pll1_q_ck: pll1_q_ck.map(Hertz),
+            pll1_r_ck: pll1_r_ck.map(Hertz),
+            pll2_p_ck: pll2_p_ck.map(Hertz),
+            pll2_q_ck: pll2_q_ck.map(Hertz),
+            pll2_r_ck: pll2_r_ck.map(Hertz),
+            pll3_p_ck: pll3_p_ck.map(Hertz),
+            pll3_q_ck: pll3_q_ck.map(Hertz),
+            pll3_r_ck: pll3_r_ck.map(Hertz),
+            pll1_dividers: if cfgr.sws().bits() == 0b011 {
+                Some((
+                    (pllckselr >> 4) & 0x3F,
+                    (divrs[0] & 0x1FF) + 1,
+                    ((divrs[0] >> 9) & 0x7F) + 1,
+                ))
+            } else {
+                None
+            },
+            spi123_ck: sai_ck(d2ccip1r.spi123src().bits()).map(Hertz),
+            sai1_ck: sai_ck(d2ccip1r.sai1src().bits()).map(Hertz),
+            sai23_ck: sai_ck(d2ccip1r.sai23src().bits()).map(Hertz),
+            usart16_ck: Hertz(usart_ck(d2ccip2r.usart16src().bits(), pclk2).unwrap_or(0)),
+            usart234578_ck: Hertz(usart_ck(d2ccip2r.usart234578src().bits(), pclk1).unwrap_or(0)),
+            lpuart1_ck: Hertz(usart_ck(d3ccipr.lpuart1src().bits(), pclk4).unwrap_or(0)),
+            lptim1_ck: Hertz(match d2ccip2r.lptim1src().bits() {
+                0b000 => Some(pclk1),
+                0b001 => pll2_p_ck,
+                0b010 => pll3_r_ck,
+                0b011 => lse,
+                0b100 => lsi,
+                0b101 => per_ck,
+                _ => None,
+            }.unwrap_or(0)),
+            i2c123_ck: Hertz(i2c_ck(d2ccip2r.i2c123src().bits(), pclk1).unwrap_or(0)),
+            i2c4_ck: Hertz(i2c_ck(d3ccipr.i2c4src().bits(), pclk4).unwrap_or(0)),
+            adc_ck: Hertz(match d3ccipr.adcsrc().bits() {
+                0b00 => pll2_p_ck,
+                0b01 => pll3_r_ck,
+                0b10 => per_ck,
+                _ => None,
+            }.unwrap_or(0)),
+            rng_ck: match d2ccip2r.rngsrc().bits() {
+                0b00 => hsi48,
+                0b01 => pll1_q_ck,
+                0b10 => lse,
+                _ => lsi,
+            }.map(Hertz),
+            qspi_ck: Hertz(hclk3_ck(d1ccipr.qspisrc().bits()).unwrap_or(0)),
+            fmc_ck: Hertz(hclk3_ck(d1ccipr.fmcsrc().bits()).unwrap_or(0)),
+            sdmmc_ck: if d1ccipr.sdmmcsrc().bit_is_set() { pll2_r_ck } else { pll1_q_ck }.map(Hertz),
+            fdcan_ck: match d2ccip1r.fdcansrc().bits() {
+                0b00 => hse,
+                0b01 => pll1_q_ck,
+                0b10 => pll2_q_ck,
+                _ => None,
+            }.map(Hertz),
+            usb_ck: match d2ccip2r.usbsrc().bits() {
+                0b01 => pll1_q_ck,
+                0b10 => pll3_q_ck,
+                0b11 => hsi48,
+                _ => None,
+            }.map(Hertz),
+            mco1_ck: match cfgr.mco1sel().bits() {
+                0b000 => Some(hsi),
+                0b001 => lse,
+                0b010 => hse,
+                0b011 => pll1_q_ck,
+                0b100 => hsi48,
+                _ => None,
+            }.map(|ck| Hertz(ck / u32(cfgr.mco1pre().bits().max(1)))),
+            mco2_ck: match cfgr.mco2sel().bits() {
+                0b000 => Some(sys_ck),
+                0b001 => pll2_p_ck,
+                0b010 => hse,
+                0b011 => pll1_p_ck,
+                0b100 => csi,
+                0b101 => lsi,
+                _ => None,
+            }.map(|ck| Hertz(ck / u32(cfgr.mco2pre().bits().max(1)))),
+        }
+    }
+
     /// Getter for sys_ck
     pub fn sys_ck(&self) -> Hertz {
         self.sys_ck
@@ -521,20 +2658,428 @@ impl Clocks {
     pub fn hclk4(&self) -> Hertz {
         self.hclk4
     }
-    /// Getter for d1ppre
+    /// Getter for the HPRE field as written to D1CFGR, use `hpre_div` for the divider
+    pub fn hpre(&self) -> u8 {
+        encode_hpre(u32::from(self.hpre))
+    }
+    /// Getter for the D1PPRE field as written to D1CFGR, use `d1ppre_div` for the divider
     pub fn d1ppre(&self) -> u8 {
-        self.d1ppre
+        encode_ppre(u32::from(self.d1ppre))
     }
-    /// Getter for de2ppre1
+    /// Getter for the D2PPRE1 field as written to D2CFGR, use `d2ppre1_div` for the divider
     pub fn d2ppre1(&self) -> u8 {
-        self.d2ppre1
+        encode_ppre(u32::from(self.d2ppre1))
     }
-    /// Getter for d2ppre2
+    /// Getter for the D2PPRE2 field as written to D2CFGR, use `d2ppre2_div` for the divider
     pub fn d2ppre2(&self) -> u8 {
-        self.d2ppre2
+        encode_ppre(u32::from(self.d2ppre2))
     }
-    /// Getter for d3ppre
+    /// Getter for the D3PPRE field as written to D3CFGR, use `d3ppre_div` for the divider
     pub fn d3ppre(&self) -> u8 {
+        encode_ppre(u32::from(self.d3ppre))
+    }
+    /// Getter for the divider of sys_ck that generates hclk, 1 to 512
+    pub fn hpre_div(&self) -> u16 {
+        self.hpre
+    }
+    /// Getter for the divider of hclk that generates pclk3, 1 to 16
+    pub fn d1ppre_div(&self) -> u8 {
+        self.d1ppre
+    }
+    /// Getter for the divider of hclk that generates pclk1, 1 to 16
+    pub fn d2ppre1_div(&self) -> u8 {
+        self.d2ppre1
+    }
+    /// Getter for the divider of hclk that generates pclk2, 1 to 16
+    pub fn d2ppre2_div(&self) -> u8 {
+        self.d2ppre2
+    }
+    /// Getter for the divider of hclk that generates pclk4, 1 to 16
+    pub fn d3ppre_div(&self) -> u8 {
+        self.d3ppre
+    }
+    /// The divider of the APB1 prescaler, same as `d2ppre1_div`
+    pub fn apb1_prescaler(&self) -> u8 {
+        self.d2ppre1
+    }
+    /// The divider of the APB2 prescaler, same as `d2ppre2_div`
+    pub fn apb2_prescaler(&self) -> u8 {
+        self.d2ppre2
+    }
+    /// The divider of the APB3 prescaler, same as `d1ppre_div`
+    pub fn apb3_prescaler(&self) -> u8 {
+        self.d1ppre
+    }
+    /// The divider of the APB4 prescaler, same as `d3ppre_div`
+    pub fn apb4_prescaler(&self) -> u8 {
         self.d3ppre
     }
-}
\ No newline at end of file
+    /// Whether the timers on APB1 run faster than pclk1, which is the case if the APB1
+    /// prescaler is not 1
+    pub fn timers_double_apb1(&self) -> bool {
+        self.d2ppre1 != 1
+    }
+    /// Whether the timers on APB2 run faster than pclk2, which is the case if the APB2
+    /// prescaler is not 1
+    pub fn timers_double_apb2(&self) -> bool {
+        self.d2ppre2 != 1
+    }
+    /// Getter for the hclk1 frequency requested from `CFGR`, `None` if it was left to the default
+    pub fn hclk1_requested(&self) -> Option<Hertz> {
+        self.hclk1_requested
+    }
+    /// Getter for the hclk2 frequency requested from `CFGR`, `None` if it was left to the default
+    pub fn hclk2_requested(&self) -> Option<Hertz> {
+        self.hclk2_requested
+    }
+    /// Getter for the hclk3 frequency requested from `CFGR`, `None` if it was left to the default
+    pub fn hclk3_requested(&self) -> Option<Hertz> {
+        self.hclk3_requested
+    }
+    /// Getter for the hclk4 frequency requested from `CFGR`, `None` if it was left to the default
+    pub fn hclk4_requested(&self) -> Option<Hertz> {
+        self.hclk4_requested
+    }
+    /// Getter for the pclk1 frequency requested from `CFGR`, `None` if it was left to the default
+    pub fn pclk1_requested(&self) -> Option<Hertz> {
+        self.pclk1_requested
+    }
+    /// Getter for the pclk2 frequency requested from `CFGR`, `None` if it was left to the default
+    pub fn pclk2_requested(&self) -> Option<Hertz> {
+        self.pclk2_requested
+    }
+    /// Getter for the pclk3 frequency requested from `CFGR`, `None` if it was left to the default
+    pub fn pclk3_requested(&self) -> Option<Hertz> {
+        self.pclk3_requested
+    }
+    /// Getter for the pclk4 frequency requested from `CFGR`, `None` if it was left to the default
+    pub fn pclk4_requested(&self) -> Option<Hertz> {
+        self.pclk4_requested
+    }
+    /// Getter for hsi_ck, `None` if the HSI is stopped
+    pub fn hsi_ck(&self) -> Option<Hertz> {
+        self.hsi_ck
+    }
+    /// Getter for hsi48_ck, `None` if the HSI48 is disabled
+    pub fn hsi48_ck(&self) -> Option<Hertz> {
+        self.hsi48_ck
+    }
+    /// Getter for pll1_q_ck, `None` if the output is disabled
+    pub fn pll1_q_ck(&self) -> Option<Hertz> {
+        self.pll1_q_ck
+    }
+    /// Getter for pll1_r_ck, `None` if the output is disabled
+    pub fn pll1_r_ck(&self) -> Option<Hertz> {
+        self.pll1_r_ck
+    }
+    /// Getter for pll2_p_ck, `None` if the output is disabled
+    pub fn pll2_p_ck(&self) -> Option<Hertz> {
+        self.pll2_p_ck
+    }
+    /// Getter for pll2_q_ck, `None` if the output is disabled
+    pub fn pll2_q_ck(&self) -> Option<Hertz> {
+        self.pll2_q_ck
+    }
+    /// Getter for pll2_r_ck, `None` if the output is disabled
+    pub fn pll2_r_ck(&self) -> Option<Hertz> {
+        self.pll2_r_ck
+    }
+    /// Getter for pll3_p_ck, `None` if the output is disabled
+    pub fn pll3_p_ck(&self) -> Option<Hertz> {
+        self.pll3_p_ck
+    }
+    /// Getter for pll3_q_ck, `None` if the output is disabled
+    pub fn pll3_q_ck(&self) -> Option<Hertz> {
+        self.pll3_q_ck
+    }
+    /// Getter for pll3_r_ck, `None` if the output is disabled
+    pub fn pll3_r_ck(&self) -> Option<Hertz> {
+        self.pll3_r_ck
+    }
+
+    /// Getter for the (divm, divn, divp) dividers of the pll1, `None` if sys_ck is not driven by the pll1
+    pub fn pll1_dividers(&self) -> Option<(u32, u32, u32)> {
+        self.pll1_dividers
+    }
+
+    /// Getter for lse_ck, `None` if the LSE is disabled
+    pub fn lse_ck(&self) -> Option<Hertz> {
+        self.lse_ck
+    }
+
+    /// Getter for lsi_ck, `None` if the LSI is disabled
+    pub fn lsi_ck(&self) -> Option<Hertz> {
+        self.lsi_ck
+    }
+
+    /// Getter for rtc_ck, `None` if the RTC is not clocked
+    pub fn rtc_ck(&self) -> Option<Hertz> {
+        self.rtc_ck
+    }
+
+    /// Getter for the kernel clock of SPI1, SPI2 and SPI3, `None` if the selected source is disabled
+    pub fn spi123_ck(&self) -> Option<Hertz> {
+        self.spi123_ck
+    }
+
+    /// Getter for the kernel clock of SAI1, `None` if the selected source is disabled
+    pub fn sai1_ck(&self) -> Option<Hertz> {
+        self.sai1_ck
+    }
+
+    /// Getter for the kernel clock of SAI2 and SAI3, `None` if the selected source is disabled
+    pub fn sai23_ck(&self) -> Option<Hertz> {
+        self.sai23_ck
+    }
+
+    /// Getter for the kernel clock of USART1 and USART6
+    pub fn usart16_ck(&self) -> Hertz {
+        self.usart16_ck
+    }
+
+    /// Getter for the kernel clock of USART2, USART3, UART4, UART5, UART7 and UART8
+    pub fn usart234578_ck(&self) -> Hertz {
+        self.usart234578_ck
+    }
+
+    /// Getter for the kernel clock of LPUART1
+    pub fn lpuart1_ck(&self) -> Hertz {
+        self.lpuart1_ck
+    }
+
+    /// Getter for the kernel clock of LPTIM1
+    pub fn lptim1_ck(&self) -> Hertz {
+        self.lptim1_ck
+    }
+
+    /// Getter for the kernel clock of I2C1, I2C2 and I2C3
+    pub fn i2c123_ck(&self) -> Hertz {
+        self.i2c123_ck
+    }
+
+    /// Getter for the kernel clock of I2C4
+    pub fn i2c4_ck(&self) -> Hertz {
+        self.i2c4_ck
+    }
+
+    /// Getter for the kernel clock of the ADCs
+    pub fn adc_ck(&self) -> Hertz {
+        self.adc_ck
+    }
+
+    /// Getter for the kernel clock of the RNG, `None` if the selected source is disabled
+    pub fn rng_ck(&self) -> Option<Hertz> {
+        self.rng_ck
+    }
+
+    /// Getter for the kernel clock of the QUADSPI
+    pub fn qspi_ck(&self) -> Hertz {
+        self.qspi_ck
+    }
+
+    /// Getter for the kernel clock of the FMC
+    pub fn fmc_ck(&self) -> Hertz {
+        self.fmc_ck
+    }
+
+    /// Getter for the kernel clock of SDMMC1 and SDMMC2, `None` if the selected source is disabled
+    pub fn sdmmc_ck(&self) -> Option<Hertz> {
+        self.sdmmc_ck
+    }
+
+    /// Getter for the kernel clock of FDCAN1 and FDCAN2, `None` if the selected source is disabled
+    pub fn fdcan_ck(&self) -> Option<Hertz> {
+        self.fdcan_ck
+    }
+
+    /// Getter for the kernel clock of the USB OTG cores, `None` if the selected source is disabled
+    pub fn usb_ck(&self) -> Option<Hertz> {
+        self.usb_ck
+    }
+
+    /// Getter for the frequency on the MCO1 pin, `None` if no clock is routed to it
+    pub fn mco1_ck(&self) -> Option<Hertz> {
+        self.mco1_ck
+    }
+
+    /// Getter for the frequency on the MCO2 pin, `None` if no clock is routed to it
+    pub fn mco2_ck(&self) -> Option<Hertz> {
+        self.mco2_ck
+    }
+
+    /// The kernel clock of the timers on APB1 (TIM2 to TIM7 and TIM12 to TIM14), which runs
+    /// faster than pclk1 if the APB1 prescaler is not 1
+    pub fn timx_ker_ck(&self) -> Hertz {
+        Hertz(timer_ker_ck(self.pclk1.0, self.hclk1.0, self.d2ppre1, self.timpre))
+    }
+
+    /// The kernel clock of the timers on APB2 (TIM1, TIM8 and TIM15 to TIM17), which runs
+    /// faster than pclk2 if the APB2 prescaler is not 1
+    pub fn timy_ker_ck(&self) -> Hertz {
+        Hertz(timer_ker_ck(self.pclk2.0, self.hclk1.0, self.d2ppre2, self.timpre))
+    }
+
+    /// The timer clock of APB1, same as `timx_ker_ck`
+    pub fn timer_clock_apb1(&self) -> Hertz {
+        self.timx_ker_ck()
+    }
+
+    /// The timer clock of APB2, same as `timy_ker_ck`
+    pub fn timer_clock_apb2(&self) -> Hertz {
+        self.timy_ker_ck()
+    }
+
+    /// The clock of the HRTIM
+    pub fn hrtim_ck(&self) -> Hertz {
+        match self.hrtim_src {
+            HrtimClkSource::TimyKerCk => self.timy_ker_ck(),
+            // the CPU clock prescaler is left at 1
+            HrtimClkSource::CpuCk => self.sys_ck,
+        }
+    }
+}
+
+/// The timer clock of an APB running at `pclk` with the divider `ppre`, see table 55 of
+/// the reference manual
+fn timer_ker_ck(pclk: u32, hclk: u32, ppre: u8, timpre: bool) -> u32 {
+    match (timpre, ppre) {
+        (_, 1) => pclk,
+        (false, _) => pclk * 2,
+        (true, 2) | (true, 4) => hclk,
+        (true, _) => pclk * 4,
+    }
+}
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn hclk_above_sys_ck_is_clamped() {
+        // the divider search never subtracts a frequency from a smaller one
+        assert_eq!(best_divider(240_000_000, HSI, &HPRE_DIVIDERS), 1);
+    }
+
+    #[test]
+    fn hclk_equal_to_sys_ck_is_undivided() {
+        assert_eq!(best_divider(HSI, HSI, &HPRE_DIVIDERS), 1);
+    }
+
+    #[test]
+    fn best_divider_hits_exact_target() {
+        assert_eq!(best_divider(50_000_000, 200_000_000, &HPRE_DIVIDERS), 4);
+        assert_eq!(best_divider(50_000_000, 200_000_000, &PPRE_DIVIDERS), 4);
+    }
+
+    #[test]
+    fn best_divider_doesnt_exceed_target() {
+        assert_eq!(best_divider(60_000_000, 200_000_000, &HPRE_DIVIDERS), 4);
+        // there is no HPRE divider of 32, 64 is the next one below the target
+        assert_eq!(best_divider(6_250_000, 200_000_000, &HPRE_DIVIDERS), 64);
+    }
+
+    #[test]
+    fn best_divider_falls_back_to_largest() {
+        assert_eq!(best_divider(1_000_000, 200_000_000, &PPRE_DIVIDERS), 16);
+    }
+
+    #[test]
+    fn divn_above_512_is_rejected() {
+        let config = PllConfig::new(4, 513).p(2);
+        assert_eq!(config.outputs(2, HSI), Err(RccError::DivnOutOfRange));
+    }
+
+    #[test]
+    fn flash_latency_follows_the_reference_manual() {
+        assert_eq!(flash_latency(VoltageScale::Scale1, 70_000_000), Ok((0, 0)));
+        assert_eq!(flash_latency(VoltageScale::Scale1, 200_000_000), Ok((2, 2)));
+        assert_eq!(flash_latency(VoltageScale::Scale0, 240_000_000), Ok((4, 2)));
+        assert_eq!(flash_latency(VoltageScale::Scale2, 165_000_000), Ok((2, 1)));
+        assert_eq!(flash_latency(VoltageScale::Scale3, 180_000_000), Ok((3, 2)));
+        assert_eq!(flash_latency(VoltageScale::Scale3, 225_000_000), Ok((4, 2)));
+    }
+
+    #[test]
+    fn flash_latency_rejects_hclk_out_of_range() {
+        // only VOS0 runs the AXI bus above 225 MHz
+        assert_eq!(flash_latency(VoltageScale::Scale1, 240_000_000), Err(RccError::HclkTooHigh));
+        assert_eq!(flash_latency(VoltageScale::Scale0, 240_000_001), Err(RccError::HclkTooHigh));
+        assert_eq!(flash_latency(VoltageScale::Scale2, 225_000_001), Err(RccError::HclkTooHigh));
+    }
+
+    #[test]
+    fn pll1_dividers_find_200mhz() {
+        // 64 MHz / 4 * 25 / 2
+        assert_eq!(pll1_dividers(HSI, 200_000_000, 400_000_000), Some((4, 25, 2)));
+        // 8 MHz / 1 * 50 / 2
+        assert_eq!(pll1_dividers(8_000_000, 200_000_000, 400_000_000), Some((1, 50, 2)));
+    }
+
+    #[test]
+    fn pll1_dividers_dont_exceed_max() {
+        let (divm, divn, divp) = pll1_dividers(HSI, 500_000_000, 400_000_000).unwrap();
+        assert_eq!(HSI / divm * divn / divp, 400_000_000);
+    }
+
+    #[test]
+    fn timer_clock_follows_the_apb_prescaler() {
+        // no doubling with a prescaler of 1
+        assert_eq!(timer_ker_ck(200_000_000, 200_000_000, 1, false), 200_000_000);
+        assert_eq!(timer_ker_ck(50_000_000, 200_000_000, 4, false), 100_000_000);
+        // TIMPRE lets the timers run at hclk
+        assert_eq!(timer_ker_ck(50_000_000, 200_000_000, 4, true), 200_000_000);
+        assert_eq!(timer_ker_ck(25_000_000, 200_000_000, 8, true), 100_000_000);
+    }
+
+    #[test]
+    fn pll1_outputs_without_sys_ck_are_rejected() {
+        assert_eq!(CFGR::new().pll1_q(2).pll1(HSI).err(), Some(RccError::Pll1NotConfigured));
+        assert_eq!(CFGR::new().pll1_r(2).pll1(HSI).err(), Some(RccError::Pll1NotConfigured));
+        assert!(CFGR::new().pll1(HSI).unwrap().is_none());
+        assert!(CFGR::new().sys_ck(4, 50, 2).pll1_q(2).pll1(HSI).unwrap().is_some());
+    }
+
+    #[test]
+    fn hpre_encoding() {
+        assert_eq!(encode_hpre(1), 0b0000);
+        assert_eq!(encode_hpre(2), 0b1000);
+        assert_eq!(encode_hpre(16), 0b1011);
+        assert_eq!(encode_hpre(64), 0b1100);
+        assert_eq!(encode_hpre(512), 0b1111);
+        for &divider in &HPRE_DIVIDERS {
+            assert_eq!(decode_hpre(encode_hpre(divider)), divider);
+        }
+    }
+
+    #[test]
+    fn ppre_encoding() {
+        assert_eq!(encode_ppre(1), 0b000);
+        assert_eq!(encode_ppre(2), 0b100);
+        assert_eq!(encode_ppre(4), 0b101);
+        assert_eq!(encode_ppre(8), 0b110);
+        assert_eq!(encode_ppre(16), 0b111);
+        for &divider in &PPRE_DIVIDERS {
+            assert_eq!(decode_ppre(encode_ppre(divider)), divider);
+        }
+    }
+
+    #[test]
+    fn conflicting_hclk_is_rejected() {
+        let (hclk1, hclk2) = (Some(200_000_000), Some(100_000_000));
+        assert_eq!(requested_hclk(&[None, hclk1, hclk2, None, None]), Err(RccError::ConflictingHclk));
+        assert_eq!(requested_hclk(&[None, hclk1, hclk1, None, None]), Ok(hclk1));
+        assert_eq!(requested_hclk(&[None; 5]), Ok(None));
+    }
+
+    #[test]
+    fn pclk_equal_to_hclk_is_undivided() {
+        assert_eq!(ppre_divider(Some(200_000_000), 200_000_000, 2), Ok(1));
+    }
+
+    #[test]
+    fn pclk_above_hclk_is_rejected() {
+        assert_eq!(
+            ppre_divider(Some(200_000_001), 200_000_000, 2),
+            Err(RccError::PclkTooHigh { bus: 2 })
+        );
+    }
+}
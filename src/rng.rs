@@ -0,0 +1,89 @@
+//! True Random Number Generator
+
+use rand_core::{impls, Error, RngCore};
+use stm32h7::stm32h7x3::RNG;
+
+use crate::rcc::{Clocks, Enable, Reset, AHB2};
+
+/// The highest kernel clock frequency of the RNG
+const MAX_RNG_CK: u32 = 48_000_000;
+
+/// True random number generator
+pub struct Rng {
+    rng: RNG,
+}
+
+impl Rng {
+    /// Enables the RNG, it is clocked by the kernel clock selected with `CFGR::rng_src`
+    ///
+    /// # Panics
+    ///
+    /// Panics if the kernel clock is disabled, faster than 48 MHz or too slow for the
+    /// clock error detection, which requires at least hclk / 32
+    pub fn new(rng: RNG, ahb2: &mut AHB2, clocks: &Clocks) -> Self {
+        let rng_ck = clocks.rng_ck().expect("the kernel clock of the RNG is disabled");
+        assert!(rng_ck.0 <= MAX_RNG_CK, "the kernel clock of the RNG is too fast");
+        assert!(rng_ck.0 >= clocks.hclk2().0 / 32, "the kernel clock of the RNG is too slow");
+
+        RNG::enable(ahb2);
+        RNG::reset(ahb2);
+
+        // CED = 0: the clock error detection is enabled
+        rng.rng_cr.write(|w| w.rngen().set_bit());
+
+        Rng { rng }
+    }
+
+    /// Releases the RNG
+    pub fn free(self) -> RNG {
+        self.rng.rng_cr.modify(|_, w| w.rngen().clear_bit());
+        self.rng
+    }
+
+    /// Recovers from a seed error with the sequence of the reference manual
+    fn recover_seed_error(&mut self) {
+        self.rng.rng_sr.modify(|_, w| w.seis().clear_bit());
+
+        // the words in the pipeline are discarded
+        for _ in 0..12 {
+            self.rng.rng_dr.read();
+        }
+
+        if self.rng.rng_sr.read().secs().bit_is_set() {
+            self.rng.rng_cr.modify(|_, w| w.rngen().clear_bit());
+            self.rng.rng_cr.modify(|_, w| w.rngen().set_bit());
+        }
+    }
+}
+
+impl RngCore for Rng {
+    /// Waits for a random word, errors of the generator are recovered from without
+    /// returning words generated during the error
+    fn next_u32(&mut self) -> u32 {
+        loop {
+            let sr = self.rng.rng_sr.read();
+
+            if sr.seis().bit_is_set() {
+                self.recover_seed_error();
+            } else if sr.ceis().bit_is_set() {
+                // the generator continues once the clock is correct again
+                self.rng.rng_sr.modify(|_, w| w.ceis().clear_bit());
+            } else if sr.drdy().bit_is_set() {
+                return self.rng.rng_dr.read().rndata().bits();
+            }
+        }
+    }
+
+    fn next_u64(&mut self) -> u64 {
+        impls::next_u64_via_u32(self)
+    }
+
+    fn fill_bytes(&mut self, dest: &mut [u8]) {
+        impls::fill_bytes_via_next(self, dest)
+    }
+
+    fn try_fill_bytes(&mut self, dest: &mut [u8]) -> Result<(), Error> {
+        self.fill_bytes(dest);
+        Ok(())
+    }
+}
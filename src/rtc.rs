@@ -0,0 +1,453 @@
+//! Real Time Clock
+//!
+//! The RTC keeps a calendar in the backup domain, it keeps running through resets and in
+//! the low power modes as long as its clock, selected with `CFGR::rtc_src`, runs
+
+use core::ptr;
+use core::time::Duration;
+
+use stm32h7::stm32h7x3::{EXTI, RTC};
+
+use crate::exti;
+use crate::pwr::Pwr;
+use crate::rcc::{Clocks, Enable, APB4};
+
+/// A time of the day in the 24 hour format
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Time {
+    /// 0 to 23
+    pub hours: u8,
+    /// 0 to 59
+    pub minutes: u8,
+    /// 0 to 59
+    pub seconds: u8,
+}
+
+/// A date of the years 2000 to 2099
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Date {
+    /// 2000 to 2099
+    pub year: u16,
+    /// 1 to 12
+    pub month: u8,
+    /// 1 to 31
+    pub day: u8,
+    /// 1 (Monday) to 7 (Sunday)
+    pub weekday: u8,
+}
+
+/// The two alarms of the RTC
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Alarm {
+    /// Alarm A
+    A,
+    /// Alarm B
+    B,
+}
+
+/// Interrupt events of the RTC, they are routed through EXTI lines so they can wake the
+/// chip from the Stop mode
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Event {
+    /// Alarm A fired, EXTI line 17
+    AlarmA,
+    /// Alarm B fired, EXTI line 17
+    AlarmB,
+    /// The wakeup timer expired, EXTI line 19
+    Wakeup,
+}
+
+impl Event {
+    /// The EXTI line of the event
+    fn exti_line(self) -> u8 {
+        match self {
+            Event::AlarmA | Event::AlarmB => 17,
+            Event::Wakeup => 19,
+        }
+    }
+}
+
+/// The time an alarm fires at, the fields that are `None` match every value, e.g. an alarm
+/// with only `seconds` set fires once a minute
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct AlarmTime {
+    /// The day of the month, 1 to 31
+    pub day: Option<u8>,
+    /// 0 to 23
+    pub hours: Option<u8>,
+    /// 0 to 59
+    pub minutes: Option<u8>,
+    /// 0 to 59
+    pub seconds: Option<u8>,
+}
+
+/// Real time clock
+pub struct Rtc {
+    rtc: RTC,
+    rtc_ck: u32,
+}
+
+/// The tens and the units of `value`
+fn to_bcd(value: u8) -> (u8, u8) {
+    (value / 10, value % 10)
+}
+
+/// The value of `tens` and `units`
+fn from_bcd(tens: u8, units: u8) -> u8 {
+    tens * 10 + units
+}
+
+/// PREDIV_A and PREDIV_S dividing `rtc_ck` down to 1 Hz, PREDIV_A is as high as possible
+/// to save power
+fn prescalers(rtc_ck: u32) -> (u8, u16) {
+    let a = (1..=128)
+        .rev()
+        .find(|&a| rtc_ck.is_multiple_of(a) && rtc_ck / a <= 0x8000)
+        .unwrap_or(128);
+    let s = (rtc_ck / a).clamp(1, 0x8000);
+    ((a - 1) as u8, (s - 1) as u16)
+}
+
+/// WUCKSEL and WUT of a wakeup timer that runs from `rtc_ck` and expires every `interval`
+fn wakeup_config(rtc_ck: u32, interval: Duration) -> (u8, u16) {
+    let nanos = interval.as_secs() as u128 * 1_000_000_000 + u128::from(interval.subsec_nanos());
+    // WUCKSEL = 0bx11 to 0b000: RTCCLK divided by 2 to 16, the period is WUT + 1 ticks
+    let divided = [(0b011, 2), (0b010, 4), (0b001, 8), (0b000, 16)]
+        .iter()
+        .map(|&(wucksel, div)| (wucksel, nanos * u128::from(rtc_ck) / div / 1_000_000_000))
+        .find(|&(_, ticks)| ticks <= 0x1_0000);
+    match divided {
+        Some((_, 0)) => panic!("the wakeup interval is too short"),
+        Some((wucksel, ticks)) => (wucksel, (ticks - 1) as u16),
+        None => match interval.as_secs() {
+            0 => panic!("the wakeup interval is too short"),
+            // WUCKSEL = 10x: ck_spre, the period is WUT + 1 s
+            secs @ 1..=0x1_0000 => (0b100, (secs - 1) as u16),
+            // WUCKSEL = 11x: ck_spre with 2^16 added to WUT, the period is WUT + 2^16 + 1 s
+            secs @ 0x1_0001..=0x2_0000 => (0b110, (secs - 0x1_0001) as u16),
+            _ => panic!("the wakeup interval is too long"),
+        },
+    }
+}
+
+impl Rtc {
+    /// Enables the RTC and starts the calendar at 1 Hz, a calendar that is already running
+    /// with the same prescalers, e.g. after a reset, keeps its date and time
+    ///
+    /// # Panics
+    ///
+    /// Panics if the RTC is not clocked
+    pub fn new(rtc: RTC, clocks: &Clocks, apb4: &mut APB4, pwr: &mut Pwr) -> Self {
+        let rtc_ck = clocks.rtc_ck().expect("the RTC is not clocked").0;
+
+        RTC::enable(apb4);
+        pwr.cr1.enable_backup_domain_access();
+
+        let mut rtc = Rtc { rtc, rtc_ck };
+        let (prediv_a, prediv_s) = prescalers(rtc_ck);
+        let prer = rtc.rtc.rtc_prer.read();
+        let initialized = rtc.rtc.rtc_isr.read().inits().bit_is_set()
+            && prer.prediv_a().bits() == prediv_a
+            && prer.prediv_s().bits() == prediv_s;
+
+        if !initialized {
+            rtc.init_mode(|rtc| {
+                // the prescalers have to be written with two separate writes
+                rtc.rtc_prer.modify(|_, w| unsafe { w.prediv_s().bits(prediv_s) });
+                rtc.rtc_prer.modify(|_, w| unsafe { w.prediv_a().bits(prediv_a) });
+                rtc.rtc_cr.modify(|_, w| w.fmt().clear_bit());
+            });
+        }
+
+        rtc
+    }
+
+    /// Sets the time
+    pub fn set_time(&mut self, time: &Time) {
+        assert!(time.hours < 24 && time.minutes < 60 && time.seconds < 60);
+
+        let (ht, hu) = to_bcd(time.hours);
+        let (mnt, mnu) = to_bcd(time.minutes);
+        let (st, su) = to_bcd(time.seconds);
+        self.init_mode(|rtc| {
+            rtc.rtc_tr.write(|w| unsafe {
+                w.ht().bits(ht).hu().bits(hu)
+                    .mnt().bits(mnt).mnu().bits(mnu)
+                    .st().bits(st).su().bits(su)
+            })
+        });
+    }
+
+    /// Sets the date
+    pub fn set_date(&mut self, date: &Date) {
+        assert!(date.year >= 2000 && date.year < 2100);
+        assert!(date.month >= 1 && date.month <= 12 && date.day >= 1 && date.day <= 31);
+        assert!(date.weekday >= 1 && date.weekday <= 7);
+
+        let (yt, yu) = to_bcd((date.year - 2000) as u8);
+        let (mt, mu) = to_bcd(date.month);
+        let (dt, du) = to_bcd(date.day);
+        self.init_mode(|rtc| {
+            rtc.rtc_dr.write(|w| unsafe {
+                w.yt().bits(yt).yu().bits(yu)
+                    .mt().bit(mt == 1).mu().bits(mu)
+                    .dt().bits(dt).du().bits(du)
+                    .wdu().bits(date.weekday)
+            })
+        });
+    }
+
+    /// The current time
+    pub fn time(&self) -> Time {
+        // reading TR locks DR until it is read as well
+        let tr = self.rtc.rtc_tr.read();
+        self.rtc.rtc_dr.read();
+
+        Time {
+            hours: from_bcd(tr.ht().bits(), tr.hu().bits()),
+            minutes: from_bcd(tr.mnt().bits(), tr.mnu().bits()),
+            seconds: from_bcd(tr.st().bits(), tr.su().bits()),
+        }
+    }
+
+    /// The current date
+    pub fn date(&self) -> Date {
+        // TR has to be read before DR to unlock the shadow registers
+        self.rtc.rtc_tr.read();
+        let dr = self.rtc.rtc_dr.read();
+
+        Date {
+            year: 2000 + u16::from(from_bcd(dr.yt().bits(), dr.yu().bits())),
+            month: from_bcd(dr.mt().bit() as u8, dr.mu().bits()),
+            day: from_bcd(dr.dt().bits(), dr.du().bits()),
+            weekday: dr.wdu().bits(),
+        }
+    }
+
+    /// The raw sub second register, it counts down from PREDIV_S to 0 every second
+    pub fn subseconds(&self) -> u16 {
+        let ss = self.rtc.rtc_ssr.read().ss().bits();
+        // reading SSR locks TR and DR until DR is read
+        self.rtc.rtc_tr.read();
+        self.rtc.rtc_dr.read();
+        ss
+    }
+
+    /// The milliseconds that passed in the current second
+    pub fn subsecond_millis(&self) -> u32 {
+        let prediv_s = u32::from(self.rtc.rtc_prer.read().prediv_s().bits());
+        let ss = u32::from(self.subseconds()).min(prediv_s);
+        (prediv_s - ss) * 1000 / (prediv_s + 1)
+    }
+
+    /// Sets and enables an alarm
+    pub fn set_alarm(&mut self, alarm: Alarm, time: AlarmTime) {
+        let field = |value: Option<u8>| match value {
+            Some(value) => (false, to_bcd(value)),
+            None => (true, (0, 0)),
+        };
+        let (msk4, (dt, du)) = field(time.day);
+        let (msk3, (ht, hu)) = field(time.hours);
+        let (msk2, (mnt, mnu)) = field(time.minutes);
+        let (msk1, (st, su)) = field(time.seconds);
+
+        self.write_protected(|rtc| {
+            macro_rules! set_alarm {
+                ($alrxe:ident, $alrxwf:ident, $alrmxr:ident) => {{
+                    rtc.rtc_cr.modify(|_, w| w.$alrxe().clear_bit());
+                    while rtc.rtc_isr.read().$alrxwf().bit_is_clear() {}
+                    rtc.$alrmxr.write(|w| unsafe {
+                        w.msk4().bit(msk4).wdsel().clear_bit().dt().bits(dt).du().bits(du)
+                            .msk3().bit(msk3).ht().bits(ht).hu().bits(hu)
+                            .msk2().bit(msk2).mnt().bits(mnt).mnu().bits(mnu)
+                            .msk1().bit(msk1).st().bits(st).su().bits(su)
+                    });
+                    rtc.rtc_cr.modify(|_, w| w.$alrxe().set_bit());
+                }};
+            }
+
+            match alarm {
+                Alarm::A => set_alarm!(alrae, alrawf, rtc_alrmar),
+                Alarm::B => set_alarm!(alrbe, alrbwf, rtc_alrmbr),
+            }
+        });
+    }
+
+    /// Disables an alarm
+    pub fn disable_alarm(&mut self, alarm: Alarm) {
+        self.write_protected(|rtc| match alarm {
+            Alarm::A => rtc.rtc_cr.modify(|_, w| w.alrae().clear_bit()),
+            Alarm::B => rtc.rtc_cr.modify(|_, w| w.alrbe().clear_bit()),
+        });
+    }
+
+    /// Whether an alarm fired since its flag was cleared
+    pub fn is_alarm(&self, alarm: Alarm) -> bool {
+        let isr = self.rtc.rtc_isr.read();
+        match alarm {
+            Alarm::A => isr.alraf().bit_is_set(),
+            Alarm::B => isr.alrbf().bit_is_set(),
+        }
+    }
+
+    /// Clears the flag of an alarm
+    pub fn clear_alarm(&mut self, alarm: Alarm) {
+        self.write_protected(|rtc| match alarm {
+            Alarm::A => rtc.rtc_isr.modify(|_, w| w.alraf().clear_bit()),
+            Alarm::B => rtc.rtc_isr.modify(|_, w| w.alrbf().clear_bit()),
+        });
+    }
+
+    /// Starts the wakeup timer, it raises `Event::Wakeup` every `interval`
+    ///
+    /// The clock of the timer is picked automatically, intervals of up to 65536 periods
+    /// of RTCCLK / 16 run from a division of RTCCLK with the finest resolution, longer ones
+    /// from the 1 Hz calendar clock with a resolution of a second
+    ///
+    /// # Panics
+    ///
+    /// Panics if the interval is shorter than two periods of RTCCLK or longer than 131072 s
+    pub fn enable_wakeup(&mut self, interval: Duration) {
+        let (wucksel, wut) = wakeup_config(self.rtc_ck, interval);
+
+        self.write_protected(|rtc| {
+            rtc.rtc_cr.modify(|_, w| w.wute().clear_bit());
+            while rtc.rtc_isr.read().wutwf().bit_is_clear() {}
+            rtc.rtc_wutr.write(|w| unsafe { w.wut().bits(wut) });
+            rtc.rtc_cr.modify(|_, w| unsafe { w.wucksel().bits(wucksel).wute().set_bit() });
+        });
+    }
+
+    /// Stops the wakeup timer
+    pub fn disable_wakeup(&mut self) {
+        self.write_protected(|rtc| rtc.rtc_cr.modify(|_, w| w.wute().clear_bit()));
+    }
+
+    /// Starts listening for an `event`, the interrupt is raised through its EXTI line
+    pub fn listen(&mut self, exti: &mut EXTI, event: Event) {
+        exti::trigger_on_edge(exti, event.exti_line(), exti::Edge::Rising);
+        exti::enable_interrupt(exti, event.exti_line());
+
+        self.write_protected(|rtc| match event {
+            Event::AlarmA => rtc.rtc_cr.modify(|_, w| w.alraie().set_bit()),
+            Event::AlarmB => rtc.rtc_cr.modify(|_, w| w.alrbie().set_bit()),
+            Event::Wakeup => rtc.rtc_cr.modify(|_, w| w.wutie().set_bit()),
+        });
+    }
+
+    /// Stops listening for an `event`
+    pub fn unlisten(&mut self, exti: &mut EXTI, event: Event) {
+        self.write_protected(|rtc| match event {
+            Event::AlarmA => rtc.rtc_cr.modify(|_, w| w.alraie().clear_bit()),
+            Event::AlarmB => rtc.rtc_cr.modify(|_, w| w.alrbie().clear_bit()),
+            Event::Wakeup => rtc.rtc_cr.modify(|_, w| w.wutie().clear_bit()),
+        });
+
+        // the alarms share their EXTI line
+        let alarms = self.rtc.rtc_cr.read();
+        if event == Event::Wakeup || !(alarms.alraie().bit_is_set() || alarms.alrbie().bit_is_set()) {
+            exti::disable_interrupt(exti, event.exti_line());
+        }
+    }
+
+    /// Clears the flag of an `event` and the pending bit of its EXTI line, this has to be
+    /// done in the interrupt handler
+    pub fn clear_interrupt(&mut self, event: Event) {
+        match event {
+            Event::AlarmA => self.clear_alarm(Alarm::A),
+            Event::AlarmB => self.clear_alarm(Alarm::B),
+            Event::Wakeup => {
+                self.write_protected(|rtc| rtc.rtc_isr.modify(|_, w| w.wutf().clear_bit()))
+            }
+        }
+        exti::clear_interrupt_pending_bit(event.exti_line());
+    }
+
+    /// Reads the backup register `n`, the 32 backup registers keep their content in
+    /// Standby and on VBAT
+    ///
+    /// # Panics
+    ///
+    /// Panics if `n` is not below 32
+    pub fn read_backup_register(&self, n: usize) -> u32 {
+        assert!(n < 32, "the RTC has 32 backup registers");
+        // NOTE(unsafe) the backup registers are consecutive words following RTC_BKP0R
+        unsafe { ptr::read_volatile((&self.rtc.rtc_bkp0r as *const _ as *const u32).add(n)) }
+    }
+
+    /// Writes `value` to the backup register `n`
+    ///
+    /// # Panics
+    ///
+    /// Panics if `n` is not below 32
+    pub fn write_backup_register(&mut self, n: usize, value: u32) {
+        assert!(n < 32, "the RTC has 32 backup registers");
+        // NOTE(unsafe) the backup registers are consecutive words following RTC_BKP0R
+        unsafe {
+            ptr::write_volatile((&self.rtc.rtc_bkp0r as *const _ as *mut u32).add(n), value)
+        };
+    }
+
+    /// Releases the RTC, the calendar keeps running
+    pub fn free(self) -> RTC {
+        self.rtc
+    }
+
+    /// Runs `f` with the write protection of the registers disabled
+    fn write_protected<F>(&mut self, f: F)
+    where
+        F: FnOnce(&RTC),
+    {
+        self.rtc.rtc_wpr.write(|w| unsafe { w.key().bits(0xCA) });
+        self.rtc.rtc_wpr.write(|w| unsafe { w.key().bits(0x53) });
+        f(&self.rtc);
+        // any wrong key enables the protection again
+        self.rtc.rtc_wpr.write(|w| unsafe { w.key().bits(0xFF) });
+    }
+
+    /// Runs `f` with the calendar stopped in the initialization mode
+    fn init_mode<F>(&mut self, f: F)
+    where
+        F: FnOnce(&RTC),
+    {
+        self.write_protected(|rtc| {
+            rtc.rtc_isr.modify(|_, w| w.init().set_bit());
+            while rtc.rtc_isr.read().initf().bit_is_clear() {}
+            f(rtc);
+            rtc.rtc_isr.modify(|_, w| w.init().clear_bit());
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn short_wakeup_intervals_use_the_divided_rtcclk() {
+        // 32768 Hz / 2 ticks 16384 times a second
+        assert_eq!(wakeup_config(32_768, Duration::from_secs(1)), (0b011, 16_383));
+        // 32768 Hz / 16 ticks 2048 times a second
+        assert_eq!(wakeup_config(32_768, Duration::from_secs(32)), (0b000, 0xFFFF));
+    }
+
+    #[test]
+    fn long_wakeup_intervals_use_ck_spre() {
+        assert_eq!(wakeup_config(32_768, Duration::from_secs(33)), (0b100, 32));
+        assert_eq!(wakeup_config(32_768, Duration::from_secs(0x1_0000)), (0b100, 0xFFFF));
+        assert_eq!(wakeup_config(32_768, Duration::from_secs(0x1_0001)), (0b110, 0));
+        assert_eq!(wakeup_config(32_768, Duration::from_secs(0x2_0000)), (0b110, 0xFFFF));
+    }
+
+    #[test]
+    #[should_panic]
+    fn too_long_wakeup_interval_is_rejected() {
+        wakeup_config(32_768, Duration::from_secs(0x2_0001));
+    }
+
+    #[test]
+    #[should_panic]
+    fn zero_wakeup_interval_is_rejected() {
+        wakeup_config(32_768, Duration::from_secs(0));
+    }
+}
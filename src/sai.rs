@@ -0,0 +1,563 @@
+//! Serial Audio Interface
+//!
+//! A block of SAI1 or SAI2 runs as master transmitter or master receiver with the I2S or
+//! the PCM (short frame sync) protocol. The master clock runs at 256 times the sample rate
+//! and is divided down from the SAI kernel clock, the bit clock is derived from the master
+//! clock and the length of the frame. A kernel clock of a multiple of 256 times the sample
+//! rate, e.g. 49.152 MHz for 48 kHz or 45.1584 MHz for 44.1 kHz generated with the
+//! fractional mode of the pll2 or the pll3, gives an exact sample rate
+//!
+//! The SAI is owned by the driver, so only one of its blocks can be used at a time
+
+use core::marker::PhantomData;
+
+use crate::dma::dmamux::{DmaRequest, RequestId};
+use crate::dma::{Config as DmaConfig, PeripheralAddress, Stream, Transfer};
+use crate::gpio::gpioa::{PA0, PA1, PA12, PA2};
+use crate::gpio::gpiod::{PD11, PD12, PD13};
+use crate::gpio::gpioe::{PE0, PE2, PE3, PE4, PE5, PE6};
+use crate::gpio::gpiof::{PF6, PF7, PF8, PF9};
+use crate::gpio::{Output, PushPull, AF10, AF6, AF8};
+use crate::rcc::{Clocks, Enable, Reset, APB2};
+use crate::stm32h7x3::{SAI1, SAI2};
+use crate::time::Hertz;
+
+/// The ratio of the master clock to the sample rate
+const MCLK_RATIO: u32 = 256;
+/// The depth of the FIFO of a block in words
+const FIFO_DEPTH: usize = 8;
+
+/// SAI error
+#[derive(Debug)]
+#[non_exhaustive]
+pub enum Error {
+    /// The kernel clock can not be divided down to the sample rate
+    SampleRate,
+    /// The receiver FIFO was full and data was lost
+    Overrun,
+    /// The transmitter FIFO was empty and a slot was sent without data
+    Underrun,
+}
+
+/// Block A of an SAI
+pub struct BlockA;
+
+/// Block B of an SAI
+pub struct BlockB;
+
+/// MCLK pin - DO NOT IMPLEMENT THIS TRAIT
+///
+/// # Safety
+///
+/// Only the pins whose alternate function connects them to MCLK of `BLOCK` of `SAI` may
+/// implement this trait
+pub unsafe trait MclkPin<SAI, BLOCK> {}
+
+/// SCK pin - DO NOT IMPLEMENT THIS TRAIT
+///
+/// # Safety
+///
+/// Only the pins whose alternate function connects them to SCK of `BLOCK` of `SAI` may
+/// implement this trait
+pub unsafe trait SckPin<SAI, BLOCK> {}
+
+/// FS pin - DO NOT IMPLEMENT THIS TRAIT
+///
+/// # Safety
+///
+/// Only the pins whose alternate function connects them to FS of `BLOCK` of `SAI` may
+/// implement this trait
+pub unsafe trait FsPin<SAI, BLOCK> {}
+
+/// SD pin - DO NOT IMPLEMENT THIS TRAIT
+///
+/// # Safety
+///
+/// Only the pins whose alternate function connects them to SD of `BLOCK` of `SAI` may
+/// implement this trait
+pub unsafe trait SdPin<SAI, BLOCK> {}
+
+/// The pins of a block, with or without the master clock output - DO NOT IMPLEMENT THIS
+/// TRAIT
+///
+/// # Safety
+///
+/// Only tuples of the pins of `BLOCK` of `SAI` may implement this trait
+pub unsafe trait Pins<SAI, BLOCK> {}
+
+unsafe impl<SAI, BLOCK, SCK, FS, SD> Pins<SAI, BLOCK> for (SCK, FS, SD)
+where
+    SCK: SckPin<SAI, BLOCK>,
+    FS: FsPin<SAI, BLOCK>,
+    SD: SdPin<SAI, BLOCK>,
+{
+}
+
+unsafe impl<SAI, BLOCK, MCLK, SCK, FS, SD> Pins<SAI, BLOCK> for (MCLK, SCK, FS, SD)
+where
+    MCLK: MclkPin<SAI, BLOCK>,
+    SCK: SckPin<SAI, BLOCK>,
+    FS: FsPin<SAI, BLOCK>,
+    SD: SdPin<SAI, BLOCK>,
+{
+}
+
+// SAI1 block A
+unsafe impl MclkPin<SAI1, BlockA> for PE2<Output<PushPull>, AF6> {}
+unsafe impl FsPin<SAI1, BlockA> for PE4<Output<PushPull>, AF6> {}
+unsafe impl SckPin<SAI1, BlockA> for PE5<Output<PushPull>, AF6> {}
+unsafe impl SdPin<SAI1, BlockA> for PE6<Output<PushPull>, AF6> {}
+
+// SAI1 block B
+unsafe impl SdPin<SAI1, BlockB> for PE3<Output<PushPull>, AF6> {}
+unsafe impl SdPin<SAI1, BlockB> for PF6<Output<PushPull>, AF6> {}
+unsafe impl MclkPin<SAI1, BlockB> for PF7<Output<PushPull>, AF6> {}
+unsafe impl SckPin<SAI1, BlockB> for PF8<Output<PushPull>, AF6> {}
+unsafe impl FsPin<SAI1, BlockB> for PF9<Output<PushPull>, AF6> {}
+
+// SAI2 block A
+unsafe impl SdPin<SAI2, BlockA> for PD11<Output<PushPull>, AF10> {}
+unsafe impl FsPin<SAI2, BlockA> for PD12<Output<PushPull>, AF10> {}
+unsafe impl SckPin<SAI2, BlockA> for PD13<Output<PushPull>, AF10> {}
+unsafe impl MclkPin<SAI2, BlockA> for PE0<Output<PushPull>, AF10> {}
+
+// SAI2 block B
+unsafe impl SdPin<SAI2, BlockB> for PA0<Output<PushPull>, AF10> {}
+unsafe impl MclkPin<SAI2, BlockB> for PA1<Output<PushPull>, AF10> {}
+unsafe impl SckPin<SAI2, BlockB> for PA2<Output<PushPull>, AF8> {}
+unsafe impl FsPin<SAI2, BlockB> for PA12<Output<PushPull>, AF8> {}
+
+/// The direction of the audio data
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Direction {
+    /// Master transmitter
+    Transmit = 0b00,
+    /// Master receiver
+    Receive = 0b01,
+}
+
+/// The protocol of the frames
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Protocol {
+    /// I2S with a left and a right slot, the frame sync selects the slot
+    I2s,
+    /// PCM with 1, 2, 4 or 8 slots and a one bit frame sync before the first slot
+    Pcm {
+        /// The number of slots
+        slots: u8,
+    },
+}
+
+/// The size of the audio samples
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum DataSize {
+    /// 16 bit samples in 16 bit slots
+    Bits16,
+    /// 24 bit samples in 32 bit slots
+    Bits24,
+    /// 32 bit samples in 32 bit slots
+    Bits32,
+}
+
+impl DataSize {
+    /// The DS field of CR1
+    fn ds(self) -> u8 {
+        match self {
+            DataSize::Bits16 => 0b100,
+            DataSize::Bits24 => 0b110,
+            DataSize::Bits32 => 0b111,
+        }
+    }
+
+    /// The SLOTSZ field of SLOTR, matching `slot_bits`
+    fn slotsz(self) -> u8 {
+        match self {
+            DataSize::Bits16 => 0b01,
+            DataSize::Bits24 | DataSize::Bits32 => 0b10,
+        }
+    }
+
+    /// The number of bits of a slot
+    fn slot_bits(self) -> u32 {
+        match self {
+            DataSize::Bits16 => 16,
+            DataSize::Bits24 | DataSize::Bits32 => 32,
+        }
+    }
+}
+
+/// Configuration of a block
+///
+/// `new` creates the configuration of a 16 bit I2S transmitter
+#[derive(Clone, Copy)]
+pub struct Config {
+    sample_rate: u32,
+    direction: Direction,
+    protocol: Protocol,
+    data_size: DataSize,
+}
+
+impl Config {
+    /// Creates the configuration of a transmitter running at `sample_rate`
+    pub fn new<F>(sample_rate: F) -> Self
+    where
+        F: Into<Hertz>,
+    {
+        Config {
+            sample_rate: sample_rate.into().0,
+            direction: Direction::Transmit,
+            protocol: Protocol::I2s,
+            data_size: DataSize::Bits16,
+        }
+    }
+
+    /// Sets the direction of the audio data
+    pub fn direction(mut self, direction: Direction) -> Self {
+        self.direction = direction;
+        self
+    }
+
+    /// Sets the protocol of the frames
+    ///
+    /// # Panics
+    ///
+    /// Panics if a PCM frame has another number of slots than 1, 2, 4 or 8
+    pub fn protocol(mut self, protocol: Protocol) -> Self {
+        if let Protocol::Pcm { slots } = protocol {
+            assert!(
+                slots.is_power_of_two() && slots <= 8,
+                "a PCM frame has 1, 2, 4 or 8 slots"
+            );
+        }
+        self.protocol = protocol;
+        self
+    }
+
+    /// Sets the size of the audio samples
+    pub fn data_size(mut self, data_size: DataSize) -> Self {
+        self.data_size = data_size;
+        self
+    }
+
+    /// The number of slots of a frame
+    fn slots(&self) -> u32 {
+        match self.protocol {
+            Protocol::I2s => 2,
+            Protocol::Pcm { slots } => u32::from(slots),
+        }
+    }
+}
+
+/// Computes MCKDIV for a master clock of 256 times `sample_rate`
+///
+/// The sample rate may deviate by less than 1 Hz, which covers kernel clocks generated with
+/// the fractional mode of a pll, whose frequency is truncated to whole Hz
+fn mckdiv(ker_ck: u32, sample_rate: u32) -> Result<u32, Error> {
+    let mclk = MCLK_RATIO * sample_rate;
+    if sample_rate == 0 || mclk > ker_ck {
+        return Err(Error::SampleRate);
+    }
+
+    let div = (ker_ck + mclk / 2) / mclk;
+    // |ker_ck / (256 * div) - sample_rate| < 1
+    let deviation = (i64::from(ker_ck) - i64::from(mclk) * i64::from(div)).abs();
+    if div > 63 || deviation >= i64::from(MCLK_RATIO * div) {
+        return Err(Error::SampleRate);
+    }
+    Ok(div)
+}
+
+/// A block of an SAI along with its pins
+pub struct Sai<SAI, BLOCK, PINS> {
+    sai: SAI,
+    pins: PINS,
+    config: Config,
+    sample_rate: u32,
+    _block: PhantomData<BLOCK>,
+}
+
+macro_rules! hal {
+    ($($SAIX:ident: ($sai_ck:ident, [$(
+        $BLOCK:ident: ($saiXy:ident, $REQUEST:ident, $crx1:ident, $crx2:ident, $frcrx:ident,
+            $slotrx:ident, $srx:ident, $clrfrx:ident, $drx:ident),
+    )+]),)+) => {
+        $($(
+            impl<PINS> Sai<$SAIX, $BLOCK, PINS>
+            where
+                PINS: Pins<$SAIX, $BLOCK>,
+            {
+                /// Enables the SAI and configures the block as master, the receiver starts
+                /// right away, the transmitter once the first frame was written
+                ///
+                /// The master clock is divided down from the kernel clock selected with
+                /// `CFGR::sai1_src` or `CFGR::sai23_src`, the bit clock runs at the sample
+                /// rate times the bits of a frame
+                ///
+                /// # Panics
+                ///
+                /// Panics if the kernel clock is disabled
+                pub fn $saiXy(
+                    sai: $SAIX,
+                    pins: PINS,
+                    config: Config,
+                    apb2: &mut APB2,
+                    clocks: &Clocks,
+                ) -> Result<Self, Error> {
+                    let ker_ck = clocks.$sai_ck().expect("the kernel clock of the SAI is disabled");
+                    let div = mckdiv(ker_ck.0, config.sample_rate)?;
+
+                    $SAIX::enable(apb2);
+                    $SAIX::reset(apb2);
+
+                    let slot_bits = config.data_size.slot_bits();
+                    let slots = config.slots();
+                    let frame_bits = slot_bits * slots;
+                    let (fsall, fsdef, fspol) = match config.protocol {
+                        // the frame sync is low during the left and high during the right slot
+                        Protocol::I2s => (frame_bits / 2 - 1, true, false),
+                        Protocol::Pcm { .. } => (0, false, true),
+                    };
+
+                    // NOTE(unsafe) FSDEF is read-only in the SVD
+                    sai.$frcrx.write(|w| unsafe {
+                        w.bits((fsdef as u32) << 16)
+                            .frl().bits((frame_bits - 1) as u8)
+                            .fsall().bits(fsall as u8)
+                            .fspol().bit(fspol)
+                            .fsoff().set_bit()
+                    });
+                    sai.$slotrx.write(|w| unsafe {
+                        w.fboff().bits(0)
+                            .slotsz().bits(config.data_size.slotsz())
+                            .nbslot().bits((slots - 1) as u8)
+                            .sloten().bits(((1u32 << slots) - 1) as u16)
+                    });
+                    // a quarter full FIFO requests the DMA
+                    sai.$crx2.write(|w| unsafe { w.fth().bits(0b001).fflush().set_bit() });
+                    // NOTE(unsafe) MCKDIV is six bits wide, the SVD only declares four
+                    // the data changes on the falling and is sampled on the rising edge of SCK
+                    sai.$crx1.write(|w| unsafe {
+                        w.bits(div << 20)
+                            .mode().bits(config.direction as u8)
+                            .prtcfg().bits(0b00)
+                            .ds().bits(config.data_size.ds())
+                            .ckstr().set_bit()
+                            .syncen().bits(0b00)
+                            .nomck().clear_bit()
+                            .osr().clear_bit()
+                    });
+
+                    if config.direction == Direction::Receive {
+                        sai.$crx1.modify(|_, w| w.saixen().set_bit());
+                    }
+
+                    Ok(Sai {
+                        sai,
+                        pins,
+                        config,
+                        sample_rate: ker_ck.0 / (MCLK_RATIO * div),
+                        _block: PhantomData,
+                    })
+                }
+
+                /// The sample rate the block runs at
+                pub fn sample_rate(&self) -> Hertz {
+                    Hertz(self.sample_rate)
+                }
+
+                /// Writes the slots of one frame to the FIFO, e.g. `[left, right]` for I2S
+                ///
+                /// The samples are right aligned in the words
+                ///
+                /// # Panics
+                ///
+                /// Panics if `frame` doesn't hold one word per slot or if the block is a
+                /// receiver
+                pub fn send(&mut self, frame: &[u32]) -> nb::Result<(), Error> {
+                    assert!(self.config.direction == Direction::Transmit, "the block is a receiver");
+                    assert!(frame.len() == self.config.slots() as usize, "a frame holds one word per slot");
+
+                    let sr = self.sai.$srx.read();
+                    if sr.ovrudr().bit_is_set() {
+                        self.sai.$clrfrx.write(|w| w.covrudr().set_bit());
+                        return Err(nb::Error::Other(Error::Underrun));
+                    }
+                    // the free words guaranteed by the FIFO level
+                    let free = match sr.flvl().bits() {
+                        0b000 => FIFO_DEPTH,
+                        0b001 => FIFO_DEPTH * 3 / 4,
+                        0b010 => FIFO_DEPTH / 2,
+                        0b011 => FIFO_DEPTH / 4,
+                        _ => 0,
+                    };
+                    if free < frame.len() {
+                        return Err(nb::Error::WouldBlock);
+                    }
+
+                    for &word in frame {
+                        // NOTE(unsafe) any word is a valid sample
+                        self.sai.$drx.write(|w| unsafe { w.data().bits(word) });
+                    }
+                    if self.sai.$crx1.read().saixen().bit_is_clear() {
+                        self.sai.$crx1.modify(|_, w| w.saixen().set_bit());
+                    }
+                    Ok(())
+                }
+
+                /// Reads the slots of one frame from the FIFO, e.g. `[left, right]` for I2S
+                ///
+                /// The samples are right aligned in the words. After an overrun the frames
+                /// may start with another slot
+                ///
+                /// # Panics
+                ///
+                /// Panics if `frame` doesn't hold one word per slot or if the block is a
+                /// transmitter
+                pub fn receive(&mut self, frame: &mut [u32]) -> nb::Result<(), Error> {
+                    assert!(self.config.direction == Direction::Receive, "the block is a transmitter");
+                    assert!(frame.len() == self.config.slots() as usize, "a frame holds one word per slot");
+
+                    let sr = self.sai.$srx.read();
+                    if sr.ovrudr().bit_is_set() {
+                        self.sai.$clrfrx.write(|w| w.covrudr().set_bit());
+                        return Err(nb::Error::Other(Error::Overrun));
+                    }
+                    // the words guaranteed to be available by the FIFO level
+                    let available = match sr.flvl().bits() {
+                        0b001 => 1,
+                        0b010 => FIFO_DEPTH / 4 + 1,
+                        0b011 => FIFO_DEPTH / 2 + 1,
+                        0b100 => FIFO_DEPTH * 3 / 4 + 1,
+                        0b101 => FIFO_DEPTH,
+                        _ => 0,
+                    };
+                    if available < frame.len() {
+                        return Err(nb::Error::WouldBlock);
+                    }
+
+                    for word in frame {
+                        *word = self.sai.$drx.read().data().bits();
+                    }
+                    Ok(())
+                }
+
+                /// Streams `buffer` to the transmitter with a DMA stream, the block is
+                /// returned by `free` of the transfer
+                ///
+                /// A circular `config` repeats the buffer until the transfer is freed, the half
+                /// transfer interrupt tells when the first half can be refilled
+                ///
+                /// # Panics
+                ///
+                /// Panics if the block is a receiver
+                pub fn send_dma<S: Stream>(
+                    self,
+                    stream: S,
+                    buffer: &'static [u32],
+                    config: DmaConfig,
+                ) -> Transfer<S, Self, &'static [u32]> {
+                    assert!(self.config.direction == Direction::Transmit, "the block is a receiver");
+
+                    let transfer = Transfer::memory_to_peripheral(stream, self, buffer, config);
+
+                    // NOTE(unsafe) the SAI is owned by the transfer, the FIFO gets filled by
+                    // the DMA before the frames start
+                    let sai = unsafe { &*$SAIX::ptr() };
+                    sai.$crx1.modify(|_, w| w.dmaen().set_bit());
+                    sai.$crx1.modify(|_, w| w.saixen().set_bit());
+
+                    transfer
+                }
+
+                /// Streams the received frames into the two buffers with a DMA stream, one
+                /// buffer can be processed while the other one is filled
+                ///
+                /// # Panics
+                ///
+                /// Panics if the block is a transmitter or if the buffers differ in length
+                pub fn receive_dma<S: Stream>(
+                    self,
+                    stream: S,
+                    buffers: [&'static mut [u32]; 2],
+                    config: DmaConfig,
+                ) -> Transfer<S, Self, [&'static mut [u32]; 2]> {
+                    assert!(self.config.direction == Direction::Receive, "the block is a transmitter");
+
+                    let transfer =
+                        Transfer::peripheral_to_memory_double_buffer(stream, self, buffers, config);
+
+                    // NOTE(unsafe) the SAI is owned by the transfer
+                    let sai = unsafe { &*$SAIX::ptr() };
+                    sai.$crx1.modify(|_, w| w.dmaen().set_bit());
+
+                    transfer
+                }
+
+                /// Disables the block and its DMA requests and releases the SAI and the pins
+                pub fn free(self) -> ($SAIX, PINS) {
+                    self.sai.$crx1.modify(|_, w| w.saixen().clear_bit().dmaen().clear_bit());
+                    while self.sai.$crx1.read().saixen().bit_is_set() {}
+                    (self.sai, self.pins)
+                }
+            }
+
+            unsafe impl<PINS> PeripheralAddress for Sai<$SAIX, $BLOCK, PINS> {
+                type Word = u32;
+
+                fn address(&self) -> u32 {
+                    &self.sai.$drx as *const _ as u32
+                }
+            }
+
+            unsafe impl<PINS> DmaRequest for Sai<$SAIX, $BLOCK, PINS> {
+                const REQUEST: RequestId = RequestId::$REQUEST;
+            }
+        )+)+
+    };
+}
+
+hal!(
+    SAI1: (sai1_ck, [
+        BlockA: (sai1a, SAI1_A, sai_acr1, sai_acr2, sai_afrcr, sai_aslotr, sai_asr, sai_aclrfr, sai_adr),
+        BlockB: (sai1b, SAI1_B, sai_bcr1, sai_bcr2, sai_bfrcr, sai_bslotr, sai_bsr, sai_bclrfr, sai_bdr),
+    ]),
+    SAI2: (sai23_ck, [
+        BlockA: (sai2a, SAI2_A, sai_acr1, sai_acr2, sai_afrcr, sai_aslotr, sai_asr, sai_aclrfr, sai_adr),
+        BlockB: (sai2b, SAI2_B, sai_bcr1, sai_bcr2, sai_bfrcr, sai_bslotr, sai_bsr, sai_bclrfr, sai_bdr),
+    ]),
+);
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn mckdiv_divides_exact_kernel_clocks() {
+        assert_eq!(mckdiv(49_152_000, 48_000).ok(), Some(4));
+        assert_eq!(mckdiv(45_158_400, 44_100).ok(), Some(4));
+    }
+
+    #[test]
+    fn mckdiv_tolerates_less_than_1_hz() {
+        // a fractional pll truncated to whole Hz
+        assert_eq!(mckdiv(49_151_999, 48_000).ok(), Some(4));
+    }
+
+    #[test]
+    fn mckdiv_rejects_inexact_kernel_clocks() {
+        // 50 MHz / (256 * 4) = 48.828 kHz
+        assert!(mckdiv(50_000_000, 48_000).is_err());
+    }
+
+    #[test]
+    fn mckdiv_rejects_dividers_above_63() {
+        // 256 * 8 kHz * 64
+        assert!(mckdiv(131_072_000, 8_000).is_err());
+        assert_eq!(mckdiv(129_024_000, 8_000).ok(), Some(63));
+    }
+
+    #[test]
+    fn mckdiv_rejects_0_hz() {
+        assert!(mckdiv(49_152_000, 0).is_err());
+    }
+}
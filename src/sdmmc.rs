@@ -0,0 +1,617 @@
+//! Secure Digital / MultiMediaCard interface for SD cards
+//!
+//! The card is accessed in 512 byte blocks through the `BlockDevice` trait of
+//! `embedded-sdmmc`. Blocks in the AXI SRAM are transferred by the internal DMA (IDMA) of
+//! the SDMMC, the IDMA can't reach the DTCM, so other blocks are copied through the FIFO
+
+use core::cmp;
+use core::ops::Deref;
+use core::ptr;
+
+use cortex_m::asm;
+use embedded_sdmmc::{Block, BlockCount, BlockDevice, BlockIdx};
+
+use crate::gpio::gpioa::PA0;
+use crate::gpio::gpiob::{PB3, PB4, PB14, PB15};
+use crate::gpio::gpioc::{PC1, PC8, PC9, PC10, PC11, PC12};
+use crate::gpio::gpiod::{PD2, PD6, PD7};
+use crate::gpio::gpiog::{PG9, PG10, PG11, PG12};
+use crate::gpio::{Output, PushPull, AF10, AF11, AF12, AF9};
+use crate::rcc::{Clocks, Enable, Reset, AHB2, AHB3};
+use crate::stm32h7x3::{sdmmc1, SDMMC1, SDMMC2};
+use crate::time::Hertz;
+
+/// The highest SDMMC_CK frequency of the default speed mode
+const MAX_BUS_CK: u32 = 25_000_000;
+/// The SDMMC_CK frequency during the identification of the card
+const INIT_BUS_CK: u32 = 400_000;
+/// All static flags of the ICR register
+const ICR_ALL: u32 = 0x1FE0_0FFF;
+/// The region of the AXI SRAM, which is reachable by the IDMA
+const AXI_SRAM: (u32, u32) = (0x2400_0000, 0x2408_0000);
+/// The number of ACMD41 retries until the card has to be powered up
+const POWER_UP_RETRIES: u32 = 10_000;
+
+/// SDMMC error
+#[derive(Debug)]
+#[non_exhaustive]
+pub enum Error {
+    /// The card did not respond to a command
+    CommandTimeout,
+    /// The CRC of a command response failed
+    CommandCrc,
+    /// The card did not transfer the data in time
+    DataTimeout,
+    /// The CRC of a data block failed
+    DataCrc,
+    /// The FIFO overran on a read or underran on a write
+    Fifo,
+    /// The IDMA failed to access the buffer
+    Dma,
+    /// The card doesn't support the voltage range or didn't power up
+    UnsupportedCard,
+    /// No card was initialized
+    NoCard,
+}
+
+/// CK pin - DO NOT IMPLEMENT THIS TRAIT
+///
+/// # Safety
+///
+/// Only the pins whose alternate function connects them to CK of `SDMMC` may
+/// implement this trait
+pub unsafe trait CkPin<SDMMC> {}
+
+/// CMD pin - DO NOT IMPLEMENT THIS TRAIT
+///
+/// # Safety
+///
+/// Only the pins whose alternate function connects them to CMD of `SDMMC` may
+/// implement this trait
+pub unsafe trait CmdPin<SDMMC> {}
+
+/// D0 pin - DO NOT IMPLEMENT THIS TRAIT
+///
+/// # Safety
+///
+/// Only the pins whose alternate function connects them to D0 of `SDMMC` may
+/// implement this trait
+pub unsafe trait D0Pin<SDMMC> {}
+
+/// D1 pin - DO NOT IMPLEMENT THIS TRAIT
+///
+/// # Safety
+///
+/// Only the pins whose alternate function connects them to D1 of `SDMMC` may
+/// implement this trait
+pub unsafe trait D1Pin<SDMMC> {}
+
+/// D2 pin - DO NOT IMPLEMENT THIS TRAIT
+///
+/// # Safety
+///
+/// Only the pins whose alternate function connects them to D2 of `SDMMC` may
+/// implement this trait
+pub unsafe trait D2Pin<SDMMC> {}
+
+/// D3 pin - DO NOT IMPLEMENT THIS TRAIT
+///
+/// # Safety
+///
+/// Only the pins whose alternate function connects them to D3 of `SDMMC` may
+/// implement this trait
+pub unsafe trait D3Pin<SDMMC> {}
+
+// SDMMC1
+unsafe impl CkPin<SDMMC1> for PC12<Output<PushPull>, AF12> {}
+unsafe impl CmdPin<SDMMC1> for PD2<Output<PushPull>, AF12> {}
+unsafe impl D0Pin<SDMMC1> for PC8<Output<PushPull>, AF12> {}
+unsafe impl D1Pin<SDMMC1> for PC9<Output<PushPull>, AF12> {}
+unsafe impl D2Pin<SDMMC1> for PC10<Output<PushPull>, AF12> {}
+unsafe impl D3Pin<SDMMC1> for PC11<Output<PushPull>, AF12> {}
+
+// SDMMC2
+unsafe impl CkPin<SDMMC2> for PC1<Output<PushPull>, AF9> {}
+unsafe impl CkPin<SDMMC2> for PD6<Output<PushPull>, AF11> {}
+unsafe impl CmdPin<SDMMC2> for PA0<Output<PushPull>, AF9> {}
+unsafe impl CmdPin<SDMMC2> for PD7<Output<PushPull>, AF11> {}
+unsafe impl D0Pin<SDMMC2> for PB14<Output<PushPull>, AF9> {}
+unsafe impl D0Pin<SDMMC2> for PG9<Output<PushPull>, AF11> {}
+unsafe impl D1Pin<SDMMC2> for PB15<Output<PushPull>, AF9> {}
+unsafe impl D1Pin<SDMMC2> for PG10<Output<PushPull>, AF11> {}
+unsafe impl D2Pin<SDMMC2> for PB3<Output<PushPull>, AF9> {}
+unsafe impl D2Pin<SDMMC2> for PG11<Output<PushPull>, AF10> {}
+unsafe impl D3Pin<SDMMC2> for PB4<Output<PushPull>, AF9> {}
+unsafe impl D3Pin<SDMMC2> for PG12<Output<PushPull>, AF10> {}
+
+/// The pins of a SDMMC - DO NOT IMPLEMENT THIS TRAIT
+///
+/// Implemented for (CK, CMD, D0) for the 1 bit bus and (CK, CMD, D0, D1, D2, D3) for the
+/// 4 bit bus
+///
+/// # Safety
+///
+/// Only tuples of the pins of `SDMMC` may implement this trait, `WIDE` has to tell
+/// whether D1 to D3 are among them
+pub unsafe trait Pins<SDMMC> {
+    /// Whether the 4 bit bus is connected
+    const WIDE: bool;
+}
+
+unsafe impl<SDMMC, CK, CMD, D0> Pins<SDMMC> for (CK, CMD, D0)
+where
+    CK: CkPin<SDMMC>,
+    CMD: CmdPin<SDMMC>,
+    D0: D0Pin<SDMMC>,
+{
+    const WIDE: bool = false;
+}
+
+unsafe impl<SDMMC, CK, CMD, D0, D1, D2, D3> Pins<SDMMC> for (CK, CMD, D0, D1, D2, D3)
+where
+    CK: CkPin<SDMMC>,
+    CMD: CmdPin<SDMMC>,
+    D0: D0Pin<SDMMC>,
+    D1: D1Pin<SDMMC>,
+    D2: D2Pin<SDMMC>,
+    D3: D3Pin<SDMMC>,
+{
+    const WIDE: bool = true;
+}
+
+/// The response a command expects, the encoding of WAITRESP
+#[derive(Clone, Copy, PartialEq)]
+enum Response {
+    None = 0b00,
+    Short = 0b01,
+    ShortNoCrc = 0b10,
+    Long = 0b11,
+}
+
+/// The initialized card
+#[derive(Clone, Copy, Debug)]
+pub struct Card {
+    /// The relative card address
+    rca: u16,
+    /// Whether the card is a SDHC or SDXC card, which are addressed in blocks
+    high_capacity: bool,
+    /// The capacity in blocks
+    blocks: u32,
+}
+
+impl Card {
+    /// The capacity in 512 byte blocks
+    pub fn blocks(&self) -> u32 {
+        self.blocks
+    }
+
+    /// Whether the card is a SDHC or SDXC card
+    pub fn is_high_capacity(&self) -> bool {
+        self.high_capacity
+    }
+}
+
+/// SD card interface
+pub struct Sdmmc<SDMMC, PINS> {
+    sdmmc: SDMMC,
+    pins: PINS,
+    ker_ck: u32,
+    sys_ck: u32,
+    bus_ck: u32,
+    card: Option<Card>,
+}
+
+macro_rules! hal {
+    ($($SDMMCX:ident: ($sdmmcX:ident, $AHB:ident),)+) => {
+        $(
+            impl<PINS> Sdmmc<$SDMMCX, PINS>
+            where
+                PINS: Pins<$SDMMCX>,
+            {
+                /// Enables the SDMMC and powers the card, `init_card` identifies the card
+                ///
+                /// The SDMMC is clocked by the kernel clock selected with `CFGR::sdmmc_src`
+                ///
+                /// # Panics
+                ///
+                /// Panics if the kernel clock is disabled
+                pub fn $sdmmcX(sdmmc: $SDMMCX, pins: PINS, ahb: &mut $AHB, clocks: &Clocks) -> Self {
+                    let ker_ck = clocks.sdmmc_ck().expect("the kernel clock of the SDMMC is disabled");
+
+                    $SDMMCX::enable(ahb);
+                    $SDMMCX::reset(ahb);
+
+                    let mut sdmmc = Sdmmc {
+                        sdmmc,
+                        pins,
+                        ker_ck: ker_ck.0,
+                        sys_ck: clocks.sys_ck().0,
+                        bus_ck: 0,
+                        card: None,
+                    };
+                    sdmmc.power_up();
+                    sdmmc
+                }
+            }
+        )+
+    };
+}
+
+hal!(
+    SDMMC1: (sdmmc1, AHB3),
+    SDMMC2: (sdmmc2, AHB2),
+);
+
+impl<SDMMC, PINS> Sdmmc<SDMMC, PINS>
+where
+    SDMMC: Deref<Target = sdmmc1::RegisterBlock>,
+    PINS: Pins<SDMMC>,
+{
+    /// Identifies and selects the card, afterwards the bus is clocked with at most `freq`
+    /// but not faster than the 25 MHz of the default speed mode
+    ///
+    /// This has to be repeated after the card was replaced
+    pub fn init_card<F>(&mut self, freq: F) -> Result<Card, Error>
+    where
+        F: Into<Hertz>,
+    {
+        self.card = None;
+        self.set_bus(INIT_BUS_CK, false);
+        self.power_up();
+
+        // CMD0: GO_IDLE_STATE
+        self.cmd(0, 0, Response::None)?;
+
+        // CMD8: SEND_IF_COND, 2.7 V to 3.6 V and check pattern 0xAA, version 1 cards don't respond
+        let version2 = match self.cmd(8, 0x1AA, Response::Short) {
+            Ok(()) if self.sdmmc.sdmmc_resp1r.read().bits() & 0xFFF == 0x1AA => true,
+            Ok(()) => return Err(Error::UnsupportedCard),
+            Err(Error::CommandTimeout) => false,
+            Err(e) => return Err(e),
+        };
+
+        // ACMD41: SD_SEND_OP_COND until the card finished its power up
+        let hcs = if version2 { 1 << 30 } else { 0 };
+        let mut ocr = 0;
+        for _ in 0..POWER_UP_RETRIES {
+            self.cmd(55, 0, Response::Short)?;
+            // R3 has no valid CRC
+            self.cmd(41, 0x00FF_8000 | hcs, Response::ShortNoCrc)?;
+            ocr = self.sdmmc.sdmmc_resp1r.read().bits();
+            if ocr & (1 << 31) != 0 {
+                break;
+            }
+        }
+        if ocr & (1 << 31) == 0 {
+            return Err(Error::UnsupportedCard);
+        }
+        let high_capacity = ocr & (1 << 30) != 0;
+
+        // CMD2: ALL_SEND_CID, CMD3: SEND_RELATIVE_ADDR
+        self.cmd(2, 0, Response::Long)?;
+        self.cmd(3, 0, Response::Short)?;
+        let rca = (self.sdmmc.sdmmc_resp1r.read().bits() >> 16) as u16;
+
+        // CMD9: SEND_CSD
+        self.cmd(9, u32::from(rca) << 16, Response::Long)?;
+        let blocks = capacity(self.csd());
+
+        // CMD7: SELECT_CARD
+        self.cmd(7, u32::from(rca) << 16, Response::Short)?;
+        self.wait_not_busy();
+
+        if PINS::WIDE {
+            // ACMD6: SET_BUS_WIDTH to 4 bit
+            self.cmd(55, u32::from(rca) << 16, Response::Short)?;
+            self.cmd(6, 0b10, Response::Short)?;
+        }
+        if !high_capacity {
+            // CMD16: SET_BLOCKLEN
+            self.cmd(16, Block::LEN_U32, Response::Short)?;
+        }
+
+        self.set_bus(cmp::min(freq.into().0, MAX_BUS_CK), PINS::WIDE);
+
+        let card = Card {
+            rca,
+            high_capacity,
+            blocks,
+        };
+        self.card = Some(card);
+        Ok(card)
+    }
+
+    /// The initialized card
+    pub fn card(&self) -> Option<Card> {
+        self.card
+    }
+
+    /// Powers the card down and releases the SDMMC and the pins
+    pub fn free(self) -> (SDMMC, PINS) {
+        self.sdmmc.sdmmc_power.write(|w| unsafe { w.pwrctrl().bits(0b00) });
+        (self.sdmmc, self.pins)
+    }
+
+    /// Powers the card and waits the 74 cycles the card needs to power up
+    fn power_up(&mut self) {
+        if self.bus_ck == 0 {
+            self.set_bus(INIT_BUS_CK, false);
+        }
+        self.sdmmc.sdmmc_power.write(|w| unsafe { w.pwrctrl().bits(0b11) });
+        asm::delay(self.sys_ck / INIT_BUS_CK * 80);
+    }
+
+    /// Sets the frequency of SDMMC_CK to at most `freq` along with the bus width
+    fn set_bus(&mut self, freq: u32, wide: bool) {
+        // SDMMC_CK = ker_ck / (2 * CLKDIV), CLKDIV = 0 bypasses the divider
+        let clkdiv = if freq >= self.ker_ck {
+            0
+        } else {
+            cmp::min(self.ker_ck.div_ceil(2 * freq), 0x3FF)
+        };
+        self.bus_ck = if clkdiv == 0 { self.ker_ck } else { self.ker_ck / (2 * clkdiv) };
+
+        // the hardware flow control stops the clock instead of overrunning the FIFO
+        self.sdmmc.sdmmc_clkcr.write(|w| unsafe {
+            w.clkdiv()
+                .bits(clkdiv as u16)
+                .widbus()
+                .bits(if wide { 0b01 } else { 0b00 })
+                .hwfc_en()
+                .set_bit()
+        });
+    }
+
+    /// Sends a command and waits for its response
+    fn cmd(&self, index: u8, arg: u32, response: Response) -> Result<(), Error> {
+        self.send(index, arg, response, false, false);
+        self.wait_response(response)
+    }
+
+    fn send(&self, index: u8, arg: u32, response: Response, trans: bool, stop: bool) {
+        self.sdmmc.sdmmc_icr.write(|w| unsafe { w.bits(ICR_ALL) });
+        self.sdmmc.sdmmc_argr.write(|w| unsafe { w.cmdarg().bits(arg) });
+        self.sdmmc.sdmmc_cmdr.write(|w| unsafe {
+            w.cmdindex()
+                .bits(index)
+                .waitresp()
+                .bits(response as u8)
+                .cmdtrans()
+                .bit(trans)
+                .cmdstop()
+                .bit(stop)
+                .cpsmen()
+                .set_bit()
+        });
+    }
+
+    fn wait_response(&self, response: Response) -> Result<(), Error> {
+        loop {
+            let star = self.sdmmc.sdmmc_star.read();
+            if response == Response::None {
+                if star.cmdsent().bit_is_set() {
+                    return Ok(());
+                }
+            } else if star.ctimeout().bit_is_set() {
+                return Err(Error::CommandTimeout);
+            } else if star.ccrcfail().bit_is_set() {
+                return if response == Response::ShortNoCrc {
+                    Ok(())
+                } else {
+                    Err(Error::CommandCrc)
+                };
+            } else if star.cmdrend().bit_is_set() {
+                return Ok(());
+            }
+        }
+    }
+
+    /// Waits until the card releases D0 after a command with a busy response
+    fn wait_not_busy(&self) {
+        while self.sdmmc.sdmmc_star.read().busyd0().bit_is_set() {}
+    }
+
+    /// The CSD register from the long response of CMD9
+    fn csd(&self) -> u128 {
+        u128::from(self.sdmmc.sdmmc_resp1r.read().bits()) << 96
+            | u128::from(self.sdmmc.sdmmc_resp2r.read().bits()) << 64
+            | u128::from(self.sdmmc.sdmmc_resp3r.read().bits()) << 32
+            | u128::from(self.sdmmc.sdmmc_resp4r.read().bits())
+    }
+
+    /// Waits until the card finished programming and is back in the transfer state
+    fn wait_ready(&self, card: &Card) -> Result<(), Error> {
+        loop {
+            // CMD13: SEND_STATUS
+            self.cmd(13, u32::from(card.rca) << 16, Response::Short)?;
+            let status = self.sdmmc.sdmmc_resp1r.read().bits();
+            // READY_FOR_DATA and CURRENT_STATE = tran
+            if status & (1 << 8) != 0 && (status >> 9) & 0xF == 4 {
+                return Ok(());
+            }
+        }
+    }
+
+    /// Transfers `blocks` blocks starting at `buffer` with the data command `index`
+    fn transfer(
+        &self,
+        index: u8,
+        start: BlockIdx,
+        buffer: *mut u8,
+        blocks: usize,
+        read: bool,
+    ) -> Result<(), Error> {
+        let card = self.card.ok_or(Error::NoCard)?;
+        if blocks == 0 {
+            return Ok(());
+        }
+        let address = if card.high_capacity { start.0 } else { start.0 * Block::LEN_U32 };
+        let len = blocks * Block::LEN;
+
+        let idma = (buffer as u32).is_multiple_of(4)
+            && buffer as u32 >= AXI_SRAM.0
+            && buffer as u32 + len as u32 <= AXI_SRAM.1;
+
+        // a timeout of 250 ms
+        self.sdmmc.sdmmc_dtimer.write(|w| unsafe { w.datatime().bits(self.bus_ck / 4) });
+        self.sdmmc.sdmmc_dlenr.write(|w| unsafe { w.datalength().bits(len as u32) });
+        if idma {
+            self.sdmmc.sdmmc_idmabase0r.write(|w| unsafe { w.idmabase0().bits(buffer as u32) });
+            self.sdmmc.sdmmc_idmactrlr.write(|w| w.idmaen().set_bit());
+        }
+        // DBLOCKSIZE = 9: 512 byte blocks
+        self.sdmmc.sdmmc_dctrl.write(|w| unsafe { w.dblocksize().bits(9).dtdir().bit(read) });
+
+        self.send(index, address, Response::Short, true, false);
+        let result = self.wait_response(Response::Short).and_then(|_| {
+            if idma {
+                self.wait_data_end()
+            } else if read {
+                self.read_fifo(buffer, len)
+            } else {
+                self.write_fifo(buffer, len)
+            }
+        });
+
+        self.sdmmc.sdmmc_idmactrlr.write(|w| w.idmaen().clear_bit());
+
+        let failed = result.is_err();
+        if blocks > 1 || failed {
+            // CMD12: STOP_TRANSMISSION, CMDSTOP aborts the DPSM, so a failed single block
+            // transfer is stopped as well, the card may reject it if it left the data state
+            self.send(12, 0, Response::Short, false, true);
+            let stopped = self.wait_response(Response::Short);
+            self.wait_not_busy();
+            if !failed {
+                stopped?;
+            }
+        }
+        if failed {
+            // flush the data the aborted transfer left in the FIFO
+            self.sdmmc.sdmmc_dctrl.modify(|_, w| w.fiforst().set_bit());
+        }
+        result?;
+
+        if !read {
+            self.wait_ready(&card)?;
+        }
+        Ok(())
+    }
+
+    /// Checks the data error flags
+    fn data_error(&self) -> Result<(), Error> {
+        let star = self.sdmmc.sdmmc_star.read();
+        if star.dcrcfail().bit_is_set() {
+            Err(Error::DataCrc)
+        } else if star.dtimeout().bit_is_set() {
+            Err(Error::DataTimeout)
+        } else if star.rxoverr().bit_is_set() || star.txunderr().bit_is_set() {
+            Err(Error::Fifo)
+        } else if star.idmate().bit_is_set() {
+            Err(Error::Dma)
+        } else {
+            Ok(())
+        }
+    }
+
+    fn wait_data_end(&self) -> Result<(), Error> {
+        while self.sdmmc.sdmmc_star.read().dataend().bit_is_clear() {
+            self.data_error()?;
+        }
+        self.data_error()
+    }
+
+    /// Reads the data through the FIFO, a half full FIFO holds 8 words
+    fn read_fifo(&self, buffer: *mut u8, len: usize) -> Result<(), Error> {
+        let mut offset = 0;
+        while offset < len {
+            self.data_error()?;
+            if self.sdmmc.sdmmc_star.read().rxfifohf().bit_is_set() {
+                for _ in 0..8 {
+                    let word = self.sdmmc.sdmmc_fifor.read().bits();
+                    // NOTE(unsafe) the buffer holds `len` bytes, which is a multiple of 32
+                    unsafe {
+                        ptr::write_unaligned(buffer.add(offset) as *mut [u8; 4], word.to_le_bytes())
+                    };
+                    offset += 4;
+                }
+            }
+        }
+        self.wait_data_end()
+    }
+
+    /// Writes the data through the FIFO, a half empty FIFO takes 8 words
+    fn write_fifo(&self, buffer: *const u8, len: usize) -> Result<(), Error> {
+        let mut offset = 0;
+        while offset < len {
+            self.data_error()?;
+            if self.sdmmc.sdmmc_star.read().txfifohe().bit_is_set() {
+                for _ in 0..8 {
+                    // NOTE(unsafe) the buffer holds `len` bytes, which is a multiple of 32
+                    let word = unsafe { ptr::read_unaligned(buffer.add(offset) as *const [u8; 4]) };
+                    self.sdmmc
+                        .sdmmc_fifor
+                        .write(|w| unsafe { w.bits(u32::from_le_bytes(word)) });
+                    offset += 4;
+                }
+            }
+        }
+        self.wait_data_end()
+    }
+}
+
+impl<SDMMC, PINS> BlockDevice for Sdmmc<SDMMC, PINS>
+where
+    SDMMC: Deref<Target = sdmmc1::RegisterBlock>,
+    PINS: Pins<SDMMC>,
+{
+    type Error = Error;
+
+    fn read(&self, blocks: &mut [Block], start_block_idx: BlockIdx, _reason: &str) -> Result<(), Error> {
+        // CMD17: READ_SINGLE_BLOCK, CMD18: READ_MULTIPLE_BLOCK
+        let index = if blocks.len() > 1 { 18 } else { 17 };
+        self.transfer(index, start_block_idx, blocks.as_mut_ptr() as *mut u8, blocks.len(), true)
+    }
+
+    fn write(&self, blocks: &[Block], start_block_idx: BlockIdx) -> Result<(), Error> {
+        // CMD24: WRITE_BLOCK, CMD25: WRITE_MULTIPLE_BLOCK
+        let index = if blocks.len() > 1 { 25 } else { 24 };
+        self.transfer(index, start_block_idx, blocks.as_ptr() as *mut u8, blocks.len(), false)
+    }
+
+    fn num_blocks(&self) -> Result<BlockCount, Error> {
+        self.card.map(|card| BlockCount(card.blocks)).ok_or(Error::NoCard)
+    }
+}
+
+/// The capacity in 512 byte blocks described by a CSD register
+fn capacity(csd: u128) -> u32 {
+    let bits = |high: u32, low: u32| ((csd >> low) & ((1 << (high - low + 1)) - 1)) as u32;
+
+    match bits(127, 126) {
+        // CSD version 2: (C_SIZE + 1) * 512 KiB
+        1 => (bits(69, 48) + 1) * 1024,
+        // CSD version 1: (C_SIZE + 1) * 2 ^ (C_SIZE_MULT + 2) blocks of 2 ^ READ_BL_LEN bytes
+        _ => {
+            let blocks = (bits(73, 62) + 1) << (bits(49, 47) + 2);
+            blocks << bits(83, 80) >> 9
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn capacity_of_csd_version_1() {
+        // C_SIZE = 3892, C_SIZE_MULT = 6, READ_BL_LEN = 10: a 1 GB card
+        assert_eq!(capacity(0x005E_0032_5F5A_83CD_2DDB_7FBF_1680_0000), 1_993_216);
+    }
+
+    #[test]
+    fn capacity_of_csd_version_2() {
+        // C_SIZE = 15159: an 8 GB SDHC card
+        assert_eq!(capacity(0x400E_0032_5B59_0000_3B37_7F80_0A40_4000), 15_523_840);
+    }
+}
@@ -1,18 +1,22 @@
+use core::fmt;
 use core::marker::PhantomData;
 use core::ptr;
+use core::time::Duration;
 
+use cortex_m::interrupt;
 use hal::serial;
-use nb;
+use nb::{self, block};
 use void::Void;
-use crate::gpio::{Floating, Input, Output, PushPull, AF4, AF7};
-use crate::gpio::gpioa::{PA2, PA3, PA9, PA10};
-use crate::gpio::gpiob::{PB6, PB7, PB10, PB11, PB14, PB15};
+use crate::dma::{bdma, Config as DmaConfig, PeripheralAddress, Stream, Transfer};
+use crate::gpio::{Floating, Input, Output, PushPull, AF3, AF4, AF7, AF8};
+use crate::gpio::gpioa::{PA0, PA1, PA2, PA3, PA9, PA10, PA11, PA12};
+use crate::gpio::gpiob::{PB6, PB7, PB10, PB11, PB13, PB14, PB15};
 use crate::gpio::gpioc::{PC6, PC10, PC11};
-use crate::gpio::gpiod::{PD5, PD6, PD8, PD9};
-use crate::gpio::gpiog::{PG9, PG14};
-use crate::stm32h7x3::{USART1, USART2, USART3, USART6};
-use crate::time::Bps;
-use crate::rcc::{APB1L, APB2, Clocks};
+use crate::gpio::gpiod::{PD3, PD4, PD5, PD6, PD8, PD9, PD11, PD12};
+use crate::gpio::gpiog::{PG8, PG9, PG12, PG13, PG14, PG15};
+use crate::stm32h7x3::{EXTI, LPUART1, USART1, USART2, USART3, USART6};
+use crate::time::{Bps, U32Ext};
+use crate::rcc::{APB1L, APB2, APB4, Clocks, Enable, Reset};
 
 /// Interrupt event
 pub enum Event {
@@ -37,6 +41,114 @@ pub enum Error {
     _Extensible,
 }
 
+/// Number of bits of a frame, a parity bit is included in this number
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum WordLength {
+    /// 7 bits
+    DataBits7,
+    /// 8 bits
+    DataBits8,
+    /// 9 bits
+    DataBits9,
+}
+
+/// Parity bit
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Parity {
+    /// No parity bit
+    ParityNone,
+    /// Even parity
+    ParityEven,
+    /// Odd parity
+    ParityOdd,
+}
+
+/// Number of stop bits
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum StopBits {
+    /// 1 stop bit
+    Stop1,
+    /// 0.5 stop bits
+    Stop0p5,
+    /// 2 stop bits
+    Stop2,
+    /// 1.5 stop bits
+    Stop1p5,
+}
+
+/// Configuration of the frame format and the baud rate
+///
+/// The default is 115200 baud, 8 data bits, no parity and one stop bit, a `Bps` can be
+/// used as configuration with the other defaults
+#[derive(Clone, Copy)]
+pub struct Config {
+    baud_rate: Bps,
+    word_length: WordLength,
+    parity: Parity,
+    stop_bits: StopBits,
+}
+
+impl Config {
+    /// Sets the baud rate
+    pub fn baud_rate(mut self, baud_rate: Bps) -> Self {
+        self.baud_rate = baud_rate;
+        self
+    }
+
+    /// Sets the number of bits of a frame, including the parity bit
+    pub fn word_length(mut self, word_length: WordLength) -> Self {
+        self.word_length = word_length;
+        self
+    }
+
+    /// Sets the parity bit
+    pub fn parity(mut self, parity: Parity) -> Self {
+        self.parity = parity;
+        self
+    }
+
+    /// Sets the number of stop bits
+    pub fn stop_bits(mut self, stop_bits: StopBits) -> Self {
+        self.stop_bits = stop_bits;
+        self
+    }
+}
+
+impl Default for Config {
+    fn default() -> Config {
+        Config {
+            baud_rate: 115_200.bps(),
+            word_length: WordLength::DataBits8,
+            parity: Parity::ParityNone,
+            stop_bits: StopBits::Stop1,
+        }
+    }
+}
+
+impl From<Bps> for Config {
+    fn from(baud_rate: Bps) -> Config {
+        Config::default().baud_rate(baud_rate)
+    }
+}
+
+/// Computes BRR and whether to oversample by 8 for the kernel clock `ker_ck`
+///
+/// Oversampling by 16 is more tolerant to clock deviations and is used whenever the ratio
+/// allows it, oversampling by 8 doubles the highest possible baud rate
+fn brr(ker_ck: u32, baud_rate: u32) -> (u32, bool) {
+    // round to the nearest divider
+    let usartdiv = (ker_ck + baud_rate / 2) / baud_rate;
+    if usartdiv >= 16 {
+        assert!(usartdiv <= 0xFFFF, "impossible baud rate");
+        (usartdiv, false)
+    } else {
+        // USARTDIV = 2 * ker_ck / baud rate, BRR[2:0] = USARTDIV[3:0] >> 1
+        let usartdiv = (2 * ker_ck + baud_rate / 2) / baud_rate;
+        assert!(usartdiv >= 16, "impossible baud rate");
+        ((usartdiv & !0xF) | ((usartdiv & 0xF) >> 1), true)
+    }
+}
+
 /// TX pin - DO NOT IMPLEMENT THIS TRAIT
 pub unsafe trait TxPin<USART> {}
 
@@ -79,6 +191,86 @@ unsafe impl RxPin<USART3> for PC11<Input<Floating>, AF7> {}
 unsafe impl RxPin<USART6> for PC6<Input<Floating>, AF7> {}
 unsafe impl RxPin<USART6> for PG9<Input<Floating>, AF7> {}
 
+/// RTS pin of the hardware flow control - DO NOT IMPLEMENT THIS TRAIT
+///
+/// # Safety
+///
+/// Only the pins whose alternate function connects them to RTS of `USART` may
+/// implement this trait, `CONNECTED` is only false for `NoPin`
+pub unsafe trait RtsPin<USART> {
+    #[doc(hidden)]
+    const CONNECTED: bool = true;
+}
+
+/// CTS pin of the hardware flow control - DO NOT IMPLEMENT THIS TRAIT
+///
+/// # Safety
+///
+/// Only the pins whose alternate function connects them to CTS of `USART` may
+/// implement this trait, `CONNECTED` is only false for `NoPin`
+pub unsafe trait CtsPin<USART> {
+    #[doc(hidden)]
+    const CONNECTED: bool = true;
+}
+
+/// Placeholder for a flow control pin that is not used
+pub struct NoPin;
+
+unsafe impl<USART> RtsPin<USART> for NoPin {
+    const CONNECTED: bool = false;
+}
+
+unsafe impl<USART> CtsPin<USART> for NoPin {
+    const CONNECTED: bool = false;
+}
+
+// USART1 RTS / CTS
+unsafe impl RtsPin<USART1> for PA12<Output<PushPull>, AF7> {}
+unsafe impl CtsPin<USART1> for PA11<Input<Floating>, AF7> {}
+
+// USART2 RTS / CTS
+unsafe impl RtsPin<USART2> for PA1<Output<PushPull>, AF7> {}
+unsafe impl RtsPin<USART2> for PD4<Output<PushPull>, AF7> {}
+unsafe impl CtsPin<USART2> for PA0<Input<Floating>, AF7> {}
+unsafe impl CtsPin<USART2> for PD3<Input<Floating>, AF7> {}
+
+// USART3 RTS / CTS
+unsafe impl RtsPin<USART3> for PB14<Output<PushPull>, AF7> {}
+unsafe impl RtsPin<USART3> for PD12<Output<PushPull>, AF7> {}
+unsafe impl CtsPin<USART3> for PB13<Input<Floating>, AF7> {}
+unsafe impl CtsPin<USART3> for PD11<Input<Floating>, AF7> {}
+
+// USART6 RTS / CTS
+unsafe impl RtsPin<USART6> for PG8<Output<PushPull>, AF7> {}
+unsafe impl RtsPin<USART6> for PG12<Output<PushPull>, AF7> {}
+unsafe impl CtsPin<USART6> for PG13<Input<Floating>, AF7> {}
+unsafe impl CtsPin<USART6> for PG15<Input<Floating>, AF7> {}
+
+/// RS485 driver enable pin, the alternate function of the RTS pin - DO NOT IMPLEMENT THIS TRAIT
+///
+/// # Safety
+///
+/// Only the pins whose alternate function connects them to DE of `USART` may
+/// implement this trait
+pub unsafe trait DePin<USART> {}
+
+unsafe impl DePin<USART1> for PA12<Output<PushPull>, AF7> {}
+unsafe impl DePin<USART2> for PA1<Output<PushPull>, AF7> {}
+unsafe impl DePin<USART2> for PD4<Output<PushPull>, AF7> {}
+unsafe impl DePin<USART3> for PB14<Output<PushPull>, AF7> {}
+unsafe impl DePin<USART3> for PD12<Output<PushPull>, AF7> {}
+unsafe impl DePin<USART6> for PG8<Output<PushPull>, AF7> {}
+unsafe impl DePin<USART6> for PG12<Output<PushPull>, AF7> {}
+
+/// Converts a driver enable time into sample times (1/16 or 1/8 of a bit time depending
+/// on the oversampling), rounded up and limited to the 5 bits of DEAT and DEDT
+fn de_time(time: Duration, baud_rate: Bps, over8: bool) -> u8 {
+    let samples_per_sec = u64::from(baud_rate.0) * if over8 { 8 } else { 16 };
+    let ns = time.as_secs().saturating_mul(1_000_000_000) + u64::from(time.subsec_nanos());
+    let samples = ns.saturating_mul(samples_per_sec).saturating_add(999_999_999) / 1_000_000_000;
+    samples.min(31) as u8
+}
+
 /// Serial abstraction
 pub struct Serial<USART, PINS> {
     usart: USART,
@@ -86,6 +278,9 @@ pub struct Serial<USART, PINS> {
 }
 
 /// Serial receiver
+///
+/// The halves only access the registers of their side of the peripheral, they can be
+/// dropped independently without affecting the other half
 pub struct Rx<USART> {
     _usart: PhantomData<USART>,
 }
@@ -97,46 +292,162 @@ pub struct Tx<USART> {
 
 macro_rules! hal {
     ($(
-        $USARTX:ident: ($usartX:ident, $APB:ident, $usartXen:ident, $usartXrst:ident, $pclkX:ident),
+        $USARTX:ident: (
+            $usartX:ident,
+            $usartX_with_flow_control:ident,
+            $usartX_rs485:ident,
+            $APB:ident,
+            $ker_ck:ident
+        ),
     )+) => {
         $(
             impl<TX, RX> Serial<$USARTX, (TX, RX)> {
-                /// Configures a USART peripheral to provide serial communication
-                pub fn $usartX(
+                /// Configures a USART peripheral to provide serial communication, `config`
+                /// may be a `Config` or just a baud rate
+                pub fn $usartX<C>(
                     usart: $USARTX,
                     pins: (TX, RX),
-                    baud_rate: Bps,
+                    config: C,
                     clocks: Clocks,
                     apb: &mut $APB,
                 ) -> Self
                 where
+                    C: Into<Config>,
                     TX: TxPin<$USARTX>,
                     RX: RxPin<$USARTX>,
                 {
-                    // enable or reset $USARTX
-                    apb.enr().modify(|_, w| w.$usartXen().set_bit());
-                    apb.rstr().modify(|_, w| w.$usartXrst().set_bit());
-                    apb.rstr().modify(|_, w| w.$usartXrst().clear_bit());
+                    Self::configure(&usart, config.into(), clocks, apb);
+                    Self::enable(&usart);
+
+                    Serial { usart, pins }
+                }
+
+                /// Switches to single-wire half-duplex mode, TX and RX share the TX pin and
+                /// the RX pin is released
+                ///
+                /// The TX pin stays a push-pull output, but the USART only drives it while a
+                /// byte is sent and leaves it floating otherwise, so the line needs an external
+                /// pull-up to idle high, every byte sent is received as well
+                pub fn into_half_duplex(self) -> (Serial<$USARTX, (TX, NoPin)>, RX) {
+                    // HDSEL can only be written while the peripheral is disabled
+                    self.usart.cr1.modify(|_, w| w.ue().clear_bit());
+                    self.usart.cr3.modify(|_, w| w.hdsel().set_bit());
+                    Self::enable(&self.usart);
+
+                    let (tx, rx) = self.pins;
+                    (Serial { usart: self.usart, pins: (tx, NoPin) }, rx)
+                }
+            }
+
+            impl<TX, RX, DE> Serial<$USARTX, (TX, RX, DE)> {
+                #[doc = concat!(
+                    "Configures a USART peripheral like `",
+                    stringify!($usartX),
+                    "` for a RS485 transceiver, the\nDE pin enables the driver of the transceiver while a frame is sent"
+                )]
+                ///
+                /// DE is asserted `assertion_time` before the start bit and deasserted
+                /// `deassertion_time` after the stop bit, both times are rounded up to
+                /// sample times and are limited to 31 sample times
+                pub fn $usartX_rs485<C>(
+                    usart: $USARTX,
+                    pins: (TX, RX, DE),
+                    config: C,
+                    assertion_time: Duration,
+                    deassertion_time: Duration,
+                    clocks: Clocks,
+                    apb: &mut $APB,
+                ) -> Self
+                where
+                    C: Into<Config>,
+                    TX: TxPin<$USARTX>,
+                    RX: RxPin<$USARTX>,
+                    DE: DePin<$USARTX>,
+                {
+                    let config = config.into();
+                    let over8 = Self::configure(&usart, config, clocks, apb);
+
+                    let deat = de_time(assertion_time, config.baud_rate, over8);
+                    let dedt = de_time(deassertion_time, config.baud_rate, over8);
+                    usart.cr1.modify(|_, w| w.deat().bits(deat).dedt().bits(dedt));
+                    // DE is active high
+                    usart.cr3.write(|w| w.dem().set_bit().dep().clear_bit());
+                    Self::enable(&usart);
 
-                    // Word length = 8 bit
-                    usart.cr1.write(|w|  w.m0().clear_bit().m1().clear_bit());
+                    Serial { usart, pins }
+                }
+            }
+
+            impl<TX, RX, RTS, CTS> Serial<$USARTX, (TX, RX, RTS, CTS)> {
+                /// Configures a USART peripheral like `$usartX` with hardware flow control,
+                /// pass `NoPin` for an unused RTS or CTS pin
+                ///
+                /// RTS is asserted while the receiver can take a byte, the transmitter holds
+                /// back the next byte while CTS is deasserted, `write` keeps returning
+                /// `WouldBlock` in the mean time
+                pub fn $usartX_with_flow_control<C>(
+                    usart: $USARTX,
+                    pins: (TX, RX, RTS, CTS),
+                    config: C,
+                    clocks: Clocks,
+                    apb: &mut $APB,
+                ) -> Self
+                where
+                    C: Into<Config>,
+                    TX: TxPin<$USARTX>,
+                    RX: RxPin<$USARTX>,
+                    RTS: RtsPin<$USARTX>,
+                    CTS: CtsPin<$USARTX>,
+                {
+                    Self::configure(&usart, config.into(), clocks, apb);
+                    usart.cr3.write(|w| w.rtse().bit(RTS::CONNECTED).ctse().bit(CTS::CONNECTED));
+                    Self::enable(&usart);
+
+                    Serial { usart, pins }
+                }
+            }
+
+            impl<PINS> Serial<$USARTX, PINS> {
+                /// Enables and resets the peripheral and configures the frame format and the
+                /// baud rate, the peripheral is left disabled so CR3 can still be written
+                ///
+                /// Returns whether the receiver oversamples by 8 instead of 16
+                fn configure(usart: &$USARTX, config: Config, clocks: Clocks, apb: &mut $APB) -> bool {
+                    // enable or reset $USARTX
+                    $USARTX::enable(apb);
+                    $USARTX::reset(apb);
 
                     // Configure the baud rate
-                    let brr = clocks.$pclkX().0 / baud_rate.0;
-                    assert!(brr >= 16, "impossible baud rate");
+                    let (brr, over8) = brr(clocks.$ker_ck().0, config.baud_rate.0);
                     usart.brr.write(|w| unsafe { w.bits(brr) });
 
-                    // Set stop bits to 1
-                    usart.cr2.write(|w| w.stop().bits(0b00));
+                    // Configure the stop bits
+                    usart.cr2.write(|w| {
+                        w.stop().bits(match config.stop_bits {
+                            StopBits::Stop1 => 0b00,
+                            StopBits::Stop0p5 => 0b01,
+                            StopBits::Stop2 => 0b10,
+                            StopBits::Stop1p5 => 0b11,
+                        })
+                    });
+
+                    // M1:M0 = 10: 7 bits, 00: 8 bits, 01: 9 bits
+                    usart.cr1.write(|w| {
+                        w.m1().bit(config.word_length == WordLength::DataBits7)
+                            .m0().bit(config.word_length == WordLength::DataBits9)
+                            .pce().bit(config.parity != Parity::ParityNone)
+                            .ps().bit(config.parity == Parity::ParityOdd)
+                            .over8().bit(over8)
+                    });
+
+                    over8
+                }
 
+                fn enable(usart: &$USARTX) {
                     // UE: enable USART
                     // RE: enable receiver
                     // TE: enable transceiver
-                    usart
-                        .cr1
-                        .write(|w| w.ue().set_bit().re().set_bit().te().set_bit());
-
-                    Serial { usart, pins }
+                    usart.cr1.modify(|_, w| w.ue().set_bit().re().set_bit().te().set_bit());
                 }
 
                 /// Starts listening for an interrupt event
@@ -151,7 +462,7 @@ macro_rules! hal {
                     }
                 }
 
-                /// Starts listening for an interrupt event
+                /// Stops listening for an interrupt event
                 pub fn unlisten(&mut self, event: Event) {
                     match event {
                         Event::Rxne => {
@@ -176,25 +487,304 @@ macro_rules! hal {
                 }
 
                 /// Releases the USART peripheral and associated pins
-                pub fn free(self) -> ($USARTX, (TX, RX)) {
+                pub fn free(self) -> ($USARTX, PINS) {
                     (self.usart, self.pins)
                 }
             }
 
+            impl Tx<$USARTX> {
+                /// Sends `buffer` with a DMA stream, the transmitter is returned by `free`
+                /// of the transfer
+                ///
+                /// The transfer is done once the last byte was handed to the transmitter,
+                /// `flush` waits until it was sent. The transmitter keeps requesting DMA
+                /// transfers afterwards, this doesn't affect the blocking writes
+                pub fn write_dma<S: Stream>(
+                    self,
+                    stream: S,
+                    buffer: &'static [u8],
+                ) -> Transfer<S, Self, &'static [u8]> {
+                    // NOTE(unsafe) write 1 to clear register, TC is only used by the transmitter
+                    unsafe { (*$USARTX::ptr()).icr.write(|w| w.tccf().set_bit()) };
+
+                    let transfer =
+                        Transfer::memory_to_peripheral(stream, self, buffer, DmaConfig::default());
+
+                    // the stream has to be enabled before the first request
+                    interrupt::free(|_| unsafe {
+                        (*$USARTX::ptr()).cr3.modify(|_, w| w.dmat().set_bit())
+                    });
+
+                    transfer
+                }
+            }
+
+            unsafe impl PeripheralAddress for Tx<$USARTX> {
+                type Word = u8;
+
+                fn address(&self) -> u32 {
+                    // NOTE(unsafe) only the address of TDR is taken
+                    unsafe { &(*$USARTX::ptr()).tdr as *const _ as u32 }
+                }
+            }
+        )+
+    }
+}
+
+hal! {
+    USART1: (usart1, usart1_with_flow_control, usart1_rs485, APB2, usart16_ck),
+    USART2: (usart2, usart2_with_flow_control, usart2_rs485, APB1L, usart234578_ck),
+    USART3: (usart3, usart3_with_flow_control, usart3_rs485, APB1L, usart234578_ck),
+    USART6: (usart6, usart6_with_flow_control, usart6_rs485, APB2, usart16_ck),
+}
+
+/// The dividers of the LPUART1 prescaler, indexed by PRESC
+const LPUART_PRESCALERS: [u32; 12] = [1, 2, 4, 6, 8, 10, 12, 16, 32, 64, 128, 256];
+
+/// Computes PRESC and BRR of LPUART1 for the kernel clock `ker_ck`
+///
+/// BRR is 256 times the prescaled kernel clock divided by the baud rate and has to be
+/// within 0x300 to 0xFFFFF, the smallest prescaler that keeps it in range gives the finest
+/// resolution
+fn lpuart_brr(ker_ck: u32, baud_rate: u32) -> (u8, u32) {
+    for (presc, &div) in LPUART_PRESCALERS.iter().enumerate() {
+        let divisor = u64::from(div) * u64::from(baud_rate);
+        // round to the nearest divider
+        let brr = (256 * u64::from(ker_ck) + divisor / 2) / divisor;
+        // BRR only gets smaller with the larger prescalers
+        assert!(brr >= 0x300, "impossible baud rate");
+        if brr <= 0xF_FFFF {
+            return (presc as u8, brr as u32);
+        }
+    }
+    panic!("impossible baud rate");
+}
+
+// LPUART1 TX
+unsafe impl TxPin<LPUART1> for PA9<Output<PushPull>, AF3> {}
+unsafe impl TxPin<LPUART1> for PB6<Output<PushPull>, AF8> {}
+
+// LPUART1 RX
+unsafe impl RxPin<LPUART1> for PA10<Input<Floating>, AF3> {}
+unsafe impl RxPin<LPUART1> for PB7<Input<Floating>, AF8> {}
+
+/// The EXTI line of the LPUART1 receiver wakeup, a direct line that is cleared with the
+/// wakeup flag of LPUART1
+const LPUART1_RX_WAKEUP_LINE: u8 = 34;
+
+impl<TX, RX> Serial<LPUART1, (TX, RX)> {
+    /// Configures LPUART1 to provide serial communication, `config` may be a `Config` or
+    /// just a baud rate
+    ///
+    /// The kernel clock selected with `CFGR::lpuart1_src` is multiplied by 256 before it
+    /// is divided down to the baud rate, so the LSE is enough for up to 9600 baud
+    ///
+    /// # Panics
+    ///
+    /// Panics if the baud rate can't be derived from the kernel clock or if 0.5 or 1.5
+    /// stop bits are requested
+    pub fn lpuart1<C>(
+        lpuart: LPUART1,
+        pins: (TX, RX),
+        config: C,
+        clocks: Clocks,
+        apb4: &mut APB4,
+    ) -> Self
+    where
+        C: Into<Config>,
+        TX: TxPin<LPUART1>,
+        RX: RxPin<LPUART1>,
+    {
+        let config = config.into();
+
+        LPUART1::enable(apb4);
+        LPUART1::reset(apb4);
+
+        let (presc, brr) = lpuart_brr(clocks.lpuart1_ck().0, config.baud_rate.0);
+        lpuart.presc.write(|w| unsafe { w.prescaler().bits(presc) });
+        lpuart.brr.write(|w| unsafe { w.brr().bits(brr) });
+
+        lpuart.cr2.write(|w| unsafe {
+            w.stop().bits(match config.stop_bits {
+                StopBits::Stop1 => 0b00,
+                StopBits::Stop2 => 0b10,
+                _ => panic!("LPUART1 only supports 1 or 2 stop bits"),
+            })
+        });
+
+        // M1:M0 = 10: 7 bits, 00: 8 bits, 01: 9 bits
+        lpuart.cr1.write(|w| {
+            w.m1().bit(config.word_length == WordLength::DataBits7)
+                .m0().bit(config.word_length == WordLength::DataBits9)
+                .pce().bit(config.parity != Parity::ParityNone)
+                .ps().bit(config.parity == Parity::ParityOdd)
+                .ue().set_bit()
+                .re().set_bit()
+                .te().set_bit()
+        });
+
+        Serial { usart: lpuart, pins }
+    }
+}
+
+impl<PINS> Serial<LPUART1, PINS> {
+    /// Starts listening for an interrupt event
+    pub fn listen(&mut self, event: Event) {
+        match event {
+            Event::Rxne => self.usart.cr1.modify(|_, w| w.rxneie().set_bit()),
+            Event::Txe => self.usart.cr1.modify(|_, w| w.txeie().set_bit()),
+        }
+    }
+
+    /// Stops listening for an interrupt event
+    pub fn unlisten(&mut self, event: Event) {
+        match event {
+            Event::Rxne => self.usart.cr1.modify(|_, w| w.rxneie().clear_bit()),
+            Event::Txe => self.usart.cr1.modify(|_, w| w.txeie().clear_bit()),
+        }
+    }
+
+    /// Lets a received byte wake up the core from Stop mode
+    ///
+    /// The wakeup interrupt of LPUART1 gets enabled and its EXTI line unmasked, the received
+    /// byte can be read after the wakeup and `clear_wakeup` has to be called in the
+    /// interrupt handler. The kernel clock has to be the LSE, the HSI or the CSI
+    pub fn enable_wakeup(&mut self, exti: &mut EXTI) {
+        // WUS can only be written while LPUART1 is disabled, 0b11: wake up on RXNE
+        self.usart.cr1.modify(|_, w| w.ue().clear_bit());
+        self.usart.cr3.modify(|_, w| unsafe { w.wus().bits(0b11).wufie().set_bit() });
+        self.usart.cr1.modify(|_, w| w.ue().set_bit().uesm().set_bit());
+
+        exti.cpuimr2.modify(|r, w| unsafe {
+            w.bits(r.bits() | 1 << (LPUART1_RX_WAKEUP_LINE - 32))
+        });
+    }
+
+    /// Stops waking up the core from Stop mode
+    pub fn disable_wakeup(&mut self, exti: &mut EXTI) {
+        exti.cpuimr2.modify(|r, w| unsafe {
+            w.bits(r.bits() & !(1 << (LPUART1_RX_WAKEUP_LINE - 32)))
+        });
+        self.usart.cr1.modify(|_, w| w.uesm().clear_bit());
+        self.usart.cr3.modify(|_, w| w.wufie().clear_bit());
+    }
+
+    /// Clears the wakeup flag, which also clears the EXTI line
+    pub fn clear_wakeup(&mut self) {
+        self.usart.icr.write(|w| w.wucf().set_bit());
+    }
+
+    /// Splits the `Serial` abstraction into a transmitter and a receiver half
+    pub fn split(self) -> (Tx<LPUART1>, Rx<LPUART1>) {
+        (Tx { _usart: PhantomData }, Rx { _usart: PhantomData })
+    }
+
+    /// Releases LPUART1 and the associated pins
+    pub fn free(self) -> (LPUART1, PINS) {
+        (self.usart, self.pins)
+    }
+}
+
+impl Tx<LPUART1> {
+    /// Sends `buffer` with a BDMA channel, the transmitter is returned by `free` of the
+    /// transfer
+    ///
+    /// The transfer is done once the last byte was handed to the transmitter, `flush`
+    /// waits until it was sent
+    ///
+    /// # Panics
+    ///
+    /// Panics if `buffer` isn't placed in SRAM4 or in the backup SRAM
+    pub fn write_dma<C: bdma::Channel>(
+        self,
+        channel: C,
+        buffer: &'static [u8],
+    ) -> bdma::Transfer<C, Self, &'static [u8]> {
+        // NOTE(unsafe) write 1 to clear register, TC is only used by the transmitter
+        unsafe { (*LPUART1::ptr()).icr.write(|w| w.tccf().set_bit()) };
+
+        let transfer =
+            bdma::Transfer::memory_to_peripheral(channel, self, buffer, DmaConfig::default());
+
+        // the channel has to be enabled before the first request
+        interrupt::free(|_| unsafe {
+            (*LPUART1::ptr()).cr3.modify(|_, w| w.dmat().set_bit())
+        });
+
+        transfer
+    }
+}
+
+unsafe impl PeripheralAddress for Tx<LPUART1> {
+    type Word = u8;
+
+    fn address(&self) -> u32 {
+        // NOTE(unsafe) only the address of TDR is taken
+        unsafe { &(*LPUART1::ptr()).tdr as *const _ as u32 }
+    }
+}
+
+// the halves and the embedded-hal traits, LPUART1 names the noise flag NE instead of NF
+macro_rules! halves {
+    ($($USARTX:ident: $nf:ident,)+) => {
+        $(
+            impl Rx<$USARTX> {
+                /// Starts listening for the `Rxne` interrupt event
+                pub fn listen(&mut self) {
+                    // NOTE(unsafe) CR1 is shared with the transmitter half, the critical
+                    // section makes the read-modify-write atomic
+                    interrupt::free(|_| unsafe {
+                        (*$USARTX::ptr()).cr1.modify(|_, w| w.rxneie().set_bit())
+                    });
+                }
+
+                /// Stops listening for the `Rxne` interrupt event
+                pub fn unlisten(&mut self) {
+                    interrupt::free(|_| unsafe {
+                        (*$USARTX::ptr()).cr1.modify(|_, w| w.rxneie().clear_bit())
+                    });
+                }
+            }
+
+            impl Tx<$USARTX> {
+                /// Starts listening for the `Txe` interrupt event
+                pub fn listen(&mut self) {
+                    // NOTE(unsafe) CR1 is shared with the receiver half, the critical
+                    // section makes the read-modify-write atomic
+                    interrupt::free(|_| unsafe {
+                        (*$USARTX::ptr()).cr1.modify(|_, w| w.txeie().set_bit())
+                    });
+                }
+
+                /// Stops listening for the `Txe` interrupt event
+                pub fn unlisten(&mut self) {
+                    interrupt::free(|_| unsafe {
+                        (*$USARTX::ptr()).cr1.modify(|_, w| w.txeie().clear_bit())
+                    });
+                }
+            }
+
             impl serial::Read<u8> for Rx<$USARTX> {
                 type Error = Error;
 
                 fn read(&mut self) -> nb::Result<u8, Error> {
                     // NOTE(unsafe) atomic read with no side effects
                     let isr = unsafe { (*$USARTX::ptr()).isr.read() };
+                    // NOTE(unsafe) atomic write to a write 1 to clear register, only the
+                    // error flags of the receiver are cleared
+                    let icr = unsafe { &(*$USARTX::ptr()).icr };
 
                     Err(if isr.pe().bit_is_set() {
+                        icr.write(|w| w.pecf().set_bit());
                         nb::Error::Other(Error::Parity)
                     } else if isr.fe().bit_is_set() {
+                        icr.write(|w| w.fecf().set_bit());
                         nb::Error::Other(Error::Framing)
-                    } else if isr.nf().bit_is_set() {
+                    } else if isr.$nf().bit_is_set() {
+                        icr.write(|w| w.ncf().set_bit());
                         nb::Error::Other(Error::Noise)
                     } else if isr.ore().bit_is_set() {
+                        icr.write(|w| w.orecf().set_bit());
                         nb::Error::Other(Error::Overrun)
                     } else if isr.rxne().bit_is_set() {
                         // NOTE(read_volatile) see `write_volatile` below
@@ -209,9 +799,9 @@ macro_rules! hal {
 
             impl serial::Write<u8> for Tx<$USARTX> {
                 // NOTE(Void) See section "29.7 USART interrupts"; the only possible errors during
-                // transmission are: clear to send (which is disabled in this case) errors and
-                // framing errors (which only occur in SmartCard mode); neither of these apply to
-                // our hardware configuration
+                // transmission are: clear to send errors (with hardware flow control the
+                // transmitter just waits for CTS) and framing errors (which only occur in
+                // SmartCard mode); neither of these apply to our hardware configuration
                 type Error = Void;
 
                 fn flush(&mut self) -> nb::Result<(), Void> {
@@ -233,7 +823,7 @@ macro_rules! hal {
                         // NOTE(unsafe) atomic write to stateless register
                         // NOTE(write_volatile) 8-bit write that's not possible through the svd2rust API
                         unsafe {
-                            ptr::write_volatile(&(*$USARTX::ptr()).tdr as *const _ as *mut _, byte)
+                            ptr::write_volatile(ptr::addr_of!((*$USARTX::ptr()).tdr) as *mut u8, byte)
                         }
 
                         // NOTE(point 8) we maybe gotta implement point 8 on page 2031 of the reference manual here if we encounter bugs
@@ -243,13 +833,53 @@ macro_rules! hal {
                     }
                 }
             }
+
+            impl ::hal::blocking::serial::write::Default<u8> for Tx<$USARTX> {}
+
+            impl fmt::Write for Tx<$USARTX> {
+                fn write_str(&mut self, s: &str) -> fmt::Result {
+                    for byte in s.bytes() {
+                        block!(serial::Write::write(self, byte)).map_err(|_| fmt::Error)?;
+                    }
+                    Ok(())
+                }
+            }
+
+            impl<PINS> serial::Read<u8> for Serial<$USARTX, PINS> {
+                type Error = Error;
+
+                fn read(&mut self) -> nb::Result<u8, Error> {
+                    Rx::<$USARTX> { _usart: PhantomData }.read()
+                }
+            }
+
+            impl<PINS> serial::Write<u8> for Serial<$USARTX, PINS> {
+                type Error = Void;
+
+                fn flush(&mut self) -> nb::Result<(), Void> {
+                    Tx::<$USARTX> { _usart: PhantomData }.flush()
+                }
+
+                fn write(&mut self, byte: u8) -> nb::Result<(), Void> {
+                    Tx::<$USARTX> { _usart: PhantomData }.write(byte)
+                }
+            }
+
+            impl<PINS> ::hal::blocking::serial::write::Default<u8> for Serial<$USARTX, PINS> {}
+
+            impl<PINS> fmt::Write for Serial<$USARTX, PINS> {
+                fn write_str(&mut self, s: &str) -> fmt::Result {
+                    Tx::<$USARTX> { _usart: PhantomData }.write_str(s)
+                }
+            }
         )+
     }
 }
 
-hal! {
-    USART1: (usart1, APB2, usart1en, usart1rst, pclk2),
-    USART2: (usart2, APB1L, usart2en, usart2rst, pclk1),
-    USART3: (usart3, APB1L, usart3en, usart3rst, pclk1),
-    USART6: (usart6, APB2, usart6en, usart6rst, pclk2),
-}
\ No newline at end of file
+halves! {
+    USART1: nf,
+    USART2: nf,
+    USART3: nf,
+    USART6: nf,
+    LPUART1: ne,
+}
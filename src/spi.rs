@@ -0,0 +1,844 @@
+//! Serial Peripheral Interface implementation
+
+use core::marker::PhantomData;
+use core::ptr;
+
+use hal::spi::{FullDuplex, Mode, Phase, Polarity, MODE_0};
+use nb;
+use crate::gpio::{Floating, Input, Output, PushPull, AF5, AF6, AF7, AF8};
+use crate::gpio::gpioa::{PA4, PA5, PA6, PA7, PA9, PA11, PA12, PA15};
+use crate::gpio::gpiob::{PB2, PB3, PB4, PB5, PB9, PB10, PB12, PB13, PB14, PB15};
+use crate::gpio::gpioc::{PC1, PC2, PC3, PC4, PC6, PC7, PC10, PC11, PC12};
+use crate::gpio::gpiod::{PD3, PD6, PD7};
+use crate::gpio::gpioe::{PE2, PE4, PE5, PE6, PE11, PE12, PE13, PE14};
+use crate::gpio::gpiof::{PF6, PF7, PF8, PF9, PF11};
+use crate::gpio::gpiog::{PG8, PG9, PG10, PG11, PG12, PG13, PG14};
+use crate::gpio::gpioh::{PH5, PH6, PH7};
+use crate::gpio::gpioi::{PI0, PI1, PI2, PI3};
+use crate::gpio::gpioj::{PJ10, PJ11};
+use crate::gpio::gpiok::{PK0, PK1};
+use crate::stm32h7x3::{SPI1, SPI2, SPI3, SPI4, SPI5, SPI6};
+use crate::time::Hertz;
+use crate::rcc::{Clocks, Enable, Reset, APB1L, APB2, APB4};
+
+/// SPI error
+#[derive(Debug)]
+#[non_exhaustive]
+pub enum Error {
+    /// Overrun occurred
+    Overrun,
+    /// Mode fault occurred
+    ModeFault,
+    /// CRC error
+    Crc,
+    /// The transmit FIFO was empty when data had to be sent
+    Underrun,
+}
+
+/// SCK pin - DO NOT IMPLEMENT THIS TRAIT
+///
+/// # Safety
+///
+/// Only the pins whose alternate function connects them to SCK of `SPI` may
+/// implement this trait
+pub unsafe trait SckPin<SPI> {}
+
+/// MISO pin - DO NOT IMPLEMENT THIS TRAIT
+///
+/// # Safety
+///
+/// Only the pins whose alternate function connects them to MISO of `SPI` may
+/// implement this trait
+pub unsafe trait MisoPin<SPI> {}
+
+/// MOSI pin - DO NOT IMPLEMENT THIS TRAIT
+///
+/// # Safety
+///
+/// Only the pins whose alternate function connects them to MOSI of `SPI` may
+/// implement this trait
+pub unsafe trait MosiPin<SPI> {}
+
+/// Hardware NSS pin - DO NOT IMPLEMENT THIS TRAIT
+///
+/// # Safety
+///
+/// Only the pins whose alternate function connects them to NSS of `SPI` may
+/// implement this trait
+pub unsafe trait NssPin<SPI> {}
+
+// SPI1
+unsafe impl SckPin<SPI1> for PA5<Output<PushPull>, AF5> {}
+unsafe impl SckPin<SPI1> for PB3<Output<PushPull>, AF5> {}
+unsafe impl SckPin<SPI1> for PG11<Output<PushPull>, AF5> {}
+unsafe impl MisoPin<SPI1> for PA6<Input<Floating>, AF5> {}
+unsafe impl MisoPin<SPI1> for PB4<Input<Floating>, AF5> {}
+unsafe impl MisoPin<SPI1> for PG9<Input<Floating>, AF5> {}
+unsafe impl MosiPin<SPI1> for PA7<Output<PushPull>, AF5> {}
+unsafe impl MosiPin<SPI1> for PB5<Output<PushPull>, AF5> {}
+unsafe impl MosiPin<SPI1> for PD7<Output<PushPull>, AF5> {}
+unsafe impl NssPin<SPI1> for PA4<Output<PushPull>, AF5> {}
+unsafe impl NssPin<SPI1> for PA15<Output<PushPull>, AF5> {}
+unsafe impl NssPin<SPI1> for PG10<Output<PushPull>, AF5> {}
+
+// SPI2
+unsafe impl SckPin<SPI2> for PA9<Output<PushPull>, AF5> {}
+unsafe impl SckPin<SPI2> for PA12<Output<PushPull>, AF5> {}
+unsafe impl SckPin<SPI2> for PB10<Output<PushPull>, AF5> {}
+unsafe impl SckPin<SPI2> for PB13<Output<PushPull>, AF5> {}
+unsafe impl SckPin<SPI2> for PD3<Output<PushPull>, AF5> {}
+unsafe impl SckPin<SPI2> for PI1<Output<PushPull>, AF5> {}
+unsafe impl MisoPin<SPI2> for PB14<Input<Floating>, AF5> {}
+unsafe impl MisoPin<SPI2> for PC2<Input<Floating>, AF5> {}
+unsafe impl MisoPin<SPI2> for PI2<Input<Floating>, AF5> {}
+unsafe impl MosiPin<SPI2> for PB15<Output<PushPull>, AF5> {}
+unsafe impl MosiPin<SPI2> for PC1<Output<PushPull>, AF5> {}
+unsafe impl MosiPin<SPI2> for PC3<Output<PushPull>, AF5> {}
+unsafe impl MosiPin<SPI2> for PI3<Output<PushPull>, AF5> {}
+unsafe impl NssPin<SPI2> for PA11<Output<PushPull>, AF5> {}
+unsafe impl NssPin<SPI2> for PB9<Output<PushPull>, AF5> {}
+unsafe impl NssPin<SPI2> for PB12<Output<PushPull>, AF5> {}
+unsafe impl NssPin<SPI2> for PI0<Output<PushPull>, AF5> {}
+
+// SPI3
+unsafe impl SckPin<SPI3> for PB3<Output<PushPull>, AF6> {}
+unsafe impl SckPin<SPI3> for PC10<Output<PushPull>, AF6> {}
+unsafe impl MisoPin<SPI3> for PB4<Input<Floating>, AF6> {}
+unsafe impl MisoPin<SPI3> for PC11<Input<Floating>, AF6> {}
+unsafe impl MosiPin<SPI3> for PB2<Output<PushPull>, AF7> {}
+unsafe impl MosiPin<SPI3> for PB5<Output<PushPull>, AF7> {}
+unsafe impl MosiPin<SPI3> for PC12<Output<PushPull>, AF6> {}
+unsafe impl MosiPin<SPI3> for PD6<Output<PushPull>, AF5> {}
+unsafe impl NssPin<SPI3> for PA4<Output<PushPull>, AF6> {}
+unsafe impl NssPin<SPI3> for PA15<Output<PushPull>, AF6> {}
+
+// SPI4
+unsafe impl SckPin<SPI4> for PE2<Output<PushPull>, AF5> {}
+unsafe impl SckPin<SPI4> for PE12<Output<PushPull>, AF5> {}
+unsafe impl MisoPin<SPI4> for PE5<Input<Floating>, AF5> {}
+unsafe impl MisoPin<SPI4> for PE13<Input<Floating>, AF5> {}
+unsafe impl MosiPin<SPI4> for PE6<Output<PushPull>, AF5> {}
+unsafe impl MosiPin<SPI4> for PE14<Output<PushPull>, AF5> {}
+unsafe impl NssPin<SPI4> for PE4<Output<PushPull>, AF5> {}
+unsafe impl NssPin<SPI4> for PE11<Output<PushPull>, AF5> {}
+
+// SPI5
+unsafe impl SckPin<SPI5> for PF7<Output<PushPull>, AF5> {}
+unsafe impl SckPin<SPI5> for PH6<Output<PushPull>, AF5> {}
+unsafe impl SckPin<SPI5> for PK0<Output<PushPull>, AF5> {}
+unsafe impl MisoPin<SPI5> for PF8<Input<Floating>, AF5> {}
+unsafe impl MisoPin<SPI5> for PH7<Input<Floating>, AF5> {}
+unsafe impl MisoPin<SPI5> for PJ11<Input<Floating>, AF5> {}
+unsafe impl MosiPin<SPI5> for PF9<Output<PushPull>, AF5> {}
+unsafe impl MosiPin<SPI5> for PF11<Output<PushPull>, AF5> {}
+unsafe impl MosiPin<SPI5> for PJ10<Output<PushPull>, AF5> {}
+unsafe impl NssPin<SPI5> for PF6<Output<PushPull>, AF5> {}
+unsafe impl NssPin<SPI5> for PH5<Output<PushPull>, AF5> {}
+unsafe impl NssPin<SPI5> for PK1<Output<PushPull>, AF5> {}
+
+// SPI6
+unsafe impl SckPin<SPI6> for PA5<Output<PushPull>, AF8> {}
+unsafe impl SckPin<SPI6> for PB3<Output<PushPull>, AF8> {}
+unsafe impl SckPin<SPI6> for PG13<Output<PushPull>, AF5> {}
+unsafe impl MisoPin<SPI6> for PA6<Input<Floating>, AF8> {}
+unsafe impl MisoPin<SPI6> for PB4<Input<Floating>, AF8> {}
+unsafe impl MisoPin<SPI6> for PG12<Input<Floating>, AF5> {}
+unsafe impl MosiPin<SPI6> for PA7<Output<PushPull>, AF8> {}
+unsafe impl MosiPin<SPI6> for PB5<Output<PushPull>, AF8> {}
+unsafe impl MosiPin<SPI6> for PG14<Output<PushPull>, AF5> {}
+unsafe impl NssPin<SPI6> for PA4<Output<PushPull>, AF8> {}
+unsafe impl NssPin<SPI6> for PA15<Output<PushPull>, AF7> {}
+unsafe impl NssPin<SPI6> for PG8<Output<PushPull>, AF5> {}
+
+/// Frame size of a SPI transfer - DO NOT IMPLEMENT THIS TRAIT
+///
+/// The FIFO is accessed with the width of the word, so only the sizes that
+/// can be accessed atomically are supported
+///
+/// # Safety
+///
+/// `BITS` has to be the size of the type, otherwise the FIFO accesses push or pop
+/// frames of the wrong size
+pub unsafe trait Word: Copy {
+    /// The number of bits of a frame
+    const BITS: u8;
+}
+
+unsafe impl Word for u8 {
+    const BITS: u8 = 8;
+}
+
+unsafe impl Word for u16 {
+    const BITS: u8 = 16;
+}
+
+/// SPI peripheral operating in full duplex master mode
+///
+/// The peripheral transfers 8 bit words after construction, use `frame_size`
+/// to switch to another `WORD`
+pub struct Spi<SPI, PINS, WORD = u8> {
+    spi: SPI,
+    pins: PINS,
+    _word: PhantomData<WORD>,
+}
+
+/// The MBR value that divides `ker_ck` to the highest frequency not above `freq`
+///
+/// The baud rate prescaler divides the kernel clock by 2 to 256, so `freq` must not be
+/// 0 or below 1/256 of the kernel clock
+fn baud_rate_prescaler(ker_ck: u32, freq: u32) -> u8 {
+    assert!(freq > 0, "the SCK frequency must not be 0");
+    match ker_ck.div_ceil(freq) {
+        0..=2 => 0b000,
+        3..=4 => 0b001,
+        5..=8 => 0b010,
+        9..=16 => 0b011,
+        17..=32 => 0b100,
+        33..=64 => 0b101,
+        65..=128 => 0b110,
+        129..=256 => 0b111,
+        _ => panic!("the SCK frequency is below 1/256 of the kernel clock"),
+    }
+}
+
+macro_rules! hal {
+    ($(
+        $SPIX:ident: ($spiX:ident, $spiX_hw_cs:ident, $spiX_ti_mode:ident, $APB:ident, $ker_ck:expr),
+    )+) => {
+        $(
+            impl<SCK, MISO, MOSI> Spi<$SPIX, (SCK, MISO, MOSI)> {
+                /// Configures a SPI peripheral as full duplex master, the SCK frequency
+                /// is the highest one possible that does not exceed `freq`
+                ///
+                /// The chip select has to be driven by software, e.g. through a GPIO pin
+                ///
+                /// # Panics
+                ///
+                /// Panics if the kernel clock is disabled, or if `freq` is 0 or below 1/256 of
+                /// the kernel clock
+                pub fn $spiX<F>(
+                    spi: $SPIX,
+                    pins: (SCK, MISO, MOSI),
+                    mode: Mode,
+                    freq: F,
+                    clocks: Clocks,
+                    apb: &mut $APB,
+                ) -> Self
+                where
+                    F: Into<Hertz>,
+                    SCK: SckPin<$SPIX>,
+                    MISO: MisoPin<$SPIX>,
+                    MOSI: MosiPin<$SPIX>,
+                {
+                    Self::configure(spi, pins, mode, freq.into(), clocks, apb, false)
+                }
+            }
+
+            impl<SCK, MISO, MOSI, NSS> Spi<$SPIX, (SCK, MISO, MOSI, NSS)> {
+                #[doc = concat!(
+                    "Configures a SPI peripheral as full duplex master like `",
+                    stringify!($spiX),
+                    "`, but the\nNSS pin is driven low by the hardware while the peripheral is enabled"
+                )]
+                #[doc = ""]
+                #[doc = "# Panics"]
+                #[doc = ""]
+                #[doc = "Panics if the kernel clock is disabled, or if `freq` is 0 or below 1/256 of"]
+                #[doc = "the kernel clock"]
+                pub fn $spiX_hw_cs<F>(
+                    spi: $SPIX,
+                    pins: (SCK, MISO, MOSI, NSS),
+                    mode: Mode,
+                    freq: F,
+                    clocks: Clocks,
+                    apb: &mut $APB,
+                ) -> Self
+                where
+                    F: Into<Hertz>,
+                    SCK: SckPin<$SPIX>,
+                    MISO: MisoPin<$SPIX>,
+                    MOSI: MosiPin<$SPIX>,
+                    NSS: NssPin<$SPIX>,
+                {
+                    Self::configure(spi, pins, mode, freq.into(), clocks, apb, true)
+                }
+
+                /// Configures a SPI peripheral as full duplex master with the TI protocol,
+                /// NSS is pulsed for one clock cycle before each frame
+                ///
+                /// The clock polarity and phase are fixed by the protocol
+                ///
+                /// # Panics
+                ///
+                /// Panics if the kernel clock is disabled, or if `freq` is 0 or below 1/256 of
+                /// the kernel clock
+                pub fn $spiX_ti_mode<F>(
+                    spi: $SPIX,
+                    pins: (SCK, MISO, MOSI, NSS),
+                    freq: F,
+                    clocks: Clocks,
+                    apb: &mut $APB,
+                ) -> Self
+                where
+                    F: Into<Hertz>,
+                    SCK: SckPin<$SPIX>,
+                    MISO: MisoPin<$SPIX>,
+                    MOSI: MosiPin<$SPIX>,
+                    NSS: NssPin<$SPIX>,
+                {
+                    let mut spi = Self::configure(spi, pins, MODE_0, freq.into(), clocks, apb, true);
+
+                    // the configuration can only be changed while the peripheral is disabled
+                    spi.spi.cr1.modify(|_, w| w.spe().clear_bit());
+                    spi.spi.cfg2.modify(|_, w| unsafe { w.sp().bits(0b001) });
+                    spi.start();
+                    spi
+                }
+            }
+
+            impl<SCK, MISO, MOSI, NSS, WORD> Spi<$SPIX, (SCK, MISO, MOSI, NSS), WORD> {
+                /// Enables or disables pulsing NSS high between the frames
+                pub fn set_nss_pulse(&mut self, on: bool) {
+                    // the configuration can only be changed while the peripheral is disabled
+                    self.spi.cr1.modify(|_, w| w.spe().clear_bit());
+                    self.spi.cfg2.modify(|_, w| w.ssom().bit(on));
+                    self.start();
+                }
+            }
+
+            impl<PINS> Spi<$SPIX, PINS> {
+                fn configure(
+                    spi: $SPIX,
+                    pins: PINS,
+                    mode: Mode,
+                    freq: Hertz,
+                    clocks: Clocks,
+                    apb: &mut $APB,
+                    hardware_nss: bool,
+                ) -> Self {
+                    $SPIX::enable(apb);
+                    $SPIX::reset(apb);
+
+                    let ker_ck: Hertz = ($ker_ck)(&clocks);
+                    let mbr = baud_rate_prescaler(ker_ck.0, freq.0);
+
+                    // 8 bit frames
+                    spi.cfg1.write(|w| unsafe { w.mbr().bits(mbr).dsize().bits(8 - 1) });
+
+                    // with software NSS the internal NSS is kept high so the master does not
+                    // run into a mode fault, with hardware NSS the pin is driven active low
+                    // AFCNTR keeps the pins driven while the peripheral is disabled
+                    spi.cr1.write(|w| w.ssi().bit(!hardware_nss));
+                    spi.cfg2.write(|w| unsafe {
+                        w.afcntr().set_bit()
+                            .ssm().bit(!hardware_nss)
+                            .ssoe().bit(hardware_nss)
+                            .ssiop().clear_bit()
+                            .master().set_bit()
+                            .comm().bits(0b00)
+                            .cpol().bit(mode.polarity == Polarity::IdleHigh)
+                            .cpha().bit(mode.phase == Phase::CaptureOnSecondTransition)
+                    });
+
+                    let mut spi = Spi { spi, pins, _word: PhantomData };
+                    spi.start();
+                    spi
+                }
+            }
+
+            impl<PINS, WORD> Spi<$SPIX, PINS, WORD> {
+                /// Changes the size of the transferred words, e.g. `spi.frame_size::<u16>()`
+                pub fn frame_size<W: Word>(self) -> Spi<$SPIX, PINS, W> {
+                    // the configuration can only be changed while the peripheral is disabled
+                    self.spi.cr1.modify(|_, w| w.spe().clear_bit());
+                    self.spi.cfg1.modify(|_, w| unsafe { w.dsize().bits(W::BITS - 1) });
+
+                    let mut spi = Spi { spi: self.spi, pins: self.pins, _word: PhantomData };
+                    spi.start();
+                    spi
+                }
+
+                /// Enables the peripheral and starts an endless transfer (TSIZE = 0),
+                /// afterwards every word written to TXDR gets clocked out
+                fn start(&mut self) {
+                    self.spi.cr1.modify(|_, w| w.spe().set_bit());
+                    // the svd lacks a writer for CSTART (bit 9)
+                    self.spi.cr1.modify(|r, w| unsafe { w.bits(r.bits() | (1 << 9)) });
+                }
+
+                /// Clears and returns the error flagged in SR, if any
+                fn take_error(&mut self) -> Option<Error> {
+                    let sr = self.spi.sr.read();
+
+                    if sr.ovr().bit_is_set() {
+                        self.spi.ifcr.write(|w| w.ovrc().set_bit());
+                        Some(Error::Overrun)
+                    } else if sr.modf().bit_is_set() {
+                        self.spi.ifcr.write(|w| w.modfc().set_bit());
+                        // a mode fault disables the peripheral
+                        self.start();
+                        Some(Error::ModeFault)
+                    } else if sr.crce().bit_is_set() {
+                        self.spi.ifcr.write(|w| w.crcec().set_bit());
+                        Some(Error::Crc)
+                    } else {
+                        None
+                    }
+                }
+
+                /// Releases the SPI peripheral and associated pins
+                pub fn free(self) -> ($SPIX, PINS) {
+                    self.spi.cr1.modify(|_, w| w.spe().clear_bit());
+                    (self.spi, self.pins)
+                }
+            }
+
+            impl<PINS, WORD: Word> FullDuplex<WORD> for Spi<$SPIX, PINS, WORD> {
+                type Error = Error;
+
+                fn read(&mut self) -> nb::Result<WORD, Error> {
+                    if let Some(error) = self.take_error() {
+                        return Err(nb::Error::Other(error));
+                    }
+
+                    Err(if self.spi.sr.read().rxp().bit_is_set() {
+                        // NOTE(read_volatile) read only a single word, a 32 bit read
+                        // would pop several frames from the FIFO
+                        return Ok(unsafe {
+                            ptr::read_volatile(&self.spi.rxdr as *const _ as *const WORD)
+                        });
+                    } else {
+                        nb::Error::WouldBlock
+                    })
+                }
+
+                fn send(&mut self, word: WORD) -> nb::Result<(), Error> {
+                    if let Some(error) = self.take_error() {
+                        return Err(nb::Error::Other(error));
+                    }
+
+                    Err(if self.spi.sr.read().txp().bit_is_set() {
+                        // NOTE(write_volatile) write only a single word, a 32 bit write
+                        // would push several frames into the FIFO
+                        unsafe { ptr::write_volatile(ptr::addr_of!(self.spi.txdr) as *mut WORD, word) }
+                        return Ok(());
+                    } else {
+                        nb::Error::WouldBlock
+                    })
+                }
+            }
+
+            impl<PINS, WORD: Word> ::hal::blocking::spi::transfer::Default<WORD> for Spi<$SPIX, PINS, WORD> {}
+
+            impl<PINS, WORD: Word> ::hal::blocking::spi::write::Default<WORD> for Spi<$SPIX, PINS, WORD> {}
+        )+
+    }
+}
+
+hal! {
+    SPI1: (spi1, spi1_hw_cs, spi1_ti_mode, APB2, |clocks: &Clocks| clocks.spi123_ck().expect("the kernel clock of SPI1 is disabled")),
+    SPI2: (spi2, spi2_hw_cs, spi2_ti_mode, APB1L, |clocks: &Clocks| clocks.spi123_ck().expect("the kernel clock of SPI2 is disabled")),
+    SPI3: (spi3, spi3_hw_cs, spi3_ti_mode, APB1L, |clocks: &Clocks| clocks.spi123_ck().expect("the kernel clock of SPI3 is disabled")),
+    // SPI4, SPI5 and SPI6 are left at their reset kernel clock, the clock of their APB
+    SPI4: (spi4, spi4_hw_cs, spi4_ti_mode, APB2, |clocks: &Clocks| clocks.pclk2()),
+    SPI5: (spi5, spi5_hw_cs, spi5_ti_mode, APB2, |clocks: &Clocks| clocks.pclk2()),
+    SPI6: (spi6, spi6_hw_cs, spi6_ti_mode, APB4, |clocks: &Clocks| clocks.pclk4()),
+}
+
+/// I2S master clock pin - DO NOT IMPLEMENT THIS TRAIT
+///
+/// # Safety
+///
+/// Only the pins whose alternate function connects them to MCK of `SPI` may
+/// implement this trait
+pub unsafe trait MckPin<SPI> {}
+
+/// I2S serial data pin, the MOSI pin transmits and the MISO pin receives - DO NOT
+/// IMPLEMENT THIS TRAIT
+///
+/// # Safety
+///
+/// Only the MOSI and MISO pins of `SPI` may implement this trait, `TRANSMIT` has to be
+/// true for exactly the MOSI pins
+pub unsafe trait SdPin<SPI> {
+    #[doc(hidden)]
+    const TRANSMIT: bool;
+}
+
+unsafe impl MckPin<SPI1> for PC4<Output<PushPull>, AF5> {}
+unsafe impl MckPin<SPI2> for PC6<Output<PushPull>, AF5> {}
+unsafe impl MckPin<SPI3> for PC7<Output<PushPull>, AF6> {}
+
+macro_rules! sd_pins {
+    ($($SPIX:ident: ([$($SDO:ty),+], [$($SDI:ty),+]),)+) => {
+        $(
+            $(
+                unsafe impl SdPin<$SPIX> for $SDO {
+                    const TRANSMIT: bool = true;
+                }
+            )+
+            $(
+                unsafe impl SdPin<$SPIX> for $SDI {
+                    const TRANSMIT: bool = false;
+                }
+            )+
+        )+
+    };
+}
+
+sd_pins! {
+    SPI1: (
+        [PA7<Output<PushPull>, AF5>, PB5<Output<PushPull>, AF5>, PD7<Output<PushPull>, AF5>],
+        [PA6<Input<Floating>, AF5>, PB4<Input<Floating>, AF5>, PG9<Input<Floating>, AF5>]
+    ),
+    SPI2: (
+        [PB15<Output<PushPull>, AF5>, PC1<Output<PushPull>, AF5>, PC3<Output<PushPull>, AF5>,
+            PI3<Output<PushPull>, AF5>],
+        [PB14<Input<Floating>, AF5>, PC2<Input<Floating>, AF5>, PI2<Input<Floating>, AF5>]
+    ),
+    SPI3: (
+        [PB2<Output<PushPull>, AF7>, PB5<Output<PushPull>, AF7>, PC12<Output<PushPull>, AF6>,
+            PD6<Output<PushPull>, AF5>],
+        [PB4<Input<Floating>, AF6>, PC11<Input<Floating>, AF6>]
+    ),
+}
+
+/// The pins of an I2S peripheral, with or without the master clock output - DO NOT
+/// IMPLEMENT THIS TRAIT
+///
+/// The pins are the clock (SCK), the word select (NSS) and the serial data pin
+///
+/// # Safety
+///
+/// Only tuples of the I2S pins of `SPI` may implement this trait, `MCK` has to tell
+/// whether the master clock pin is among them and `TRANSMIT` has to match the serial
+/// data pin
+pub unsafe trait I2sPins<SPI> {
+    #[doc(hidden)]
+    const MCK: bool;
+    #[doc(hidden)]
+    const TRANSMIT: bool;
+}
+
+unsafe impl<SPI, CK, WS, SD> I2sPins<SPI> for (CK, WS, SD)
+where
+    CK: SckPin<SPI>,
+    WS: NssPin<SPI>,
+    SD: SdPin<SPI>,
+{
+    const MCK: bool = false;
+    const TRANSMIT: bool = SD::TRANSMIT;
+}
+
+unsafe impl<SPI, MCK, CK, WS, SD> I2sPins<SPI> for (MCK, CK, WS, SD)
+where
+    MCK: MckPin<SPI>,
+    CK: SckPin<SPI>,
+    WS: NssPin<SPI>,
+    SD: SdPin<SPI>,
+{
+    const MCK: bool = true;
+    const TRANSMIT: bool = SD::TRANSMIT;
+}
+
+/// The I2S standard
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum I2sStandard {
+    /// Philips I2S, the data starts one clock cycle after the word select changes
+    Philips = 0b00,
+    /// MSB justified, the data starts when the word select changes
+    MsbJustified = 0b01,
+    /// LSB justified, the data ends when the word select changes
+    LsbJustified = 0b10,
+    /// PCM with a short frame sync and one sample per frame
+    Pcm = 0b11,
+}
+
+/// The size of the I2S samples
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum I2sDataLength {
+    /// 16 bit samples in 16 bit channels
+    Bits16,
+    /// 24 bit samples in 32 bit channels
+    Bits24,
+    /// 32 bit samples in 32 bit channels
+    Bits32,
+}
+
+/// Configuration of an I2S peripheral
+///
+/// `new` creates the configuration of a Philips I2S peripheral with 16 bit samples
+#[derive(Clone, Copy)]
+pub struct I2sConfig {
+    sample_rate: u32,
+    standard: I2sStandard,
+    data_length: I2sDataLength,
+}
+
+impl I2sConfig {
+    /// Creates the configuration of a peripheral running at `sample_rate`
+    pub fn new<F>(sample_rate: F) -> Self
+    where
+        F: Into<Hertz>,
+    {
+        I2sConfig {
+            sample_rate: sample_rate.into().0,
+            standard: I2sStandard::Philips,
+            data_length: I2sDataLength::Bits16,
+        }
+    }
+
+    /// Sets the I2S standard
+    pub fn standard(mut self, standard: I2sStandard) -> Self {
+        self.standard = standard;
+        self
+    }
+
+    /// Sets the size of the samples
+    pub fn data_length(mut self, data_length: I2sDataLength) -> Self {
+        self.data_length = data_length;
+        self
+    }
+
+    /// The number of samples of a frame
+    fn channels(&self) -> usize {
+        if self.standard == I2sStandard::Pcm { 1 } else { 2 }
+    }
+}
+
+/// Computes I2SDIV and ODD, which divide the kernel clock by 2 * I2SDIV + ODD, for
+/// `ratio` kernel clock cycles per sample
+///
+/// Returns the register values and the sample rate they result in
+fn i2s_div(ker_ck: u32, sample_rate: u32, ratio: u32) -> (u8, bool, u32) {
+    let cycles = ratio * sample_rate;
+    // round to the nearest divider, I2SDIV = 1 is not allowed and I2SDIV = 0 bypasses the
+    // divider
+    let div = (ker_ck + cycles / 2) / cycles;
+    assert!(div == 1 || (4..=511).contains(&div), "impossible sample rate");
+
+    let (i2sdiv, odd) = if div == 1 { (0, false) } else { ((div / 2) as u8, div % 2 == 1) };
+    (i2sdiv, odd, ker_ck / (ratio * div))
+}
+
+/// SPI peripheral operating in I2S master mode
+///
+/// The data direction follows from the data pin, the MOSI pin transmits and the MISO pin
+/// receives
+pub struct I2s<SPI, PINS> {
+    spi: SPI,
+    pins: PINS,
+    config: I2sConfig,
+    sample_rate: u32,
+}
+
+macro_rules! i2s {
+    ($($SPIX:ident: ($i2sX:ident, $APB:ident),)+) => {
+        $(
+            impl<PINS> I2s<$SPIX, PINS>
+            where
+                PINS: I2sPins<$SPIX>,
+            {
+                /// Configures a SPI peripheral as I2S master, the receiver starts right away,
+                /// the transmitter once the first frame was written
+                ///
+                /// The linear prescaler divides the kernel clock selected with
+                /// `CFGR::spi123_src` down to 256 times the sample rate (128 for PCM) if the
+                /// master clock is output, otherwise down to the bit clock. The sample rate
+                /// that is reached is returned by `sample_rate`
+                ///
+                /// # Panics
+                ///
+                /// Panics if the kernel clock is disabled or the sample rate can't be
+                /// derived from it
+                pub fn $i2sX(
+                    spi: $SPIX,
+                    pins: PINS,
+                    config: I2sConfig,
+                    clocks: Clocks,
+                    apb: &mut $APB,
+                ) -> Self {
+                    let ker_ck = clocks.spi123_ck().expect("the kernel clock of the SPI is disabled");
+
+                    $SPIX::enable(apb);
+                    $SPIX::reset(apb);
+
+                    let channel_bits = match config.data_length {
+                        I2sDataLength::Bits16 => 16,
+                        I2sDataLength::Bits24 | I2sDataLength::Bits32 => 32,
+                    };
+                    let ratio = if PINS::MCK {
+                        128 * config.channels() as u32
+                    } else {
+                        channel_bits * config.channels() as u32
+                    };
+                    let (i2sdiv, odd, sample_rate) = i2s_div(ker_ck.0, config.sample_rate, ratio);
+
+                    // a frame of two samples can be written or read at once
+                    spi.cfg1.write(|w| unsafe { w.fthvl().bits(2 - 1) });
+                    // AFCNTR keeps the pins driven while the peripheral is disabled
+                    spi.cfg2.write(|w| w.afcntr().set_bit());
+                    // the data changes on the falling and is sampled on the rising edge of CK
+                    spi.cgfr.write(|w| unsafe {
+                        w.i2smod().set_bit()
+                            .i2scfg().bits(if PINS::TRANSMIT { 0b010 } else { 0b011 })
+                            .i2sstd().bits(config.standard as u8)
+                            .pcmsync().clear_bit()
+                            .datlen().bits(match config.data_length {
+                                I2sDataLength::Bits16 => 0b00,
+                                I2sDataLength::Bits24 => 0b01,
+                                I2sDataLength::Bits32 => 0b10,
+                            })
+                            .chlen().bit(channel_bits == 32)
+                            .ckpol().clear_bit()
+                            .i2sdiv().bits(i2sdiv)
+                            .odd().bit(odd)
+                            .mckoe().bit(PINS::MCK)
+                    });
+
+                    let mut i2s = I2s { spi, pins, config, sample_rate };
+                    if !PINS::TRANSMIT {
+                        i2s.start();
+                    }
+                    i2s
+                }
+
+                /// The sample rate the peripheral runs at
+                pub fn sample_rate(&self) -> Hertz {
+                    Hertz(self.sample_rate)
+                }
+
+                /// Writes the samples of one frame to the FIFO, `[left, right]` or a single
+                /// sample for PCM
+                ///
+                /// The samples are right aligned in the words
+                ///
+                /// # Panics
+                ///
+                /// Panics if `frame` doesn't hold one word per sample or if the peripheral
+                /// receives
+                pub fn send(&mut self, frame: &[u32]) -> nb::Result<(), Error> {
+                    assert!(PINS::TRANSMIT, "the peripheral receives");
+                    assert!(frame.len() == self.config.channels(), "a frame holds one word per sample");
+
+                    let sr = self.spi.sr.read();
+                    if sr.udr().bit_is_set() {
+                        self.spi.ifcr.write(|w| w.udrc().set_bit());
+                        return Err(nb::Error::Other(Error::Underrun));
+                    }
+                    let started = self.spi.cr1.read().spe().bit_is_set();
+                    if started && sr.txp().bit_is_clear() {
+                        return Err(nb::Error::WouldBlock);
+                    }
+
+                    for &word in frame {
+                        self.write_sample(word);
+                    }
+                    if !started {
+                        self.start();
+                    }
+                    Ok(())
+                }
+
+                /// Reads the samples of one frame from the FIFO, `[left, right]` or a single
+                /// sample for PCM
+                ///
+                /// The samples are right aligned in the words
+                ///
+                /// # Panics
+                ///
+                /// Panics if `frame` doesn't hold one word per sample or if the peripheral
+                /// transmits
+                pub fn receive(&mut self, frame: &mut [u32]) -> nb::Result<(), Error> {
+                    assert!(!PINS::TRANSMIT, "the peripheral transmits");
+                    assert!(frame.len() == self.config.channels(), "a frame holds one word per sample");
+
+                    let sr = self.spi.sr.read();
+                    if sr.ovr().bit_is_set() {
+                        self.spi.ifcr.write(|w| w.ovrc().set_bit());
+                        return Err(nb::Error::Other(Error::Overrun));
+                    }
+                    if sr.rxp().bit_is_clear() {
+                        return Err(nb::Error::WouldBlock);
+                    }
+
+                    for word in frame {
+                        *word = self.read_sample();
+                    }
+                    Ok(())
+                }
+
+                /// Writes a single sample, 16 bit samples are written as half word so they
+                /// occupy a single FIFO entry
+                fn write_sample(&mut self, word: u32) {
+                    let txdr = ptr::addr_of!(self.spi.txdr);
+                    // NOTE(unsafe) TXDR is owned by the peripheral
+                    unsafe {
+                        if self.config.data_length == I2sDataLength::Bits16 {
+                            ptr::write_volatile(txdr as *mut u16, word as u16)
+                        } else {
+                            ptr::write_volatile(txdr as *mut u32, word)
+                        }
+                    }
+                }
+
+                /// Reads a single sample with the width it was written with
+                fn read_sample(&mut self) -> u32 {
+                    let rxdr = &self.spi.rxdr as *const _;
+                    // NOTE(unsafe) RXDR is owned by the peripheral
+                    unsafe {
+                        if self.config.data_length == I2sDataLength::Bits16 {
+                            u32::from(ptr::read_volatile(rxdr as *const u16))
+                        } else {
+                            ptr::read_volatile(rxdr as *const u32)
+                        }
+                    }
+                }
+
+                /// Enables the peripheral and starts the communication
+                fn start(&mut self) {
+                    self.spi.cr1.modify(|_, w| w.spe().set_bit());
+                    // the svd lacks a writer for CSTART (bit 9)
+                    self.spi.cr1.modify(|r, w| unsafe { w.bits(r.bits() | (1 << 9)) });
+                }
+
+                /// Releases the SPI peripheral and associated pins
+                pub fn free(self) -> ($SPIX, PINS) {
+                    self.spi.cr1.modify(|_, w| w.spe().clear_bit());
+                    (self.spi, self.pins)
+                }
+            }
+        )+
+    };
+}
+
+// only SPI1, SPI2 and SPI3 implement the I2S protocol
+i2s! {
+    SPI1: (i2s1, APB2),
+    SPI2: (i2s2, APB1L),
+    SPI3: (i2s3, APB1L),
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn baud_rate_prescaler_doesnt_exceed_freq() {
+        assert_eq!(baud_rate_prescaler(100_000_000, 50_000_000), 0b000);
+        assert_eq!(baud_rate_prescaler(100_000_000, 30_000_000), 0b001);
+        assert_eq!(baud_rate_prescaler(100_000_000, 390_625), 0b111);
+    }
+
+    #[test]
+    #[should_panic]
+    fn baud_rate_prescaler_rejects_0_hz() {
+        baud_rate_prescaler(100_000_000, 0);
+    }
+
+    #[test]
+    #[should_panic]
+    fn baud_rate_prescaler_rejects_too_low_freq() {
+        baud_rate_prescaler(100_000_000, 390_624);
+    }
+}
@@ -0,0 +1,46 @@
+//! System configuration controller
+
+use stm32h7::stm32h7x3::{RCC, SYSCFG};
+
+use crate::rcc::{Enable, APB4};
+
+/// Extension trait to constrain the SYSCFG peripheral
+pub trait SysCfgExt {
+    /// Enables the clock of the SYSCFG peripheral and constrains it to play nicely
+    /// with the other abstractions
+    fn constrain(self, apb4: &mut APB4) -> SysCfg;
+}
+
+impl SysCfgExt for SYSCFG {
+    fn constrain(self, apb4: &mut APB4) -> SysCfg {
+        SYSCFG::enable(apb4);
+        SysCfg { syscfg: self }
+    }
+}
+
+/// Constrained SYSCFG peripheral
+pub struct SysCfg {
+    pub(crate) syscfg: SYSCFG,
+}
+
+impl SysCfg {
+    /// Enables the I/O compensation cell and waits until it is ready
+    ///
+    /// The compensation cell keeps the slew rate of pins configured to `Speed::High`
+    /// or `Speed::VeryHigh` in spec, it is not needed for slower pins. The cell is
+    /// clocked by the CSI, which is started if it is not running yet
+    pub fn enable_io_compensation(&mut self) {
+        // NOTE(unsafe) setting CSION is an atomic modify which does not change the clock tree
+        let rcc = unsafe { &*RCC::ptr() };
+        rcc.cr.modify(|_, w| w.csion().set_bit());
+        while rcc.cr.read().csirdy().bit_is_clear() {}
+
+        self.syscfg.cccsr.modify(|_, w| w.en().set_bit());
+        while self.syscfg.cccsr.read().ready().bit_is_clear() {}
+    }
+
+    /// Disables the I/O compensation cell
+    pub fn disable_io_compensation(&mut self) {
+        self.syscfg.cccsr.modify(|_, w| w.en().clear_bit());
+    }
+}
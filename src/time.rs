@@ -1,3 +1,7 @@
+use core::fmt;
+use core::ops::{Add, Div, Mul, Sub};
+use core::time::Duration;
+
 use cortex_m::peripheral::DWT;
 
 use crate::rcc::Clocks;
@@ -7,17 +11,93 @@ use crate::rcc::Clocks;
 pub struct Bps(pub u32);
 
 /// Hertz
-#[derive(Clone, Copy)]
+#[derive(PartialEq, Eq, PartialOrd, Ord, Clone, Copy, Debug)]
 pub struct Hertz(pub u32);
 
 /// KiloHertz
-#[derive(Clone, Copy)]
+#[derive(PartialEq, Eq, PartialOrd, Ord, Clone, Copy, Debug)]
 pub struct KiloHertz(pub u32);
 
 /// MegaHertz
-#[derive(Clone, Copy)]
+#[derive(PartialEq, Eq, PartialOrd, Ord, Clone, Copy, Debug)]
 pub struct MegaHertz(pub u32);
 
+// Like the conversions, the arithmetic saturates at 0 and u32::MAX instead of wrapping
+
+macro_rules! frequency_ops {
+    ($($Freq:ident,)+) => {
+        $(
+            impl Add for $Freq {
+                type Output = Self;
+
+                fn add(self, rhs: Self) -> Self {
+                    $Freq(self.0.saturating_add(rhs.0))
+                }
+            }
+
+            impl Sub for $Freq {
+                type Output = Self;
+
+                fn sub(self, rhs: Self) -> Self {
+                    $Freq(self.0.saturating_sub(rhs.0))
+                }
+            }
+
+            impl Mul<u32> for $Freq {
+                type Output = Self;
+
+                fn mul(self, rhs: u32) -> Self {
+                    $Freq(self.0.saturating_mul(rhs))
+                }
+            }
+
+            /// Divides the frequency, e.g. by a prescaler, the result is rounded towards zero
+            impl Div<u32> for $Freq {
+                type Output = Self;
+
+                fn div(self, rhs: u32) -> Self {
+                    $Freq(self.0 / rhs)
+                }
+            }
+        )+
+    };
+}
+
+frequency_ops!(
+    Hertz,
+    KiloHertz,
+    MegaHertz,
+);
+
+impl Hertz {
+    /// The frequency in the largest unit it is a whole multiple of
+    fn scaled(self) -> (u32, &'static str) {
+        match self.0 {
+            0 => (0, "Hz"),
+            hz if hz % 1_000_000 == 0 => (hz / 1_000_000, "MHz"),
+            hz if hz % 1_000 == 0 => (hz / 1_000, "kHz"),
+            hz => (hz, "Hz"),
+        }
+    }
+}
+
+/// Prints the frequency like "200 MHz"
+impl fmt::Display for Hertz {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let (value, unit) = self.scaled();
+        write!(f, "{} {}", value, unit)
+    }
+}
+
+/// Prints the frequency like "200 MHz"
+#[cfg(feature = "defmt")]
+impl defmt::Format for Hertz {
+    fn format(&self, f: defmt::Formatter) {
+        let (value, unit) = self.scaled();
+        defmt::write!(f, "{=u32} {=str}", value, unit)
+    }
+}
+
 /// MilliSeconds
 #[derive(PartialEq, PartialOrd, Clone, Copy)]
 pub struct MilliSeconds(pub u32);
@@ -62,21 +142,56 @@ impl U32Ext for u32 {
     }
 }
 
+// The conversions saturate at u32::MAX instead of wrapping, a frequency that large is
+// rejected by every consumer anyways
+
 impl Into<Hertz> for KiloHertz {
     fn into(self) -> Hertz {
-        Hertz(self.0 * 1_000)
+        Hertz(self.0.saturating_mul(1_000))
     }
 }
 
 impl Into<Hertz> for MegaHertz {
     fn into(self) -> Hertz {
-        Hertz(self.0 * 1_000_000)
+        Hertz(self.0.saturating_mul(1_000_000))
     }
 }
 
 impl Into<KiloHertz> for MegaHertz {
     fn into(self) -> KiloHertz {
-        KiloHertz(self.0 * 1_000)
+        KiloHertz(self.0.saturating_mul(1_000))
+    }
+}
+
+impl Hertz {
+    /// The period of the frequency, truncated to whole nanoseconds
+    ///
+    /// The period of 0 Hz saturates at `u64::MAX` seconds
+    pub fn duration(self) -> Duration {
+        if self.0 == 0 {
+            return Duration::from_secs(u64::MAX);
+        }
+        Duration::from_nanos(1_000_000_000 / u64::from(self.0))
+    }
+
+    /// The period of the frequency in nanoseconds, truncated
+    ///
+    /// The period of 0 Hz saturates at `u32::MAX`
+    pub fn period_ns(self) -> u32 {
+        if self.0 == 0 {
+            return u32::MAX;
+        }
+        1_000_000_000 / self.0
+    }
+
+    /// The frequency of a period given in nanoseconds, truncated
+    ///
+    /// A period of 0 ns saturates at `u32::MAX` Hz
+    pub fn from_period_ns(period_ns: u32) -> Self {
+        if period_ns == 0 {
+            return Hertz(u32::MAX);
+        }
+        Hertz(1_000_000_000 / period_ns)
     }
 }
 
@@ -123,4 +238,29 @@ impl Instant {
     pub fn elapsed(&self) -> u32 {
         DWT::get_cycle_count().wrapping_sub(self.now)
     }
-}
\ No newline at end of file
+}
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn frequencies_are_ordered() {
+        assert!(Hertz(1) < Hertz(2));
+        assert!(KiloHertz(400) > KiloHertz(100));
+        assert_eq!(MegaHertz(200).max(MegaHertz(100)), MegaHertz(200));
+    }
+
+    #[test]
+    fn division_rounds_towards_zero() {
+        assert_eq!(Hertz(7) / 2, Hertz(3));
+        assert_eq!(Hertz(1) / 2, Hertz(0));
+        assert_eq!(MegaHertz(200) / 3, MegaHertz(66));
+    }
+
+    #[test]
+    fn arithmetic_saturates() {
+        assert_eq!(Hertz(1) - Hertz(2), Hertz(0));
+        assert_eq!(Hertz(u32::MAX) + Hertz(1), Hertz(u32::MAX));
+        assert_eq!(MegaHertz(5000) * 1_000_000, MegaHertz(u32::MAX));
+    }
+}
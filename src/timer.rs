@@ -0,0 +1,421 @@
+//! Timers
+
+use core::marker::PhantomData;
+use core::time::Duration;
+
+use hal::timer::{Cancel, CountDown, Periodic};
+use nb;
+use stm32h7::stm32h7x3::{
+    TIM1, TIM2, TIM3, TIM4, TIM5, TIM6, TIM7, TIM8, TIM12, TIM13, TIM14, TIM15, TIM16, TIM17,
+};
+use cast::u16;
+use void::Void;
+
+use crate::exti::Edge;
+use crate::pwm::{C1, C2};
+use crate::qei::{PinA, PinB};
+use crate::rcc::{Clocks, Enable, RccBus, Reset, APB1L, APB2};
+use crate::time::Hertz;
+
+/// Interrupt events
+pub enum Event {
+    /// The counter reached the end of a period
+    Update,
+}
+
+/// Timer errors
+#[derive(Debug, PartialEq)]
+#[non_exhaustive]
+pub enum Error {
+    /// The timer was not running
+    Disabled,
+    /// A capture was overwritten before it was read
+    Overcapture,
+}
+
+/// A hardware timer counting down periods, e.g. to run a task periodically
+pub struct Timer<TIM> {
+    tim: TIM,
+    ker_ck: Hertz,
+}
+
+/// Input pin of a timer channel - DO NOT IMPLEMENT THIS TRAIT
+///
+/// Only channel 1 and 2 can be used for input capture
+///
+/// # Safety
+///
+/// Only the pins whose alternate function connects them to `CHANNEL` of `TIM` may
+/// implement this trait
+pub unsafe trait CapturePin<TIM, CHANNEL> {}
+
+unsafe impl<TIM, PIN: PinA<TIM>> CapturePin<TIM, C1> for PIN {}
+unsafe impl<TIM, PIN: PinB<TIM>> CapturePin<TIM, C2> for PIN {}
+
+/// A timer channel that supports input capture
+pub trait CaptureChannel {
+    #[doc(hidden)]
+    const INDEX: u32;
+}
+
+impl CaptureChannel for C1 {
+    const INDEX: u32 = 0;
+}
+
+impl CaptureChannel for C2 {
+    const INDEX: u32 = 1;
+}
+
+/// A timer channel capturing the counter on the edges of its input, e.g. to measure
+/// the period of a signal
+///
+/// The counter runs over its full range and wraps around, differences of captures
+/// have to be computed with wrapping arithmetic
+pub struct InputCapture<TIM, CHANNEL> {
+    tim: TIM,
+    tick: Hertz,
+    _channel: PhantomData<CHANNEL>,
+}
+
+/// Extension trait to use a timer for input capture
+pub trait InputCaptureExt: Sized + RccBus {
+    /// Captures the counter on `edge` of `pin`, the counter counts at `tick` or the
+    /// closest lower frequency that can be derived from the timer kernel clock
+    fn input_capture<PIN, CHANNEL, T>(
+        self,
+        pin: PIN,
+        edge: Edge,
+        tick: T,
+        clocks: Clocks,
+        bus: &mut Self::Bus,
+    ) -> InputCapture<Self, CHANNEL>
+    where
+        PIN: CapturePin<Self, CHANNEL>,
+        CHANNEL: CaptureChannel,
+        T: Into<Hertz>;
+}
+
+/// Computes the prescaler and the auto reload value for `ticks` kernel clock cycles
+///
+/// Among the prescalers that allow for an auto reload value up to `max_arr` the one with
+/// the smallest deviation from `ticks` is picked, the search stops at an exact match
+pub(crate) fn psc_arr(ticks: u64, max_arr: u32) -> (u16, u32) {
+    let ticks = ticks.max(1);
+    let max_arr = u64::from(max_arr);
+
+    // the smallest prescaler that fits, the search is limited to keep `start` fast
+    let first = ticks.div_ceil(max_arr + 1).clamp(1, 1 << 16);
+    let last = (first + 1024).min(1 << 16);
+
+    let mut best = (first, ((ticks / first).max(1) - 1).min(max_arr), u64::MAX);
+    for div in first..=last {
+        let reload = (ticks + div / 2) / div;
+        if reload == 0 || reload > max_arr + 1 {
+            continue;
+        }
+        let error = (div * reload).abs_diff(ticks);
+        if error < best.2 {
+            best = (div, reload - 1, error);
+            if error == 0 {
+                break;
+            }
+        }
+    }
+
+    ((best.0 - 1) as u16, best.1 as u32)
+}
+
+/// A monotonic nondecreasing timer counting microseconds, built on one of the 32 bit timers
+pub struct MonoTimer<TIM> {
+    tim: TIM,
+}
+
+/// A measurement of a `MonoTimer`
+#[derive(Clone, Copy)]
+pub struct Instant {
+    now: u32,
+}
+
+impl Instant {
+    /// Microseconds passed between `earlier` and this instant, the counter wraps
+    /// around after about 71 minutes
+    pub fn duration_since(&self, earlier: Instant) -> u32 {
+        self.now.wrapping_sub(earlier.now)
+    }
+}
+
+macro_rules! mono_timers {
+    ($($TIMX:ident: $timx:ident,)+) => {
+        $(
+            impl MonoTimer<$TIMX> {
+                /// Configures the timer to free-run at 1 MHz
+                pub fn $timx(tim: $TIMX, clocks: &Clocks, apb1l: &mut APB1L) -> Self {
+                    $TIMX::enable(apb1l);
+                    $TIMX::reset(apb1l);
+
+                    let psc = u16((clocks.timx_ker_ck().0 / 1_000_000).max(1) - 1).unwrap();
+                    tim.psc.write(|w| unsafe { w.psc().bits(psc) });
+                    tim.arr.write(|w| unsafe { w.bits(0xFFFF_FFFF) });
+
+                    // load the prescaler right away instead of at the first overflow
+                    tim.egr.write(|w| w.ug().set_bit());
+                    tim.cr1.modify(|_, w| w.cen().set_bit());
+
+                    MonoTimer { tim }
+                }
+
+                /// Returns an `Instant` corresponding to "now"
+                pub fn now(&self) -> Instant {
+                    Instant {
+                        now: self.tim.cnt.read().bits(),
+                    }
+                }
+
+                /// Microseconds elapsed since `instant`
+                pub fn elapsed(&self, instant: Instant) -> u32 {
+                    self.now().duration_since(instant)
+                }
+
+                /// Stops the timer and releases it
+                pub fn free(self) -> $TIMX {
+                    self.tim.cr1.modify(|_, w| w.cen().clear_bit());
+                    self.tim
+                }
+            }
+        )+
+    };
+}
+
+mono_timers!(
+    TIM2: tim2,
+    TIM5: tim5,
+);
+
+macro_rules! timers {
+    ($($TIMX:ident: ($timx:ident, $APB:ident, $ker_ck:ident, $max_arr:expr),)+) => {
+        $(
+            impl Timer<$TIMX> {
+                /// Enables the timer, which stays stopped until `start` is called
+                pub fn $timx(tim: $TIMX, clocks: &Clocks, apb: &mut $APB) -> Self {
+                    $TIMX::enable(apb);
+                    $TIMX::reset(apb);
+
+                    Timer {
+                        tim,
+                        ker_ck: clocks.$ker_ck(),
+                    }
+                }
+
+                /// Starts listening for an `event`
+                pub fn listen(&mut self, event: Event) {
+                    match event {
+                        Event::Update => self.tim.dier.modify(|_, w| w.uie().set_bit()),
+                    }
+                }
+
+                /// Stops listening for an `event`
+                pub fn unlisten(&mut self, event: Event) {
+                    match event {
+                        Event::Update => self.tim.dier.modify(|_, w| w.uie().clear_bit()),
+                    }
+                }
+
+                /// Clears the interrupt flag of an `event`, this has to be done in the
+                /// interrupt handler
+                pub fn clear_interrupt(&mut self, event: Event) {
+                    match event {
+                        Event::Update => self.tim.sr.modify(|_, w| w.uif().clear_bit()),
+                    }
+                }
+
+                /// Stops the timer and releases it
+                pub fn free(self) -> $TIMX {
+                    self.tim.cr1.modify(|_, w| w.cen().clear_bit());
+                    self.tim
+                }
+            }
+
+            impl CountDown for Timer<$TIMX> {
+                type Time = Hertz;
+
+                /// Starts a period of `timeout`, the prescaler and the auto reload value are
+                /// picked to match the frequency as close as possible
+                fn start<T>(&mut self, timeout: T)
+                where
+                    T: Into<Hertz>,
+                {
+                    self.tim.cr1.modify(|_, w| w.cen().clear_bit());
+                    self.tim.cnt.reset();
+
+                    let ticks = u64::from(self.ker_ck.0) / u64::from(timeout.into().0.max(1));
+                    let (psc, arr) = psc_arr(ticks, $max_arr);
+                    self.tim.psc.write(|w| unsafe { w.psc().bits(psc) });
+                    self.tim.arr.write(|w| unsafe { w.bits(arr) });
+
+                    // load the prescaler right away, URS keeps this update from setting UIF
+                    self.tim.cr1.modify(|_, w| w.urs().set_bit());
+                    self.tim.egr.write(|w| w.ug().set_bit());
+                    self.tim.cr1.modify(|_, w| w.urs().clear_bit().cen().set_bit());
+                }
+
+                fn wait(&mut self) -> nb::Result<(), Void> {
+                    if self.tim.sr.read().uif().bit_is_clear() {
+                        Err(nb::Error::WouldBlock)
+                    } else {
+                        self.tim.sr.modify(|_, w| w.uif().clear_bit());
+                        Ok(())
+                    }
+                }
+            }
+
+            impl Periodic for Timer<$TIMX> {}
+
+            impl Cancel for Timer<$TIMX> {
+                type Error = Error;
+
+                fn cancel(&mut self) -> Result<(), Error> {
+                    if self.tim.cr1.read().cen().bit_is_clear() {
+                        return Err(Error::Disabled);
+                    }
+
+                    self.tim.cr1.modify(|_, w| w.cen().clear_bit());
+                    Ok(())
+                }
+            }
+        )+
+    };
+}
+
+macro_rules! input_capture {
+    ($($TIMX:ident: ($ker_ck:ident, $max_arr:expr),)+) => {
+        $(
+            impl InputCaptureExt for $TIMX {
+                fn input_capture<PIN, CHANNEL, T>(
+                    self,
+                    _pin: PIN,
+                    edge: Edge,
+                    tick: T,
+                    clocks: Clocks,
+                    bus: &mut Self::Bus,
+                ) -> InputCapture<$TIMX, CHANNEL>
+                where
+                    PIN: CapturePin<$TIMX, CHANNEL>,
+                    CHANNEL: CaptureChannel,
+                    T: Into<Hertz>,
+                {
+                    $TIMX::enable(bus);
+                    $TIMX::reset(bus);
+
+                    let tim = self;
+                    let ker_ck = clocks.$ker_ck().0;
+                    let psc = (ker_ck / tick.into().0.max(1)).max(1).min(1 << 16) - 1;
+                    tim.psc.write(|w| unsafe { w.psc().bits(psc as u16) });
+                    tim.arr.write(|w| unsafe { w.bits($max_arr) });
+                    tim.egr.write(|w| w.ug().set_bit());
+
+                    // CCxS = 01: the input of the channel is mapped to the channel
+                    let n = CHANNEL::INDEX;
+                    tim.ccmr1_output.modify(|r, w| unsafe { w.bits(r.bits() | (0b01 << (8 * n))) });
+
+                    // CCxP and CCxNP select the edge, CCxE enables the capture
+                    let polarity = match edge {
+                        Edge::Rising => 0b0000,
+                        Edge::Falling => 0b0010,
+                        Edge::RisingFalling => 0b1010,
+                    };
+                    tim.ccer.modify(|r, w| unsafe { w.bits(r.bits() | ((polarity | 1) << (4 * n))) });
+
+                    tim.cr1.modify(|_, w| w.cen().set_bit());
+
+                    InputCapture {
+                        tim,
+                        tick: Hertz(ker_ck / (psc + 1)),
+                        _channel: PhantomData,
+                    }
+                }
+            }
+
+            impl<CHANNEL: CaptureChannel> InputCapture<$TIMX, CHANNEL> {
+                /// The frequency the counter counts at
+                pub fn tick(&self) -> Hertz {
+                    self.tick
+                }
+
+                /// Converts a number of counter ticks, e.g. the difference of two captures,
+                /// into a duration
+                pub fn to_duration(&self, ticks: u32) -> Duration {
+                    Duration::from_nanos(u64::from(ticks) * 1_000_000_000 / u64::from(self.tick.0))
+                }
+
+                /// Converts a number of counter ticks of one period into the frequency of
+                /// the signal, `ticks` of 0 result in 0 Hz
+                pub fn to_hertz(&self, ticks: u32) -> Hertz {
+                    Hertz(if ticks == 0 { 0 } else { self.tick.0 / ticks })
+                }
+
+                /// Returns the counter at the last capture, reading it clears the capture flag
+                ///
+                /// Returns `Error::Overcapture` once if a capture was lost since the last read
+                pub fn read(&mut self) -> nb::Result<u32, Error> {
+                    // CCxIF and CCxOF
+                    let ccxif = 1 << (CHANNEL::INDEX + 1);
+                    let ccxof = 1 << (CHANNEL::INDEX + 9);
+
+                    let sr = self.tim.sr.read().bits();
+                    if sr & ccxof != 0 {
+                        // the flags are cleared by writing 0, the other flags are left alone
+                        self.tim.sr.write(|w| unsafe { w.bits(!ccxof) });
+                        // drop the capture that overwrote the lost one
+                        self.ccr();
+                        Err(nb::Error::Other(Error::Overcapture))
+                    } else if sr & ccxif != 0 {
+                        Ok(self.ccr())
+                    } else {
+                        Err(nb::Error::WouldBlock)
+                    }
+                }
+
+                fn ccr(&self) -> u32 {
+                    match CHANNEL::INDEX {
+                        0 => self.tim.ccr1.read().bits(),
+                        _ => self.tim.ccr2.read().bits(),
+                    }
+                }
+
+                /// Stops the timer and releases it
+                pub fn free(self) -> $TIMX {
+                    self.tim.cr1.modify(|_, w| w.cen().clear_bit());
+                    self.tim
+                }
+            }
+        )+
+    };
+}
+
+// TIM2 and TIM5 have 32 bit counters, the others 16 bit counters
+input_capture!(
+    TIM1: (timy_ker_ck, 0xFFFF),
+    TIM2: (timx_ker_ck, 0xFFFF_FFFF),
+    TIM3: (timx_ker_ck, 0xFFFF),
+    TIM4: (timx_ker_ck, 0xFFFF),
+    TIM5: (timx_ker_ck, 0xFFFF_FFFF),
+    TIM8: (timy_ker_ck, 0xFFFF),
+);
+
+// TIM2 and TIM5 have 32 bit counters, the others 16 bit counters
+timers!(
+    TIM1: (tim1, APB2, timy_ker_ck, 0xFFFF),
+    TIM2: (tim2, APB1L, timx_ker_ck, 0xFFFF_FFFF),
+    TIM3: (tim3, APB1L, timx_ker_ck, 0xFFFF),
+    TIM4: (tim4, APB1L, timx_ker_ck, 0xFFFF),
+    TIM5: (tim5, APB1L, timx_ker_ck, 0xFFFF_FFFF),
+    TIM6: (tim6, APB1L, timx_ker_ck, 0xFFFF),
+    TIM7: (tim7, APB1L, timx_ker_ck, 0xFFFF),
+    TIM8: (tim8, APB2, timy_ker_ck, 0xFFFF),
+    TIM12: (tim12, APB1L, timx_ker_ck, 0xFFFF),
+    TIM13: (tim13, APB1L, timx_ker_ck, 0xFFFF),
+    TIM14: (tim14, APB1L, timx_ker_ck, 0xFFFF),
+    TIM15: (tim15, APB2, timy_ker_ck, 0xFFFF),
+    TIM16: (tim16, APB2, timy_ker_ck, 0xFFFF),
+    TIM17: (tim17, APB2, timy_ker_ck, 0xFFFF),
+);
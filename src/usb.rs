@@ -0,0 +1,115 @@
+//! USB full-speed device on the OTG2 core
+//!
+//! `UsbBus` implements the `UsbBus` trait of `usb-device` through the Synopsys OTG driver,
+//! so the classes of the usb-device ecosystem (e.g. CDC or HID) can be used on top of it
+
+use cortex_m::interrupt;
+pub use synopsys_usb_otg::UsbBus;
+use synopsys_usb_otg::UsbPeripheral;
+
+use crate::gpio::gpioa::{PA11, PA12};
+use crate::gpio::{Output, PushPull, AF10};
+use crate::pwr::Pwr;
+use crate::rcc::{Clocks, Enable, Reset, AHB1};
+use crate::stm32h7x3::{OTG2_HS_DEVICE, OTG2_HS_GLOBAL, OTG2_HS_PWRCLK, RCC};
+use crate::time::Hertz;
+
+/// The frequency the USB kernel clock has to run at
+const USB_CK: u32 = 48_000_000;
+
+/// DM pin - DO NOT IMPLEMENT THIS TRAIT
+///
+/// # Safety
+///
+/// Only the pins whose alternate function connects them to DM of `USB` may
+/// implement this trait
+pub unsafe trait DmPin<USB> {}
+
+/// DP pin - DO NOT IMPLEMENT THIS TRAIT
+///
+/// # Safety
+///
+/// Only the pins whose alternate function connects them to DP of `USB` may
+/// implement this trait
+pub unsafe trait DpPin<USB> {}
+
+unsafe impl DmPin<OTG2_HS_GLOBAL> for PA11<Output<PushPull>, AF10> {}
+unsafe impl DpPin<OTG2_HS_GLOBAL> for PA12<Output<PushPull>, AF10> {}
+
+/// The OTG2 core along with its pins
+pub struct Usb<DM, DP> {
+    global: OTG2_HS_GLOBAL,
+    device: OTG2_HS_DEVICE,
+    pwrclk: OTG2_HS_PWRCLK,
+    pins: (DM, DP),
+    hclk: Hertz,
+}
+
+impl<DM, DP> Usb<DM, DP>
+where
+    DM: DmPin<OTG2_HS_GLOBAL>,
+    DP: DpPin<OTG2_HS_GLOBAL>,
+{
+    /// Enables the OTG2 core and the regulator of its transceiver, the core is clocked by
+    /// the kernel clock selected with `CFGR::usb_src`
+    ///
+    /// Pass the result to `UsbBus::new` along with the endpoint memory
+    ///
+    /// # Panics
+    ///
+    /// Panics if the kernel clock is disabled or doesn't run at 48 MHz
+    pub fn usb2(
+        global: OTG2_HS_GLOBAL,
+        device: OTG2_HS_DEVICE,
+        pwrclk: OTG2_HS_PWRCLK,
+        pins: (DM, DP),
+        ahb1: &mut AHB1,
+        pwr: &mut Pwr,
+        clocks: &Clocks,
+    ) -> Self {
+        let usb_ck = clocks.usb_ck().expect("the kernel clock of the USB is disabled");
+        assert!(usb_ck.0 == USB_CK, "the kernel clock of the USB has to run at 48 MHz");
+
+        pwr.enable_usb_regulator();
+
+        OTG2_HS_GLOBAL::enable(ahb1);
+        OTG2_HS_GLOBAL::reset(ahb1);
+
+        Usb {
+            global,
+            device,
+            pwrclk,
+            pins,
+            hclk: clocks.hclk1(),
+        }
+    }
+
+    /// Releases the core and the pins, the core keeps running
+    pub fn free(self) -> (OTG2_HS_GLOBAL, OTG2_HS_DEVICE, OTG2_HS_PWRCLK, (DM, DP)) {
+        (self.global, self.device, self.pwrclk, self.pins)
+    }
+}
+
+// NOTE(unsafe) the core is owned through its three register blocks
+unsafe impl<DM, DP> Sync for Usb<DM, DP> {}
+
+unsafe impl<DM: Send, DP: Send> UsbPeripheral for Usb<DM, DP> {
+    // the address of OTG2_HS_GLOBAL, `ptr` is not a const fn
+    const REGISTERS: *const () = 0x4008_0000 as *const ();
+
+    // the OTG2 is a high speed core without a high speed PHY
+    const HIGH_SPEED: bool = true;
+    const FIFO_DEPTH_WORDS: usize = 1024;
+    const ENDPOINT_COUNT: usize = 9;
+
+    fn enable() {
+        // NOTE(unsafe) atomic read-modify-write of a bit owned by the OTG2 core
+        interrupt::free(|_| unsafe {
+            (*RCC::ptr()).ahb1enr.modify(|_, w| w.usb2otgen().set_bit());
+        });
+    }
+
+    fn ahb_frequency_hz(&self) -> u32 {
+        self.hclk.0
+    }
+}
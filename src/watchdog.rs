@@ -1,6 +1,6 @@
-//! System Window Watchdog implementation
+//! System Window Watchdog and Independent Watchdog implementation
 
-use stm32h7::stm32h7x3::WWDG;
+use stm32h7::stm32h7x3::{IWDG, WWDG};
 use embedded_hal::watchdog::{Watchdog, WatchdogEnable};
 use cast::u8;
 use crate::time::MilliSeconds;
@@ -9,10 +9,14 @@ use crate::rcc::APB3;
 
 
 /// Implements the System Window Watchdog
+///
+/// The watchdog resets the chip if it isn't fed in time, and if a window is set with
+/// `set_window` also if it is fed too early
 pub struct SystemWindowWatchdog {
     wwdg: WWDG,
     clocks: Clocks,
     down_counter: u8,
+    window: Option<u32>,
 }
 
 
@@ -22,7 +26,55 @@ impl SystemWindowWatchdog {
     pub fn new(wwdg: WWDG, clocks: Clocks, apb3: &mut APB3) -> Self {
         // enable the peripheral inside the APB3
         apb3.enr().modify(|_, w| w.wwdg1en().set_bit());
-        SystemWindowWatchdog {wwdg, clocks, down_counter: 0}
+        SystemWindowWatchdog {wwdg, clocks, down_counter: 0, window: None}
+    }
+
+    /// Only allows feeding the watchdog during the last `window` of the timeout period,
+    /// feeding it earlier causes a reset
+    ///
+    /// The window is rounded up to the resolution of the counter and takes effect
+    /// immediately if the watchdog is already running
+    pub fn set_window<T>(&mut self, window: T)
+    where
+        T: Into<MilliSeconds>,
+    {
+        self.window = Some(window.into().0);
+        if self.down_counter != 0 {
+            let window = self.window_value();
+            self.wwdg.cfr.modify(|_, w| w.w().bits(window));
+        }
+    }
+
+    /// Starts listening for the early wakeup interrupt, which is raised when the counter
+    /// reaches 0x40, one tick before the reset, it can only be disabled by a reset
+    pub fn listen(&mut self) {
+        self.wwdg.cfr.modify(|_, w| w.ewi().set_bit());
+    }
+
+    /// Whether the early wakeup interrupt is pending
+    pub fn is_early_wakeup(&self) -> bool {
+        self.wwdg.sr.read().ewif().bit_is_set()
+    }
+
+    /// Clears the early wakeup interrupt flag, this has to be done in the interrupt handler
+    pub fn clear_interrupt(&mut self) {
+        self.wwdg.sr.write(|w| w.ewif().clear_bit());
+    }
+
+    /// The value of the window register for the configured window and prescaler, feeding is
+    /// allowed once the counter dropped below it
+    fn window_value(&self) -> u8 {
+        let window = match self.window {
+            Some(window) => window,
+            None => return self.down_counter,
+        };
+        let wdgtb = u32::from(self.wwdg.cfr.read().wdgtb().bits());
+        // duration of one tick of the counter in microseconds
+        let tick = u64::from(4096u32 << wdgtb) * 1_000_000 / u64::from(self.clocks.pclk3().0);
+        let ticks = (u64::from(window) * 1000).div_ceil(tick);
+
+        // the counter resets the chip when it drops below 0x40
+        (0x3F + ticks).min(u64::from(self.down_counter)) as u8
     }
 }
 
@@ -71,9 +123,93 @@ impl WatchdogEnable for SystemWindowWatchdog {
 
         // write the config values, matching the set timeout the most
         self.wwdg.cfr.modify(|_, w| w.wdgtb().bits(wdgtb));
-        self.wwdg.cfr.modify(|_, w| w.w().bits(self.down_counter));
+        let window = self.window_value();
+        self.wwdg.cfr.modify(|_, w| w.w().bits(window));
         self.wwdg.cr.modify(|_, w| w.t().bits(self.down_counter));
         // enable the watchdog
         self.wwdg.cr.modify(|_, w| w.wdga().set_bit());
     }
 }
+
+
+/// The nominal frequency of the LSI, which clocks the Independent Watchdog
+const LSI: u32 = 32_000;
+
+/// The keys of the key register of the Independent Watchdog
+const KEY_RELOAD: u16 = 0xAAAA;
+const KEY_UNLOCK: u16 = 0x5555;
+const KEY_START: u16 = 0xCCCC;
+
+/// Implements the Independent Watchdog
+///
+/// The Independent Watchdog is clocked by the LSI, which is started by the hardware
+/// together with the watchdog regardless of `CFGR::lsi`. The timeouts are calculated for
+/// the nominal 32 kHz of the LSI, the LSI varies between chips and with the temperature so
+/// the actual timeouts deviate accordingly. Once started the watchdog can't be stopped
+/// except by a reset
+pub struct IndependentWatchdog {
+    iwdg: IWDG,
+}
+
+impl IndependentWatchdog {
+    /// Returns an Independent Watchdog object, the watchdog stays stopped until `start`
+    /// is called
+    pub fn new(iwdg: IWDG) -> Self {
+        IndependentWatchdog { iwdg }
+    }
+
+    /// The longest timeout in milliseconds
+    pub fn max_timeout() -> u32 {
+        // prescaler 256 and a reload value of 0xFFF
+        256 * 0x1000 * 1000 / LSI
+    }
+
+    /// Waits until the prescaler, reload and window registers were updated, writes to them
+    /// are ignored while an update is ongoing
+    fn wait_for_update(&self) {
+        loop {
+            let sr = self.iwdg.sr.read();
+            if sr.pvu().bit_is_clear() && sr.rvu().bit_is_clear() && sr.wvu().bit_is_clear() {
+                break;
+            }
+        }
+    }
+}
+
+impl Watchdog for IndependentWatchdog {
+    /// Feeds the watchdog in order to avoid a reset by reloading the counter
+    fn feed(&mut self) {
+        self.iwdg.kr.write(|w| unsafe { w.key().bits(KEY_RELOAD) });
+    }
+}
+
+impl WatchdogEnable for IndependentWatchdog {
+    type Time = MilliSeconds;
+
+    /// Starts the watchdog with a given timeout period, if this period is out of bounds the
+    /// function is going to panic
+    fn start<T>(&mut self, period: T)
+    where
+        T: Into<MilliSeconds>,
+    {
+        let period = period.into().0;
+        assert!(period <= Self::max_timeout());
+
+        // timeout = 4 * 2^PR * (RL + 1) / LSI, the smallest prescaler gives the finest
+        // resolution
+        let ticks = u64::from(period) * u64::from(LSI) / 1000;
+        let pr = (0..7).find(|pr| ticks / (4 << pr) <= 0x1000).unwrap_or(6);
+        let rl = (ticks / (4 << pr)).max(1) - 1;
+
+        // starting the watchdog also starts the LSI
+        self.iwdg.kr.write(|w| unsafe { w.key().bits(KEY_START) });
+        self.iwdg.kr.write(|w| unsafe { w.key().bits(KEY_UNLOCK) });
+        self.wait_for_update();
+        self.iwdg.pr.write(|w| w.pr().bits(pr as u8));
+        self.iwdg.rlr.write(|w| w.rl().bits(rl as u16));
+        self.wait_for_update();
+
+        // reloading the counter locks the registers again
+        self.feed();
+    }
+}